@@ -0,0 +1,258 @@
+use crate::trash;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What happens to the files of a category (`[categories.<name>] action`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Action {
+    /// Into the category folder
+    #[default]
+    Move,
+    /// Left where they are
+    Ignore,
+    /// Into the system trash
+    Trash,
+    /// Copied into this folder; the original stays
+    CopyTo(PathBuf),
+    /// Linked from this folder; the original stays
+    SymlinkInto(PathBuf),
+    /// Handed to this program, with the file's path as its argument
+    Run(PathBuf),
+}
+
+impl Action {
+    /// Parses "move", "ignore", "delete-to-trash", "copy-to PATH",
+    /// "symlink-into PATH" or "run PROGRAM"
+    pub fn parse(text: &str) -> Result<Action, String> {
+        let (verb, arg) = match text.trim().split_once(char::is_whitespace) {
+            Some((verb, arg)) => (verb, Some(arg.trim()).filter(|a| !a.is_empty())),
+            None => (text.trim(), None),
+        };
+        match (verb, arg) {
+            ("move", None) => Ok(Action::Move),
+            ("ignore", None) => Ok(Action::Ignore),
+            ("delete-to-trash", None) => Ok(Action::Trash),
+            ("copy-to", Some(dir)) => Ok(Action::CopyTo(PathBuf::from(dir))),
+            ("symlink-into", Some(dir)) => Ok(Action::SymlinkInto(PathBuf::from(dir))),
+            ("run", Some(program)) => Ok(Action::Run(PathBuf::from(program))),
+            ("copy-to" | "symlink-into", None) => Err(format!("'{}' needs a folder", verb)),
+            ("run", None) => Err("'run' needs a program".to_string()),
+            _ => Err(format!(
+                "unknown action '{}' (move, ignore, delete-to-trash, copy-to PATH, symlink-into PATH or run PROGRAM)",
+                text
+            )),
+        }
+    }
+
+    /// Whether files are planned as moves into the category folder
+    pub fn is_move(&self) -> bool {
+        *self == Action::Move
+    }
+
+    /// What `perform` did, for output: "copied to /backup", "trashed"...
+    pub fn describe(&self) -> String {
+        match self {
+            Action::Move => "moved".to_string(),
+            Action::Ignore => "ignored".to_string(),
+            Action::Trash => "trashed".to_string(),
+            Action::CopyTo(dir) => format!("copied to {}", dir.display()),
+            Action::SymlinkInto(dir) => format!("linked from {}", dir.display()),
+            Action::Run(program) => format!("handed to {}", program.display()),
+        }
+    }
+}
+
+/// Whether the copy or link `perform` would make is already there, from an
+/// earlier run: files that are copied or linked stay where they are
+pub fn done(action: &Action, path: &Path, target_dir: &Path) -> bool {
+    let Some(name) = path.file_name() else {
+        return false;
+    };
+    match action {
+        Action::CopyTo(dir) => {
+            let dest = target_dir.join(dir).join(name);
+            match (fs::metadata(&dest), fs::metadata(path)) {
+                (Ok(copy), Ok(original)) => copy.len() == original.len(),
+                _ => false,
+            }
+        }
+        Action::SymlinkInto(dir) => {
+            let dest = target_dir.join(dir).join(name);
+            let source = std::path::absolute(path).unwrap_or(path.to_path_buf());
+            fs::read_link(dest).is_ok_and(|link| link == source)
+        }
+        _ => false,
+    }
+}
+
+/// Carries out a non-move action on `path`, a file of `category` in
+/// `target_dir`, for run `run`. Relative folders, and programs given with a
+/// relative path (`scripts/hook.sh`, not a bare `notify-send`), are taken
+/// from `target_dir`. Returns where the file (or its copy or link) ended up,
+/// if anywhere new.
+pub fn perform(
+    action: &Action,
+    path: &Path,
+    category: &str,
+    target_dir: &Path,
+    run: &str,
+) -> io::Result<Option<PathBuf>> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let source = std::path::absolute(path)?;
+    match action {
+        Action::Move | Action::Ignore => Ok(None),
+        Action::Trash => trash::move_to_trash(path).map(Some),
+        Action::CopyTo(dir) => {
+            let dest = target_dir.join(dir).join(name);
+            fs::create_dir_all(target_dir.join(dir))?;
+            // create_new: an earlier copy (or anything else there) is never replaced
+            let mut to = File::options().write(true).create_new(true).open(&dest)?;
+            let copied = io::copy(&mut File::open(path)?, &mut to).and_then(|_| {
+                if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+                    to.set_modified(modified)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = copied {
+                let _ = fs::remove_file(&dest);
+                return Err(e);
+            }
+            Ok(Some(dest))
+        }
+        Action::SymlinkInto(dir) => {
+            let dest = target_dir.join(dir).join(name);
+            fs::create_dir_all(target_dir.join(dir))?;
+            symlink(&source, &dest)?;
+            Ok(Some(dest))
+        }
+        Action::Run(program) => {
+            let program_path = if program.components().count() > 1 {
+                target_dir.join(program)
+            } else {
+                program.clone()
+            };
+            let status = Command::new(program_path)
+                .arg(&source)
+                .env("AUTO_ORGANIZE_CATEGORY", category)
+                .env("AUTO_ORGANIZE_TARGET", std::path::absolute(target_dir)?)
+                .env("AUTO_ORGANIZE_RUN", run)
+                .status()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "{} failed ({})",
+                    program.display(),
+                    status
+                )));
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(original, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_original: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "links are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!(Action::parse(" ignore "), Ok(Action::Ignore));
+        assert_eq!(Action::parse("delete-to-trash"), Ok(Action::Trash));
+        assert_eq!(
+            Action::parse("copy-to  /backup/photos"),
+            Ok(Action::CopyTo(PathBuf::from("/backup/photos")))
+        );
+        assert_eq!(
+            Action::parse("run scripts/hook.sh"),
+            Ok(Action::Run(PathBuf::from("scripts/hook.sh")))
+        );
+        for bad in ["copy-to", "symlink-into ", "run", "delete", "move there"] {
+            assert!(Action::parse(bad).is_err(), "{:?} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn copies_and_links_leave_the_original_and_are_made_once() {
+        let dir = std::env::temp_dir().join("auto-organize-actions-copy");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.jpg");
+        fs::write(&file, "photo").unwrap();
+
+        let copy = Action::CopyTo(PathBuf::from("backup"));
+        assert!(!done(&copy, &file, &dir));
+        let dest = perform(&copy, &file, "images", &dir, "run").unwrap();
+        assert_eq!(dest, Some(dir.join("backup/a.jpg")));
+        assert_eq!(
+            fs::read_to_string(dir.join("backup/a.jpg")).unwrap(),
+            "photo"
+        );
+        assert!(file.exists());
+        assert!(done(&copy, &file, &dir));
+        // An earlier copy is never replaced
+        fs::write(dir.join("backup/a.jpg"), "edited").unwrap();
+        let err = perform(&copy, &file, "images", &dir, "run").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(dir.join("backup/a.jpg")).unwrap(),
+            "edited"
+        );
+
+        if cfg!(unix) {
+            let link = Action::SymlinkInto(PathBuf::from("links"));
+            assert!(!done(&link, &file, &dir));
+            perform(&link, &file, "images", &dir, "run").unwrap();
+            assert_eq!(fs::read_link(dir.join("links/a.jpg")).unwrap(), file);
+            assert!(done(&link, &file, &dir));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn programs_get_the_file_and_its_category() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join("auto-organize-actions-run");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        let file = dir.join("a.pdf");
+        fs::write(&file, "").unwrap();
+        let hook = dir.join("scripts/hook.sh");
+        fs::write(
+            &hook,
+            "#!/bin/sh\necho \"$1 $AUTO_ORGANIZE_CATEGORY $AUTO_ORGANIZE_RUN\" > \"$AUTO_ORGANIZE_TARGET/out\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let run = Action::Run(PathBuf::from("scripts/hook.sh"));
+        assert_eq!(perform(&run, &file, "documents", &dir, "r1").unwrap(), None);
+        let out = fs::read_to_string(dir.join("out")).unwrap();
+        assert_eq!(out, format!("{} documents r1\n", file.display()));
+
+        let failing = Action::Run(PathBuf::from("false"));
+        assert!(perform(&failing, &file, "documents", &dir, "r1").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::date::DateTime;
+use crate::json;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// How `analyze` prints its report
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Tables for reading
+    Text,
+    /// One JSON object, for scripts
+    Json,
+}
+
+/// Width of the size bar in the text report
+const BAR_WIDTH: usize = 20;
+
+#[derive(Clone)]
+pub struct FileInfo {
+    /// Relative to the analyzed directory
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+pub struct ExtensionStats {
+    /// Lowercased, without the dot; empty for files without an extension
+    pub extension: String,
+    pub count: usize,
+    pub size: u64,
+}
+
+/// What a directory holds: totals, a per-extension breakdown and the
+/// largest and oldest files, to help decide on retention rules
+pub struct Analysis {
+    pub files: usize,
+    pub size: u64,
+    /// Most files first, then biggest total size
+    pub extensions: Vec<ExtensionStats>,
+    pub largest: Vec<FileInfo>,
+    pub oldest: Vec<FileInfo>,
+}
+
+/// Walks `root` recursively (without following symlinks). Entries starting
+/// with `skip_prefix` (the tool's own files) are ignored.
+pub fn analyze(root: &Path, top: usize, skip_prefix: &str) -> io::Result<Analysis> {
+    let mut files = files(root, skip_prefix)?;
+
+    let mut by_ext: HashMap<String, ExtensionStats> = HashMap::new();
+    for file in &files {
+        let extension = file
+            .path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let stats = by_ext
+            .entry(extension.clone())
+            .or_insert_with(|| ExtensionStats {
+                extension,
+                count: 0,
+                size: 0,
+            });
+        stats.count += 1;
+        stats.size += file.size;
+    }
+    let mut extensions: Vec<ExtensionStats> = by_ext.into_values().collect();
+    extensions.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then(b.size.cmp(&a.size))
+            .then_with(|| a.extension.cmp(&b.extension))
+    });
+
+    let size = files.iter().map(|f| f.size).sum();
+    let count = files.len();
+
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+    let largest = files.iter().take(top).cloned().collect();
+    files.sort_by(|a, b| {
+        a.modified
+            .cmp(&b.modified)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    let oldest = files.into_iter().take(top).collect();
+
+    Ok(Analysis {
+        files: count,
+        size,
+        extensions,
+        largest,
+        oldest,
+    })
+}
+
+/// Every file under `root`, with paths relative to it (see `analyze`)
+pub fn files(root: &Path, skip_prefix: &str) -> io::Result<Vec<FileInfo>> {
+    let mut files = Vec::new();
+    walk(root, root, skip_prefix, &mut files)?;
+    Ok(files)
+}
+
+fn walk(root: &Path, dir: &Path, skip_prefix: &str, files: &mut Vec<FileInfo>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(skip_prefix) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            if let Err(e) = walk(root, &path, skip_prefix, files) {
+                eprintln!("Error reading {:?}: {}", path, e);
+            }
+        } else if file_type.is_file()
+            && let Ok(meta) = entry.metadata()
+        {
+            files.push(FileInfo {
+                path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                size: meta.len(),
+                modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+    Ok(())
+}
+
+impl Analysis {
+    pub fn print_text(&self) {
+        println!("{} files, {} in total", self.files, human_size(self.size));
+
+        println!();
+        println!("By extension (most files first):");
+        let biggest = self.extensions.iter().map(|e| e.size).max().unwrap_or(0);
+        for ext in &self.extensions {
+            let label = if ext.extension.is_empty() {
+                "(none)".to_string()
+            } else {
+                format!(".{}", ext.extension)
+            };
+            // Bar length is the extension's share of the biggest total
+            let bar = (ext.size as u128 * BAR_WIDTH as u128)
+                .checked_div(biggest as u128)
+                .unwrap_or(0) as usize;
+            println!(
+                "  {:<12} {:>7} files {:>10}  {}",
+                label,
+                ext.count,
+                human_size(ext.size),
+                "#".repeat(bar.max(usize::from(ext.size > 0)))
+            );
+        }
+
+        println!();
+        println!("Largest files:");
+        for file in &self.largest {
+            println!("  {:>10}  {}", human_size(file.size), file.path.display());
+        }
+
+        println!();
+        println!("Oldest files (by last modified):");
+        for file in &self.oldest {
+            println!(
+                "  {}  {}",
+                &DateTime::from_system_time(file.modified).to_iso()[..10],
+                file.path.display()
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let extensions: Vec<String> = self
+            .extensions
+            .iter()
+            .map(|e| {
+                format!(
+                    "{{\"extension\":{},\"count\":{},\"size\":{}}}",
+                    json::string(&e.extension),
+                    e.count,
+                    e.size
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"files\":{},\"size\":{},\"extensions\":[{}],\"largest\":[{}],\"oldest\":[{}]}}",
+            self.files,
+            self.size,
+            extensions.join(","),
+            files_json(&self.largest),
+            files_json(&self.oldest)
+        )
+    }
+}
+
+fn files_json(files: &[FileInfo]) -> String {
+    files
+        .iter()
+        .map(|f| {
+            format!(
+                "{{\"path\":{},\"size\":{},\"modified\":{}}}",
+                json::string(&f.path.to_string_lossy()),
+                f.size,
+                json::string(&DateTime::from_system_time(f.modified).to_iso())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 1536 -> "1.5 KiB"
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
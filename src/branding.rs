@@ -0,0 +1,227 @@
+use crate::config::{BrandingConfig, FolderBrand};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// First line of every file written here; files without it are the user's
+/// and are never replaced
+const MARKER: &str = "written by auto-organize (folder_branding)";
+
+/// Files that brand the folder they are in (whoever wrote them: the file
+/// manager writes them too): they belong to that folder, not to a category
+pub const FILES: &[&str] = &["desktop.ini", ".directory", "Icon\r"];
+
+/// Where macOS keeps the standard folder icons
+const MACOS_ICONS: &str = "/System/Library/CoreServices/CoreTypes.bundle/Contents/Resources";
+
+/// Icons for built-in categories: freedesktop icon name, Windows icon
+/// resource, macOS icon in `MACOS_ICONS`
+const BUILTIN_ICONS: &[(&str, &str, &str, &str)] = &[
+    (
+        "images",
+        "folder-pictures",
+        "%SystemRoot%\\system32\\imageres.dll,-113",
+        "PicturesFolderIcon.icns",
+    ),
+    (
+        "documents",
+        "folder-documents",
+        "%SystemRoot%\\system32\\imageres.dll,-112",
+        "DocumentsFolderIcon.icns",
+    ),
+    (
+        "audio",
+        "folder-music",
+        "%SystemRoot%\\system32\\imageres.dll,-108",
+        "MusicFolderIcon.icns",
+    ),
+    (
+        "video",
+        "folder-videos",
+        "%SystemRoot%\\system32\\imageres.dll,-189",
+        "MovieFolderIcon.icns",
+    ),
+];
+
+/// The icon and name for `category` on this platform: configured values
+/// over the built-in icon
+fn brand_for(category: &str, config: &BrandingConfig) -> FolderBrand {
+    let configured = config.folders.get(category).cloned().unwrap_or_default();
+    let builtin = BUILTIN_ICONS
+        .iter()
+        .find(|(name, ..)| *name == category)
+        .map(|(_, freedesktop, windows, macos)| {
+            if cfg!(windows) {
+                windows.to_string()
+            } else if cfg!(target_os = "macos") {
+                format!("{}/{}", MACOS_ICONS, macos)
+            } else {
+                freedesktop.to_string()
+            }
+        });
+    FolderBrand {
+        icon: configured.icon.or(builtin),
+        name: configured.name,
+    }
+}
+
+/// Brands the category folder `dir` (named `category`) for the platform's
+/// file manager. Returns whether anything was written; folders already
+/// branded the same way are left alone, so this is cheap to repeat.
+pub fn brand(dir: &Path, category: &str, config: &BrandingConfig) -> io::Result<bool> {
+    let brand = brand_for(category, config);
+    if brand.icon.is_none() && brand.name.is_none() {
+        return Ok(false);
+    }
+    sys::brand(dir, &brand)
+}
+
+/// Writes `contents` to `path` unless it is already there; a file not
+/// written by us is kept. Returns whether the file was written.
+fn write_marked(path: &Path, contents: &str) -> io::Result<bool> {
+    match fs::read_to_string(path) {
+        Ok(existing) if existing == contents || !existing.contains(MARKER) => Ok(false),
+        Ok(_) | Err(_) => {
+            sys::prepare_overwrite(path);
+            fs::write(path, contents)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Runs a helper program; failures (e.g. no `gio` outside GNOME) are not errors
+#[cfg_attr(windows, allow(dead_code))]
+fn run_quietly(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::{FolderBrand, MARKER, write_marked};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+    const FILE_ATTRIBUTE_NORMAL: u32 = 0x80;
+    const INVALID_FILE_ATTRIBUTES: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetFileAttributesW(name: *const u16) -> u32;
+        fn SetFileAttributesW(name: *const u16, attributes: u32) -> i32;
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    /// A hidden system file cannot be opened for writing as is
+    pub fn prepare_overwrite(path: &Path) {
+        unsafe { SetFileAttributesW(wide(path).as_ptr(), FILE_ATTRIBUTE_NORMAL) };
+    }
+
+    /// `desktop.ini`, which Explorer only reads in read-only or system folders
+    pub fn brand(dir: &Path, brand: &FolderBrand) -> io::Result<bool> {
+        let mut ini = format!("; {}\r\n[.ShellClassInfo]\r\n", MARKER);
+        if let Some(icon) = &brand.icon {
+            ini.push_str(&format!("IconResource={}\r\n", icon));
+        }
+        if let Some(name) = &brand.name {
+            ini.push_str(&format!("LocalizedResourceName={}\r\n", name));
+        }
+        let path = dir.join("desktop.ini");
+        let written = write_marked(&path, &ini)?;
+        if written {
+            unsafe {
+                SetFileAttributesW(
+                    wide(&path).as_ptr(),
+                    FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM,
+                );
+                let dir = wide(dir);
+                let attributes = GetFileAttributesW(dir.as_ptr());
+                if attributes != INVALID_FILE_ATTRIBUTES {
+                    SetFileAttributesW(dir.as_ptr(), attributes | FILE_ATTRIBUTE_READONLY);
+                }
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use super::{FolderBrand, MARKER, run_quietly, write_marked};
+    use std::io;
+    use std::path::Path;
+
+    pub fn prepare_overwrite(_path: &Path) {}
+
+    /// Finder keeps custom icons in the folder's resource fork; NSWorkspace
+    /// sets one from an image file. A marker file remembers which icon was
+    /// set. Finder has no per-folder display names.
+    pub fn brand(dir: &Path, brand: &FolderBrand) -> io::Result<bool> {
+        let Some(icon) = &brand.icon else {
+            return Ok(false);
+        };
+        let marker = dir.join(".auto-organize-icon");
+        let contents = format!("# {}\n{}\n", MARKER, icon);
+        if std::fs::read_to_string(&marker).is_ok_and(|existing| existing == contents) {
+            return Ok(false);
+        }
+        let script = format!(
+            "ObjC.import('AppKit'); \
+             $.NSWorkspace.sharedWorkspace.setIconForFileOptions(\
+             $.NSImage.alloc.initWithContentsOfFile({}), {}, 0)",
+            js_string(icon),
+            js_string(&dir.to_string_lossy())
+        );
+        if !run_quietly("osascript", &["-l", "JavaScript", "-e", &script]) {
+            return Err(io::Error::other(format!("cannot set the icon {:?}", icon)));
+        }
+        write_marked(&marker, &contents)
+    }
+
+    fn js_string(text: &str) -> String {
+        format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod sys {
+    use super::{FolderBrand, MARKER, run_quietly, write_marked};
+    use std::io;
+    use std::path::Path;
+
+    pub fn prepare_overwrite(_path: &Path) {}
+
+    /// `.directory` for KDE; GNOME (Nautilus) reads the icon from GIO
+    /// metadata instead, set along with it when `gio` is available
+    pub fn brand(dir: &Path, brand: &FolderBrand) -> io::Result<bool> {
+        let mut entry = format!("# {}\n[Desktop Entry]\n", MARKER);
+        if let Some(icon) = &brand.icon {
+            entry.push_str(&format!("Icon={}\n", icon));
+        }
+        if let Some(name) = &brand.name {
+            entry.push_str(&format!("Name={}\n", name));
+        }
+        let written = write_marked(&dir.join(".directory"), &entry)?;
+        if written && let Some(icon) = &brand.icon {
+            let dir = dir.to_string_lossy();
+            // A theme icon by name, or an image file by URI
+            let (key, value) = if icon.starts_with('/') {
+                ("metadata::custom-icon", format!("file://{}", icon))
+            } else {
+                ("metadata::custom-icon-name", icon.clone())
+            };
+            run_quietly("gio", &["set", &dir, key, &value]);
+        }
+        Ok(written)
+    }
+}
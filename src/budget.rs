@@ -0,0 +1,74 @@
+use std::time::{Duration, Instant};
+
+/// Caps on how much one run moves; what is left is planned again next run
+pub struct Budget {
+    max_items: Option<u64>,
+    max_bytes: Option<u64>,
+    deadline: Option<Instant>,
+    items: u64,
+    bytes: u64,
+}
+
+impl Budget {
+    /// The time budget starts counting now
+    pub fn new(max_items: Option<u64>, max_bytes: Option<u64>, seconds: Option<u64>) -> Self {
+        Budget {
+            max_items,
+            max_bytes,
+            deadline: seconds.map(|s| Instant::now() + Duration::from_secs(s)),
+            items: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Why the next move (of `size` bytes) would go over budget, if it would.
+    /// The first move of a run is always allowed, so a file larger than
+    /// `--max-bytes` does not hold up every later run.
+    pub fn exceeded_by(&self, size: u64) -> Option<String> {
+        if let Some(max) = self.max_items
+            && self.items >= max
+        {
+            return Some(format!("--max-files {}", max));
+        }
+        if let Some(max) = self.max_bytes
+            && self.items > 0
+            && self.bytes.saturating_add(size) > max
+        {
+            return Some(format!("--max-bytes {}", max));
+        }
+        if let Some(deadline) = self.deadline
+            && Instant::now() >= deadline
+        {
+            return Some("--time-budget".to_string());
+        }
+        None
+    }
+
+    pub fn spend(&mut self, size: u64) {
+        self.items += 1;
+        self.bytes = self.bytes.saturating_add(size);
+    }
+}
+
+/// Parses "1000", "500K", "200M" or "2G" (binary units) into bytes
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+    let scale: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("'{}': use a number with K, M, G or T", text)),
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(scale)
+            .ok_or_else(|| format!("'{}' is too large", text)),
+        _ => Err(format!("'{}': use a number with K, M, G or T", text)),
+    }
+}
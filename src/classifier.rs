@@ -0,0 +1,475 @@
+use crate::config;
+use crate::encrypted;
+use crate::wasm::{Instance, Module};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How much of a file `Input::peek` reads
+pub const PEEK_BYTES: u64 = 8 * 1024;
+
+/// The confidence the built-in rules answer with (they give none for `Others`)
+pub const RULES_CONFIDENCE: u8 = 50;
+
+/// How long a plugin may take over one file before it is given up on
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a classifier is told about a file. The metadata and the first bytes
+/// are read once, the first time a classifier asks, and shared by the rest.
+pub struct Input<'a> {
+    pub path: &'a Path,
+    metadata: OnceLock<Option<fs::Metadata>>,
+    peek: OnceLock<Vec<u8>>,
+    encrypted: OnceLock<bool>,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        Input {
+            path,
+            metadata: OnceLock::new(),
+            peek: OnceLock::new(),
+            encrypted: OnceLock::new(),
+        }
+    }
+
+    /// The file's metadata (not following symlinks), if it can be read
+    pub fn metadata(&self) -> Option<&fs::Metadata> {
+        self.metadata
+            .get_or_init(|| fs::symlink_metadata(self.path).ok())
+            .as_ref()
+    }
+
+    /// Up to `PEEK_BYTES` from the start of the file; empty if it cannot be read
+    pub fn peek(&self) -> &[u8] {
+        self.peek.get_or_init(|| {
+            let mut head = Vec::new();
+            if let Ok(file) = File::open(self.path) {
+                let _ = file.take(PEEK_BYTES).read_to_end(&mut head);
+            }
+            head
+        })
+    }
+
+    /// Whether the file is an archive that needs a password (see
+    /// `encrypted::is_encrypted`)
+    pub fn is_encrypted(&self) -> bool {
+        *self
+            .encrypted
+            .get_or_init(|| encrypted::is_encrypted(self.path).unwrap_or(false))
+    }
+}
+
+/// A classifier's answer: a category (may have subfolders, "documents/finance")
+/// and how sure it is, from 0 to 100
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub category: String,
+    pub confidence: u8,
+}
+
+/// Something that can tell which category a file belongs to. Implement it
+/// to teach `RuleSet` (see `RuleSet::add_classifier`) about files the
+/// built-in rules do not know; return `None` for no opinion.
+pub trait Classifier: Send + Sync {
+    /// Shown in messages about the classifier
+    fn name(&self) -> &str;
+
+    fn classify(&self, input: &Input) -> Option<Verdict>;
+}
+
+/// Something that can give a file another name as it is moved. Implement it
+/// to name files by what they hold (see `RuleSet::add_renamer`); return
+/// `None` to keep the name.
+pub trait Renamer: Send + Sync {
+    /// Shown in messages about the renamer
+    fn name(&self) -> &str;
+
+    /// The new file name (not a path) of a file going to `category`
+    fn rename(&self, input: &Input, category: &str) -> Option<String>;
+}
+
+impl<T: Classifier + ?Sized> Classifier for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn classify(&self, input: &Input) -> Option<Verdict> {
+        (**self).classify(input)
+    }
+}
+
+impl<T: Renamer + ?Sized> Renamer for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn rename(&self, input: &Input, category: &str) -> Option<String> {
+        (**self).rename(input, category)
+    }
+}
+
+/// Picks among the verdicts of `classifiers`, asked in order: the highest
+/// confidence wins, and on a tie the earlier answer. `first` is an answer
+/// given before any of them (the built-in rules'). Verdicts with a category
+/// that is not a usable folder name are ignored.
+pub fn resolve(
+    first: Option<Verdict>,
+    classifiers: &[Box<dyn Classifier>],
+    input: &Input,
+) -> Option<Verdict> {
+    let mut best = first;
+    for classifier in classifiers {
+        let Some(verdict) = classifier.classify(input) else {
+            continue;
+        };
+        if let Err(e) = config::validate_category(&verdict.category) {
+            eprintln!(
+                "Warning: classifier '{}' answered {:?} for {:?}: {}",
+                classifier.name(),
+                verdict.category,
+                input.path,
+                e
+            );
+            continue;
+        }
+        if best
+            .as_ref()
+            .is_none_or(|best| verdict.confidence > best.confidence)
+        {
+            best = Some(verdict);
+        }
+    }
+    best
+}
+
+/// A classifier in another program (`[classifiers.<name>] command`), kept
+/// running for the whole run. It is sent one path per line on its stdin and
+/// answers each with one line on its stdout: the path, a tab, then
+/// `<category> <confidence>`, or nothing for no opinion. Answers are matched
+/// to files by that path. Paths that cannot be sent as one line of UTF-8
+/// (a name with a line break in it) are not asked about. A plugin that
+/// fails, exits or takes longer than `PLUGIN_TIMEOUT` is not asked again.
+pub struct Subprocess {
+    name: String,
+    command: Vec<String>,
+    state: Mutex<State>,
+}
+
+enum State {
+    /// Started on first use
+    NotStarted,
+    Running {
+        child: Child,
+        stdin: ChildStdin,
+        lines: Receiver<String>,
+    },
+    Failed,
+}
+
+impl Subprocess {
+    pub fn new(name: &str, command: &[String]) -> Self {
+        Subprocess {
+            name: name.to_string(),
+            command: command.to_vec(),
+            state: Mutex::new(State::NotStarted),
+        }
+    }
+
+    fn start(&self) -> io::Result<State> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| io::Error::other("no command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(io::Error::other("no pipes to it"));
+        };
+        // Answers are read on a thread of their own, so a stuck plugin times out
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(State::Running {
+            child,
+            stdin,
+            lines,
+        })
+    }
+}
+
+impl Classifier for Subprocess {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn classify(&self, input: &Input) -> Option<Verdict> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if let State::NotStarted = *state {
+            *state = self.start().unwrap_or_else(|e| {
+                eprintln!("Warning: cannot start classifier '{}': {}", self.name, e);
+                State::Failed
+            });
+        }
+        let State::Running { stdin, lines, .. } = &mut *state else {
+            return None;
+        };
+        match ask(stdin, lines, input.path) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                eprintln!(
+                    "Warning: classifier '{}' is not asked again: {}",
+                    self.name, e
+                );
+                if let State::Running { mut child, .. } =
+                    std::mem::replace(&mut *state, State::Failed)
+                {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Subprocess {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let State::Running {
+            mut child, stdin, ..
+        } = std::mem::replace(state, State::Failed)
+        {
+            // Closing its stdin tells the plugin to finish; it gets a moment
+            drop(stdin);
+            for _ in 0..10 {
+                if !matches!(child.try_wait(), Ok(None)) {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// A classifier and/or renamer in a WebAssembly module (`[plugins.<name>]
+/// wasm`). The module can import nothing, so it sees no files, network or
+/// clock: only what it is given about each file. It exports
+/// `alloc(len: i32) -> i32`, to make room for that, and one or both of
+/// `classify(ptr: i32, len: i32) -> i64` and `rename(ptr: i32, len: i32) -> i64`.
+///
+/// What it is given is lines of `key: value` (`name`, `size`, and for
+/// `rename` the file's `category`), an empty line, then up to `PEEK_BYTES`
+/// from the start of the file. It answers with `ptr << 32 | len` of UTF-8
+/// text in its memory, or 0 for no opinion: `<category> <confidence>` as a
+/// `Subprocess` would, or the new file name. Each file runs on a fresh copy
+/// of the module as it was loaded; a module that traps, runs too long or
+/// answers something else is not asked again.
+pub struct Wasm {
+    name: String,
+    instance: Instance,
+    classifies: bool,
+    renames: bool,
+    failed: AtomicBool,
+}
+
+impl Wasm {
+    pub fn load(name: &str, path: &Path) -> io::Result<Wasm> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let module = Module::parse(&fs::read(path)?).map_err(invalid)?;
+        let classifies = module.exports_function("classify");
+        let renames = module.exports_function("rename");
+        if !classifies && !renames {
+            return Err(invalid(
+                "it exports neither classify nor rename".to_string(),
+            ));
+        }
+        if !module.exports_function("alloc") {
+            return Err(invalid("it does not export alloc".to_string()));
+        }
+        let instance = Instance::new(Arc::new(module)).map_err(invalid)?;
+        Ok(Wasm {
+            name: name.to_string(),
+            instance,
+            classifies,
+            renames,
+            failed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn classifies(&self) -> bool {
+        self.classifies
+    }
+
+    pub fn renames(&self) -> bool {
+        self.renames
+    }
+
+    /// Runs `export` on what there is to know about a file, turning its
+    /// answer into a `T` with `parse`
+    fn ask<T>(
+        &self,
+        export: &str,
+        input: &Input,
+        category: Option<&str>,
+        parse: impl FnOnce(String) -> Result<Option<T>, String>,
+    ) -> Option<T> {
+        if self.failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let answer = self
+            .call(export, &wasm_message(input, category))
+            .and_then(|answer| answer.map_or(Ok(None), parse));
+        answer.unwrap_or_else(|e| {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                eprintln!("Warning: plugin '{}' is not asked again: {}", self.name, e);
+            }
+            None
+        })
+    }
+
+    fn call(&self, export: &str, message: &[u8]) -> Result<Option<String>, String> {
+        let mut run = self.instance.clone();
+        let len = message.len();
+        let ptr = run.call("alloc", &[len as u64])?;
+        let ptr = *ptr.first().ok_or("alloc returned nothing")? as usize;
+        run.memory_mut()
+            .get_mut(ptr..ptr + len)
+            .ok_or("alloc returned memory it does not have")?
+            .copy_from_slice(message);
+        let answer = run.call(export, &[ptr as u64, len as u64])?;
+        let answer = *answer
+            .first()
+            .ok_or_else(|| format!("{} returned nothing", export))?;
+        if answer == 0 {
+            return Ok(None);
+        }
+        let (ptr, len) = ((answer >> 32) as usize, (answer & 0xffff_ffff) as usize);
+        let text = run
+            .memory()
+            .get(ptr..ptr + len)
+            .ok_or_else(|| format!("{} answered with memory it does not have", export))?;
+        String::from_utf8(text.to_vec())
+            .map(Some)
+            .map_err(|_| format!("{} answered with text that is not UTF-8", export))
+    }
+}
+
+impl Classifier for Wasm {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn classify(&self, input: &Input) -> Option<Verdict> {
+        if !self.classifies {
+            return None;
+        }
+        self.ask("classify", input, None, |answer| parse_answer(&answer))
+    }
+}
+
+impl Renamer for Wasm {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn rename(&self, input: &Input, category: &str) -> Option<String> {
+        if !self.renames {
+            return None;
+        }
+        self.ask("rename", input, Some(category), |answer| {
+            Ok(Some(answer).filter(|name| !name.is_empty()))
+        })
+    }
+}
+
+/// "name: report.pdf\nsize: 1234\n\n" and the first bytes of the file
+fn wasm_message(input: &Input, category: Option<&str>) -> Vec<u8> {
+    let name = input.path.file_name().unwrap_or_default().to_string_lossy();
+    let size = input.metadata().map_or(0, |m| m.len());
+    let mut message = format!("name: {}\nsize: {}\n", name, size);
+    if let Some(category) = category {
+        message.push_str(&format!("category: {}\n", category));
+    }
+    message.push('\n');
+    let mut message = message.into_bytes();
+    message.extend_from_slice(input.peek());
+    message
+}
+
+/// Sends one path and waits for the answer about it
+fn ask(
+    stdin: &mut ChildStdin,
+    lines: &Receiver<String>,
+    path: &Path,
+) -> io::Result<Option<Verdict>> {
+    let Some(text) = path.to_str().filter(|text| !text.contains(['\n', '\r'])) else {
+        return Ok(None);
+    };
+    stdin.write_all(format!("{}\n", text).as_bytes())?;
+    stdin.flush()?;
+    let deadline = Instant::now() + PLUGIN_TIMEOUT;
+    loop {
+        let answer = match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(answer) => answer,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "it gave no answer in time",
+                ));
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "it exited"));
+            }
+        };
+        let Some((answered, verdict)) = answer.split_once('\t') else {
+            return Err(io::Error::other(format!(
+                "answered {:?}, not \"<path>\\t<category> <confidence>\"",
+                answer
+            )));
+        };
+        // An answer about another path is a late one; it is dropped
+        if answered == text {
+            return parse_answer(verdict).map_err(io::Error::other);
+        }
+    }
+}
+
+/// "images 80" -> images, 80; "" -> no opinion
+fn parse_answer(line: &str) -> Result<Option<Verdict>, String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let (category, confidence) = line
+        .rsplit_once(char::is_whitespace)
+        .ok_or_else(|| format!("answered {:?}, not \"<category> <confidence>\"", line))?;
+    let confidence: u8 = confidence
+        .parse()
+        .ok()
+        .filter(|c| *c <= 100)
+        .ok_or_else(|| format!("answered a confidence of {:?}, not 0 to 100", confidence))?;
+    Ok(Some(Verdict {
+        category: category.trim().to_string(),
+        confidence,
+    }))
+}
@@ -0,0 +1,69 @@
+use clap::ValueEnum;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// What to do with online-only files from OneDrive, Dropbox, iCloud and the like
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloudPlaceholders {
+    /// Leave them where they are
+    Skip,
+    /// Download them first, then organize them like any other file
+    Hydrate,
+}
+
+/// Whether `path` is a cloud placeholder whose contents are not on disk.
+///
+/// Windows: the recall-on-access/open or offline attributes (OneDrive, Dropbox,
+/// iCloud all use the Cloud Files API). macOS: the `SF_DATALESS` flag set by
+/// File Provider clients. Elsewhere: a file with no allocated blocks that is
+/// bigger than anything a filesystem would store inline, which is how FUSE
+/// clients present files they have not downloaded.
+pub fn is_placeholder(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| is_placeholder_metadata(&meta))
+}
+
+/// Same as `is_placeholder`, for metadata already read (not following symlinks)
+pub fn is_placeholder_metadata(meta: &fs::Metadata) -> bool {
+    meta.is_file() && placeholder_metadata(meta)
+}
+
+#[cfg(windows)]
+fn placeholder_metadata(meta: &fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x4_0000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x40_0000;
+    meta.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE
+            | FILE_ATTRIBUTE_RECALL_ON_OPEN
+            | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+#[cfg(target_os = "macos")]
+fn placeholder_metadata(meta: &fs::Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+    const SF_DATALESS: u32 = 0x4000_0000;
+    meta.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn placeholder_metadata(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    // ext4 and btrfs keep small files inside the inode, with no blocks either
+    const INLINE_LIMIT: u64 = 4096;
+    meta.blocks() == 0 && meta.len() > INLINE_LIMIT
+}
+
+#[cfg(not(any(windows, unix)))]
+fn placeholder_metadata(_meta: &fs::Metadata) -> bool {
+    false
+}
+
+/// Downloads a placeholder by reading it through; the cloud client fetches
+/// the contents on access. Returns whether the file is now fully local.
+pub fn hydrate(path: &Path) -> io::Result<bool> {
+    io::copy(&mut File::open(path)?, &mut io::sink())?;
+    Ok(!is_placeholder(path))
+}
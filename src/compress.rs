@@ -0,0 +1,196 @@
+use crate::date::DateTime;
+use crate::glob;
+use crate::mover;
+use crate::zip::{self, ZipWriter};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Leaves room for headers and the central directory under the 4 GiB zip limit
+const ARCHIVE_BUDGET: u64 = zip::MAX_SIZE - 64 * 1024 * 1024;
+const MAX_MEMBERS: usize = u16::MAX as usize;
+
+/// An archive made (or, in a dry run, planned) from old files of a category
+pub struct Archive {
+    pub path: PathBuf,
+    pub members: Vec<PathBuf>,
+}
+
+/// Zips files in `category` that were last modified more than `after_days`
+/// days ago into one archive per month, e.g. `archives/archives-2024-05.zip`.
+///
+/// Each archive is written to a `.part` file, read back and checked against
+/// the originals, and only then renamed into place; the originals are deleted
+/// last. An archive that fails verification is discarded and its originals kept.
+pub fn compress_category(
+    target_dir: &Path,
+    category: &str,
+    after_days: u64,
+    dry_run: bool,
+) -> io::Result<Vec<Archive>> {
+    let dir = target_dir.join(category);
+    let leaf = Path::new(category)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let own_archive = format!("{}-[0-9][0-9][0-9][0-9]-[0-9][0-9]*.zip", leaf);
+    let cutoff = SystemTime::now() - Duration::from_secs(after_days.saturating_mul(86_400));
+
+    // Old files grouped by the month they were last modified
+    let mut months: BTreeMap<(i64, u32), Vec<(PathBuf, u64)>> = BTreeMap::new();
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Hidden files, unfinished copies and earlier archives are left alone
+        if name.starts_with('.') || name.ends_with(".part") || glob::matches(&own_archive, &name) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        let modified = meta.modified().unwrap_or(SystemTime::now());
+        if modified >= cutoff {
+            continue;
+        }
+        if meta.len() > ARCHIVE_BUDGET {
+            println!("[SKIP] {:?} (too large to archive)", entry.file_name());
+            continue;
+        }
+
+        let date = DateTime::from_system_time(modified);
+        months
+            .entry((date.year, date.month))
+            .or_default()
+            .push((entry.path(), meta.len()));
+    }
+
+    let mut taken: HashSet<PathBuf> = HashSet::new();
+    let mut archives = Vec::new();
+
+    for ((year, month), mut files) in months {
+        files.sort();
+        for members in split_to_fit(files) {
+            let path = archive_path(&dir, &leaf, year, month, &mut taken);
+            let archive_name = path.file_name().unwrap_or_default().to_string_lossy();
+            for member in &members {
+                println!(
+                    "[{:<12}] {:?} -> {}",
+                    "COMPRESS",
+                    member.file_name().unwrap_or_default(),
+                    archive_name
+                );
+            }
+
+            if !dry_run {
+                if let Err(e) = write_verified(&path, &members) {
+                    eprintln!(
+                        "Error compressing into {}: {} (originals kept)",
+                        archive_name, e
+                    );
+                    continue;
+                }
+                for member in &members {
+                    if let Err(e) = fs::remove_file(member) {
+                        eprintln!("Error removing {:?}: {}", member, e);
+                    }
+                }
+            }
+            archives.push(Archive { path, members });
+        }
+    }
+
+    Ok(archives)
+}
+
+/// Splits one month's files into batches that each fit in a plain zip
+fn split_to_fit(files: Vec<(PathBuf, u64)>) -> Vec<Vec<PathBuf>> {
+    let mut batches: Vec<Vec<PathBuf>> = Vec::new();
+    let mut current = Vec::new();
+    let mut size = 0;
+    for (path, len) in files {
+        if !current.is_empty() && (size + len > ARCHIVE_BUDGET || current.len() == MAX_MEMBERS) {
+            batches.push(std::mem::take(&mut current));
+            size = 0;
+        }
+        size += len;
+        current.push(path);
+    }
+    if !current.is_empty() {
+        batches.push(current);
+    }
+    batches
+}
+
+/// `<dir>/<leaf>-YYYY-MM.zip`, or `-2`, `-3`... if that month already has an archive
+fn archive_path(
+    dir: &Path,
+    leaf: &str,
+    year: i64,
+    month: u32,
+    taken: &mut HashSet<PathBuf>,
+) -> PathBuf {
+    let base = format!("{}-{:04}-{:02}", leaf, year, month);
+    let mut path = dir.join(format!("{}.zip", base));
+    let mut n = 2;
+    while path.exists() || taken.contains(&path) {
+        path = dir.join(format!("{}-{}.zip", base, n));
+        n += 1;
+    }
+    taken.insert(path.clone());
+    path
+}
+
+/// Writes the archive via `.part` and renames it into place once verified
+fn write_verified(path: &Path, members: &[PathBuf]) -> io::Result<()> {
+    mover::remove_stale_part(path)?;
+    let part = mover::part_path(path);
+
+    match write_and_check(&part, members) {
+        Ok(()) => fs::rename(&part, path),
+        Err(e) => {
+            let _ = fs::remove_file(&part);
+            Err(e)
+        }
+    }
+}
+
+/// Writes `members` into a new archive at `part`, reads it back, and checks
+/// every member against the original file
+fn write_and_check(part: &Path, members: &[PathBuf]) -> io::Result<()> {
+    let mut writer = ZipWriter::create(part)?;
+    let mut written = Vec::with_capacity(members.len());
+    for member in members {
+        let name = member
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| io::Error::other(format!("{:?} is not valid UTF-8", member)))?;
+        written.push(writer.add_file(name, member)?);
+    }
+    writer.finish()?;
+
+    if zip::read_back(part)? != written {
+        return Err(io::Error::other(
+            "archive contents differ from what was written",
+        ));
+    }
+    // The originals must still be exactly what went into the archive
+    for (member, entry) in members.iter().zip(&written) {
+        if fs::metadata(member)?.len() != entry.size || zip::crc_file(member)? != entry.crc32 {
+            return Err(io::Error::other(format!(
+                "{:?} changed while being archived",
+                entry.name
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1601 @@
+use crate::actions::Action;
+use crate::budget;
+use crate::downloads::KeepCopy;
+use crate::encrypted::Routing;
+use crate::listing::Order;
+use crate::paths;
+use crate::pipeline::{Keep, Stage};
+use crate::plan::{ConflictName, ConflictPolicy, DirPolicy};
+use crate::regex::Regex;
+use crate::schedule::{Schedule, Window};
+use crate::style::{Color, Label};
+use crate::throttle::Limits;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// A value in the config file (a small subset of TOML)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c if c.is_control() => write!(f, "\\u{:04X}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// A single `key = value` line, remembered with its line number for diagnostics
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// The raw parsed file: table name ("" for the root) -> entries in file order
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub tables: BTreeMap<String, Vec<Entry>>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse { line: usize, message: String },
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read '{}': {}", path.display(), e),
+            ConfigError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// User configuration. Every section is optional; missing ones fall back to built-ins.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    /// Extension (lowercased, no dot) -> category; overrides the built-in table
+    pub extensions: BTreeMap<String, String>,
+    /// Extensionless file names (lowercased) -> category, e.g. `makefile = "code"`
+    pub filenames: BTreeMap<String, String>,
+    /// Shebang interpreters -> category, e.g. `python = "code"`
+    pub shebangs: BTreeMap<String, String>,
+    /// Download site (lowercased, subdomains included) -> category,
+    /// e.g. `"github.com" = "code/downloads"`
+    pub origins: BTreeMap<String, String>,
+    /// Extra top-level folders that must never be swept into `Folders`
+    pub protected_folders: Vec<String>,
+    /// Sidecar extensions (lowercased, no dot); replaces the built-in list when set
+    pub sidecar_extensions: Option<Vec<String>>,
+    /// Days replaced files stay in the staging area; 0 deletes them at once
+    pub staging_days: Option<u64>,
+    /// More files than this of one unknown extension in a run get a category
+    /// of their own suggested; 0 turns suggestions off
+    pub others_threshold: Option<u64>,
+    /// Numbered video or audio files of one name in a folder, at least this
+    /// many, go into a folder of their own; 0 turns this off
+    pub series_min_files: Option<u64>,
+    /// Never overwrite, delete or merge, whatever the flags say (as `--safe`)
+    pub safe_mode: bool,
+    /// What happens to loose folders when `--dirs` is not given
+    pub dirs: Option<DirPolicy>,
+    /// The order files are organized in when `--order` is not given
+    pub order: Option<Order>,
+    pub video: VideoConfig,
+    pub code: CodeConfig,
+    pub documents: DocumentsConfig,
+    pub email: EmailConfig,
+    pub archives: ArchivesConfig,
+    pub shared: SharedConfig,
+    pub sync: SyncConfig,
+    pub downloads: DownloadsConfig,
+    /// `[categories.<name>]`: per-category settings
+    pub categories: BTreeMap<String, CategoryConfig>,
+    /// `[group_by.<name>]`: rules that file names sharing a token into a subfolder
+    pub group_by: BTreeMap<String, GroupRule>,
+    /// `[limits.<backend>]`: how hard runs may work a destination filesystem
+    /// (see `throttle::Policy`)
+    pub limits: BTreeMap<String, Limits>,
+    pub folder_branding: BrandingConfig,
+    pub pipeline: PipelineConfig,
+    /// `[schedule]`: when watch mode may run passes
+    pub schedule: Schedule,
+    /// `[classifiers.<name>] command`: programs asked about each file after
+    /// the built-in rules (see `classifier::Subprocess`)
+    pub classifiers: BTreeMap<String, Vec<String>>,
+    /// `[plugins.<name>] wasm`: WebAssembly modules that classify or rename
+    /// files (see `classifier::Wasm`); relative paths are in the config directory
+    pub plugins: BTreeMap<String, PathBuf>,
+}
+
+/// Files whose name matches `pattern` go into a subfolder named after the match
+/// (its first capture group, if any), e.g. `PROJ-1234_spec.pdf` -> `documents/PROJ-1234`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupRule {
+    /// Without one, every name matches (the rule then needs `folder`)
+    pub pattern: Option<Regex>,
+    /// Only files of this account (a name, or a uid on Unix) match
+    pub owner: Option<String>,
+    /// Category the groups live under; defaults to the file's own category
+    pub category: Option<String>,
+    /// One subfolder for every match instead, e.g. `screenshots`
+    pub folder: Option<String>,
+    /// Rules are tried highest priority first, then by name
+    pub priority: u64,
+}
+
+/// Settings for one category, e.g. `[categories.archives]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryConfig {
+    /// Files older than this many days get zipped into monthly archives
+    pub compress_after_days: Option<u64>,
+    /// When false, files only go into the category folder if it already
+    /// exists; otherwise they stay where they are
+    pub create_if_missing: bool,
+    /// What to do when a file of the same name is already there; `--on-conflict` wins
+    pub on_conflict: Option<ConflictPolicy>,
+    /// How a file is named when `rename` or `version` keeps both
+    pub conflict_name: Option<ConflictName>,
+    /// Files bigger than this (bytes) stay where they are
+    pub skip_larger_than: Option<u64>,
+    /// Read back copies across filesystems before deleting the original
+    pub verify_copies: Option<bool>,
+    /// Whether a file's contents or its extension decide when they disagree
+    pub trust: Option<Trust>,
+    /// What happens to the files instead of a move, e.g. "delete-to-trash"
+    pub action: Option<Action>,
+    /// Mode, owner and group given to the folders made for the category
+    pub permissions: FolderPermissions,
+    /// Color and emoji of the category's tag in color output
+    pub label: Label,
+}
+
+/// `mode`, `owner`, `group` and `enforce_permissions` of a category: set on
+/// its folders when they are created, and on every run with `enforce`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FolderPermissions {
+    /// Unix permission bits, e.g. 0o2775 (setgid keeps new files in the group)
+    pub mode: Option<u32>,
+    /// Account and group names, or numeric ids
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// Also put them back on existing folders a run moves files into
+    pub enforce: bool,
+}
+
+impl FolderPermissions {
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none() && self.owner.is_none() && self.group.is_none()
+    }
+}
+
+/// What decides a file's category when its contents are of another known
+/// type than its extension says (`[categories.<name>] trust`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    /// Files named like this category stay in it; files whose contents
+    /// belong here are left to their extension
+    Extension,
+    /// Files whose contents belong here come here; files named like this
+    /// category but holding something else go where their contents belong
+    Contents,
+}
+
+impl Default for CategoryConfig {
+    fn default() -> Self {
+        CategoryConfig {
+            compress_after_days: None,
+            create_if_missing: true,
+            on_conflict: None,
+            conflict_name: None,
+            skip_larger_than: None,
+            verify_copies: None,
+            trust: None,
+            action: None,
+            permissions: FolderPermissions::default(),
+            label: Label::default(),
+        }
+    }
+}
+
+/// `[video]`: optionally split `video` into `video/recordings` and `video/movies`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoConfig {
+    pub split: bool,
+    /// File name globs marking screen recordings; replaces the built-in list when set
+    pub recording_patterns: Option<Vec<String>>,
+    /// Videos at least this long count as movies
+    pub movie_min_minutes: u64,
+}
+
+/// `[code]`: optionally split `code` by language and give web assets their own folder
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeConfig {
+    /// `code/rust`, `code/python`, `code/web`...
+    pub split: bool,
+    /// `web = "web"`: html/css/js/json go to `web` instead of `code`
+    pub separate_web: bool,
+}
+
+/// `[documents]`: optionally read the start of PDFs and text files and send
+/// those matching keywords to a subfolder (`documents/finance`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentsConfig {
+    pub inspect: bool,
+    /// How many pages of a PDF (or screens of text) are read
+    pub max_pages: u64,
+    /// `[documents.<subfolder>] keywords = [...]`; replaces the built-in `finance` when set
+    pub subfolders: BTreeMap<String, Vec<String>>,
+}
+
+/// `[email]`: messages (.eml, .emlx, .msg) go into `email/<year>/<sender>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailConfig {
+    /// Off: every message stays in `email`
+    pub split: bool,
+    /// Off: by year only
+    pub by_sender: bool,
+}
+
+/// `[archives]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ArchivesConfig {
+    /// Where archives that need a password go; with the rest by default
+    pub encrypted: Routing,
+}
+
+/// `[shared]`: with `--shared`, files go to per-user trees
+/// (`<root>/<user>/<category>`) owned by that user, for family or shared computers
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedConfig {
+    pub root: PathBuf,
+    /// `match = "prefix"`: a file named `alice_scan.pdf` belongs to alice;
+    /// others (and `match = "owner"`) go by the file's owner
+    pub by_prefix: bool,
+    /// Users prefixes may name; any account on the system when empty
+    pub users: Vec<String>,
+    /// Make each user's tree readable by that user only
+    pub private: bool,
+}
+
+/// `[folder_branding]`: icons and display names for category folders in
+/// Explorer, KDE/GNOME and Finder
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrandingConfig {
+    pub enabled: bool,
+    /// `[folder_branding.<category>]`, over the built-in icons
+    pub folders: BTreeMap<String, FolderBrand>,
+}
+
+/// How one category folder looks in a file manager
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FolderBrand {
+    /// Icon theme name (KDE/GNOME), `file.dll,-index` or `.ico` (Windows), image file (macOS)
+    pub icon: Option<String>,
+    /// Display name (Explorer, KDE)
+    pub name: Option<String>,
+}
+
+/// `[sync]`: files of sync tools (Syncthing, Dropbox, rsync...)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncConfig {
+    /// Globs for in-progress or internal files that are never moved; replaces the built-in list
+    pub ignore_patterns: Option<Vec<String>>,
+    /// Globs for conflict copies; replaces the built-in list
+    pub conflict_patterns: Option<Vec<String>>,
+    /// Leave conflict copies in place instead of moving them to `Conflicts`
+    pub skip_conflicts: bool,
+}
+
+/// `[downloads]`: files of downloads still in progress
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadsConfig {
+    /// Extensions of the files a download is written into before it completes
+    /// (`crdownload`, `part`...); replaces the built-in list
+    pub temp_extensions: Option<Vec<String>>,
+    /// Collapse numbered copies (`report (1).pdf`) with the same contents;
+    /// off when unset
+    pub numbered_duplicates: Option<KeepCopy>,
+}
+
+/// `[pipeline]`: the stages `auto-organize run` goes through, in order, and
+/// `[pipeline.<stage>]` tables with their options
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineConfig {
+    pub stages: Vec<Stage>,
+    pub classify: ClassifyStage,
+    pub dedupe: DedupeStage,
+    pub retention: RetentionStage,
+    pub compress: CompressStage,
+}
+
+/// `[pipeline.classify]`: flags of a normal run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassifyStage {
+    pub recursive: bool,
+    pub dirs: Option<DirPolicy>,
+    pub on_conflict: Option<ConflictPolicy>,
+}
+
+/// `[pipeline.dedupe]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DedupeStage {
+    /// Only look for duplicates inside these categories; everywhere when empty
+    pub categories: Vec<String>,
+    pub keep: Keep,
+}
+
+/// `[pipeline.retention]`: both keys are required when the stage is used
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionStage {
+    pub after_days: Option<u64>,
+    pub categories: Vec<String>,
+}
+
+/// `[pipeline.compress]`: as the `compress` command's options
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressStage {
+    pub categories: Vec<String>,
+    pub older_than_days: Option<u64>,
+}
+
+impl Default for SharedConfig {
+    fn default() -> Self {
+        SharedConfig {
+            root: PathBuf::from("/srv/organized"),
+            by_prefix: false,
+            users: Vec::new(),
+            private: true,
+        }
+    }
+}
+
+impl Default for DocumentsConfig {
+    fn default() -> Self {
+        DocumentsConfig {
+            inspect: false,
+            max_pages: 2,
+            subfolders: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            split: true,
+            by_sender: true,
+        }
+    }
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            split: false,
+            recording_patterns: None,
+            movie_min_minutes: 40,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Config::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::from_document(&parse_document(text)?)
+    }
+
+    /// Validates a parsed document and builds the config from it
+    pub fn from_document(doc: &Document) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        // Line of a `stages` list with retention in it: that stage deletes
+        // files, so it never runs on defaults
+        let mut retention_line = None;
+
+        for (table, entries) in &doc.tables {
+            match table.as_str() {
+                "" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "protected_folders" => {
+                                config.protected_folders = string_list(entry)?;
+                            }
+                            "sidecar_extensions" => {
+                                let exts = string_list(entry)?
+                                    .iter()
+                                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                                    .collect();
+                                config.sidecar_extensions = Some(exts);
+                            }
+                            "staging_days" => config.staging_days = Some(integer_value(entry)?),
+                            "others_threshold" => {
+                                config.others_threshold = Some(integer_value(entry)?)
+                            }
+                            "series_min_files" => {
+                                config.series_min_files = Some(integer_value(entry)?)
+                            }
+                            "safe_mode" => config.safe_mode = bool_value(entry)?,
+                            "dirs" => config.dirs = Some(dirs_value(entry)?),
+                            "order" => config.order = Some(order_value(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "extensions" => {
+                    for entry in entries {
+                        let category = category_value(entry)?;
+                        let ext = entry.key.trim_start_matches('.').to_lowercase();
+                        config.extensions.insert(ext, category);
+                    }
+                }
+                "origins" => {
+                    for entry in entries {
+                        let category = category_value(entry)?;
+                        let domain = entry.key.trim_start_matches("www.").to_lowercase();
+                        config.origins.insert(domain, category);
+                    }
+                }
+                "filenames" => {
+                    for entry in entries {
+                        let category = category_value(entry)?;
+                        config.filenames.insert(entry.key.to_lowercase(), category);
+                    }
+                }
+                "shebangs" => {
+                    for entry in entries {
+                        let category = category_value(entry)?;
+                        config.shebangs.insert(entry.key.to_lowercase(), category);
+                    }
+                }
+                "email" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "split" => config.email.split = bool_value(entry)?,
+                            "by_sender" => config.email.by_sender = bool_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "archives" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "encrypted" => {
+                                config.archives.encrypted = match entry.value.as_str() {
+                                    Some("keep") => Routing::Keep,
+                                    Some("subfolder") => Routing::Subfolder,
+                                    Some("quarantine") => Routing::Quarantine,
+                                    _ => {
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: "'encrypted' must be \"keep\", \"subfolder\" or \"quarantine\"".into(),
+                                        });
+                                    }
+                                };
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "video" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "split" => config.video.split = bool_value(entry)?,
+                            "recording_patterns" => {
+                                config.video.recording_patterns = Some(string_list(entry)?);
+                            }
+                            "movie_min_minutes" => {
+                                config.video.movie_min_minutes = integer_value(entry)?;
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "code" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "split" => config.code.split = bool_value(entry)?,
+                            "web" => {
+                                config.code.separate_web = match entry.value.as_str() {
+                                    Some("code") => false,
+                                    Some("web") => true,
+                                    _ => {
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: "'web' must be \"code\" or \"web\"".into(),
+                                        });
+                                    }
+                                };
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "documents" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "inspect" => config.documents.inspect = bool_value(entry)?,
+                            "max_pages" => config.documents.max_pages = integer_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                _ if table.starts_with("documents.") => {
+                    let name = &table["documents.".len()..];
+                    let line = entries.first().map(|e| e.line).unwrap_or(0);
+                    validate_category(name).map_err(|message| ConfigError::Invalid {
+                        line,
+                        message: format!("[documents.{}]: {}", name, message),
+                    })?;
+                    let mut keywords = None;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "keywords" => keywords = Some(string_list(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    let Some(keywords) = keywords else {
+                        return Err(ConfigError::Invalid {
+                            line,
+                            message: format!("[documents.{}] needs 'keywords'", name),
+                        });
+                    };
+                    config
+                        .documents
+                        .subfolders
+                        .insert(name.to_string(), keywords);
+                }
+                "shared" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "root" => config.shared.root = PathBuf::from(string_value(entry)?),
+                            "match" => {
+                                config.shared.by_prefix = match entry.value.as_str() {
+                                    Some("owner") => false,
+                                    Some("prefix") => true,
+                                    _ => {
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: "'match' must be \"owner\" or \"prefix\""
+                                                .into(),
+                                        });
+                                    }
+                                };
+                            }
+                            "users" => config.shared.users = string_list(entry)?,
+                            "private" => config.shared.private = bool_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "sync" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "ignore_patterns" => {
+                                config.sync.ignore_patterns = Some(string_list(entry)?);
+                            }
+                            "conflict_patterns" => {
+                                config.sync.conflict_patterns = Some(string_list(entry)?);
+                            }
+                            "skip_conflicts" => config.sync.skip_conflicts = bool_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "downloads" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "temp_extensions" => {
+                                let exts = string_list(entry)?
+                                    .iter()
+                                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                                    .collect();
+                                config.downloads.temp_extensions = Some(exts);
+                            }
+                            "numbered_duplicates" => {
+                                config.downloads.numbered_duplicates = Some(
+                                    match entry.value.as_str() {
+                                        Some("keep-original") => KeepCopy::Original,
+                                        Some("keep-newest") => KeepCopy::Newest,
+                                        _ => {
+                                            return Err(ConfigError::Invalid {
+                                                line: entry.line,
+                                                message: "'numbered_duplicates' must be \"keep-original\" or \"keep-newest\"".into(),
+                                            });
+                                        }
+                                    },
+                                );
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "folder_branding" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "enabled" => config.folder_branding.enabled = bool_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                _ if table.starts_with("folder_branding.") => {
+                    let name = &table["folder_branding.".len()..];
+                    let mut brand = FolderBrand::default();
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "icon" => brand.icon = Some(string_value(entry)?),
+                            "name" => brand.name = Some(string_value(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    config
+                        .folder_branding
+                        .folders
+                        .insert(name.to_string(), brand);
+                }
+                // Only holds dotted keys like `archives.compress_after_days = 30`
+                "categories" => {
+                    if let Some(entry) = entries.first() {
+                        return Err(unknown_key(entry));
+                    }
+                }
+                // Only holds dotted keys like `tickets.pattern = "..."`
+                "group_by" => {
+                    if let Some(entry) = entries.first() {
+                        return Err(unknown_key(entry));
+                    }
+                }
+                // Only holds dotted keys like `mime.command = [...]`
+                "classifiers" => {
+                    if let Some(entry) = entries.first() {
+                        return Err(unknown_key(entry));
+                    }
+                }
+                _ if table.starts_with("classifiers.") => {
+                    let name = &table["classifiers.".len()..];
+                    let mut command = None;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "command" => command = Some(string_list(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    let Some(command) = command.filter(|c| !c.is_empty()) else {
+                        return Err(ConfigError::Invalid {
+                            line: entries.first().map(|e| e.line).unwrap_or(0),
+                            message: format!(
+                                "[classifiers.{}] needs a 'command', e.g. [\"/path/to/program\"]",
+                                name
+                            ),
+                        });
+                    };
+                    config.classifiers.insert(name.to_string(), command);
+                }
+                // Only holds dotted keys like `routing.wasm = "..."`
+                "plugins" => {
+                    if let Some(entry) = entries.first() {
+                        return Err(unknown_key(entry));
+                    }
+                }
+                _ if table.starts_with("plugins.") => {
+                    let name = &table["plugins.".len()..];
+                    let mut wasm = None;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "wasm" => wasm = Some(PathBuf::from(string_value(entry)?)),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    let Some(wasm) = wasm.filter(|w| !w.as_os_str().is_empty()) else {
+                        return Err(ConfigError::Invalid {
+                            line: entries.first().map(|e| e.line).unwrap_or(0),
+                            message: format!(
+                                "[plugins.{}] needs a 'wasm' module, e.g. \"{}.wasm\"",
+                                name, name
+                            ),
+                        });
+                    };
+                    config.plugins.insert(name.to_string(), wasm);
+                }
+                // Only holds dotted keys like `nfs4.transfers = 1`
+                "limits" => {
+                    if let Some(entry) = entries.first() {
+                        return Err(unknown_key(entry));
+                    }
+                }
+                _ if table.starts_with("limits.") => {
+                    let name = &table["limits.".len()..];
+                    let mut limits = Limits::default();
+                    for entry in entries {
+                        let value = integer_value(entry)?;
+                        if value == 0 {
+                            return Err(ConfigError::Invalid {
+                                line: entry.line,
+                                message: format!("'{}' must be at least 1", entry.key),
+                            });
+                        }
+                        match entry.key.as_str() {
+                            "ops_per_second" => limits.ops_per_second = Some(value),
+                            "transfers" => limits.transfers = Some(value),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    config.limits.insert(name.to_string(), limits);
+                }
+                _ if table.starts_with("group_by.") => {
+                    let name = &table["group_by.".len()..];
+                    let mut pattern = None;
+                    let mut owner = None;
+                    let mut category = None;
+                    let mut folder = None;
+                    let mut priority = 0;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "pattern" => pattern = Some(regex_value(entry)?),
+                            "owner" => owner = Some(string_value(entry)?),
+                            "category" => category = Some(category_value(entry)?),
+                            "folder" => folder = Some(category_value(entry)?),
+                            "priority" => priority = integer_value(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                    if pattern.is_none() && (owner.is_none() || folder.is_none()) {
+                        return Err(ConfigError::Invalid {
+                            line: entries.first().map(|e| e.line).unwrap_or(0),
+                            message: format!(
+                                "[group_by.{}] needs a 'pattern', or an 'owner' and a 'folder'",
+                                name
+                            ),
+                        });
+                    }
+                    config.group_by.insert(
+                        name.to_string(),
+                        GroupRule {
+                            pattern,
+                            owner,
+                            category,
+                            folder,
+                            priority,
+                        },
+                    );
+                }
+                _ if table.starts_with("categories.") => {
+                    let name = &table["categories.".len()..];
+                    let line = entries.first().map(|e| e.line).unwrap_or(0);
+                    validate_category(name).map_err(|message| ConfigError::Invalid {
+                        line,
+                        message: format!("[categories.{}]: {}", name, message),
+                    })?;
+
+                    let settings = config.categories.entry(name.to_string()).or_default();
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "compress_after_days" => {
+                                settings.compress_after_days = Some(integer_value(entry)?);
+                            }
+                            "create_if_missing" => {
+                                settings.create_if_missing = bool_value(entry)?;
+                            }
+                            "on_conflict" => {
+                                settings.on_conflict = Some(conflict_value(entry)?);
+                            }
+                            "conflict_name" => {
+                                let pattern = string_value(entry)?;
+                                let name = ConflictName::parse(&pattern).map_err(|message| {
+                                    ConfigError::Invalid {
+                                        line: entry.line,
+                                        message: format!("'conflict_name': {}", message),
+                                    }
+                                })?;
+                                settings.conflict_name = Some(name);
+                            }
+                            "skip_larger_than" => {
+                                settings.skip_larger_than = Some(size_value(entry)?);
+                            }
+                            "verify_copies" => {
+                                settings.verify_copies = Some(bool_value(entry)?);
+                            }
+                            "trust" => {
+                                settings.trust = Some(match entry.value.as_str() {
+                                    Some("extension") => Trust::Extension,
+                                    Some("contents") => Trust::Contents,
+                                    _ => {
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message:
+                                                "'trust' must be \"extension\" or \"contents\""
+                                                    .into(),
+                                        });
+                                    }
+                                });
+                            }
+                            "action" => {
+                                let action =
+                                    Action::parse(&string_value(entry)?).map_err(|message| {
+                                        ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: format!("'action': {}", message),
+                                        }
+                                    })?;
+                                settings.action = Some(action);
+                            }
+                            "mode" => settings.permissions.mode = Some(mode_value(entry)?),
+                            "owner" => settings.permissions.owner = Some(string_value(entry)?),
+                            "group" => settings.permissions.group = Some(string_value(entry)?),
+                            "enforce_permissions" => {
+                                settings.permissions.enforce = bool_value(entry)?;
+                            }
+                            "color" => {
+                                let color =
+                                    Color::parse(&string_value(entry)?).map_err(|message| {
+                                        ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: format!("'color': {}", message),
+                                        }
+                                    })?;
+                                settings.label.color = Some(color);
+                            }
+                            "emoji" => settings.label.emoji = Some(string_value(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "schedule" => {
+                    for entry in entries {
+                        let windows = match entry.key.as_str() {
+                            "active" => &mut config.schedule.active,
+                            "quiet" => &mut config.schedule.quiet,
+                            _ => return Err(unknown_key(entry)),
+                        };
+                        *windows = string_list(entry)?
+                            .iter()
+                            .map(|text| Window::parse(text))
+                            .collect::<Result<_, _>>()
+                            .map_err(|message| ConfigError::Invalid {
+                                line: entry.line,
+                                message,
+                            })?;
+                    }
+                }
+                "pipeline" => {
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "stages" => {
+                                let mut stages = Vec::new();
+                                for name in string_list(entry)? {
+                                    let Some(stage) = Stage::parse(&name) else {
+                                        let names: Vec<&str> =
+                                            Stage::ALL.iter().map(|s| s.name()).collect();
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: format!(
+                                                "unknown stage '{}' (stages: {})",
+                                                name,
+                                                names.join(", ")
+                                            ),
+                                        });
+                                    };
+                                    stages.push(stage);
+                                }
+                                if stages.contains(&Stage::Retention) {
+                                    retention_line = Some(entry.line);
+                                }
+                                config.pipeline.stages = stages;
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "pipeline.classify" => {
+                    let stage = &mut config.pipeline.classify;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "recursive" => stage.recursive = bool_value(entry)?,
+                            "dirs" => stage.dirs = Some(dirs_value(entry)?),
+                            "on_conflict" => stage.on_conflict = Some(conflict_value(entry)?),
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "pipeline.dedupe" => {
+                    let stage = &mut config.pipeline.dedupe;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "categories" => stage.categories = category_list(entry)?,
+                            "keep" => {
+                                stage.keep = match entry.value.as_str() {
+                                    Some("oldest") => Keep::Oldest,
+                                    Some("newest") => Keep::Newest,
+                                    _ => {
+                                        return Err(ConfigError::Invalid {
+                                            line: entry.line,
+                                            message: "'keep' must be \"oldest\" or \"newest\""
+                                                .into(),
+                                        });
+                                    }
+                                };
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "pipeline.retention" => {
+                    let stage = &mut config.pipeline.retention;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "after_days" => stage.after_days = Some(integer_value(entry)?),
+                            "categories" => stage.categories = category_list(entry)?,
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                "pipeline.compress" => {
+                    let stage = &mut config.pipeline.compress;
+                    for entry in entries {
+                        match entry.key.as_str() {
+                            "categories" => stage.categories = category_list(entry)?,
+                            "older_than_days" => {
+                                stage.older_than_days = Some(integer_value(entry)?);
+                            }
+                            _ => return Err(unknown_key(entry)),
+                        }
+                    }
+                }
+                _ => {
+                    return Err(ConfigError::Invalid {
+                        line: entries.first().map(|e| e.line).unwrap_or(0),
+                        message: format!("unknown section [{}]", table),
+                    });
+                }
+            }
+        }
+
+        if let Some(line) = retention_line {
+            let retention = &config.pipeline.retention;
+            if retention.after_days.is_none() || retention.categories.is_empty() {
+                return Err(ConfigError::Invalid {
+                    line,
+                    message: "the retention stage needs [pipeline.retention] with after_days and categories"
+                        .into(),
+                });
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Sets `key = "value"` in `[table]` of the config file, creating the file or
+/// section as needed and preserving everything else (comments included).
+/// The edited text is validated before it replaces the file, so a bad value
+/// never leaves a config that fails to load.
+pub fn set_string(path: &Path, table: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ConfigError::Io(path.to_path_buf(), e)),
+    };
+
+    let new_line = format!("{} = {}", format_key(key), Value::String(value.to_string()));
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let header = format!("[{}]", table);
+
+    match lines.iter().position(|l| strip_comment(l).trim() == header) {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|l| strip_comment(l).trim().starts_with('['))
+                .map_or(lines.len(), |i| start + 1 + i);
+
+            let existing = (start + 1..end).find(|&i| {
+                split_key_value(strip_comment(&lines[i]))
+                    .and_then(|(k, _)| parse_key_path(k.trim(), i + 1).ok())
+                    .is_some_and(|parts| parts == [key])
+            });
+            match existing {
+                Some(i) => lines[i] = new_line,
+                None => {
+                    // After the last non-blank line of the section
+                    let insert_at = (start + 1..end)
+                        .rev()
+                        .find(|&i| !lines[i].trim().is_empty())
+                        .map_or(start + 1, |i| i + 1);
+                    lines.insert(insert_at, new_line);
+                }
+            }
+        }
+        None => {
+            if lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(new_line);
+        }
+    }
+
+    let mut new_text = lines.join("\n");
+    new_text.push('\n');
+    Config::parse(&new_text)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| ConfigError::Io(parent.to_path_buf(), e))?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, new_text)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+}
+
+/// Writes a key bare when TOML allows it, quoted otherwise
+pub fn format_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+/// `config.toml` in the config directory (see `paths::config_dir`)
+pub fn default_path() -> Option<PathBuf> {
+    Some(paths::config_dir()?.join("config.toml"))
+}
+
+/// Reads a category name and checks it is a safe relative folder path
+fn category_value(entry: &Entry) -> Result<String, ConfigError> {
+    let Some(category) = entry.value.as_str() else {
+        return Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!(
+                "'{}' must be a category name (string), found {}",
+                entry.key,
+                entry.value.type_name()
+            ),
+        });
+    };
+    validate_category(category).map_err(|message| ConfigError::Invalid {
+        line: entry.line,
+        message,
+    })?;
+    Ok(category.to_string())
+}
+
+fn unknown_key(entry: &Entry) -> ConfigError {
+    ConfigError::Invalid {
+        line: entry.line,
+        message: format!("unknown key '{}'", entry.key),
+    }
+}
+
+fn bool_value(entry: &Entry) -> Result<bool, ConfigError> {
+    match entry.value {
+        Value::Boolean(b) => Ok(b),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be true or false", entry.key),
+        }),
+    }
+}
+
+/// Reads the order files are organized in: "name", "mtime" or "size"
+fn order_value(entry: &Entry) -> Result<Order, ConfigError> {
+    match entry.value.as_str() {
+        Some("name") => Ok(Order::Name),
+        Some("mtime") => Ok(Order::Mtime),
+        Some("size") => Ok(Order::Size),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be \"name\", \"mtime\" or \"size\"", entry.key),
+        }),
+    }
+}
+
+/// Reads what to do with loose folders: "skip", "folders" or "classify"
+fn dirs_value(entry: &Entry) -> Result<DirPolicy, ConfigError> {
+    match entry.value.as_str() {
+        Some("skip") => Ok(DirPolicy::Skip),
+        Some("folders") => Ok(DirPolicy::Folders),
+        Some("classify") => Ok(DirPolicy::Classify),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!(
+                "'{}' must be \"skip\", \"folders\" or \"classify\"",
+                entry.key
+            ),
+        }),
+    }
+}
+
+/// Reads a conflict policy: "skip", "rename" or "overwrite"
+fn conflict_value(entry: &Entry) -> Result<ConflictPolicy, ConfigError> {
+    match entry.value.as_str() {
+        Some("skip") => Ok(ConflictPolicy::Skip),
+        Some("rename") => Ok(ConflictPolicy::Rename),
+        Some("overwrite") => Ok(ConflictPolicy::Overwrite),
+        Some("version") => Ok(ConflictPolicy::Version),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!(
+                "'{}' must be \"skip\", \"rename\", \"overwrite\" or \"version\"",
+                entry.key
+            ),
+        }),
+    }
+}
+
+/// Reads a size: bytes as an integer, or a string like "8G"
+fn size_value(entry: &Entry) -> Result<u64, ConfigError> {
+    match &entry.value {
+        Value::Integer(i) if *i >= 0 => Ok(*i as u64),
+        Value::String(s) => budget::parse_size(s).map_err(|message| ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}': {}", entry.key, message),
+        }),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be a size like \"8G\"", entry.key),
+        }),
+    }
+}
+
+/// Reads Unix permission bits written in octal, e.g. "2775" or "0o755"
+fn mode_value(entry: &Entry) -> Result<u32, ConfigError> {
+    let invalid = || ConfigError::Invalid {
+        line: entry.line,
+        message: format!("'{}' must be octal permissions like \"2775\"", entry.key),
+    };
+    let text = entry.value.as_str().ok_or_else(invalid)?;
+    let digits = text.strip_prefix("0o").unwrap_or(text);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(mode),
+        _ => Err(invalid()),
+    }
+}
+
+/// Reads a non-negative integer
+fn integer_value(entry: &Entry) -> Result<u64, ConfigError> {
+    match entry.value {
+        Value::Integer(i) if i >= 0 => Ok(i as u64),
+        _ => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be a non-negative integer", entry.key),
+        }),
+    }
+}
+
+/// Reads a regular expression (see `regex::Regex` for the supported syntax)
+fn regex_value(entry: &Entry) -> Result<Regex, ConfigError> {
+    let Some(pattern) = entry.value.as_str() else {
+        return Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be a regular expression (string)", entry.key),
+        });
+    };
+    Regex::new(pattern).map_err(|e| ConfigError::Invalid {
+        line: entry.line,
+        message: format!("invalid pattern {:?}: {}", pattern, e),
+    })
+}
+
+/// Reads a string
+fn string_value(entry: &Entry) -> Result<String, ConfigError> {
+    match entry.value.as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(ConfigError::Invalid {
+            line: entry.line,
+            message: format!("'{}' must be a string", entry.key),
+        }),
+    }
+}
+
+/// Reads an array of strings, e.g. `["Projects", "Inbox"]`
+fn string_list(entry: &Entry) -> Result<Vec<String>, ConfigError> {
+    let invalid = || ConfigError::Invalid {
+        line: entry.line,
+        message: format!("'{}' must be an array of strings", entry.key),
+    };
+    let Value::Array(items) = &entry.value else {
+        return Err(invalid());
+    };
+    items
+        .iter()
+        .map(|item| item.as_str().map(str::to_string).ok_or_else(invalid))
+        .collect()
+}
+
+/// Reads an array of category names
+fn category_list(entry: &Entry) -> Result<Vec<String>, ConfigError> {
+    let categories = string_list(entry)?;
+    for category in &categories {
+        validate_category(category).map_err(|message| ConfigError::Invalid {
+            line: entry.line,
+            message,
+        })?;
+    }
+    Ok(categories)
+}
+
+/// Category names become folders, so they must stay inside the target directory
+pub fn validate_category(category: &str) -> Result<(), String> {
+    let path = Path::new(category);
+    if category.is_empty()
+        || path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return Err(format!("invalid category '{}'", category));
+    }
+    Ok(())
+}
+
+/// A category name given on the command line, checked like those of the
+/// config: a subcommand acting on its folder must not leave the target
+pub fn parse_category(text: &str) -> Result<String, String> {
+    validate_category(text)?;
+    Ok(text.to_string())
+}
+
+impl Document {
+    /// Flattens to `table.key` -> value, for comparing two versions of a file
+    pub fn flatten(&self) -> BTreeMap<String, &Value> {
+        let mut flat = BTreeMap::new();
+        for (table, entries) in &self.tables {
+            for entry in entries {
+                let key = if table.is_empty() {
+                    entry.key.clone()
+                } else {
+                    format!("{}.{}", table, entry.key)
+                };
+                flat.insert(key, &entry.value);
+            }
+        }
+        flat
+    }
+}
+
+pub fn parse_document(text: &str) -> Result<Document, ConfigError> {
+    let mut doc = Document::default();
+    let mut table = String::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((idx, raw)) = lines.next() {
+        let line_no = idx + 1;
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header.strip_suffix(']').ok_or_else(|| ConfigError::Parse {
+                line: line_no,
+                message: "unterminated section header".to_string(),
+            })?;
+            table = parse_key_path(name.trim(), line_no)?.join(".");
+            doc.tables.entry(table.clone()).or_default();
+            continue;
+        }
+
+        let Some((key, rest)) = split_key_value(line) else {
+            return Err(ConfigError::Parse {
+                line: line_no,
+                message: format!("expected 'key = value', found '{}'", line),
+            });
+        };
+
+        // Arrays may span several lines; keep reading until the brackets balance
+        let mut value_text = rest.trim().to_string();
+        while value_text.starts_with('[') && !brackets_balanced(&value_text) {
+            match lines.next() {
+                Some((_, next)) => {
+                    value_text.push(' ');
+                    value_text.push_str(strip_comment(next).trim());
+                }
+                None => {
+                    return Err(ConfigError::Parse {
+                        line: line_no,
+                        message: "unterminated array".to_string(),
+                    });
+                }
+            }
+        }
+
+        // Dotted keys (`archives.days = 3`) address sub-tables
+        let mut path = parse_key_path(key.trim(), line_no)?;
+        let key = path.pop().unwrap_or_default();
+        let full_table = if path.is_empty() {
+            table.clone()
+        } else if table.is_empty() {
+            path.join(".")
+        } else {
+            format!("{}.{}", table, path.join("."))
+        };
+
+        let (value, remainder) =
+            parse_value(&value_text).map_err(|message| ConfigError::Parse {
+                line: line_no,
+                message,
+            })?;
+        if !remainder.trim().is_empty() {
+            return Err(ConfigError::Parse {
+                line: line_no,
+                message: format!("unexpected trailing text '{}'", remainder.trim()),
+            });
+        }
+
+        let entries = doc.tables.entry(full_table).or_default();
+        if entries.iter().any(|e| e.key == key) {
+            return Err(ConfigError::Parse {
+                line: line_no,
+                message: format!("duplicate key '{}'", key),
+            });
+        }
+        entries.push(Entry {
+            key,
+            value,
+            line: line_no,
+        });
+    }
+
+    Ok(doc)
+}
+
+/// Removes a trailing `# comment`, ignoring `#` inside strings
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && q == '"' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+            }
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '#' => return &line[..i],
+                _ => {}
+            },
+        }
+    }
+    line
+}
+
+/// Splits on the first `=` that is not inside a quoted key
+fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '=' => return Some((&line[..i], &line[i + 1..])),
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+fn brackets_balanced(text: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth <= 0
+}
+
+/// Parses `a.b."c d"` into its segments
+fn parse_key_path(text: &str, line: usize) -> Result<Vec<String>, ConfigError> {
+    let err = |message: String| ConfigError::Parse { line, message };
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+
+    loop {
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            let (value, remainder) = parse_value(rest).map_err(err)?;
+            if let Value::String(s) = value {
+                parts.push(s);
+            }
+            rest = remainder.trim_start();
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(err(format!("invalid key '{}'", text)));
+            }
+            parts.push(rest[..end].to_string());
+            rest = rest[end..].trim_start();
+        }
+
+        if rest.is_empty() {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix('.')
+            .ok_or_else(|| err(format!("invalid key '{}'", text)))?
+            .trim_start();
+    }
+}
+
+/// Parses one value from the start of `text` and returns it with the unparsed remainder
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(out), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('\\') => out.push('\\'),
+                    Some('"') => out.push('"'),
+                    Some('u') => {
+                        let hex: String = (0..4)
+                            .filter_map(|_| chars.next().map(|(_, c)| c))
+                            .collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid unicode escape '\\u{}'", hex))?;
+                        out.push(c);
+                    }
+                    Some(other) => return Err(format!("invalid escape '\\{}'", other)),
+                    None => break,
+                },
+                _ => out.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("expected ',' or ']' in array".to_string());
+            }
+        }
+    }
+
+    let end = text
+        .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+        .unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        "" => return Err("missing value".to_string()),
+        _ => Value::Integer(
+            word.replace('_', "")
+                .parse()
+                .map_err(|_| format!("invalid value '{}'", word))?,
+        ),
+    };
+    Ok((value, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_cannot_leave_the_target() {
+        assert_eq!(
+            parse_category("documents/invoices").as_deref(),
+            Ok("documents/invoices")
+        );
+        for bad in ["../x", "documents/../../x", "/etc", "", "."] {
+            assert!(parse_category(bad).is_err(), "{:?} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn owner_rules_need_a_pattern_or_a_folder() {
+        let config =
+            Config::parse("[group_by.scans]\nowner = \"alice\"\nfolder = \"alice\"\n").unwrap();
+        let rule = &config.group_by["scans"];
+        assert_eq!(rule.owner.as_deref(), Some("alice"));
+        assert!(rule.pattern.is_none());
+        assert!(Config::parse("[group_by.scans]\nowner = \"alice\"\n").is_err());
+    }
+}
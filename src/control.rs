@@ -0,0 +1,377 @@
+use crate::interrupt;
+use crate::paths;
+use crate::schedule;
+use clap::ValueEnum;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What `auto-organize ctl` can ask of a watching daemon
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Run a pass now, without waiting for the interval (or the schedule)
+    RunNow,
+    /// Hold off passes until `resume` (the pass in progress finishes)
+    Pause,
+    /// Let passes run again
+    Resume,
+    /// Read the config file again, even if it looks unchanged
+    ReloadConfig,
+    /// What the daemon is doing, and how its last pass went
+    Status,
+    /// Stop watching, after the move in progress
+    Shutdown,
+}
+
+impl Request {
+    fn name(self) -> &'static str {
+        match self {
+            Request::RunNow => "run-now",
+            Request::Pause => "pause",
+            Request::Resume => "resume",
+            Request::ReloadConfig => "reload-config",
+            Request::Status => "status",
+            Request::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// The daemon's side of the channel: requests that need the watch loop
+/// wait here for it; the rest are answered by the listening thread
+pub struct Control {
+    target_dir: PathBuf,
+    run_now: AtomicBool,
+    reload: AtomicBool,
+    status: Mutex<Status>,
+}
+
+#[derive(Default)]
+struct Status {
+    /// "idle", "running a pass", "on hold: ..."
+    state: String,
+    passes: u64,
+    /// When the last pass finished (local time) and what it did
+    last_pass: Option<String>,
+}
+
+/// Where the daemon watching `target_dir` listens: a socket in
+/// `<data dir>/control/`, keyed like journals (a named pipe on Windows)
+pub fn address_for(target_dir: &Path) -> Option<PathBuf> {
+    sys::address(&paths::key_for(target_dir))
+}
+
+impl Control {
+    /// Starts listening for `ctl` requests about `target_dir` in the background
+    pub fn listen(target_dir: &Path) -> io::Result<Arc<Control>> {
+        let address = address_for(target_dir)
+            .ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+        let listener = sys::Listener::bind(&address)?;
+        let control = Arc::new(Control {
+            target_dir: target_dir.to_path_buf(),
+            run_now: AtomicBool::new(false),
+            reload: AtomicBool::new(false),
+            status: Mutex::new(Status {
+                state: "idle".to_string(),
+                ..Status::default()
+            }),
+        });
+        let shared = control.clone();
+        thread::spawn(move || {
+            loop {
+                match listener.accept() {
+                    Ok(stream) => {
+                        let _ = shared.answer(stream);
+                    }
+                    Err(e) => {
+                        eprintln!("Error on the control channel: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(control)
+    }
+
+    fn answer(&self, stream: impl Read + Write) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let reply = match Request::from_str(line.trim(), false) {
+            Ok(request) => self.handle(request),
+            Err(_) => format!("unknown request '{}'\n", line.trim()),
+        };
+        let mut stream = reader.into_inner();
+        stream.write_all(reply.as_bytes())?;
+        stream.flush()
+    }
+
+    fn handle(&self, request: Request) -> String {
+        match request {
+            Request::RunNow => {
+                self.run_now.store(true, Ordering::SeqCst);
+                "A pass starts now (after the one in progress, if any).\n".to_string()
+            }
+            Request::ReloadConfig => {
+                self.reload.store(true, Ordering::SeqCst);
+                "The config is read again before the next pass.\n".to_string()
+            }
+            Request::Pause => match schedule::pause(&self.target_dir, None) {
+                Ok(()) => "Paused (the pass in progress, if any, finishes).\n".to_string(),
+                Err(e) => format!("Error pausing: {}\n", e),
+            },
+            Request::Resume => match schedule::unpause(&self.target_dir) {
+                Ok(true) => "Passes resume.\n".to_string(),
+                Ok(false) => "Not paused.\n".to_string(),
+                Err(e) => format!("Error resuming: {}\n", e),
+            },
+            Request::Shutdown => {
+                interrupt::request();
+                "Stopping after the move in progress.\n".to_string()
+            }
+            Request::Status => {
+                let status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+                let mut reply = format!(
+                    "Watching: {}\nState:    {}\nPasses:   {}\n",
+                    self.target_dir.display(),
+                    status.state,
+                    status.passes
+                );
+                if let Some(last) = &status.last_pass {
+                    reply.push_str(&format!("Last:     {}\n", last));
+                }
+                reply
+            }
+        }
+    }
+
+    /// Sets what `status` reports the daemon is doing
+    pub fn set_state(&self, state: &str) {
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.state = state.to_string();
+    }
+
+    /// Records a finished pass for `status`
+    pub fn pass_done(&self, when: &str, outcome: &str) {
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        status.passes += 1;
+        status.last_pass = Some(format!("{} ({})", when, outcome));
+        status.state = "idle".to_string();
+    }
+
+    /// Whether `run-now` was asked since the last call
+    pub fn take_run_now(&self) -> bool {
+        self.run_now.swap(false, Ordering::SeqCst)
+    }
+
+    /// Whether `reload-config` was asked since the last call
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::SeqCst)
+    }
+
+    /// Sleeps like `interrupt::sleep`, but also wakes early for `run-now`
+    /// and `reload-config`; false if woken by Ctrl-C or `shutdown`
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let until = Instant::now() + duration;
+        while !interrupt::requested() {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero()
+                || self.run_now.load(Ordering::SeqCst)
+                || self.reload.load(Ordering::SeqCst)
+            {
+                return true;
+            }
+            thread::sleep(left.min(Duration::from_millis(200)));
+        }
+        false
+    }
+}
+
+/// Sends `request` to the daemon watching `target_dir` and returns its answer
+pub fn send(target_dir: &Path, request: Request) -> io::Result<String> {
+    let address = address_for(target_dir)
+        .ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+    let mut stream = sys::connect(&address)?;
+    stream.write_all(format!("{}\n", request.name()).as_bytes())?;
+    stream.flush()?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::{self, DirBuilder};
+    use std::io;
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    pub fn address(key: &str) -> Option<PathBuf> {
+        Some(
+            crate::paths::data_dir()?
+                .join("control")
+                .join(format!("{}.sock", key)),
+        )
+    }
+
+    pub struct Listener {
+        listener: UnixListener,
+    }
+
+    impl Listener {
+        pub fn bind(path: &Path) -> io::Result<Listener> {
+            if let Some(parent) = path.parent() {
+                // Only the user may reach the socket: it can stop the daemon
+                DirBuilder::new()
+                    .recursive(true)
+                    .mode(0o700)
+                    .create(parent)?;
+            }
+            // A socket left by an earlier daemon (it stays when one stops)
+            // answers nothing
+            if path.exists() {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        "another daemon is already watching this directory",
+                    ));
+                }
+                fs::remove_file(path)?;
+            }
+            Ok(Listener {
+                listener: UnixListener::bind(path)?,
+            })
+        }
+
+        pub fn accept(&self) -> io::Result<UnixStream> {
+            Ok(self.listener.accept()?.0)
+        }
+    }
+
+    pub fn connect(path: &Path) -> io::Result<UnixStream> {
+        UnixStream::connect(path)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+    const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+    const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x8;
+    const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+    const ERROR_PIPE_CONNECTED: i32 = 535;
+
+    unsafe extern "system" {
+        fn CreateNamedPipeW(
+            name: *const u16,
+            open_mode: u32,
+            pipe_mode: u32,
+            max_instances: u32,
+            out_buffer: u32,
+            in_buffer: u32,
+            timeout: u32,
+            security: *mut c_void,
+        ) -> *mut c_void;
+        fn ConnectNamedPipe(pipe: *mut c_void, overlapped: *mut c_void) -> i32;
+    }
+
+    pub fn address(key: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(format!(r"\\.\pipe\auto-organize-{}", key)))
+    }
+
+    pub struct Listener {
+        name: Vec<u16>,
+        first: AtomicBool,
+    }
+
+    impl Listener {
+        pub fn bind(path: &Path) -> io::Result<Listener> {
+            let name = path.as_os_str().encode_wide().chain([0]).collect();
+            Ok(Listener {
+                name,
+                first: AtomicBool::new(true),
+            })
+        }
+
+        /// Each client gets a pipe instance of its own
+        pub fn accept(&self) -> io::Result<File> {
+            // The first instance claims the name, so a second daemon fails here
+            let first = if self.first.swap(false, Ordering::SeqCst) {
+                FILE_FLAG_FIRST_PIPE_INSTANCE
+            } else {
+                0
+            };
+            // SAFETY: the name is NUL-terminated; no security attributes
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    self.name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX | first,
+                    PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    4096,
+                    4096,
+                    0,
+                    std::ptr::null_mut(),
+                )
+            };
+            if handle as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the handle was just created and is owned by the File
+            let pipe = unsafe { File::from_raw_handle(handle) };
+            // SAFETY: a valid pipe handle, waited on synchronously
+            if unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) } == 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(ERROR_PIPE_CONNECTED) {
+                    return Err(e);
+                }
+            }
+            Ok(pipe)
+        }
+    }
+
+    pub fn connect(path: &Path) -> io::Result<File> {
+        OpenOptions::new().read(true).write(true).open(path)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    pub fn address(_key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    pub struct Listener;
+
+    impl Listener {
+        pub fn bind(_path: &Path) -> io::Result<Listener> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no control channel on this platform",
+            ))
+        }
+
+        pub fn accept(&self) -> io::Result<File> {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+    }
+
+    pub fn connect(_path: &Path) -> io::Result<File> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
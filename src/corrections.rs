@@ -0,0 +1,345 @@
+use crate::date::DateTime;
+use crate::history;
+use crate::journal::Placed;
+use crate::paths;
+use crate::plan::EntryKind;
+use crate::tsv::{escape, unescape};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const HEADER: &str = "# auto-organize corrections v1";
+
+/// Moves of runs older than this are not looked after any more
+const RECENT_DAYS: u64 = 30;
+
+/// Corrections of an extension, all into one category, before a rule for
+/// it is suggested (one could be an exception; three are a habit)
+pub const LEARN_AFTER: usize = 3;
+
+/// Where the user put a file a run had organized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// Back into the organized folder itself
+    Root,
+    /// Into another folder, named like a category (`documents/invoices`)
+    Category(String),
+    /// Nowhere to be found: deleted or renamed
+    Gone,
+}
+
+/// A file the user moved after a run organized it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction {
+    /// The run that organized it, and the move in that run
+    pub run: String,
+    pub index: usize,
+    pub name: String,
+    /// The category the run gave it
+    pub from: String,
+    pub to: Placement,
+    /// Where it is now, unless gone
+    pub path: Option<PathBuf>,
+    /// What it looked like there (not recorded by older versions)
+    pub placed: Option<Placed>,
+}
+
+impl Correction {
+    /// Whether the file only moved within the folder of its category (say
+    /// from `documents` into `documents/2024`), which corrects nothing
+    pub fn within_category(&self) -> bool {
+        match &self.to {
+            Placement::Category(to) => Path::new(to).starts_with(&self.from),
+            _ => false,
+        }
+    }
+}
+
+/// A rule the corrections argue for: files of `extension` go to `category`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub extension: String,
+    pub category: String,
+    /// Corrections of the extension into the category
+    pub count: usize,
+}
+
+/// Where the corrections of `target_dir` are kept:
+/// `<data dir>/corrections/<key>.tsv`, keyed like journals
+pub fn path_for(target_dir: &Path) -> Option<PathBuf> {
+    Some(
+        paths::data_dir()?
+            .join("corrections")
+            .join(format!("{}.tsv", paths::key_for(target_dir))),
+    )
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+/// Every correction recorded for `target_dir`, oldest first
+pub fn load(target_dir: &Path) -> io::Result<Vec<Correction>> {
+    let Some(path) = path_for(target_dir) else {
+        return Ok(Vec::new());
+    };
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut corrections = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        // Comments, and a line cut short by a crash; older lines have no
+        // size and time, and gone files none either (`-`)
+        let (run, index, name, from, kind, to, path, placed) = match fields[..] {
+            [run, index, name, from, kind, to, path, _when] => {
+                (run, index, name, from, kind, to, path, None)
+            }
+            [run, index, name, from, kind, to, path, _when, len, modified] => {
+                let placed = match (len.parse(), modified.parse()) {
+                    (Ok(len), Ok(modified)) => Some(Placed { len, modified }),
+                    _ => None,
+                };
+                (run, index, name, from, kind, to, path, placed)
+            }
+            _ => continue,
+        };
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+        let to = match kind {
+            "root" => Placement::Root,
+            "category" => Placement::Category(unescape(to)),
+            _ => Placement::Gone,
+        };
+        corrections.push(Correction {
+            run: run.to_string(),
+            index,
+            name: unescape(name),
+            from: unescape(from),
+            path: (to != Placement::Gone).then(|| PathBuf::from(unescape(path))),
+            to,
+            placed,
+        });
+    }
+    Ok(corrections)
+}
+
+/// Adds newly found corrections to those recorded for `target_dir`
+pub fn save(target_dir: &Path, found: &[Correction]) -> io::Result<()> {
+    let path = path_for(target_dir)
+        .ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "# target: {}", absolute(target_dir).display())?;
+    }
+    let now = DateTime::now().to_unix();
+    for correction in found {
+        let (kind, to) = match &correction.to {
+            Placement::Root => ("root", "-"),
+            Placement::Category(category) => ("category", category.as_str()),
+            Placement::Gone => ("gone", "-"),
+        };
+        let path = correction
+            .path
+            .as_ref()
+            .map_or("-".to_string(), |path| path.to_string_lossy().into_owned());
+        let placed = correction
+            .placed
+            .map_or("-\t-".to_string(), |p| format!("{}\t{}", p.len, p.modified));
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            correction.run,
+            correction.index,
+            escape(&correction.name),
+            escape(&correction.from),
+            kind,
+            escape(to),
+            escape(&path),
+            now,
+            placed
+        )?;
+    }
+    Ok(())
+}
+
+/// Files recent runs over `target_dir` organized (and no later run moved on)
+/// that are no longer where the run put them, with where the user put them:
+/// a file of that name, size and modification time back in `target_dir`
+/// itself, else the first one in a folder below it. A file of that name
+/// that differs is another file (say a new download), not the one moved.
+/// Moves recorded without a size and time are passed over, and corrections
+/// in `known` are not reported again.
+pub fn detect(target_dir: &Path, known: &[Correction]) -> io::Result<Vec<Correction>> {
+    let target = absolute(target_dir);
+    let reported: HashSet<(&str, usize)> =
+        known.iter().map(|c| (c.run.as_str(), c.index)).collect();
+    let cutoff = SystemTime::now() - Duration::from_secs(RECENT_DAYS * 24 * 60 * 60);
+
+    // Where the runs left each file: destination -> run, move, category, and
+    // what the file looked like
+    let mut placed: HashMap<PathBuf, Moved> = HashMap::new();
+    for run in history::list()? {
+        if run.target != target || history::started(&run.id).is_some_and(|at| at < cutoff) {
+            continue;
+        }
+        for (index, item) in run.moves {
+            placed.remove(&item.src);
+            let Some(&looked) = run.placed.get(&index) else {
+                continue;
+            };
+            if item.kind == EntryKind::File && !run.undone.contains(&index) {
+                placed.insert(item.dest, (run.id.clone(), index, item.category, looked));
+            }
+        }
+    }
+    let mut missing: Vec<(PathBuf, Moved)> = placed
+        .into_iter()
+        .filter(|(dest, (run, index, _, _))| {
+            !reported.contains(&(run.as_str(), *index)) && fs::symlink_metadata(dest).is_err()
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(Vec::new());
+    }
+    missing.sort_by(|(_, a), (_, b)| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let wanted: HashSet<OsString> = missing
+        .iter()
+        .filter_map(|(dest, _)| dest.file_name().map(OsString::from))
+        .collect();
+    let mut found: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+    find_files(&target, &wanted, &mut found);
+
+    Ok(missing
+        .into_iter()
+        .map(|(dest, (run, index, from, looked))| {
+            let name = dest.file_name().unwrap_or_default();
+            let path = found
+                .get(name)
+                .and_then(|paths| paths.iter().find(|path| Placed::of(path) == Some(looked)))
+                .cloned();
+            let to = match path.as_deref().and_then(Path::parent) {
+                Some(parent) if parent == target => Placement::Root,
+                Some(parent) => {
+                    let folder = parent.strip_prefix(&target).unwrap_or(parent);
+                    let parts: Vec<String> = folder
+                        .iter()
+                        .map(|part| part.to_string_lossy().into_owned())
+                        .collect();
+                    Placement::Category(parts.join("/"))
+                }
+                None => Placement::Gone,
+            };
+            Correction {
+                run,
+                index,
+                name: name.to_string_lossy().into_owned(),
+                from,
+                path: (to != Placement::Gone).then_some(path).flatten(),
+                placed: (to != Placement::Gone).then_some(looked),
+                to,
+            }
+        })
+        .collect())
+}
+
+/// A move of a file that is no longer where it went: its run and index in
+/// it, its category, and what the file looked like
+type Moved = (String, usize, String, Placed);
+
+/// Finds the files of each `wanted` name in `dir`, those right in it first,
+/// then below it in name order. Hidden folders and symlinks are not followed.
+fn find_files(dir: &Path, wanted: &HashSet<OsString>, found: &mut HashMap<OsString, Vec<PathBuf>>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<fs::DirEntry> = entries.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut folders = Vec::new();
+    for entry in entries {
+        let name = entry.file_name();
+        match entry.file_type() {
+            Ok(t) if t.is_file() && wanted.contains(&name) => {
+                found.entry(name).or_default().push(entry.path());
+            }
+            Ok(t) if t.is_dir() && !name.to_string_lossy().starts_with('.') => {
+                folders.push(entry.path());
+            }
+            _ => {}
+        }
+    }
+    for folder in folders {
+        find_files(&folder, wanted, found);
+    }
+}
+
+/// Where files the user put somewhere themselves are now, and what they
+/// looked like there: runs leave them there (see `is_pinned`). Canonical,
+/// to compare with any path of them.
+pub fn pinned(corrections: &[Correction]) -> HashMap<PathBuf, Option<Placed>> {
+    corrections
+        .iter()
+        .filter_map(|c| Some((c.path.as_ref()?, c.placed)))
+        .map(|(path, placed)| (path.canonicalize().unwrap_or(path.to_path_buf()), placed))
+        .collect()
+}
+
+/// Whether the file at `path` is one the user put there (see `pinned`),
+/// and not another that took its name since
+pub fn is_pinned(pinned: &HashMap<PathBuf, Option<Placed>>, path: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    match pinned.get(&path) {
+        Some(Some(placed)) => Placed::of(&path) == Some(*placed),
+        Some(None) => true,
+        None => false,
+    }
+}
+
+/// Extension rules the corrections argue for: the last `LEARN_AFTER`
+/// corrections of an extension into another category all chose the same
+pub fn suggestions(corrections: &[Correction]) -> Vec<Suggestion> {
+    let mut by_extension: HashMap<String, Vec<&str>> = HashMap::new();
+    for correction in corrections {
+        let Placement::Category(category) = &correction.to else {
+            continue;
+        };
+        let Some(extension) = Path::new(&correction.name).extension() else {
+            continue;
+        };
+        if correction.within_category() {
+            continue;
+        }
+        by_extension
+            .entry(extension.to_string_lossy().to_lowercase())
+            .or_default()
+            .push(category);
+    }
+    let mut suggestions: Vec<Suggestion> = by_extension
+        .into_iter()
+        .filter_map(|(extension, categories)| {
+            let recent = categories.get(categories.len().checked_sub(LEARN_AFTER)?..)?;
+            let category = recent[0];
+            recent.iter().all(|c| *c == category).then(|| Suggestion {
+                count: categories.iter().filter(|c| **c == category).count(),
+                category: category.to_string(),
+                extension,
+            })
+        })
+        .collect();
+    suggestions.sort_by(|a, b| a.extension.cmp(&b.extension));
+    suggestions
+}
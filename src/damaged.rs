@@ -0,0 +1,243 @@
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Where `--empty-files quarantine` puts them
+pub const QUARANTINE_CATEGORY: &str = "Quarantine";
+
+/// What to do with empty files and files whose contents are not what their
+/// extension says, which are usually failed or unfinished downloads
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyFiles {
+    /// Leave them where they are
+    Skip,
+    /// Put them in Others, whatever their extension
+    Others,
+    /// Move them to the system trash
+    Delete,
+    /// Put them in a Quarantine folder to look at later
+    Quarantine,
+}
+
+impl EmptyFiles {
+    /// What became of them, for the summary
+    pub fn outcome(self) -> &'static str {
+        match self {
+            EmptyFiles::Skip => "left in place",
+            EmptyFiles::Others => "moved to Others",
+            EmptyFiles::Delete => "moved to the trash",
+            EmptyFiles::Quarantine => "moved to Quarantine",
+        }
+    }
+}
+
+/// Why a file looks broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// Zero bytes long
+    Empty,
+    /// Starts with something other than its extension's signature
+    Corrupt,
+}
+
+impl Damage {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Damage::Empty => "empty file",
+            Damage::Corrupt => "contents do not match its type",
+        }
+    }
+}
+
+/// A signature: these bytes at this offset
+struct Magic {
+    offset: usize,
+    bytes: &'static [u8],
+}
+
+const fn at(offset: usize, bytes: &'static [u8]) -> Magic {
+    Magic { offset, bytes }
+}
+
+/// Extensions whose files always start with one of these signatures
+const SIGNATURES: &[(&[&str], &[Magic])] = &[
+    (&["jpg", "jpeg"], &[at(0, b"\xFF\xD8\xFF")]),
+    (&["png"], &[at(0, b"\x89PNG\r\n\x1A\n")]),
+    (&["gif"], &[at(0, b"GIF87a"), at(0, b"GIF89a")]),
+    (&["bmp"], &[at(0, b"BM")]),
+    (&["tif", "tiff"], &[at(0, b"II*\0"), at(0, b"MM\0*")]),
+    (&["webp"], &[at(8, b"WEBP")]),
+    (&["ico"], &[at(0, b"\0\0\x01\0")]),
+    (&["pdf"], &[]), // `%PDF` may follow some junk, see `matches`
+    (
+        &[
+            "zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk",
+        ],
+        &[at(0, b"PK\x03\x04"), at(0, b"PK\x05\x06")],
+    ),
+    (
+        &["doc", "xls", "ppt", "msi", "msg"],
+        &[at(0, b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1")],
+    ),
+    (&["gz", "tgz"], &[at(0, b"\x1F\x8B")]),
+    (&["bz2"], &[at(0, b"BZh")]),
+    (&["xz"], &[at(0, b"\xFD7zXZ\0")]),
+    (&["7z"], &[at(0, b"7z\xBC\xAF\x27\x1C")]),
+    (&["rar"], &[at(0, b"Rar!\x1A\x07")]),
+    (&["exe", "dll"], &[at(0, b"MZ")]),
+    (
+        &["mp3"],
+        &[
+            at(0, b"ID3"),
+            at(0, b"\xFF\xFB"),
+            at(0, b"\xFF\xF3"),
+            at(0, b"\xFF\xF2"),
+        ],
+    ),
+    (&["flac"], &[at(0, b"fLaC")]),
+    (&["ogg", "oga", "ogv", "opus"], &[at(0, b"OggS")]),
+    (&["wav"], &[at(8, b"WAVE")]),
+    (&["avi"], &[at(8, b"AVI ")]),
+    (
+        &["mp4", "m4a", "m4v", "mov", "heic", "3gp"],
+        &[
+            at(4, b"ftyp"),
+            at(4, b"moov"),
+            at(4, b"mdat"),
+            at(4, b"wide"),
+            at(4, b"free"),
+        ],
+    ),
+    (&["mkv", "webm"], &[at(0, b"\x1A\x45\xDF\xA3")]),
+];
+
+/// Disk and volume images: they may well start with a kilobyte of zeros
+/// (an ISO's system area, a boot sector left blank), so that says nothing
+const ZERO_PREFIXED: &[&str] = &["iso", "img", "vhd", "vmdk", "dmg", "raw"];
+
+/// How much of a file is read to check its signature
+const HEADER_BYTES: u64 = 1024;
+
+/// Whether `path` (`len` bytes long) looks like a failed download: empty,
+/// or for an extension with a known signature, filled with zeros or not
+/// starting with that signature. A file with the signature of another
+/// known type is only misnamed and is not reported; formats without a
+/// signature (text, disk images) only when empty.
+pub fn inspect(path: &Path, len: u64) -> io::Result<Option<Damage>> {
+    if len == 0 {
+        return Ok(Some(Damage::Empty));
+    }
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    if ZERO_PREFIXED.contains(&ext.as_str()) {
+        return Ok(None);
+    }
+    let Some((_, magics)) = SIGNATURES
+        .iter()
+        .find(|(exts, _)| exts.contains(&ext.as_str()))
+    else {
+        return Ok(None);
+    };
+    let header = read_header(path)?;
+    if header.iter().all(|&b| b == 0) {
+        // Space reserved by a download that never wrote anything
+        return Ok(Some(Damage::Corrupt));
+    }
+    if matches(&header, &ext, magics) {
+        return Ok(None);
+    }
+    let known = SIGNATURES
+        .iter()
+        .any(|(exts, magics)| matches(&header, exts[0], magics));
+    Ok((!known).then_some(Damage::Corrupt))
+}
+
+/// The type `path`'s contents are (as its extensions, e.g. `["jpg", "jpeg"]`),
+/// if that is not what its extension says. `None` when the contents match
+/// the extension or no known signature.
+pub fn sniff(path: &Path) -> io::Result<Option<&'static [&'static str]>> {
+    let header = read_header(path)?;
+    let ext = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase())
+        .unwrap_or_default();
+    if let Some((_, magics)) = SIGNATURES
+        .iter()
+        .find(|(exts, _)| exts.contains(&ext.as_str()))
+        && matches(&header, &ext, magics)
+    {
+        return Ok(None);
+    }
+    Ok(SIGNATURES
+        .iter()
+        .find(|(exts, magics)| matches(&header, exts[0], magics))
+        .map(|(exts, _)| *exts))
+}
+
+/// The type `path`'s contents are by their signature (as its extensions),
+/// whatever its extension says; `None` for contents without a known one
+pub fn signature(path: &Path) -> io::Result<Option<&'static [&'static str]>> {
+    let header = read_header(path)?;
+    Ok(SIGNATURES
+        .iter()
+        .find(|(exts, magics)| matches(&header, exts[0], magics))
+        .map(|(exts, _)| *exts))
+}
+
+fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)?
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+fn matches(header: &[u8], ext: &str, magics: &[Magic]) -> bool {
+    if ext == "pdf" {
+        // Readers accept `%PDF` anywhere in the first kilobyte
+        return header.windows(4).any(|w| w == b"%PDF");
+    }
+    magics
+        .iter()
+        .any(|m| header.get(m.offset..m.offset + m.bytes.len()) == Some(m.bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A file named `name` holding `contents`, in a folder of this test's own
+    fn file(test: &str, name: &str, contents: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("auto-organize-damaged-{}", test));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn iso_with_zero_system_area_is_not_damaged() {
+        // 32 KiB of zeros, then the primary volume descriptor
+        let mut iso = vec![0u8; 32 * 1024];
+        iso.extend_from_slice(b"\x01CD001\x01");
+        iso.resize(40 * 1024, 0);
+        let path = file("iso", "install.iso", &iso);
+        assert_eq!(inspect(&path, iso.len() as u64).unwrap(), None);
+    }
+
+    #[test]
+    fn zeros_under_a_signed_extension_are_damaged() {
+        let zeros = vec![0u8; 4096];
+        let path = file("zeros", "photo.jpg", &zeros);
+        assert_eq!(inspect(&path, 4096).unwrap(), Some(Damage::Corrupt));
+        let path = file("zeros", "notes.txt", &zeros);
+        assert_eq!(inspect(&path, 4096).unwrap(), None);
+    }
+}
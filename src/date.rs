@@ -0,0 +1,288 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A broken-down UTC timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch into a UTC date and time
+    pub fn from_unix(secs: i64) -> Self {
+        let days = secs.div_euclid(86_400);
+        let rem = secs.rem_euclid(86_400);
+
+        // Civil-from-days (Howard Hinnant's algorithm)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+        }
+    }
+
+    /// Seconds since the Unix epoch (the inverse of `from_unix`)
+    pub fn to_unix(self) -> i64 {
+        // Days-from-civil, the counterpart of the algorithm above
+        let (month, day) = (i64::from(self.month), i64::from(self.day));
+        let y = if month <= 2 { self.year - 1 } else { self.year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        days * 86_400
+            + i64::from(self.hour) * 3600
+            + i64::from(self.minute) * 60
+            + i64::from(self.second)
+    }
+
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        Self::from_unix(secs)
+    }
+
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    /// The wall-clock time here (UTC shifted by `utc_offset`)
+    pub fn now_local() -> Self {
+        let now = Self::now().to_unix();
+        Self::from_unix(now + utc_offset(now))
+    }
+
+    /// Day of the week, Monday = 0 to Sunday = 6
+    pub fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.to_unix().div_euclid(86_400) + 3).rem_euclid(7) as u32
+    }
+
+    /// ISO 8601 week-numbering year and week (1-53): weeks start on Monday
+    /// and belong to the year their Thursday falls in, so 2024-12-30 is in
+    /// 2025-W01
+    pub fn iso_week(self) -> (i64, u32) {
+        let days = self.to_unix().div_euclid(86_400);
+        let thursday = DateTime::from_unix((days + 3 - i64::from(self.weekday())) * 86_400);
+        let jan1 = DateTime {
+            year: thursday.year,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let day_of_year = (thursday.to_unix() - jan1.to_unix()) / 86_400;
+        (thursday.year, (day_of_year / 7 + 1) as u32)
+    }
+
+    /// Quarter of the year, 1 to 4
+    pub fn quarter(self) -> u32 {
+        (self.month - 1) / 3 + 1
+    }
+
+    /// Formats as `YYYY-MM-DDTHH:MM:SS`
+    pub fn to_iso(self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Month names in one language, January first
+#[derive(Debug, PartialEq)]
+pub struct MonthNames {
+    pub full: [&'static str; 12],
+    /// Without the trailing dot some locales use, since they name folders
+    pub short: [&'static str; 12],
+}
+
+/// Languages with month names, by ISO 639-1 code; English is the fallback
+#[rustfmt::skip]
+const MONTH_NAMES: &[(&str, MonthNames)] = &[
+    ("en", MonthNames {
+        full: ["January", "February", "March", "April", "May", "June",
+               "July", "August", "September", "October", "November", "December"],
+        short: ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"],
+    }),
+    ("de", MonthNames {
+        full: ["Januar", "Februar", "März", "April", "Mai", "Juni",
+               "Juli", "August", "September", "Oktober", "November", "Dezember"],
+        short: ["Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez"],
+    }),
+    ("fr", MonthNames {
+        full: ["janvier", "février", "mars", "avril", "mai", "juin",
+               "juillet", "août", "septembre", "octobre", "novembre", "décembre"],
+        short: ["janv", "févr", "mars", "avr", "mai", "juin", "juil", "août", "sept", "oct", "nov", "déc"],
+    }),
+    ("es", MonthNames {
+        full: ["enero", "febrero", "marzo", "abril", "mayo", "junio",
+               "julio", "agosto", "septiembre", "octubre", "noviembre", "diciembre"],
+        short: ["ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic"],
+    }),
+    ("it", MonthNames {
+        full: ["gennaio", "febbraio", "marzo", "aprile", "maggio", "giugno",
+               "luglio", "agosto", "settembre", "ottobre", "novembre", "dicembre"],
+        short: ["gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic"],
+    }),
+    ("pt", MonthNames {
+        full: ["janeiro", "fevereiro", "março", "abril", "maio", "junho",
+               "julho", "agosto", "setembro", "outubro", "novembro", "dezembro"],
+        short: ["jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov", "dez"],
+    }),
+    ("nl", MonthNames {
+        full: ["januari", "februari", "maart", "april", "mei", "juni",
+               "juli", "augustus", "september", "oktober", "november", "december"],
+        short: ["jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec"],
+    }),
+    ("sv", MonthNames {
+        full: ["januari", "februari", "mars", "april", "maj", "juni",
+               "juli", "augusti", "september", "oktober", "november", "december"],
+        short: ["jan", "feb", "mar", "apr", "maj", "jun", "jul", "aug", "sep", "okt", "nov", "dec"],
+    }),
+    ("pl", MonthNames {
+        full: ["styczeń", "luty", "marzec", "kwiecień", "maj", "czerwiec",
+               "lipiec", "sierpień", "wrzesień", "październik", "listopad", "grudzień"],
+        short: ["sty", "lut", "mar", "kwi", "maj", "cze", "lip", "sie", "wrz", "paź", "lis", "gru"],
+    }),
+];
+
+/// Month names for a locale such as `de_DE.UTF-8`, `pt-BR` or `fr`
+pub fn month_names(locale: &str) -> &'static MonthNames {
+    let language = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    MONTH_NAMES
+        .iter()
+        .find(|(code, _)| *code == language)
+        .map_or(&MONTH_NAMES[0].1, |(_, names)| names)
+}
+
+/// The locale dates are formatted for: `LC_ALL`, then `LC_TIME`, then `LANG`,
+/// as the C library looks them up; empty when none is set
+pub fn time_locale() -> String {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// Seconds local time is ahead of UTC at `unix` (daylight saving included);
+/// 0 where the platform cannot tell
+pub fn utc_offset(unix: i64) -> i64 {
+    sys::utc_offset(unix)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_long};
+
+    /// `struct tm` as glibc, musl, macOS and the BSDs lay it out
+    #[repr(C)]
+    struct Tm {
+        tm_sec: c_int,
+        tm_min: c_int,
+        tm_hour: c_int,
+        tm_mday: c_int,
+        tm_mon: c_int,
+        tm_year: c_int,
+        tm_wday: c_int,
+        tm_yday: c_int,
+        tm_isdst: c_int,
+        tm_gmtoff: c_long,
+        tm_zone: *const c_char,
+    }
+
+    unsafe extern "C" {
+        fn localtime_r(time: *const i64, result: *mut Tm) -> *mut Tm;
+    }
+
+    pub fn utc_offset(unix: i64) -> i64 {
+        // SAFETY: Tm is plain data that localtime_r fills in
+        let mut tm: Tm = unsafe { std::mem::zeroed() };
+        // SAFETY: both pointers are valid for the duration of the call
+        if unsafe { localtime_r(&unix, &mut tm) }.is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff as i64
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use super::DateTime;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct SystemTime {
+        year: u16,
+        month: u16,
+        day_of_week: u16,
+        day: u16,
+        hour: u16,
+        minute: u16,
+        second: u16,
+        milliseconds: u16,
+    }
+
+    unsafe extern "system" {
+        fn GetLocalTime(time: *mut SystemTime);
+        fn GetSystemTime(time: *mut SystemTime);
+    }
+
+    fn to_unix(time: &SystemTime) -> i64 {
+        DateTime {
+            year: i64::from(time.year),
+            month: u32::from(time.month),
+            day: u32::from(time.day),
+            hour: u32::from(time.hour),
+            minute: u32::from(time.minute),
+            second: u32::from(time.second),
+        }
+        .to_unix()
+    }
+
+    /// The offset now: Windows only tells it for the current time, which is
+    /// the one asked about in practice
+    pub fn utc_offset(_unix: i64) -> i64 {
+        let (mut local, mut utc) = (SystemTime::default(), SystemTime::default());
+        // SAFETY: both calls only fill in the struct they are given
+        unsafe {
+            GetLocalTime(&mut local);
+            GetSystemTime(&mut utc);
+        }
+        // Rounded to the minute: the two calls are not at the same instant
+        (to_unix(&local) - to_unix(&utc) + 30).div_euclid(60) * 60
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn utc_offset(_unix: i64) -> i64 {
+        0
+    }
+}
@@ -0,0 +1,474 @@
+use std::io;
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions are tried per match; trades speed for ratio
+const MAX_CHAIN: usize = 64;
+
+/// (base length, extra bits) for length codes 257..=285
+#[rustfmt::skip]
+const LENGTH_CODES: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+/// (base distance, extra bits) for distance codes 0..=29
+#[rustfmt::skip]
+const DIST_CODES: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9), (2049, 10), (3073, 10),
+    (4097, 11), (6145, 11), (8193, 12), (12289, 12), (16385, 13), (24577, 13),
+];
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Writes `n` bits of `value`, least significant first
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most significant bit first
+    fn write_code(&mut self, code: u32, len: u32) {
+        let reversed = code.reverse_bits() >> (32 - len);
+        self.write(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Fixed Huffman code for a literal/length symbol (RFC 1951, 3.2.6)
+fn write_literal_symbol(w: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => w.write_code(0x30 + symbol, 8),
+        144..=255 => w.write_code(0x190 + symbol - 144, 9),
+        256..=279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(w: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_CODES
+        .iter()
+        .rposition(|&(base, _)| base as usize <= length)
+        .unwrap_or(0);
+    let (base, extra) = LENGTH_CODES[code];
+    write_literal_symbol(w, 257 + code as u16);
+    w.write((length - base as usize) as u32, extra as u32);
+
+    let code = DIST_CODES
+        .iter()
+        .rposition(|&(base, _)| base as usize <= distance)
+        .unwrap_or(0);
+    let (base, extra) = DIST_CODES[code];
+    w.write_code(code as u32, 5);
+    w.write((distance - base as usize) as u32, extra as u32);
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+/// Compresses `data` as a raw DEFLATE stream: one final block with fixed
+/// Huffman codes and greedy LZ77 matching
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter {
+        out: Vec::with_capacity(data.len() / 2),
+        bits: 0,
+        count: 0,
+    };
+    w.write(1, 1); // BFINAL
+    w.write(1, 2); // BTYPE = fixed Huffman
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let mut i = 0;
+
+    let insert = |head: &mut [usize], prev: &mut [usize], pos: usize| {
+        if pos + MIN_MATCH <= data.len() {
+            let h = hash3(data, pos);
+            prev[pos % WINDOW] = head[h];
+            head[h] = pos;
+        }
+    };
+
+    while i < data.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash3(data, i)];
+            let max_len = MAX_MATCH.min(data.len() - i);
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            write_match(&mut w, best_len, best_dist);
+            for pos in i..i + best_len {
+                insert(&mut head, &mut prev, pos);
+            }
+            i += best_len;
+        } else {
+            write_literal_symbol(&mut w, data[i] as u16);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+
+    write_literal_symbol(&mut w, 256); // End of block
+    w.finish()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+            value |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Reads a Huffman code one bit at a time, most significant first
+    fn code_bit(&mut self, code: u32) -> io::Result<u32> {
+        Ok(code << 1 | self.bits(1)?)
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated deflate stream")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Decodes one fixed-Huffman literal/length symbol
+fn read_fixed_symbol(r: &mut BitReader) -> io::Result<u16> {
+    let mut code = 0;
+    for _ in 0..7 {
+        code = r.code_bit(code)?;
+    }
+    if code <= 0x17 {
+        return Ok(256 + code as u16);
+    }
+    code = r.code_bit(code)?;
+    if (0x30..=0xbf).contains(&code) {
+        return Ok((code - 0x30) as u16);
+    }
+    if (0xc0..=0xc7).contains(&code) {
+        return Ok((280 + code - 0xc0) as u16);
+    }
+    code = r.code_bit(code)?;
+    if (0x190..=0x1ff).contains(&code) {
+        return Ok((144 + code - 0x190) as u16);
+    }
+    Err(invalid("bad literal/length code"))
+}
+
+/// A canonical Huffman code read from a dynamic block header: how many
+/// codes there are of each length, and the symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code from each symbol's code length (0: unused)
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    /// Decodes one symbol, a bit at a time (RFC 1951, 3.2.2)
+    fn decode(&self, r: &mut BitReader) -> io::Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= r.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+/// Order in which code length code lengths are stored (RFC 1951, 3.2.7)
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads the literal/length and distance codes of a dynamic block
+fn read_dynamic_codes(r: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literals = r.bits(5)? as usize + 257;
+    let distances = r.bits(5)? as usize + 1;
+    let code_lengths = r.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[index] = r.bits(3)? as u8;
+    }
+    let length_code = Huffman::new(&lengths);
+
+    let mut lengths = vec![0u8; literals + distances];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (value, repeat) = match length_code.decode(r)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i]
+                    .last()
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + r.bits(2)? as usize)
+            }
+            17 => (0, 3 + r.bits(3)? as usize),
+            _ => (0, 11 + r.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("no end-of-block code"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Appends `length` bytes starting `distance` back in the output
+fn copy_match(out: &mut Vec<u8>, length: usize, distance: usize) -> io::Result<()> {
+    if distance > out.len() {
+        return Err(invalid("distance too far back"));
+    }
+    let start = out.len() - distance;
+    for k in 0..length {
+        out.push(out[start + k]);
+    }
+    Ok(())
+}
+
+/// Decompresses a raw DEFLATE stream
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    decompress_prefix(data, usize::MAX)
+}
+
+/// Decompresses a raw DEFLATE stream, stopping once at least `limit` bytes
+/// are out (for reading the start of large or hostile streams)
+pub fn decompress_prefix(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut r = BitReader {
+        data,
+        pos: 0,
+        bit: 0,
+    };
+    let mut out: Vec<u8> = Vec::new();
+
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align();
+                let header = data.get(r.pos..r.pos + 4).ok_or_else(truncated)?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                r.pos += 4;
+                let block = data.get(r.pos..r.pos + len).ok_or_else(truncated)?;
+                out.extend_from_slice(block);
+                r.pos += len;
+            }
+            1 => loop {
+                let symbol = read_fixed_symbol(&mut r)?;
+                match symbol {
+                    0..=255 => out.push(symbol as u8),
+                    256 => break,
+                    _ => {
+                        let (base, extra) = *LENGTH_CODES
+                            .get(symbol as usize - 257)
+                            .ok_or_else(|| invalid("bad length code"))?;
+                        let length = base as usize + r.bits(extra as u32)? as usize;
+
+                        let mut code = 0;
+                        for _ in 0..5 {
+                            code = r.code_bit(code)?;
+                        }
+                        let (base, extra) = *DIST_CODES
+                            .get(code as usize)
+                            .ok_or_else(|| invalid("bad distance code"))?;
+                        let distance = base as usize + r.bits(extra as u32)? as usize;
+                        copy_match(&mut out, length, distance)?;
+                    }
+                }
+                if out.len() >= limit {
+                    return Ok(out);
+                }
+            },
+            2 => {
+                let (literal_code, distance_code) = read_dynamic_codes(&mut r)?;
+                loop {
+                    let symbol = literal_code.decode(&mut r)?;
+                    match symbol {
+                        0..=255 => out.push(symbol as u8),
+                        256 => break,
+                        _ => {
+                            let (base, extra) = *LENGTH_CODES
+                                .get(symbol as usize - 257)
+                                .ok_or_else(|| invalid("bad length code"))?;
+                            let length = base as usize + r.bits(extra as u32)? as usize;
+                            let (base, extra) = *DIST_CODES
+                                .get(distance_code.decode(&mut r)? as usize)
+                                .ok_or_else(|| invalid("bad distance code"))?;
+                            let distance = base as usize + r.bits(extra as u32)? as usize;
+                            copy_match(&mut out, length, distance)?;
+                        }
+                    }
+                    if out.len() >= limit {
+                        return Ok(out);
+                    }
+                }
+            }
+            _ => return Err(invalid("unsupported block type")),
+        }
+        if last || out.len() >= limit {
+            return Ok(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes from a small LCG: the same every run, and barely compressible
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = "the quick brown fox jumps over the lazy dog. ".repeat(2000);
+        let mut far = noise(40 * 1024);
+        far.extend_from_within(..1000);
+        for data in [
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabc".to_vec(),
+            vec![0; 100_000],
+            text.into_bytes(),
+            noise(70_000),
+            far,
+        ] {
+            let packed = compress(&data);
+            assert_eq!(decompress(&packed).unwrap(), data, "{} bytes", data.len());
+        }
+        assert!(compress(&[0; 100_000]).len() < 1000);
+    }
+
+    #[test]
+    fn reads_stored_blocks_and_stops_at_the_limit() {
+        // A final stored block of "abc"
+        let stored = [0x01, 3, 0, 0xfc, 0xff, b'a', b'b', b'c'];
+        assert_eq!(decompress(&stored).unwrap(), b"abc");
+
+        let data = vec![7; 1_000_000];
+        let prefix = decompress_prefix(&compress(&data), 1000).unwrap();
+        assert!(prefix.len() >= 1000 && prefix.len() < data.len());
+        assert!(data.starts_with(&prefix));
+    }
+
+    #[test]
+    fn truncated_streams_are_errors() {
+        let packed = compress(&noise(10_000));
+        assert!(decompress(&packed[..packed.len() / 2]).is_err());
+        assert!(decompress(&[0x01, 3, 0, 0xfc, 0xff, b'a']).is_err());
+        assert!(decompress(&[]).is_err());
+    }
+}
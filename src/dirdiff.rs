@@ -0,0 +1,85 @@
+use crate::hash;
+use crate::vfs::FileSystem;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How two folders of the same name differ, entry by entry. Paths are
+/// relative to the folders; a sub-folder on one side only is listed once,
+/// not with its contents.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirDiff {
+    pub only_in_src: Vec<PathBuf>,
+    pub only_in_dest: Vec<PathBuf>,
+    /// Files on both sides with other contents, or a file on one side where
+    /// the other has a folder
+    pub differing: Vec<PathBuf>,
+    /// Files on both sides with the same contents
+    pub same: usize,
+}
+
+impl DirDiff {
+    /// Whether merging `src` into `dest` would only add entries: nothing
+    /// that is in both differs
+    pub fn merges_cleanly(&self) -> bool {
+        self.differing.is_empty()
+    }
+}
+
+/// Compares folder `src` with `dest` recursively: files present on both
+/// sides by size, then by SHA-256 (so like-named files of the same size are
+/// read whole)
+pub fn compare(fs: &dyn FileSystem, src: &Path, dest: &Path) -> io::Result<DirDiff> {
+    let mut diff = DirDiff::default();
+    compare_into(fs, src, dest, Path::new(""), &mut diff)?;
+    Ok(diff)
+}
+
+fn compare_into(
+    fs: &dyn FileSystem,
+    src: &Path,
+    dest: &Path,
+    relative: &Path,
+    diff: &mut DirDiff,
+) -> io::Result<()> {
+    let listing = |dir: &Path| -> io::Result<BTreeMap<OsString, bool>> {
+        Ok(fs
+            .read_dir(dir)?
+            .into_iter()
+            .map(|entry| (entry.name, entry.is_dir))
+            .collect())
+    };
+    let ours = listing(src)?;
+    let theirs = listing(dest)?;
+
+    for (name, &is_dir) in &ours {
+        let path = relative.join(name);
+        match theirs.get(name) {
+            None => diff.only_in_src.push(path),
+            Some(&other_is_dir) if other_is_dir != is_dir => diff.differing.push(path),
+            Some(_) if is_dir => compare_into(fs, &src.join(name), &dest.join(name), &path, diff)?,
+            Some(_) => {
+                if same_file(fs, &src.join(name), &dest.join(name))? {
+                    diff.same += 1;
+                } else {
+                    diff.differing.push(path);
+                }
+            }
+        }
+    }
+    diff.only_in_dest.extend(
+        theirs
+            .keys()
+            .filter(|name| !ours.contains_key(*name))
+            .map(|name| relative.join(name)),
+    );
+    Ok(())
+}
+
+fn same_file(fs: &dyn FileSystem, a: &Path, b: &Path) -> io::Result<bool> {
+    if fs.metadata(a)?.len != fs.metadata(b)?.len {
+        return Ok(false);
+    }
+    Ok(hash::hash_file(a)? == hash::hash_file(b)?)
+}
@@ -0,0 +1,265 @@
+use crate::config::DocumentsConfig;
+use crate::deflate;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Keywords of the built-in `finance` subfolder, used when none are configured
+const FINANCE_KEYWORDS: &[&str] = &[
+    "invoice",
+    "receipt",
+    "bank statement",
+    "account statement",
+    "statement of account",
+    "amount due",
+    "total due",
+    "balance due",
+    "payment received",
+    "billing period",
+    "rechnung",
+    "quittung",
+    "kontoauszug",
+    "facture",
+    "factura",
+    "recibo",
+];
+
+/// Text files are read up to this many bytes per page of `max_pages`
+const TEXT_PAGE_BYTES: u64 = 4 * 1024;
+
+/// Upper bound on how much of a PDF is read looking for its first pages
+const PDF_READ_LIMIT: u64 = 4 * 1024 * 1024;
+
+/// Upper bound on the decompressed size of one PDF stream
+const STREAM_LIMIT: usize = 256 * 1024;
+
+/// Picks the subfolder of `documents` for a PDF or plain-text file whose
+/// first pages contain one of a subfolder's keywords (case and spacing are
+/// ignored), or `None` to leave it in `documents`. Subfolders are tried in
+/// name order. Other formats (docx, odt) are zipped XML and not inspected.
+pub fn subfolder(path: &Path, config: &DocumentsConfig) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    let text = match ext.as_str() {
+        "pdf" => pdf_text(path, config.max_pages)?,
+        "txt" | "md" => {
+            let mut bytes = Vec::new();
+            File::open(path)
+                .ok()?
+                .take(TEXT_PAGE_BYTES * config.max_pages.max(1))
+                .read_to_end(&mut bytes)
+                .ok()?;
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        _ => return None,
+    };
+    let text = compact(&text);
+    if text.is_empty() {
+        return None;
+    }
+
+    if config.subfolders.is_empty() {
+        return mentions(&text, FINANCE_KEYWORDS.iter().copied()).then(|| "finance".to_string());
+    }
+    config
+        .subfolders
+        .iter()
+        .find(|(_, keywords)| mentions(&text, keywords.iter().map(String::as_str)))
+        .map(|(name, _)| name.clone())
+}
+
+/// Whether compacted `text` contains any of `keywords`
+fn mentions<'a>(text: &str, keywords: impl IntoIterator<Item = &'a str>) -> bool {
+    keywords.into_iter().any(|k| {
+        let k = compact(k);
+        !k.is_empty() && text.contains(&k)
+    })
+}
+
+/// Lowercased, without whitespace: PDFs often place words (or letters)
+/// separately, so spacing in the extracted text is unreliable
+fn compact(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Text shown by the first `pages` content streams of a PDF (roughly its
+/// first pages). Scanned PDFs only have text if they were OCRed.
+fn pdf_text(path: &Path, pages: u64) -> Option<String> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(PDF_READ_LIMIT)
+        .read_to_end(&mut data)
+        .ok()?;
+    if !data.starts_with(b"%PDF") {
+        return None;
+    }
+
+    let mut text = String::new();
+    let mut found = 0;
+    let mut pos = 0;
+    while found < pages.max(1)
+        && let Some((dict, body, end)) = next_stream(&data, pos)
+    {
+        pos = end;
+        let content = if contains(dict, b"/FlateDecode") {
+            // zlib: a two-byte header before the DEFLATE data
+            match body
+                .get(2..)
+                .map(|raw| deflate::decompress_prefix(raw, STREAM_LIMIT))
+            {
+                Some(Ok(content)) => content,
+                _ => continue,
+            }
+        } else if contains(dict, b"/Filter") {
+            continue; // Images and other encodings
+        } else {
+            body.to_vec()
+        };
+        // Page contents draw text between BT and ET; fonts, images and
+        // metadata streams do not
+        if contains(&content, b"BT") && contains(&content, b"ET") {
+            found += 1;
+            show_text(&content, &mut text);
+        }
+    }
+    Some(text)
+}
+
+/// The next `<< dict >> stream ... endstream` at or after `from`:
+/// (dictionary, stream bytes, position after it)
+fn next_stream(data: &[u8], from: usize) -> Option<(&[u8], &[u8], usize)> {
+    let keyword = from + find(&data[from..], b"stream")?;
+    let dict_start = data[..keyword]
+        .windows(2)
+        .rposition(|w| w == b"<<")
+        .unwrap_or(keyword);
+    let dict = &data[dict_start..keyword];
+
+    let mut start = keyword + b"stream".len();
+    if data.get(start) == Some(&b'\r') {
+        start += 1;
+    }
+    if data.get(start) == Some(&b'\n') {
+        start += 1;
+    }
+    let length = find(&data[start..], b"endstream")?;
+    let body = &data[start..start + length];
+    Some((dict, body, start + length + b"endstream".len()))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+/// Appends the strings of a content stream's text operators to `text`:
+/// `(literal)` and `<hex>` strings
+fn show_text(content: &[u8], text: &mut String) {
+    let mut i = 0;
+    while i < content.len() {
+        match content[i] {
+            b'(' => {
+                let (string, end) = literal_string(content, i + 1);
+                text.extend(string.iter().map(|&b| b as char));
+                i = end;
+                continue;
+            }
+            b'<' if content.get(i + 1) != Some(&b'<') => {
+                let end = content[i..]
+                    .iter()
+                    .position(|&b| b == b'>')
+                    .map_or(content.len(), |n| i + n);
+                text.push_str(&hex_string(&content[i + 1..end]));
+                i = end + 1;
+                continue;
+            }
+            b'<' => {
+                i += 2; // A dictionary, e.g. marked-content properties
+                continue;
+            }
+            // Line and position operators end a word
+            b'\n' | b'\r' => text.push(' '),
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Reads a `(...)` string starting after its `(`; returns the bytes and the
+/// position after the closing `)`
+fn literal_string(content: &[u8], mut i: usize) -> (Vec<u8>, usize) {
+    let mut bytes = Vec::new();
+    let mut depth = 0;
+    while i < content.len() {
+        let b = content[i];
+        i += 1;
+        match b {
+            b'\\' => {
+                let Some(&next) = content.get(i) else {
+                    break;
+                };
+                i += 1;
+                match next {
+                    b'n' => bytes.push(b'\n'),
+                    b'r' => bytes.push(b'\r'),
+                    b't' => bytes.push(b'\t'),
+                    b'0'..=b'7' => {
+                        let mut value = (next - b'0') as u32;
+                        for _ in 0..2 {
+                            match content.get(i) {
+                                Some(&d @ b'0'..=b'7') => {
+                                    value = value * 8 + (d - b'0') as u32;
+                                    i += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        bytes.push(value as u8);
+                    }
+                    b'\r' | b'\n' => {} // Line continuation
+                    other => bytes.push(other),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                bytes.push(b);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                bytes.push(b);
+            }
+            _ => bytes.push(b),
+        }
+    }
+    (bytes, i)
+}
+
+/// Decodes a `<...>` string. OCR tools write two-byte codes equal to UTF-16
+/// (Tesseract's glyphless font); other fonts' codes cannot be mapped here
+/// and come out as noise, which keywords will not match.
+fn hex_string(hex: &[u8]) -> String {
+    let digits: Vec<u8> = hex
+        .iter()
+        .filter_map(|&c| (c as char).to_digit(16).map(|d| d as u8))
+        .collect();
+    let bytes: Vec<u8> = digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair.get(1).copied().unwrap_or(0))
+        .collect();
+    if bytes.len().is_multiple_of(2) && bytes.chunks(2).all(|pair| pair[0] < 0x05) {
+        let units: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
@@ -0,0 +1,245 @@
+use crate::hash;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files browsers and torrent clients write a download
+/// into until it completes: `movie.mkv.crdownload` becomes `movie.mkv`
+pub const BUILTIN_TEMP_EXTENSIONS: &[&str] = &[
+    "crdownload", // Chrome, Edge, Brave
+    "part",       // Firefox (beside an empty placeholder of the final name), Transmission
+    "partial",    // older Edge and Internet Explorer
+    "download",   // Safari (a folder holding the partial file)
+    "opdownload", // Opera
+    "!qb",        // qBittorrent
+    "!ut",        // uTorrent
+    "bc!",        // BitComet
+    "aria2",      // aria2 control file, beside the file being written
+];
+
+/// Entries looked at in a folder for a download in progress: enough for a
+/// torrent's folder, bounded for a large tree
+const FOLDER_SAMPLE: usize = 1000;
+
+/// Tells downloads still in progress apart, so they wait for a later run.
+/// Folder listings are read once per pass.
+pub struct Downloads {
+    /// Lowercased, without the dot
+    extensions: HashSet<String>,
+    /// Lowercased names in each folder looked at
+    listings: HashMap<PathBuf, HashSet<String>>,
+}
+
+impl Downloads {
+    pub fn new(extensions: &[String]) -> Self {
+        Downloads {
+            extensions: extensions.iter().map(|ext| ext.to_lowercase()).collect(),
+            listings: HashMap::new(),
+        }
+    }
+
+    /// Whether `name` is a file a download is being written into
+    pub fn is_temp(&self, name: &str) -> bool {
+        name.rsplit_once('.').is_some_and(|(stem, ext)| {
+            !stem.is_empty() && self.extensions.contains(&ext.to_lowercase())
+        })
+    }
+
+    /// Why `path` should wait, if it should: it is a download's temporary
+    /// file, a file one is being written into (`movie.mkv` beside
+    /// `movie.mkv.part`), or a folder with one inside
+    pub fn in_progress(&mut self, path: &Path, is_dir: bool) -> Option<String> {
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        if self.is_temp(&name) {
+            return Some("download in progress".to_string());
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let listing = self
+            .listings
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| list(dir));
+        let lower = name.to_lowercase();
+        if let Some(ext) = self
+            .extensions
+            .iter()
+            .find(|ext| listing.contains(&format!("{}.{}", lower, ext)))
+        {
+            return Some(format!(
+                "its download is not done: {}.{} is still there",
+                name, ext
+            ));
+        }
+        if is_dir && let Some(temp) = self.temp_inside(path) {
+            return Some(format!("a download inside is not done: {}", temp));
+        }
+        None
+    }
+
+    /// The first temporary download file found under `dir`
+    fn temp_inside(&self, dir: &Path) -> Option<String> {
+        let mut stack = vec![dir.to_path_buf()];
+        let mut seen = 0;
+        while let Some(dir) = stack.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                seen += 1;
+                if seen > FOLDER_SAMPLE {
+                    return None;
+                }
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if self.is_temp(&name) {
+                    return Some(name);
+                }
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    stack.push(entry.path());
+                }
+            }
+        }
+        None
+    }
+}
+
+fn list(dir: &Path) -> HashSet<String> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.file_name().to_string_lossy().to_lowercase())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Which of a download saved more than once (`report.pdf`, `report (1).pdf`...)
+/// `numbered_duplicates` keeps, when the copies have the same contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepCopy {
+    /// The one with the original name (else the lowest number)
+    Original,
+    /// The one modified last, under the original name
+    Newest,
+}
+
+/// The name a browser numbered because it was taken: `report (1).pdf` (or
+/// `report(1).pdf`) is copy 1 of `report.pdf`, `archive (2).tar.gz` copy 2
+/// of `archive.tar.gz`
+pub fn numbered_copy(name: &str) -> Option<(String, u64)> {
+    let open = name.rfind('(')?;
+    let (digits, rest) = name[open + 1..].split_once(')')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if !rest.is_empty() && !rest.starts_with('.') {
+        return None;
+    }
+    let stem = name[..open].strip_suffix(' ').unwrap_or(&name[..open]);
+    if stem.is_empty() || stem.ends_with(' ') {
+        return None;
+    }
+    Some((format!("{}{}", stem, rest), digits.parse().ok()?))
+}
+
+/// Collapses numbered copies of downloads among `files` that have the same
+/// contents as another copy of the name in their folder (by size, then
+/// SHA-256), removing all but the one `keep` says. Returns the copies
+/// removed, each with the one kept instead, and the names kept copies take
+/// (with `KeepCopy::Newest`, the original name among their duplicates).
+#[allow(clippy::type_complexity)]
+pub fn collapse<T>(
+    files: &mut Vec<(PathBuf, T)>,
+    keep: KeepCopy,
+) -> (Vec<(PathBuf, PathBuf)>, HashMap<PathBuf, String>) {
+    // Copies by folder and original name, with their numbers (0: the original)
+    let mut groups: HashMap<(PathBuf, String), Vec<(usize, u64)>> = HashMap::new();
+    for (i, (path, _)) in files.iter().enumerate() {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if let Some((original, number)) = numbered_copy(name) {
+            let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+            groups.entry((dir, original)).or_default().push((i, number));
+        }
+    }
+    for (i, (path, _)) in files.iter().enumerate() {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if let Some(copies) = groups.get_mut(&(dir, name.to_string())) {
+            copies.push((i, 0));
+        }
+    }
+
+    let mut dropped = Vec::new();
+    let mut names = HashMap::new();
+    let mut removed = HashSet::new();
+    let mut groups: Vec<_> = groups.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+    for copies in groups {
+        // Copies that can't be read are left as they are
+        let mut by_contents: HashMap<(u64, String), Vec<(usize, u64)>> = HashMap::new();
+        let mut sizes: HashMap<u64, usize> = HashMap::new();
+        let lens: Vec<Option<u64>> = copies
+            .iter()
+            .map(|&(i, _)| fs::metadata(&files[i].0).ok().map(|m| m.len()))
+            .collect();
+        for len in lens.iter().flatten() {
+            *sizes.entry(*len).or_default() += 1;
+        }
+        for (&(i, number), len) in copies.iter().zip(&lens) {
+            let Some(len) = *len else { continue };
+            if sizes[&len] < 2 {
+                continue;
+            }
+            if let Ok(hash) = hash::hash_file(&files[i].0) {
+                by_contents
+                    .entry((len, hash))
+                    .or_default()
+                    .push((i, number));
+            }
+        }
+        let mut same: Vec<_> = by_contents.into_values().filter(|s| s.len() > 1).collect();
+        same.sort();
+        for mut same in same {
+            same.sort_by_key(|&(_, number)| number);
+            let original = same[0].0;
+            let kept = match keep {
+                KeepCopy::Original => original,
+                // Of copies modified at the same time, the lowest number
+                KeepCopy::Newest => same
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|&(position, &(i, _))| {
+                        let modified = fs::metadata(&files[i].0).and_then(|m| m.modified());
+                        (modified.ok(), std::cmp::Reverse(position))
+                    })
+                    .map_or(original, |(_, &(i, _))| i),
+            };
+            if kept != original
+                && let Some(name) = files[original].0.file_name()
+            {
+                names.insert(files[kept].0.clone(), name.to_string_lossy().into_owned());
+            }
+            for &(i, _) in &same {
+                if i != kept {
+                    dropped.push((files[i].0.clone(), files[kept].0.clone()));
+                    removed.insert(i);
+                }
+            }
+        }
+    }
+
+    let mut i = 0;
+    files.retain(|_| {
+        i += 1;
+        !removed.contains(&(i - 1))
+    });
+    (dropped, names)
+}
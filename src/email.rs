@@ -0,0 +1,385 @@
+use crate::config::EmailConfig;
+use crate::date::DateTime;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Headers end at the first blank line; past this much, the file is not a
+/// message (or its headers are not worth reading)
+const HEADER_LIMIT: u64 = 64 * 1024;
+
+/// The subfolder of `email` for a message: `2024/sender@example.com`, or
+/// `2024` alone without `by_sender` or a sender. `.eml` and `.emlx` are read
+/// as RFC 5322 headers, `.msg` as Outlook's compound file; `None` leaves the
+/// file in `email` (an mbox, or no date to go by).
+pub fn subfolder(path: &Path, config: &EmailConfig) -> Option<String> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    let (year, sender) = match ext.as_str() {
+        "eml" => from_headers(&read_headers(path, false).ok()?),
+        "emlx" => from_headers(&read_headers(path, true).ok()?),
+        "msg" => msg_fields(path).ok()?,
+        _ => return None,
+    };
+    let year = year?;
+    match sender.filter(|_| config.by_sender) {
+        Some(sender) => Some(format!("{}/{}", year, sender)),
+        None => Some(year.to_string()),
+    }
+}
+
+/// The header block of a message file. Apple Mail's `.emlx` starts with a
+/// line giving the length of the message that follows.
+fn read_headers(path: &Path, emlx: bool) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    File::open(path)?
+        .take(HEADER_LIMIT)
+        .read_to_end(&mut bytes)?;
+    let mut text = String::from_utf8_lossy(&bytes).into_owned();
+    if emlx && let Some(end) = text.find('\n') {
+        text.drain(..=end);
+    }
+    let end = text
+        .find("\r\n\r\n")
+        .or_else(|| text.find("\n\n"))
+        .unwrap_or(text.len());
+    text.truncate(end);
+    Ok(text)
+}
+
+/// The year of `Date:` and the address of `From:` (else `Sender:`), from a
+/// header block
+fn from_headers(headers: &str) -> (Option<i64>, Option<String>) {
+    let (mut date, mut from, mut sender) = (None, None, None);
+    for (name, value) in unfold(headers) {
+        match name.to_ascii_lowercase().as_str() {
+            "date" if date.is_none() => date = header_year(&value),
+            "from" if from.is_none() => from = address(&value),
+            "sender" if sender.is_none() => sender = address(&value),
+            _ => {}
+        }
+    }
+    (date, from.or(sender))
+}
+
+/// Header fields as (name, value), with folded lines joined
+fn unfold(headers: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in headers.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    fields
+}
+
+/// The year of an RFC 5322 date (`Tue, 1 Jul 2003 10:52:37 +0200`), as
+/// written: the first number after the month name. Two-digit years are the
+/// obsolete form (`03` is 2003, `97` is 1997).
+fn header_year(value: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let mut tokens = value.split([' ', ',', '\t']).filter(|t| !t.is_empty());
+    tokens.find(|t| MONTHS.contains(&t.to_ascii_lowercase().get(..3).unwrap_or_default()))?;
+    let token = tokens.next()?;
+    if !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = token.parse().ok()?;
+    match token.len() {
+        2 if year < 50 => Some(2000 + year),
+        2 | 3 => Some(1900 + year),
+        4 => Some(year),
+        _ => None,
+    }
+}
+
+/// The address of a mailbox (`Name <a@example.com>` or `a@example.com`),
+/// lowercased; `None` if it does not look like one or would not do as a
+/// folder name
+fn address(value: &str) -> Option<String> {
+    let candidate = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value
+            .split_whitespace()
+            .find(|t| t.contains('@'))
+            .unwrap_or(value),
+    };
+    let candidate = candidate
+        .trim()
+        .trim_matches(['"', '\'', '(', ')'])
+        .to_lowercase();
+    let (local, domain) = candidate.split_once('@')?;
+    let usable = !local.is_empty()
+        && !domain.is_empty()
+        && candidate.len() <= 254
+        && !candidate.starts_with('.')
+        && !candidate
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '/' | '\\' | '<' | '>'));
+    usable.then_some(candidate)
+}
+
+/// Outlook property streams of the message (not of its attachments)
+const TRANSPORT_HEADERS: u16 = 0x007d;
+const SENDER_SMTP_ADDRESS: u16 = 0x5d01;
+const SENDER_EMAIL_ADDRESS: u16 = 0x0c1f;
+const SENT_REPRESENTING_EMAIL: u16 = 0x0065;
+const CLIENT_SUBMIT_TIME: u16 = 0x0039;
+const MESSAGE_DELIVERY_TIME: u16 = 0x0e06;
+
+/// The year and sender of an Outlook `.msg`: from the internet headers it
+/// kept, if any, else from its own properties
+fn msg_fields(path: &Path) -> io::Result<(Option<i64>, Option<String>)> {
+    let mut file = Compound::open(path)?;
+    let (mut year, mut sender) = match file.property_text(TRANSPORT_HEADERS)? {
+        Some(headers) => from_headers(&headers),
+        None => (None, None),
+    };
+    for id in [
+        SENDER_SMTP_ADDRESS,
+        SENDER_EMAIL_ADDRESS,
+        SENT_REPRESENTING_EMAIL,
+    ] {
+        if sender.is_some() {
+            break;
+        }
+        // Exchange senders have an X.500 name here, not an address
+        sender = file.property_text(id)?.and_then(|text| address(&text));
+    }
+    if year.is_none() {
+        year = file
+            .property_time(CLIENT_SUBMIT_TIME)?
+            .or(file.property_time(MESSAGE_DELIVERY_TIME)?)
+            .map(|time| time.year);
+    }
+    Ok((year, sender))
+}
+
+const SIGNATURE: [u8; 8] = [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+const END_OF_CHAIN: u32 = 0xffff_fffe;
+/// Sector numbers at or above this are markers, not sectors
+const MAX_SECTOR: u32 = 0xffff_fffa;
+const NO_STREAM: u32 = 0xffff_ffff;
+const DIR_ENTRY: usize = 128;
+/// Upper bound on a property stream read from a `.msg`
+const STREAM_LIMIT: u64 = 1024 * 1024;
+/// Upper bound on the sectors of the allocation tables followed
+const TABLE_LIMIT: usize = 64 * 1024;
+
+/// The top-level streams of a Compound File Binary (OLE2) file, the
+/// container of Outlook's `.msg`
+struct Compound {
+    file: File,
+    sector_size: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    mini_cutoff: u64,
+    /// Where the small streams are kept: the root entry's stream, read the
+    /// first time one is
+    mini_stream: (u32, u64),
+    mini_bytes: Option<Vec<u8>>,
+    /// Name, start sector and size of each stream directly in the root
+    streams: Vec<(String, u32, u64)>,
+}
+
+impl Compound {
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; 512];
+        file.read_exact(&mut header)?;
+        if header[..8] != SIGNATURE {
+            return Err(invalid("not a compound file"));
+        }
+        let shift = u16_at(&header, 0x1e);
+        if shift != 9 && shift != 12 {
+            return Err(invalid("unknown sector size"));
+        }
+        let sector_size = 1u64 << shift;
+
+        // The sectors of the FAT: 109 in the header, the rest in a chain
+        let fat_sectors = u32_at(&header, 0x2c) as usize;
+        if fat_sectors > TABLE_LIMIT {
+            return Err(invalid("allocation table too large"));
+        }
+        let mut difat: Vec<u32> = (0..109).map(|i| u32_at(&header, 0x4c + i * 4)).collect();
+        let mut next = u32_at(&header, 0x44);
+        let per_sector = (sector_size / 4) as usize - 1;
+        while next < MAX_SECTOR && difat.len() < fat_sectors {
+            let bytes = read_sector(&mut file, sector_size, next)?;
+            difat.extend((0..per_sector).map(|i| u32_at(&bytes, i * 4)));
+            next = u32_at(&bytes, per_sector * 4);
+        }
+        difat.truncate(fat_sectors);
+        let mut fat = Vec::new();
+        for sector in difat {
+            let bytes = read_sector(&mut file, sector_size, sector)?;
+            fat.extend(bytes.chunks_exact(4).map(|c| u32_at(c, 0)));
+        }
+
+        let mut compound = Compound {
+            file,
+            sector_size,
+            fat,
+            mini_fat: Vec::new(),
+            mini_cutoff: u64::from(u32_at(&header, 0x38)),
+            mini_stream: (END_OF_CHAIN, 0),
+            mini_bytes: None,
+            streams: Vec::new(),
+        };
+        let mini_fat = compound.read_chain(u32_at(&header, 0x3c), None)?;
+        compound.mini_fat = mini_fat.chunks_exact(4).map(|c| u32_at(c, 0)).collect();
+
+        let directory = compound.read_chain(u32_at(&header, 0x30), None)?;
+        let entry = |index: u32| -> Option<&[u8]> {
+            let start = (index as usize).checked_mul(DIR_ENTRY)?;
+            directory.get(start..start + DIR_ENTRY)
+        };
+        let root = entry(0).ok_or_else(|| invalid("no root entry"))?;
+        compound.mini_stream = (u32_at(root, 116), u32_at(root, 120).into());
+
+        // The root's children form a tree through their left and right siblings
+        let mut pending = vec![u32_at(root, 76)];
+        let mut seen = 0;
+        while let Some(index) = pending.pop() {
+            seen += 1;
+            let Some(child) = entry(index).filter(|_| index != NO_STREAM && seen <= 4096) else {
+                continue;
+            };
+            pending.extend([u32_at(child, 68), u32_at(child, 72)]);
+            if child[66] == 2 {
+                let len = (usize::from(u16_at(child, 64)) / 2)
+                    .saturating_sub(1)
+                    .min(31);
+                let name: Vec<u16> = (0..len).map(|i| u16_at(child, i * 2)).collect();
+                compound.streams.push((
+                    String::from_utf16_lossy(&name),
+                    u32_at(child, 116),
+                    u32_at(child, 120).into(),
+                ));
+            }
+        }
+        Ok(compound)
+    }
+
+    /// The top-level stream called `name`, if there is one
+    fn stream(&mut self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let Some(&(_, start, size)) = self.streams.iter().find(|(n, ..)| n == name) else {
+            return Ok(None);
+        };
+        if size > STREAM_LIMIT {
+            return Ok(None);
+        }
+        if size >= self.mini_cutoff {
+            return self.read_chain(start, Some(size)).map(Some);
+        }
+        // Small streams are chains of 64-byte sectors in the mini stream
+        if self.mini_bytes.is_none() {
+            let (start, size) = self.mini_stream;
+            self.mini_bytes = Some(self.read_chain(start, Some(size))?);
+        }
+        let mini_stream = self.mini_bytes.as_deref().unwrap_or_default();
+        let mut bytes = Vec::new();
+        let mut sector = start;
+        while sector < MAX_SECTOR && (bytes.len() as u64) < size {
+            let start = sector as usize * 64;
+            let chunk = mini_stream
+                .get(start..start + 64)
+                .ok_or_else(|| invalid("stream outside the mini stream"))?;
+            bytes.extend_from_slice(chunk);
+            sector = *self
+                .mini_fat
+                .get(sector as usize)
+                .ok_or_else(|| invalid("broken mini sector chain"))?;
+            if bytes.len() > STREAM_LIMIT as usize {
+                return Err(invalid("mini sector chain loops"));
+            }
+        }
+        bytes.truncate(size as usize);
+        Ok(Some(bytes))
+    }
+
+    /// The sectors of the chain starting at `start`, cut to `size`
+    fn read_chain(&mut self, start: u32, size: Option<u64>) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut sector = start;
+        let mut count = 0;
+        while sector < MAX_SECTOR && size.is_none_or(|size| (bytes.len() as u64) < size) {
+            count += 1;
+            if count > TABLE_LIMIT.max(self.fat.len()) {
+                return Err(invalid("sector chain loops"));
+            }
+            bytes.extend(read_sector(&mut self.file, self.sector_size, sector)?);
+            sector = *self
+                .fat
+                .get(sector as usize)
+                .ok_or_else(|| invalid("broken sector chain"))?;
+        }
+        if let Some(size) = size {
+            bytes.truncate(size as usize);
+        }
+        Ok(bytes)
+    }
+
+    /// A string property: Unicode (`001F`) or in the message's code page
+    /// (`001E`, read as Latin-1 unless it is UTF-8)
+    fn property_text(&mut self, id: u16) -> io::Result<Option<String>> {
+        if let Some(bytes) = self.stream(&format!("__substg1.0_{:04X}001F", id))? {
+            let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16_at(c, 0)).collect();
+            let text = String::from_utf16_lossy(&units);
+            return Ok(Some(text.trim_end_matches('\0').to_string()));
+        }
+        Ok(self
+            .stream(&format!("__substg1.0_{:04X}001E", id))?
+            .map(|bytes| match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(e) => e.into_bytes().iter().map(|&b| char::from(b)).collect(),
+            })
+            .map(|text| text.trim_end_matches('\0').to_string()))
+    }
+
+    /// A time property (`0040`), kept in the property stream as a FILETIME
+    fn property_time(&mut self, id: u16) -> io::Result<Option<DateTime>> {
+        let Some(bytes) = self.stream("__properties_version1.0")? else {
+            return Ok(None);
+        };
+        // A top-level message's property stream starts with a 32-byte header
+        let time = bytes
+            .get(32..)
+            .unwrap_or_default()
+            .chunks_exact(16)
+            .find_map(|p| {
+                (u16_at(p, 0) == 0x0040 && u16_at(p, 2) == id).then(|| {
+                    let ticks = u64::from(u32_at(p, 8)) | (u64::from(u32_at(p, 12)) << 32);
+                    // 100 ns ticks since 1601-01-01
+                    DateTime::from_unix((ticks / 10_000_000) as i64 - 11_644_473_600)
+                })
+            });
+        Ok(time.filter(|time| time.year > 1970))
+    }
+}
+
+fn read_sector(file: &mut File, sector_size: u64, sector: u32) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; sector_size as usize];
+    file.seek(SeekFrom::Start((u64::from(sector) + 1) * sector_size))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
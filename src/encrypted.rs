@@ -0,0 +1,528 @@
+use crate::lzma;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The subfolder of `archives` encrypted archives go to with `[archives]
+/// encrypted = "subfolder"`
+pub const SUBFOLDER: &str = "encrypted";
+
+/// Largest ZIP central directory or 7z header read
+const MAX_HEADER: u64 = 16 * 1024 * 1024;
+
+/// How far into a RAR the first file header is looked for
+const RAR_SCAN: u64 = 64 * 1024;
+
+const SEVEN_ZIP_MAGIC: &[u8] = b"7z\xBC\xAF\x27\x1C";
+const RAR4_MAGIC: &[u8] = b"Rar!\x1A\x07\x00";
+const RAR5_MAGIC: &[u8] = b"Rar!\x1A\x07\x01\x00";
+
+/// 7z coder IDs
+const AES: &[u8] = &[0x06, 0xF1, 0x07, 0x01];
+const LZMA: &[u8] = &[0x03, 0x01, 0x01];
+
+/// Where encrypted archives go (`[archives] encrypted`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Routing {
+    /// In `archives` with the rest
+    #[default]
+    Keep,
+    /// `archives/encrypted`
+    Subfolder,
+    /// The Quarantine folder
+    Quarantine,
+}
+
+/// Whether `path` is an archive that needs a password: a ZIP with an
+/// encrypted member, a 7z with an AES coder (for its contents or its file
+/// list) or a RAR with encrypted headers or files. Told by the contents, not
+/// the name; `Ok(false)` for anything else.
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 8];
+    let n = read_up_to(&mut file, &mut magic)?;
+    let magic = &magic[..n];
+    if magic.starts_with(b"PK") {
+        zip_encrypted(&mut file)
+    } else if magic.starts_with(SEVEN_ZIP_MAGIC) {
+        // A header that does not parse is not evidence of a password
+        Ok(seven_zip_encrypted(&mut file).unwrap_or(false))
+    } else if magic.starts_with(RAR5_MAGIC) {
+        Ok(rar5_encrypted(&head(&mut file, RAR_SCAN)?))
+    } else if magic.starts_with(RAR4_MAGIC) {
+        Ok(rar4_encrypted(&head(&mut file, RAR_SCAN)?))
+    } else {
+        Ok(false)
+    }
+}
+
+/// Any central directory entry with the "encrypted" flag (traditional
+/// PKWARE and AES encryption both set it)
+fn zip_encrypted(file: &mut File) -> io::Result<bool> {
+    let len = file.seek(SeekFrom::End(0))?;
+    // The end record: 22 bytes and a comment of up to 64 KiB
+    let tail_len = len.min(22 + 0xFFFF);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let Some(end) = tail.windows(4).rposition(|w| w == b"PK\x05\x06") else {
+        return Ok(false);
+    };
+    let Some(record) = tail.get(end..end + 22) else {
+        return Ok(false);
+    };
+    let mut size = u64::from(u32::from_le_bytes([
+        record[12], record[13], record[14], record[15],
+    ]));
+    let mut end_pos = len - tail_len + end as u64;
+    // ZIP64: a locator before the end record points at the ZIP64 end
+    // record, which comes right after the central directory
+    if end >= 20 && tail[end - 20..end - 16] == *b"PK\x06\x07" {
+        let locator = &tail[end - 20..end];
+        let at = u64::from_le_bytes(locator[8..16].try_into().unwrap_or_default());
+        let mut record = [0u8; 56];
+        file.seek(SeekFrom::Start(at))?;
+        if read_up_to(file, &mut record)? == 56 && record[..4] == *b"PK\x06\x06" {
+            size = u64::from_le_bytes(record[40..48].try_into().unwrap_or_default());
+            end_pos = at;
+        }
+    }
+    // Counted back from the end, so data before the archive (a
+    // self-extractor's stub) does not matter
+    if size == 0xFFFF_FFFF || size > end_pos {
+        // ZIP64: the first local header carries the same flag
+        file.seek(SeekFrom::Start(0))?;
+        let mut local = [0u8; 8];
+        let n = read_up_to(file, &mut local)?;
+        return Ok(n == 8 && &local[..4] == b"PK\x03\x04" && local[6] & 1 == 1);
+    }
+    file.seek(SeekFrom::Start(end_pos - size))?;
+    let mut directory = vec![0u8; size.min(MAX_HEADER) as usize];
+    file.read_exact(&mut directory)?;
+
+    let mut pos = 0;
+    while let Some(entry) = directory.get(pos..pos + 46) {
+        if &entry[..4] != b"PK\x01\x02" {
+            break;
+        }
+        if entry[8] & 1 == 1 {
+            return Ok(true);
+        }
+        let name = u16::from_le_bytes([entry[28], entry[29]]) as usize;
+        let extra = u16::from_le_bytes([entry[30], entry[31]]) as usize;
+        let comment = u16::from_le_bytes([entry[32], entry[33]]) as usize;
+        pos += 46 + name + extra + comment;
+    }
+    Ok(false)
+}
+
+/// Whether the 7z header names an AES coder: for the header itself
+/// (`-mhe`), or for the contents, in a header that may first need unpacking
+fn seven_zip_encrypted(file: &mut File) -> io::Result<bool> {
+    let mut start = [0u8; 32];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut start)?;
+    let offset = u64::from_le_bytes(start[12..20].try_into().unwrap_or_default());
+    let size = u64::from_le_bytes(start[20..28].try_into().unwrap_or_default());
+    if size == 0 || size > MAX_HEADER {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::Start(32u64.saturating_add(offset)))?;
+    let mut header = vec![0u8; size as usize];
+    file.read_exact(&mut header)?;
+
+    let mut r = Reader {
+        data: &header,
+        pos: 0,
+    };
+    match r.byte()? {
+        0x01 => header_encrypted(&mut r),
+        0x17 => {
+            let streams = streams_info(&mut r)?;
+            if streams.uses(AES) {
+                return Ok(true);
+            }
+            // The usual case: the real header, packed with LZMA
+            let [folder] = &streams.folders[..] else {
+                return Ok(false);
+            };
+            let ([coder], [unpacked], [packed]) = (
+                &folder.coders[..],
+                &folder.unpack_sizes[..],
+                &streams.pack_sizes[..],
+            ) else {
+                return Ok(false);
+            };
+            if coder.id != LZMA || *packed > MAX_HEADER || *unpacked > MAX_HEADER {
+                return Ok(false);
+            }
+            file.seek(SeekFrom::Start(32u64.saturating_add(streams.pack_pos)))?;
+            let mut data = vec![0u8; *packed as usize];
+            file.read_exact(&mut data)?;
+            let header = lzma::decompress(&coder.props, &data, *unpacked as usize)?;
+            let mut r = Reader {
+                data: &header,
+                pos: 0,
+            };
+            match r.byte()? {
+                0x01 => header_encrypted(&mut r),
+                _ => Ok(false),
+            }
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Reads a plain 7z header (after its 0x01) up to the coders of the contents
+fn header_encrypted(r: &mut Reader) -> io::Result<bool> {
+    loop {
+        match r.byte()? {
+            // Archive properties: (type, size, data) until a 0
+            0x02 => loop {
+                if r.byte()? == 0 {
+                    break;
+                }
+                let size = r.number()?;
+                r.skip(size)?;
+            },
+            // Additional streams
+            0x03 => {
+                streams_info(r)?;
+            }
+            0x04 => return Ok(streams_info(r)?.uses(AES)),
+            _ => return Ok(false),
+        }
+    }
+}
+
+struct Coder {
+    id: Vec<u8>,
+    props: Vec<u8>,
+}
+
+struct Folder {
+    coders: Vec<Coder>,
+    unpack_sizes: Vec<u64>,
+}
+
+#[derive(Default)]
+struct Streams {
+    pack_pos: u64,
+    pack_sizes: Vec<u64>,
+    folders: Vec<Folder>,
+}
+
+impl Streams {
+    fn uses(&self, id: &[u8]) -> bool {
+        self.folders
+            .iter()
+            .flat_map(|folder| &folder.coders)
+            .any(|coder| coder.id == id)
+    }
+}
+
+/// A 7z StreamsInfo: where the packed streams are and how they are coded.
+/// Stops at the substreams, which say nothing about coders.
+fn streams_info(r: &mut Reader) -> io::Result<Streams> {
+    let mut streams = Streams::default();
+    loop {
+        match r.byte()? {
+            0x00 | 0x08 => return Ok(streams),
+            // PackInfo
+            0x06 => {
+                streams.pack_pos = r.number()?;
+                let count = r.count()?;
+                loop {
+                    match r.byte()? {
+                        0x00 => break,
+                        0x09 => {
+                            streams.pack_sizes =
+                                (0..count).map(|_| r.number()).collect::<io::Result<_>>()?;
+                        }
+                        0x0A => r.digests(count)?,
+                        _ => return Err(invalid("bad 7z pack info")),
+                    }
+                }
+            }
+            // UnpackInfo
+            0x07 => {
+                if r.byte()? != 0x0B {
+                    return Err(invalid("bad 7z unpack info"));
+                }
+                let count = r.count()?;
+                if r.byte()? != 0 {
+                    return Err(invalid("external 7z folders"));
+                }
+                let mut outputs = Vec::new();
+                for _ in 0..count {
+                    let (folder, out) = folder(r)?;
+                    streams.folders.push(folder);
+                    outputs.push(out);
+                }
+                if r.byte()? != 0x0C {
+                    return Err(invalid("bad 7z unpack info"));
+                }
+                for (folder, out) in streams.folders.iter_mut().zip(outputs) {
+                    folder.unpack_sizes =
+                        (0..out).map(|_| r.number()).collect::<io::Result<_>>()?;
+                }
+                loop {
+                    match r.byte()? {
+                        0x00 => break,
+                        0x0A => r.digests(count)?,
+                        _ => return Err(invalid("bad 7z unpack info")),
+                    }
+                }
+            }
+            _ => return Err(invalid("bad 7z streams info")),
+        }
+    }
+}
+
+/// One 7z folder (a chain of coders) and its number of output streams
+fn folder(r: &mut Reader) -> io::Result<(Folder, usize)> {
+    let mut coders = Vec::new();
+    let (mut inputs, mut outputs) = (0, 0);
+    for _ in 0..r.count()? {
+        let flags = r.byte()?;
+        if flags & 0x80 != 0 {
+            return Err(invalid("alternative 7z coders"));
+        }
+        let id = r.bytes((flags & 0x0F) as u64)?.to_vec();
+        if flags & 0x10 != 0 {
+            inputs += r.count()?;
+            outputs += r.count()?;
+        } else {
+            inputs += 1;
+            outputs += 1;
+        }
+        let props = if flags & 0x20 != 0 {
+            let size = r.number()?;
+            r.bytes(size)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        coders.push(Coder { id, props });
+    }
+    let bind_pairs = outputs.saturating_sub(1);
+    for _ in 0..bind_pairs * 2 {
+        r.number()?;
+    }
+    let packed = inputs.saturating_sub(bind_pairs);
+    if packed > 1 {
+        for _ in 0..packed {
+            r.number()?;
+        }
+    }
+    Ok((
+        Folder {
+            coders,
+            unpack_sizes: Vec::new(),
+        },
+        outputs,
+    ))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, n: u64) -> io::Result<&'a [u8]> {
+        let end = usize::try_from(n)
+            .ok()
+            .and_then(|n| self.pos.checked_add(n))
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(truncated)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    /// 7z's variable-length number: the leading one bits of the first byte
+    /// say how many bytes follow
+    fn number(&mut self) -> io::Result<u64> {
+        let first = self.byte()?;
+        let mut value = 0u64;
+        for i in 0..8 {
+            let mask = 0x80u8 >> i;
+            if first & mask == 0 {
+                let high = u64::from(first & mask.wrapping_sub(1));
+                return Ok(value | (high << (8 * i)));
+            }
+            value |= u64::from(self.byte()?) << (8 * i);
+        }
+        Ok(value)
+    }
+
+    /// A number of items, each at least a byte long, so never more than
+    /// the bytes left
+    fn count(&mut self) -> io::Result<usize> {
+        let n = self.number()?;
+        usize::try_from(n)
+            .ok()
+            .filter(|&n| n <= self.data.len() - self.pos)
+            .ok_or_else(|| invalid("7z count out of range"))
+    }
+
+    /// CRCs of `count` streams: a defined-bit vector, then 4 bytes each
+    fn digests(&mut self, count: usize) -> io::Result<()> {
+        let defined = if self.byte()? != 0 {
+            count
+        } else {
+            let bits = self.bytes(count.div_ceil(8) as u64)?;
+            (0..count)
+                .filter(|i| bits[i / 8] & (0x80 >> (i % 8)) != 0)
+                .count()
+        };
+        self.skip(defined as u64 * 4)
+    }
+}
+
+/// RAR 1.5-4.x: encrypted headers in the main block, or the first file
+/// block's "encrypted" flag
+fn rar4_encrypted(data: &[u8]) -> bool {
+    let mut pos = RAR4_MAGIC.len();
+    while let Some(block) = data.get(pos..pos + 7) {
+        let kind = block[2];
+        let flags = u16::from_le_bytes([block[3], block[4]]);
+        let size = u16::from_le_bytes([block[5], block[6]]) as usize;
+        match kind {
+            0x73 if flags & 0x0080 != 0 => return true,
+            0x74 => return flags & 0x0004 != 0,
+            _ => {}
+        }
+        let added = if flags & 0x8000 != 0 {
+            match data.get(pos + 7..pos + 11) {
+                Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize,
+                None => return false,
+            }
+        } else {
+            0
+        };
+        if size < 7 {
+            return false;
+        }
+        pos += size + added;
+    }
+    false
+}
+
+/// RAR 5: an archive encryption header, or a file encryption record in the
+/// first file header's extra area
+fn rar5_encrypted(data: &[u8]) -> bool {
+    let mut pos = RAR5_MAGIC.len();
+    loop {
+        // CRC32, then the header size, which counts from after itself
+        let Some((size, used)) = data.get(pos + 4..).and_then(vint) else {
+            return false;
+        };
+        let start = pos + 4 + used;
+        let Some(header) = usize::try_from(size)
+            .ok()
+            .and_then(|size| data.get(start..start.checked_add(size)?))
+        else {
+            return false;
+        };
+        let mut r = header;
+        let mut field = || {
+            let (value, used) = vint(r)?;
+            r = &r[used..];
+            Some(value)
+        };
+        let (Some(kind), Some(flags)) = (field(), field()) else {
+            return false;
+        };
+        let extra = if flags & 1 != 0 { field() } else { Some(0) };
+        let data_size = if flags & 2 != 0 { field() } else { Some(0) };
+        let (Some(extra), Some(data_size)) = (extra, data_size) else {
+            return false;
+        };
+        match kind {
+            4 => return true,
+            2 => {
+                let Some(mut records) = usize::try_from(extra)
+                    .ok()
+                    .and_then(|extra| header.get(header.len().checked_sub(extra)?..))
+                else {
+                    return false;
+                };
+                // Records: size (of what follows), type, data
+                while let Some((record_size, used)) = vint(records) {
+                    let body = &records[used..];
+                    if vint(body).is_some_and(|(kind, _)| kind == 1) {
+                        return true;
+                    }
+                    let Some(rest) = usize::try_from(record_size)
+                        .ok()
+                        .and_then(|size| body.get(size..))
+                    else {
+                        return false;
+                    };
+                    records = rest;
+                }
+                return false;
+            }
+            _ => {}
+        }
+        let Some(next) = usize::try_from(data_size)
+            .ok()
+            .and_then(|data_size| start.checked_add(size as usize)?.checked_add(data_size))
+        else {
+            return false;
+        };
+        pos = next;
+    }
+}
+
+/// RAR 5's variable-length integer, 7 bits a byte, least significant first;
+/// the value and the bytes it took
+fn vint(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Up to `limit` bytes from the start of the file
+fn head(file: &mut File, limit: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut data = Vec::new();
+    file.take(limit).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Fills as much of `buf` as the file has
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match file.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated 7z header")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
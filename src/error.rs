@@ -0,0 +1,157 @@
+use crate::observer::Step;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Why a step of a run failed, with the entry it failed on. Observers get
+/// one of these for every error, so front-ends can tell causes apart
+/// without reading messages.
+#[derive(Debug)]
+pub enum OrganizeError {
+    /// Something took the destination after the run was planned
+    Conflict {
+        path: PathBuf,
+        dest: PathBuf,
+        step: Step,
+    },
+    /// The system refused to read, write or remove `path` (or the
+    /// filesystem is read-only)
+    PermissionDenied {
+        path: PathBuf,
+        step: Step,
+        source: io::Error,
+    },
+    /// Copying to another filesystem, or removing the original after it,
+    /// failed; the original is kept
+    CrossDevice {
+        path: PathBuf,
+        dest: PathBuf,
+        step: Step,
+        source: io::Error,
+    },
+    /// The run was stopped before this step was done
+    Cancelled { path: PathBuf, step: Step },
+    /// Any other I/O failure; `source.kind()` tells which
+    Io {
+        path: PathBuf,
+        step: Step,
+        source: io::Error,
+    },
+}
+
+/// Wraps errors from a copy to another filesystem, keeping their kind
+#[derive(Debug)]
+pub struct CrossDeviceError(pub io::Error);
+
+impl fmt::Display for CrossDeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "copying to another filesystem: {}", self.0)
+    }
+}
+
+impl Error for CrossDeviceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl OrganizeError {
+    /// Sorts an I/O error from `step` on `path` (moving it to `dest`, if it was a move)
+    pub fn new(path: &Path, dest: Option<&Path>, step: Step, error: io::Error) -> Self {
+        let path = path.to_path_buf();
+        let crossed = error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<CrossDeviceError>());
+        match (error.kind(), dest) {
+            (io::ErrorKind::Interrupted, _) => OrganizeError::Cancelled { path, step },
+            (io::ErrorKind::AlreadyExists, Some(dest)) => OrganizeError::Conflict {
+                path,
+                dest: dest.to_path_buf(),
+                step,
+            },
+            (_, Some(dest)) if crossed => OrganizeError::CrossDevice {
+                path,
+                dest: dest.to_path_buf(),
+                step,
+                source: error,
+            },
+            (io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem, _) => {
+                OrganizeError::PermissionDenied {
+                    path,
+                    step,
+                    source: error,
+                }
+            }
+            _ => OrganizeError::Io {
+                path,
+                step,
+                source: error,
+            },
+        }
+    }
+
+    /// The entry the step was working on
+    pub fn path(&self) -> &Path {
+        match self {
+            OrganizeError::Conflict { path, .. }
+            | OrganizeError::PermissionDenied { path, .. }
+            | OrganizeError::CrossDevice { path, .. }
+            | OrganizeError::Cancelled { path, .. }
+            | OrganizeError::Io { path, .. } => path,
+        }
+    }
+
+    pub fn step(&self) -> Step {
+        match self {
+            OrganizeError::Conflict { step, .. }
+            | OrganizeError::PermissionDenied { step, .. }
+            | OrganizeError::CrossDevice { step, .. }
+            | OrganizeError::Cancelled { step, .. }
+            | OrganizeError::Io { step, .. } => *step,
+        }
+    }
+
+    /// A stable name for the cause, for machine-readable output: `conflict`,
+    /// `permission_denied`, `cross_device`, `cancelled`, or for other I/O
+    /// errors `not_found`, `storage_full`, `invalid_name` or `io`
+    pub fn code(&self) -> &'static str {
+        match self {
+            OrganizeError::Conflict { .. } => "conflict",
+            OrganizeError::PermissionDenied { .. } => "permission_denied",
+            OrganizeError::CrossDevice { .. } => "cross_device",
+            OrganizeError::Cancelled { .. } => "cancelled",
+            OrganizeError::Io { source, .. } => match source.kind() {
+                io::ErrorKind::NotFound => "not_found",
+                io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => "storage_full",
+                io::ErrorKind::InvalidFilename => "invalid_name",
+                _ => "io",
+            },
+        }
+    }
+}
+
+impl fmt::Display for OrganizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrganizeError::Conflict { dest, .. } => {
+                write!(f, "{:?} was taken since the run was planned", dest)
+            }
+            OrganizeError::PermissionDenied { source, .. }
+            | OrganizeError::CrossDevice { source, .. }
+            | OrganizeError::Io { source, .. } => write!(f, "{}", source),
+            OrganizeError::Cancelled { .. } => write!(f, "stopped by Ctrl-C"),
+        }
+    }
+}
+
+impl Error for OrganizeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OrganizeError::PermissionDenied { source, .. }
+            | OrganizeError::CrossDevice { source, .. }
+            | OrganizeError::Io { source, .. } => Some(source),
+            OrganizeError::Conflict { .. } | OrganizeError::Cancelled { .. } => None,
+        }
+    }
+}
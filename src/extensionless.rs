@@ -0,0 +1,110 @@
+use crate::config::Config;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Well-known files that carry no extension
+pub const BUILTIN_FILENAMES: &[(&str, &str)] = &[
+    ("makefile", "code"),
+    ("gnumakefile", "code"),
+    ("dockerfile", "code"),
+    ("containerfile", "code"),
+    ("justfile", "code"),
+    ("rakefile", "code"),
+    ("gemfile", "code"),
+    ("procfile", "code"),
+    ("vagrantfile", "code"),
+    ("jenkinsfile", "code"),
+    ("readme", "documents"),
+    ("license", "documents"),
+    ("licence", "documents"),
+    ("copying", "documents"),
+    ("changelog", "documents"),
+    ("authors", "documents"),
+    ("contributors", "documents"),
+    ("notice", "documents"),
+    ("todo", "documents"),
+];
+
+/// Interpreters named in a `#!` line. Shell scripts follow `.sh` into APPS.
+pub const BUILTIN_SHEBANGS: &[(&str, &str)] = &[
+    ("sh", "APPS"),
+    ("bash", "APPS"),
+    ("zsh", "APPS"),
+    ("dash", "APPS"),
+    ("ksh", "APPS"),
+    ("fish", "APPS"),
+    ("python", "code"),
+    ("node", "code"),
+    ("deno", "code"),
+    ("ruby", "code"),
+    ("perl", "code"),
+    ("php", "code"),
+    ("lua", "code"),
+];
+
+/// Category for scripts whose interpreter isn't listed anywhere
+const DEFAULT_SCRIPT_CATEGORY: &str = "code";
+
+/// Classifies a file without an extension by its name, then by its shebang line.
+/// Config entries take precedence over the built-in lists.
+pub fn classify(path: &Path, config: &Config) -> Option<String> {
+    if let Some(category) = classify_name(path.file_name()?.to_str()?, config) {
+        return Some(category);
+    }
+
+    let interpreter = read_shebang_interpreter(path)?;
+    let category = config
+        .shebangs
+        .get(&interpreter)
+        .cloned()
+        .or_else(|| {
+            BUILTIN_SHEBANGS
+                .iter()
+                .find(|(i, _)| *i == interpreter)
+                .map(|(_, c)| c.to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_SCRIPT_CATEGORY.to_string());
+    Some(category)
+}
+
+/// The category of a well-known extensionless file name (Makefile, README)
+pub fn classify_name(name: &str, config: &Config) -> Option<String> {
+    let name = name.to_lowercase();
+    if let Some(category) = config.filenames.get(&name) {
+        return Some(category.clone());
+    }
+    BUILTIN_FILENAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, category)| category.to_string())
+}
+
+/// Reads the first line and returns the interpreter name from a `#!` line.
+///
+/// `#!/usr/bin/env -S python3.11 -u` -> "python", `#!/bin/bash` -> "bash".
+fn read_shebang_interpreter(path: &Path) -> Option<String> {
+    let mut buf = [0u8; 256];
+    let mut file = File::open(path).ok()?;
+    let n = file.read(&mut buf).ok()?;
+    let head = &buf[..n];
+
+    let rest = head.strip_prefix(b"#!")?;
+    let line_end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    let line = std::str::from_utf8(&rest[..line_end]).ok()?;
+
+    let mut words = line.split_whitespace();
+    let mut program = Path::new(words.next()?).file_name()?.to_str()?;
+
+    // `env` just looks the real interpreter up on PATH; skip its flags
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-'))?;
+    }
+
+    // python3.11 -> python
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.to_lowercase())
+}
@@ -0,0 +1,124 @@
+/// Matches `text` against a shell-style glob (`*`, `?`, `[abc]`, `[a-z]`, `[!x]`).
+/// Matching is case-insensitive, as file names are on most desktop systems.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    match_from(&pattern, &text)
+}
+
+/// A set of globs prepared once, for matching many names (one pass can
+/// check every file in a large directory against the same patterns)
+pub struct GlobSet {
+    patterns: Vec<Vec<char>>,
+}
+
+impl GlobSet {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        GlobSet {
+            patterns: patterns
+                .iter()
+                .map(|p| p.as_ref().to_lowercase().chars().collect())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether `text` matches any of the patterns (case-insensitively)
+    pub fn matches_any(&self, text: &str) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let text: Vec<char> = text.to_lowercase().chars().collect();
+        self.patterns.iter().any(|p| match_from(p, &text))
+    }
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Last `*` seen and the text position it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    star = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                '?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                '[' => {
+                    if let Some((matched, len)) = match_class(&pattern[p..], text[t]) {
+                        if matched {
+                            p += len;
+                            t += 1;
+                            continue;
+                        }
+                    } else if text[t] == '[' {
+                        // Unterminated class: treat '[' literally
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c if c == text[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        // Mismatch: let the last star absorb one more character
+        match star {
+            Some((sp, st)) => {
+                p = sp + 1;
+                t = st + 1;
+                star = Some((sp, st + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Evaluates a `[...]` class at the start of `pattern` against `c`.
+/// Returns whether it matched and how many pattern chars the class spans.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        if pattern[i] == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&e| e != ']') {
+            if pattern[i] <= c && c <= pattern[i + 2] {
+                matched = true;
+            }
+            i += 3;
+        } else {
+            if pattern[i] == c {
+                matched = true;
+            }
+            i += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,187 @@
+use crate::date::DateTime;
+use crate::journal::{self, Placed};
+use crate::paths;
+use crate::plan::PlannedMove;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A finished run, from its journal: the moves it made, in order
+pub struct Run {
+    pub id: String,
+    /// The directory it organized
+    pub target: PathBuf,
+    /// Moves carried out (or attempted: a failed move is left where it was)
+    pub moves: Vec<(usize, PlannedMove)>,
+    /// What the files moved looked like where the run put them, by move
+    pub placed: HashMap<usize, Placed>,
+    /// Moves `undo` has reverted since
+    pub undone: HashSet<usize>,
+}
+
+/// `<data dir>/history`: the journals of finished runs, one per run
+pub fn dir() -> Option<PathBuf> {
+    Some(paths::data_dir()?.join("history"))
+}
+
+/// Crockford's base 32, the alphabet of ULIDs
+const ULID_DIGITS: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// A new run ID: a ULID (26 characters, e.g. `01JA2B3C4D5E6F7G8H9J0K1M2N`),
+/// the time the run started in milliseconds followed by 80 random bits, so
+/// IDs sort in the order runs started and never repeat across machines
+pub fn new_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let taken = |id: &str| dir().is_some_and(|dir| dir.join(format!("{}.tsv", id)).exists());
+    loop {
+        let id = ulid(millis, random_bits());
+        if !taken(&id) {
+            return id;
+        }
+    }
+}
+
+fn ulid(millis: u64, random: u128) -> String {
+    let value = (u128::from(millis & 0xffff_ffff_ffff) << 80) | (random & ((1 << 80) - 1));
+    (0..26)
+        .map(|i| char::from(ULID_DIGITS[((value >> (125 - i * 5)) & 31) as usize]))
+        .collect()
+}
+
+/// 128 bits from the standard library's randomly keyed hasher (seeded by the
+/// OS once per process), mixed with the time and a counter
+fn random_bits() -> u128 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let half = |n: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(n);
+        hasher.write_u64(count);
+        hasher.write_u32(std::process::id());
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        );
+        hasher.finish()
+    };
+    (u128::from(half(0)) << 64) | u128::from(half(1))
+}
+
+/// When the run `id` started: a ULID's time, or for IDs of older versions
+/// the `YYYY-MM-DDTHH-MM-SS` (UTC) they are named by, with `-2`, `-3`... for
+/// runs within the same second. `None` for anything else.
+pub fn started(id: &str) -> Option<SystemTime> {
+    if id.len() == 26 && id.as_bytes()[0] <= b'7' {
+        let mut value: u128 = 0;
+        for b in id.bytes() {
+            let digit = ULID_DIGITS
+                .iter()
+                .position(|&d| d == b.to_ascii_uppercase())?;
+            value = (value << 5) | digit as u128;
+        }
+        let millis = (value >> 80) as u64;
+        return Some(UNIX_EPOCH + Duration::from_millis(millis));
+    }
+    let suffix = id.get(19..)?;
+    let numbered = suffix
+        .strip_prefix('-')
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+    if !suffix.is_empty() && !numbered {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| id.get(range)?.parse::<u32>().ok();
+    let time = DateTime {
+        year: i64::from(number(0..4)?),
+        month: number(5..7)?,
+        day: number(8..10)?,
+        hour: number(11..13)?,
+        minute: number(14..16)?,
+        second: number(17..19)?,
+    };
+    let secs = u64::try_from(time.to_unix()).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Keeps the journal of a run that completed as that run's history
+pub fn archive(journal: &Path, id: &str) -> io::Result<()> {
+    let dir = dir().ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+    fs::create_dir_all(&dir)?;
+    fs::rename(journal, dir.join(format!("{}.tsv", id)))
+}
+
+/// Every recorded run, oldest first
+pub fn list() -> io::Result<Vec<Run>> {
+    let Some(dir) = dir() else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut ids: Vec<String> = entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().into_owned();
+            name.strip_suffix(".tsv").map(str::to_string)
+        })
+        .collect();
+    // Older IDs (times) and ULIDs sort differently as text
+    ids.sort_by_cached_key(|id| (started(id), id.clone()));
+    ids.iter().map(|id| load(id)).collect()
+}
+
+/// The run with this ID
+pub fn load(id: &str) -> io::Result<Run> {
+    let path = path_of(id)?;
+    let pending = journal::load(&path)?;
+    let moves = pending
+        .plan
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| pending.done.contains(i))
+        .collect();
+    Ok(Run {
+        id: id.to_ascii_uppercase(),
+        target: pending.target.unwrap_or_default(),
+        moves,
+        placed: pending.placed,
+        undone: pending.undone,
+    })
+}
+
+/// Records that the move at `index` of run `id` was reverted
+pub fn mark_undone(id: &str, index: usize) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).open(path_of(id)?)?;
+    writeln!(file, "undone\t{}", index)
+}
+
+fn path_of(id: &str) -> io::Result<PathBuf> {
+    let not_found = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no run '{}' (see `auto-organize history`)", id),
+        )
+    };
+    // IDs are file names; anything that could lead elsewhere is not one
+    if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+        return Err(not_found());
+    }
+    // ULIDs are case-insensitive; older IDs have no lowercase letters
+    let path = dir()
+        .ok_or_else(not_found)?
+        .join(format!("{}.tsv", id.to_ascii_uppercase()));
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(not_found())
+    }
+}
@@ -0,0 +1,119 @@
+use crate::date::DateTime;
+use crate::paths;
+use crate::tsv::{escape, unescape};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "# name\thash\tcategory\tpath\torganized_at\trun";
+
+/// One organized file: where it went and what it looked like at the time
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub name: String,
+    /// SHA-256 of the contents, or "-" for directories
+    pub hash: String,
+    pub category: String,
+    pub path: PathBuf,
+    pub organized_at: String,
+    /// The run that moved it there (see `history`); `None` in records of
+    /// older versions
+    pub run: Option<String>,
+}
+
+/// Append-only index of everything the tool has organized (tab-separated text)
+pub struct Index {
+    writer: BufWriter<File>,
+}
+
+impl Index {
+    pub fn open(path: &Path) -> io::Result<Index> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let is_new = !path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        if is_new {
+            writeln!(writer, "{}", HEADER)?;
+        }
+        Ok(Index { writer })
+    }
+
+    /// Records that `path` (its new location) was organized into `category`.
+    /// `hash` is the content SHA-256, or `None` for directories.
+    pub fn record(
+        &mut self,
+        path: &Path,
+        category: &str,
+        hash: Option<&str>,
+        run: &str,
+    ) -> io::Result<()> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let abs = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        writeln!(
+            self.writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape(&name),
+            hash.unwrap_or("-"),
+            escape(category),
+            escape(&abs.to_string_lossy()),
+            DateTime::now().to_iso(),
+            run
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Loads the index, keeping only the latest record for each path
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    let file = File::open(path)?;
+    let mut latest: HashMap<PathBuf, usize> = HashMap::new();
+    let mut records: Vec<Record> = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        // Records of older versions have no run
+        let (name, hash, category, path, organized_at, run) = match fields[..] {
+            [name, hash, category, path, organized_at] => {
+                (name, hash, category, path, organized_at, None)
+            }
+            [name, hash, category, path, organized_at, run] => {
+                (name, hash, category, path, organized_at, Some(run))
+            }
+            _ => continue, // Tolerate damaged lines rather than refusing the whole index
+        };
+
+        let record = Record {
+            name: unescape(name),
+            hash: hash.to_string(),
+            category: unescape(category),
+            path: PathBuf::from(unescape(path)),
+            organized_at: organized_at.to_string(),
+            run: run.map(str::to_string),
+        };
+        match latest.get(&record.path) {
+            Some(&i) => records[i] = record,
+            None => {
+                latest.insert(record.path.clone(), records.len());
+                records.push(record);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// `index.tsv` in the data directory (see `paths::data_dir`)
+pub fn default_path() -> Option<PathBuf> {
+    Some(paths::data_dir()?.join("index.tsv"))
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Words of installer and program names that say nothing about the product
+const NOISE_WORDS: &[&str] = &[
+    "setup",
+    "installer",
+    "install",
+    "x64",
+    "x86",
+    "x86_64",
+    "amd64",
+    "arm64",
+    "aarch64",
+    "win",
+    "win32",
+    "win64",
+    "windows",
+    "mac",
+    "macos",
+    "osx",
+    "darwin",
+    "linux",
+    "universal",
+    "full",
+    "offline",
+    "online",
+    "web",
+    "stable",
+    "release",
+    "latest",
+    "user",
+    "system",
+    "en",
+    "us",
+];
+
+/// Product names shorter than this (letters and digits) match too much
+const MIN_PRODUCT_CHARS: usize = 3;
+
+/// The programs installed on this system, as far as the platform's lists
+/// tell: the uninstall entries of the registry on Windows, `/Applications`
+/// on macOS, dpkg, rpm, snap, Flatpak and `.desktop` entries elsewhere
+pub struct InstalledPrograms {
+    /// Each program's name as listed, and its meaningful words
+    programs: Vec<(String, Vec<String>)>,
+    /// The words run together, to match "VisualStudioCode" with "Visual Studio Code"
+    joined: HashSet<String>,
+}
+
+impl InstalledPrograms {
+    pub fn load() -> Self {
+        Self::from_names(sys::installed_names())
+    }
+
+    fn from_names(names: Vec<String>) -> Self {
+        let mut seen = HashSet::new();
+        let mut programs = Vec::new();
+        let mut joined = HashSet::new();
+        for name in names {
+            let key = words(&name);
+            if key.is_empty() || !seen.insert(key.clone()) {
+                continue;
+            }
+            joined.insert(key.concat());
+            programs.push((name, key));
+        }
+        InstalledPrograms { programs, joined }
+    }
+
+    /// The installed program `product` (an installer's product name, e.g.
+    /// "firefox-setup") is likely an installer of: one whose name holds its
+    /// words in order ("Mozilla Firefox"), or the same letters run together
+    pub fn find(&self, product: &str) -> Option<&str> {
+        let key = words(product);
+        let letters = key.concat();
+        if letters.chars().count() < MIN_PRODUCT_CHARS {
+            return None;
+        }
+        self.programs
+            .iter()
+            .find(|(_, program)| {
+                program
+                    .windows(key.len())
+                    .any(|window| window == key.as_slice())
+            })
+            .map(|(name, _)| name.as_str())
+            .or_else(|| {
+                self.joined.contains(&letters).then(|| {
+                    self.programs
+                        .iter()
+                        .find(|(_, program)| program.concat() == letters)
+                        .map(|(name, _)| name.as_str())
+                })?
+            })
+    }
+}
+
+/// Lowercased words of a name, without versions, architectures and the like:
+/// "Mozilla Firefox (x64 en-US)" -> ["mozilla", "firefox"]
+fn words(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .filter(|word| !word.chars().all(|c| c.is_ascii_digit()))
+        .filter(|word| !NOISE_WORDS.contains(word))
+        .filter(|word| !is_version(word))
+        .map(|word| {
+            // "FirefoxSetup", "ZoomInstaller"
+            ["setup", "installer"]
+                .iter()
+                .find_map(|suffix| word.strip_suffix(suffix))
+                .filter(|rest| rest.len() >= MIN_PRODUCT_CHARS)
+                .unwrap_or(word)
+                .to_string()
+        })
+        .collect()
+}
+
+/// "v120", "3rc1"
+fn is_version(word: &str) -> bool {
+    let rest = word.strip_prefix('v').unwrap_or(word);
+    rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Names of the entries of `dir` with extension `ext`, without it (all
+/// entries, whole names, when `ext` is empty)
+fn stems_in(dir: &Path, ext: &str) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter_map(|p| {
+                    let name = if ext.is_empty() {
+                        p.file_name()?
+                    } else if p.extension().is_some_and(|e| e == ext) {
+                        p.file_stem()?
+                    } else {
+                        return None;
+                    };
+                    Some(name.to_string_lossy().into_owned())
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Lines a program prints, or none if it is not there or fails
+fn command_lines(program: &str, args: &[&str]) -> Vec<String> {
+    match Command::new(program).args(args).output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use std::path::Path;
+
+    pub fn installed_names() -> Vec<String> {
+        let mut names = super::stems_in(Path::new("/Applications"), "app");
+        names.extend(super::stems_in(Path::new("/Applications/Utilities"), "app"));
+        if let Some(home) = super::home() {
+            names.extend(super::stems_in(&home.join("Applications"), "app"));
+        }
+        // Homebrew casks and formulae
+        names.extend(super::command_lines("brew", &["list", "-1"]));
+        names
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::path::Path;
+
+    /// Where installers register what they install, for all users (64- and
+    /// 32-bit) and for the current one
+    const UNINSTALL_KEYS: &[&str] = &[
+        r"HKLM\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKLM\SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+        r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+    ];
+
+    pub fn installed_names() -> Vec<String> {
+        let mut names = Vec::new();
+        // Programs that do not register, unpacked into a folder of their own
+        for dir in [r"C:\Program Files", r"C:\Program Files (x86)"] {
+            names.extend(super::stems_in(Path::new(dir), ""));
+        }
+        if let Some(home) = super::home() {
+            names.extend(super::stems_in(&home.join(r"AppData\Local\Programs"), ""));
+        }
+        for key in UNINSTALL_KEYS {
+            // "    DisplayName    REG_SZ    Mozilla Firefox (x64 en-US)"
+            for line in super::command_lines("reg", &["query", key, "/s", "/v", "DisplayName"]) {
+                if let Some((_, name)) = line.split_once("REG_SZ") {
+                    names.push(name.trim().to_string());
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod sys {
+    use std::fs;
+    use std::path::Path;
+
+    pub fn installed_names() -> Vec<String> {
+        let mut names = dpkg_packages();
+        names.extend(super::command_lines("rpm", &["-qa", "--qf", "%{NAME}\\n"]));
+        names.extend(super::stems_in(Path::new("/snap"), ""));
+        // Flatpak ids end with the program: org.mozilla.firefox
+        let mut flatpaks = super::stems_in(Path::new("/var/lib/flatpak/app"), "");
+        let home = super::home();
+        if let Some(home) = &home {
+            flatpaks.extend(super::stems_in(&home.join(".local/share/flatpak/app"), ""));
+        }
+        names.extend(
+            flatpaks
+                .iter()
+                .map(|id| id.rsplit('.').next().unwrap_or(id).to_string()),
+        );
+        names.extend(super::stems_in(
+            Path::new("/usr/share/applications"),
+            "desktop",
+        ));
+        if let Some(home) = &home {
+            names.extend(super::stems_in(
+                &home.join(".local/share/applications"),
+                "desktop",
+            ));
+        }
+        names.extend(super::stems_in(Path::new("/opt"), ""));
+        names
+    }
+
+    /// Packages dpkg lists as installed
+    fn dpkg_packages() -> Vec<String> {
+        let Ok(status) = fs::read_to_string("/var/lib/dpkg/status") else {
+            return Vec::new();
+        };
+        status
+            .split("\n\n")
+            .filter(|stanza| stanza.contains("\nStatus: install ok installed"))
+            .filter_map(|stanza| {
+                let line = stanza.lines().find(|l| l.starts_with("Package: "))?;
+                Some(line["Package: ".len()..].to_string())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,242 @@
+use crate::installed::InstalledPrograms;
+use crate::trash;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// What to do with installers that are older than the newest N versions
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OldInstallers {
+    /// Move them into APPS/old
+    Old,
+    /// Move them to the system trash
+    Trash,
+}
+
+impl OldInstallers {
+    fn label(self) -> &'static str {
+        match self {
+            OldInstallers::Old => "APPS/old",
+            OldInstallers::Trash => "Trash",
+        }
+    }
+}
+
+/// What to do with installers of programs that are already installed
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstalledInstallers {
+    /// Move them into APPS/installed
+    Installed,
+    /// Move them to the system trash
+    Trash,
+}
+
+impl InstalledInstallers {
+    fn label(self) -> &'static str {
+        match self {
+            InstalledInstallers::Installed => "APPS/installed",
+            InstalledInstallers::Trash => "Trash",
+        }
+    }
+}
+
+struct Installer {
+    path: PathBuf,
+    version: Vec<u64>,
+    modified: SystemTime,
+}
+
+/// Splits an installer file name into a product name and a version.
+///
+/// `node-v20.10.0-x64.msi` -> ("node", [20, 10, 0]),
+/// `Firefox Setup 120.0.1.exe` -> ("firefox-setup", [120, 0, 1]).
+/// Returns `None` when no version-looking token is found.
+pub fn parse_installer_name(file_name: &str) -> Option<(String, Vec<u64>)> {
+    let stem = Path::new(file_name).file_stem()?.to_str()?;
+    let tokens: Vec<&str> = stem
+        .split(['-', '_', ' '])
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let pos = tokens.iter().position(|t| parse_version(t).is_some())?;
+    if pos == 0 {
+        return None;
+    }
+
+    let product = tokens[..pos].join("-").to_lowercase();
+    let version = parse_version(tokens[pos])?;
+    Some((product, version))
+}
+
+/// Parses `1.2.3` or `v20` style tokens. A bare number without a dot or
+/// `v` prefix is not considered a version (too many false positives).
+fn parse_version(token: &str) -> Option<Vec<u64>> {
+    let (digits, prefixed) = match token.strip_prefix(['v', 'V']) {
+        Some(rest) => (rest, true),
+        None => (token, false),
+    };
+    if !prefixed && !digits.contains('.') {
+        return None;
+    }
+
+    let mut version = Vec::new();
+    for part in digits.split('.') {
+        // Keep the leading digits only, so "3rc1" counts as 3
+        let numeric: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+        if numeric.is_empty() {
+            return None;
+        }
+        version.push(numeric.parse().ok()?);
+    }
+    Some(version)
+}
+
+/// Keeps only the newest `keep` versions of each product among `candidates`
+/// and moves the rest to `APPS/old` or the trash. Returns the old and new
+/// location of each retired installer along with where it went ("APPS/old" or "Trash").
+pub fn cleanup(
+    candidates: &[PathBuf],
+    apps_dir: &Path,
+    keep: usize,
+    action: OldInstallers,
+    dry_run: bool,
+) -> Vec<(PathBuf, PathBuf, &'static str)> {
+    // Group by product name and extension (an .msi and an .exe are different builds)
+    let mut groups: HashMap<(String, String), Vec<Installer>> = HashMap::new();
+    for path in candidates {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some((product, version)) = parse_installer_name(file_name) else {
+            continue;
+        };
+        let ext = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        groups.entry((product, ext)).or_default().push(Installer {
+            path: path.clone(),
+            version,
+            modified,
+        });
+    }
+
+    let old_dir = apps_dir.join("old");
+    let mut retired = Vec::new();
+
+    for installers in groups.values_mut() {
+        // Newest first: highest version, then most recently modified
+        installers.sort_by(|a, b| {
+            b.version
+                .cmp(&a.version)
+                .then_with(|| b.modified.cmp(&a.modified))
+        });
+
+        for installer in installers.iter().skip(keep) {
+            let to = match action {
+                OldInstallers::Old => Some(old_dir.as_path()),
+                OldInstallers::Trash => None,
+            };
+            if let Some(dest) = retire_installer(&installer.path, to, action.label(), "", dry_run) {
+                retired.push((installer.path.clone(), dest, action.label()));
+            }
+        }
+    }
+
+    retired
+}
+
+/// Moves the installers among `candidates` whose program is already
+/// installed to `APPS/installed` or the trash. Returns what was moved, as
+/// `cleanup` does.
+pub fn retire_installed(
+    candidates: &[PathBuf],
+    apps_dir: &Path,
+    installed: &InstalledPrograms,
+    action: InstalledInstallers,
+    dry_run: bool,
+) -> Vec<(PathBuf, PathBuf, &'static str)> {
+    let installed_dir = apps_dir.join("installed");
+    let mut retired = Vec::new();
+    for path in candidates {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        // The name before the version, or the whole stem for unversioned names
+        let product = match parse_installer_name(file_name) {
+            Some((product, _)) => product,
+            None => Path::new(file_name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let Some(program) = installed.find(&product) else {
+            continue;
+        };
+        let to = match action {
+            InstalledInstallers::Installed => Some(installed_dir.as_path()),
+            InstalledInstallers::Trash => None,
+        };
+        let note = format!(" ({} is installed)", program);
+        if let Some(dest) = retire_installer(path, to, action.label(), &note, dry_run) {
+            retired.push((path.clone(), dest, action.label()));
+        }
+    }
+    retired
+}
+
+/// Moves an installer into the folder `to`, or the trash when `None`
+fn retire_installer(
+    path: &Path,
+    to: Option<&Path>,
+    label: &str,
+    note: &str,
+    dry_run: bool,
+) -> Option<PathBuf> {
+    let file_name = path.file_name().unwrap_or_default();
+
+    match to {
+        Some(old_dir) => {
+            let dest_path = old_dir.join(file_name);
+            if dest_path.exists() {
+                println!("[SKIP] {:?} (already exists in {})", file_name, label);
+                return None;
+            }
+
+            println!("[{:<12}] {:?}{}", label, file_name, note);
+
+            if !dry_run {
+                if let Err(e) = fs::create_dir_all(old_dir) {
+                    eprintln!("Error creating dir: {}", e);
+                    return None;
+                }
+                if let Err(e) = fs::rename(path, &dest_path) {
+                    eprintln!("Error moving {:?}: {}", file_name, e);
+                    return None;
+                }
+            }
+            Some(dest_path)
+        }
+        None => {
+            println!("[{:<12}] {:?}{}", label, file_name, note);
+
+            if dry_run {
+                return Some(path.to_path_buf());
+            }
+            match trash::move_to_trash(path) {
+                Ok(dest) => Some(dest),
+                Err(e) => {
+                    eprintln!("Error trashing {:?}: {}", file_name, e);
+                    None
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use crate::service::{self, home, xml_escape};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+const FOLDER_LABEL: &str = "Organize this folder";
+const FILE_LABEL: &str = "Organize this file";
+
+/// Registry keys under `HKCU\Software\Classes`, with their label and the
+/// placeholder Explorer substitutes with the clicked item
+const REGISTRY_KEYS: [(&str, &str, &str); 3] = [
+    (r"Directory\shell\auto-organize", FOLDER_LABEL, "%1"),
+    (
+        r"Directory\Background\shell\auto-organize",
+        FOLDER_LABEL,
+        "%V",
+    ),
+    (r"*\shell\auto-organize", FILE_LABEL, "%1"),
+];
+
+/// "Organize this folder" and "Organize this file" in the file manager's
+/// context menu: Nautilus scripts and Dolphin service menus, Finder Quick
+/// Actions, or Explorer verbs in the registry
+pub struct Integration {
+    /// Extra arguments for every run, e.g. `--config FILE`
+    pub extra_args: Vec<String>,
+}
+
+impl Integration {
+    /// The command a menu entry runs, before the clicked path
+    fn command(&self) -> io::Result<Vec<String>> {
+        let exe = env::current_exe()?;
+        let mut command = vec![exe.to_string_lossy().into_owned()];
+        command.extend(self.extra_args.iter().cloned());
+        Ok(command)
+    }
+
+    /// Files to write for this platform (path and contents); nothing on
+    /// Windows, where the entries live in the registry
+    pub fn files(&self) -> io::Result<Vec<(PathBuf, String)>> {
+        let command = self.command()?;
+        if cfg!(target_os = "macos") {
+            let mut files = Vec::new();
+            for (label, folders) in [(FOLDER_LABEL, true), (FILE_LABEL, false)] {
+                let contents = quick_action_dir(label)?.join("Contents");
+                files.push((
+                    contents.join("Info.plist"),
+                    quick_action_info(label, folders),
+                ));
+                files.push((
+                    contents.join("document.wflow"),
+                    quick_action_workflow(&command, folders),
+                ));
+            }
+            Ok(files)
+        } else if cfg!(windows) {
+            Ok(Vec::new())
+        } else {
+            let scripts = nautilus_scripts_dir()?;
+            let menus = dolphin_menus_dir()?;
+            Ok(vec![
+                (
+                    scripts.join(FOLDER_LABEL),
+                    nautilus_script(FOLDER_LABEL, &command, "-d"),
+                ),
+                (
+                    scripts.join(FILE_LABEL),
+                    nautilus_script(FILE_LABEL, &command, "-f"),
+                ),
+                (
+                    menus.join("auto-organize-folder.desktop"),
+                    dolphin_menu(FOLDER_LABEL, &command, "inode/directory"),
+                ),
+                (
+                    menus.join("auto-organize-file.desktop"),
+                    dolphin_menu(FILE_LABEL, &command, "all/allfiles"),
+                ),
+            ])
+        }
+    }
+
+    /// Writes the menu entries and registers them with the file manager
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        if cfg!(windows) {
+            let command = self.command()?;
+            let mut keys = Vec::new();
+            for (key, label, placeholder) in REGISTRY_KEYS {
+                let key = format!(r"HKCU\Software\Classes\{}", key);
+                let line = windows_command_line(&command, placeholder);
+                service::run("reg", &["add", &key, "/ve", "/d", label, "/f"])?;
+                service::run("reg", &["add", &key, "/v", "Icon", "/d", &command[0], "/f"])?;
+                let command_key = format!(r"{}\command", key);
+                service::run("reg", &["add", &command_key, "/ve", "/d", &line, "/f"])?;
+                keys.push(PathBuf::from(key));
+            }
+            return Ok(keys);
+        }
+
+        let files = self.files()?;
+        for (path, contents) in &files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+            // Nautilus runs only executable scripts; Dolphin asks for the
+            // same before trusting a service menu
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+        if cfg!(target_os = "macos") {
+            // Services are picked up at next login otherwise
+            let _ = Command::new("/System/Library/CoreServices/pbs")
+                .arg("-update")
+                .output();
+        }
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+/// Removes the menu entries. Returns what was removed (files, or registry
+/// keys on Windows); empty if nothing was installed.
+pub fn uninstall() -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    if cfg!(target_os = "macos") {
+        for label in [FOLDER_LABEL, FILE_LABEL] {
+            let workflow = quick_action_dir(label)?;
+            if workflow.exists() {
+                fs::remove_dir_all(&workflow)?;
+                removed.push(workflow);
+            }
+        }
+        if !removed.is_empty() {
+            let _ = Command::new("/System/Library/CoreServices/pbs")
+                .arg("-update")
+                .output();
+        }
+    } else if cfg!(windows) {
+        for (key, _, _) in REGISTRY_KEYS {
+            let key = format!(r"HKCU\Software\Classes\{}", key);
+            let status = Command::new("reg").args(["delete", &key, "/f"]).output()?;
+            if status.status.success() {
+                removed.push(PathBuf::from(key));
+            }
+        }
+    } else {
+        let scripts = nautilus_scripts_dir()?;
+        let menus = dolphin_menus_dir()?;
+        let files = [
+            scripts.join(FOLDER_LABEL),
+            scripts.join(FILE_LABEL),
+            menus.join("auto-organize-folder.desktop"),
+            menus.join("auto-organize-file.desktop"),
+        ];
+        for file in files {
+            if file.exists() {
+                fs::remove_file(&file)?;
+                removed.push(file);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// For `--print`: what would be registered on Windows, as a `.reg` file
+pub fn reg_preview(integration: &Integration) -> io::Result<String> {
+    let command = integration.command()?;
+    let mut text = "Windows Registry Editor Version 5.00\n".to_string();
+    for (key, label, placeholder) in REGISTRY_KEYS {
+        let line = windows_command_line(&command, placeholder);
+        text.push_str(&format!(
+            "\n[HKEY_CURRENT_USER\\Software\\Classes\\{}]\n@=\"{}\"\n\"Icon\"=\"{}\"\n\
+             \n[HKEY_CURRENT_USER\\Software\\Classes\\{}\\command]\n@=\"{}\"\n",
+            key,
+            label,
+            reg_escape(&command[0]),
+            key,
+            reg_escape(&line)
+        ));
+    }
+    Ok(text)
+}
+
+fn data_home() -> io::Result<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(home()?.join(".local/share")),
+    }
+}
+
+fn nautilus_scripts_dir() -> io::Result<PathBuf> {
+    Ok(data_home()?.join("nautilus/scripts"))
+}
+
+fn dolphin_menus_dir() -> io::Result<PathBuf> {
+    Ok(data_home()?.join("kio/servicemenus"))
+}
+
+fn quick_action_dir(label: &str) -> io::Result<PathBuf> {
+    Ok(home()?
+        .join("Library/Services")
+        .join(format!("{}.workflow", label)))
+}
+
+/// Nautilus passes the selected items as arguments and starts scripts in
+/// the open folder; `test` keeps folders and files to their own entry
+fn nautilus_script(label: &str, command: &[String], test: &str) -> String {
+    let exec = command
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let fallback = if test == "-d" {
+        "[ $# -eq 0 ] && set -- \"$PWD\"\n"
+    } else {
+        ""
+    };
+    format!(
+        "#!/bin/sh\n# {} (installed by `auto-organize integrate`)\n{}\
+         for item in \"$@\"; do\n    [ {} \"$item\" ] && {} \"$item\"\ndone\nexit 0\n",
+        label, fallback, test, exec
+    )
+}
+
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// `%f` runs the command once per selected item
+fn dolphin_menu(label: &str, command: &[String], mime_type: &str) -> String {
+    let exec = command
+        .iter()
+        .map(|arg| desktop_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "[Desktop Entry]\nType=Service\nX-KDE-ServiceTypes=KonqPopupMenu/Plugin\n\
+         MimeType={};\nActions=organize;\n\n\
+         [Desktop Action organize]\nName={}\nIcon=folder-sync\nExec={} %f\n",
+        mime_type, label, exec
+    )
+}
+
+/// Quotes an `Exec` argument: backslashes are escaped once for the string
+/// value and once more inside the quotes, and `%` starts a field code
+fn desktop_quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(c);
+            }
+            '%' => quoted.push_str("%%"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn quick_action_info(label: &str, folders: bool) -> String {
+    let file_type = if folders {
+        "public.folder"
+    } else {
+        "public.item"
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n\
+         \x20   <key>NSServices</key>\n    <array>\n        <dict>\n\
+         \x20           <key>NSMenuItem</key>\n            <dict>\n\
+         \x20               <key>default</key>\n                <string>{}</string>\n\
+         \x20           </dict>\n\
+         \x20           <key>NSMessage</key>\n            <string>runWorkflowAsService</string>\n\
+         \x20           <key>NSRequiredContext</key>\n            <dict>\n\
+         \x20               <key>NSApplicationIdentifier</key>\n                <string>com.apple.finder</string>\n\
+         \x20           </dict>\n\
+         \x20           <key>NSSendFileTypes</key>\n            <array>\n\
+         \x20               <string>{}</string>\n            </array>\n\
+         \x20       </dict>\n    </array>\n\
+         </dict>\n</plist>\n",
+        xml_escape(label),
+        file_type
+    )
+}
+
+/// A one-action Automator workflow: "Run Shell Script" with the selected
+/// items as arguments
+fn quick_action_workflow(command: &[String], folders: bool) -> String {
+    let (test, input_type) = if folders {
+        ("-d", "com.apple.Automator.fileSystemObject.folder")
+    } else {
+        ("-f", "com.apple.Automator.fileSystemObject")
+    };
+    let exec = command
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let script = format!(
+        "for item in \"$@\"; do\n    [ {} \"$item\" ] && {} \"$item\"\ndone",
+        test, exec
+    );
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n\
+         \x20   <key>AMApplicationBuild</key>\n    <string>523</string>\n\
+         \x20   <key>AMApplicationVersion</key>\n    <string>2.10</string>\n\
+         \x20   <key>AMDocumentVersion</key>\n    <string>2</string>\n\
+         \x20   <key>actions</key>\n    <array>\n        <dict>\n\
+         \x20           <key>action</key>\n            <dict>\n\
+         \x20               <key>AMAccepts</key>\n                <dict>\n\
+         \x20                   <key>Container</key>\n                    <string>List</string>\n\
+         \x20                   <key>Optional</key>\n                    <true/>\n\
+         \x20                   <key>Types</key>\n                    <array>\n\
+         \x20                       <string>com.apple.cocoa.string</string>\n                    </array>\n\
+         \x20               </dict>\n\
+         \x20               <key>AMActionVersion</key>\n                <string>2.0.3</string>\n\
+         \x20               <key>AMProvides</key>\n                <dict>\n\
+         \x20                   <key>Container</key>\n                    <string>List</string>\n\
+         \x20                   <key>Types</key>\n                    <array>\n\
+         \x20                       <string>com.apple.cocoa.string</string>\n                    </array>\n\
+         \x20               </dict>\n\
+         \x20               <key>ActionBundlePath</key>\n                <string>/System/Library/Automator/Run Shell Script.action</string>\n\
+         \x20               <key>ActionName</key>\n                <string>Run Shell Script</string>\n\
+         \x20               <key>ActionParameters</key>\n                <dict>\n\
+         \x20                   <key>COMMAND_STRING</key>\n                    <string>{}</string>\n\
+         \x20                   <key>CheckedForUserDefaultShell</key>\n                    <true/>\n\
+         \x20                   <key>inputMethod</key>\n                    <integer>1</integer>\n\
+         \x20                   <key>shell</key>\n                    <string>/bin/sh</string>\n\
+         \x20                   <key>source</key>\n                    <string></string>\n\
+         \x20               </dict>\n\
+         \x20               <key>BundleIdentifier</key>\n                <string>com.apple.RunShellScript</string>\n\
+         \x20               <key>CFBundleVersion</key>\n                <string>2.0.3</string>\n\
+         \x20               <key>Class Name</key>\n                <string>RunShellScriptAction</string>\n\
+         \x20           </dict>\n\
+         \x20       </dict>\n    </array>\n\
+         \x20   <key>connectors</key>\n    <dict/>\n\
+         \x20   <key>workflowMetaData</key>\n    <dict>\n\
+         \x20       <key>serviceInputTypeIdentifier</key>\n        <string>{}</string>\n\
+         \x20       <key>serviceOutputTypeIdentifier</key>\n        <string>com.apple.Automator.nothing</string>\n\
+         \x20       <key>serviceProcessesInput</key>\n        <integer>0</integer>\n\
+         \x20       <key>workflowTypeIdentifier</key>\n        <string>com.apple.Automator.servicesMenu</string>\n\
+         \x20   </dict>\n\
+         </dict>\n</plist>\n",
+        xml_escape(&script),
+        input_type
+    )
+}
+
+/// Explorer runs the verb's command line as is, with the item in place of
+/// the placeholder
+fn windows_command_line(command: &[String], placeholder: &str) -> String {
+    let mut line: Vec<String> = command
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.replace('"', "\\\"")))
+        .collect();
+    line.push(format!("\"{}\"", placeholder));
+    line.join(" ")
+}
+
+fn reg_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Where the entries are shown, for the message after installing
+pub fn where_shown() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "Finder's Quick Actions (right-click > Quick Actions or Services)"
+    } else if cfg!(windows) {
+        "Explorer's context menu (on Windows 11 under \"Show more options\")"
+    } else {
+        "the Scripts submenu of Files (Nautilus) and the Actions submenu of Dolphin"
+    }
+}
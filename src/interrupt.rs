@@ -0,0 +1,103 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches Ctrl-C (and SIGTERM): runs stop at the next safe point instead
+/// of being killed halfway through a move. A second Ctrl-C quits at once.
+pub fn install() {
+    sys::install();
+}
+
+/// Whether the run was asked to stop
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Asks the run to stop at the next safe point, as Ctrl-C does
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// The error a copy stopped halfway fails with; the original is kept
+pub fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "stopped by Ctrl-C")
+}
+
+/// Sleeps for `duration`, waking early on Ctrl-C; false if it did
+pub fn sleep(duration: Duration) -> bool {
+    let until = Instant::now() + duration;
+    while !requested() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(200)));
+    }
+    false
+}
+
+/// The handler only flips the flag (or exits): nothing else is safe in a
+/// signal handler
+fn on_request() -> bool {
+    REQUESTED.swap(true, Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::c_int;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn _exit(status: c_int) -> !;
+    }
+
+    extern "C" fn handle(_signum: c_int) {
+        if super::on_request() {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { _exit(130) }
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only touches an atomic and calls _exit
+        unsafe {
+            signal(SIGINT, handle);
+            signal(SIGTERM, handle);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    unsafe extern "system" {
+        fn SetConsoleCtrlHandler(handler: unsafe extern "system" fn(u32) -> i32, add: i32) -> i32;
+        fn ExitProcess(code: u32) -> !;
+    }
+
+    /// Runs on a thread of its own; returning TRUE keeps the process alive
+    unsafe extern "system" fn handle(_event: u32) -> i32 {
+        if super::on_request() {
+            // SAFETY: ends the process, as a second Ctrl-C should
+            unsafe { ExitProcess(130) }
+        }
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: registers a handler that only touches an atomic
+        unsafe {
+            SetConsoleCtrlHandler(handle, 1);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn install() {}
+}
@@ -0,0 +1,325 @@
+use crate::history;
+use crate::paths;
+use crate::plan::{EntryKind, PlannedMove};
+use crate::tsv::{escape, escape_path, unescape, unescape_path};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Where journals were kept before they moved to the data directory; still
+/// used when there is no data directory (HOME unset)
+const LEGACY_NAME: &str = ".auto-organize-journal";
+
+const HEADER: &str = "# auto-organize journal v1";
+
+/// Write-ahead record of a run: the whole plan first, then one `done` line per
+/// finished move. It moves to the history when the run completes, so a journal
+/// left in place means the run was interrupted and can be resumed.
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// The run's ID in the history
+    run: String,
+    /// Every `done` line goes to disk before the next move (see `make_durable`)
+    durable: bool,
+}
+
+/// A journal read back from disk
+pub struct Pending {
+    pub plan: Vec<PlannedMove>,
+    pub done: HashSet<usize>,
+    /// What the files moved looked like where they were put (not in
+    /// journals of older versions)
+    pub placed: HashMap<usize, Placed>,
+    /// Moves reverted by `undo` (only in the history)
+    pub undone: HashSet<usize>,
+    /// Not in journals written before runs had IDs
+    pub run: Option<String>,
+    pub target: Option<PathBuf>,
+    /// The config the run used, unless the default (not in older journals)
+    pub config: Option<ConfigSource>,
+}
+
+/// Where a run's config came from, so `resume` carries on with the same
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// `--config FILE`, or a profile kept in a file
+    File(PathBuf),
+    /// A profile that ships with the program (`--profile NAME`)
+    Profile(String),
+}
+
+/// The size and modification time (in nanoseconds) of a file a run moved,
+/// where it put it: tells that file from a later one of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placed {
+    pub len: u64,
+    pub modified: u64,
+}
+
+impl Placed {
+    /// The file at `path`; `None` for anything else
+    pub fn of(path: &Path) -> Option<Placed> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        if !meta.is_file() {
+            return None;
+        }
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Placed {
+            len: meta.len(),
+            modified: u64::try_from(modified.as_nanos()).ok()?,
+        })
+    }
+}
+
+/// Where the journal for a run in `target_dir` is written:
+/// `<data dir>/journals/<key>.tsv`, keyed by the directory's absolute path
+pub fn path_for(target_dir: &Path) -> PathBuf {
+    match paths::data_dir() {
+        Some(dir) => dir
+            .join("journals")
+            .join(format!("{}.tsv", paths::key_for(target_dir))),
+        None => target_dir.join(LEGACY_NAME),
+    }
+}
+
+/// The journal of an interrupted run in `target_dir`, if there is one
+/// (including one left inside the directory by an older version)
+pub fn find(target_dir: &Path) -> Option<PathBuf> {
+    [path_for(target_dir), target_dir.join(LEGACY_NAME)]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+impl Journal {
+    pub fn create(
+        path: &Path,
+        target_dir: &Path,
+        plan: &[PlannedMove],
+        run: &str,
+        config: Option<&ConfigSource>,
+    ) -> io::Result<Journal> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        // Absolute, so `resume` and `undo` work from any directory
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or(path.to_path_buf());
+        let target = absolute(target_dir);
+        writeln!(writer, "# target: {}", target.display())?;
+        writeln!(writer, "# run: {}", run)?;
+        match config {
+            Some(ConfigSource::File(path)) => {
+                writeln!(writer, "# config: {}", escape_path(&absolute(path)))?
+            }
+            Some(ConfigSource::Profile(name)) => writeln!(writer, "# profile: {}", escape(name))?,
+            None => {}
+        }
+        for item in plan {
+            let kind = match item.kind {
+                EntryKind::File => "file",
+                EntryKind::Dir => "dir",
+                EntryKind::Merge => "merge",
+            };
+            write!(
+                writer,
+                "plan\t{}\t{}\t{}\t{}",
+                kind,
+                escape_path(&absolute(&item.src)),
+                escape_path(&absolute(&item.dest)),
+                escape(&item.category)
+            )?;
+            // Older journals have no flags field; it is only written when needed
+            if item.overwrite {
+                write!(writer, "\toverwrite")?;
+            }
+            if item.verify {
+                write!(writer, "\tverify")?;
+            }
+            if let Some(uid) = item.recipient {
+                write!(writer, "\tto={}", uid)?;
+            }
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+
+        Ok(Journal {
+            path: path.to_path_buf(),
+            writer,
+            run: run.to_string(),
+            durable: false,
+        })
+    }
+
+    /// Reopens an existing journal of run `run` to record further progress
+    /// (used by `resume`)
+    pub fn reopen(path: &Path, run: &str) -> io::Result<Journal> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Journal {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            run: run.to_string(),
+            durable: false,
+        })
+    }
+
+    /// Records that the move at `index` is over; `placed` is the file it
+    /// put in place, if it moved one
+    pub fn mark_done(&mut self, index: usize, placed: Option<Placed>) -> io::Result<()> {
+        match placed {
+            Some(Placed { len, modified }) => {
+                writeln!(self.writer, "done\t{}\t{}\t{}", index, len, modified)?
+            }
+            None => writeln!(self.writer, "done\t{}", index)?,
+        }
+        if self.durable {
+            return self.checkpoint();
+        }
+        self.writer.flush()
+    }
+
+    /// Puts what was written so far on disk, and from now on every `done`
+    /// line as it is written: slower, but a power loss never loses track of
+    /// a finished move
+    pub fn make_durable(&mut self) -> io::Result<()> {
+        self.durable = true;
+        self.checkpoint()
+    }
+
+    /// Makes what was written so far survive a power loss, not only a crash
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// The run completed: the journal becomes its history (see `history`),
+    /// or is removed where there is no data directory to keep it in
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.writer);
+        if history::dir().is_none() {
+            return fs::remove_file(&self.path);
+        }
+        history::archive(&self.path, &self.run).inspect_err(|_| {
+            // A journal left behind would block the next run as interrupted
+            let _ = fs::remove_file(&self.path);
+        })
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Pending> {
+    let file = File::open(path)?;
+    let mut plan = Vec::new();
+    let mut done = HashSet::new();
+    let mut placed = HashMap::new();
+    let mut undone = HashSet::new();
+    let (mut run, mut target, mut config) = (None, None, None);
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some(id) = line.strip_prefix("# run: ") {
+            run = Some(id.to_string());
+        } else if let Some(dir) = line.strip_prefix("# target: ") {
+            target = Some(PathBuf::from(dir));
+        } else if let Some(path) = line.strip_prefix("# config: ") {
+            config = Some(ConfigSource::File(unescape_path(path)));
+        } else if let Some(name) = line.strip_prefix("# profile: ") {
+            config = Some(ConfigSource::Profile(unescape(name)));
+        }
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["plan", kind, src, dest, category, ref flags @ ..] => {
+                let kind = match kind {
+                    "dir" => EntryKind::Dir,
+                    "merge" => EntryKind::Merge,
+                    _ => EntryKind::File,
+                };
+                plan.push(PlannedMove {
+                    kind,
+                    src: unescape_path(src),
+                    dest: unescape_path(dest),
+                    category: unescape(category),
+                    overwrite: flags.contains(&"overwrite"),
+                    verify: flags.contains(&"verify"),
+                    recipient: flags
+                        .iter()
+                        .find_map(|flag| flag.strip_prefix("to=")?.parse().ok()),
+                });
+            }
+            ["done", index, ref rest @ ..] => {
+                let Ok(index) = index.parse() else {
+                    continue;
+                };
+                done.insert(index);
+                if let [len, modified] = rest
+                    && let (Ok(len), Ok(modified)) = (len.parse(), modified.parse())
+                {
+                    placed.insert(index, Placed { len, modified });
+                }
+            }
+            ["undone", index] => {
+                if let Ok(index) = index.parse() {
+                    undone.insert(index);
+                }
+            }
+            // A line cut short by a crash; everything before it is still valid
+            _ => {}
+        }
+    }
+
+    Ok(Pending {
+        plan,
+        done,
+        placed,
+        undone,
+        run,
+        target,
+        config,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn plans_read_back_as_written() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = std::env::temp_dir().join("auto-organize-journal-plan");
+        let _ = fs::remove_dir_all(&dir);
+        let src = dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9\tx.txt"));
+        let item = PlannedMove {
+            kind: EntryKind::File,
+            dest: dir.join("documents").join(src.file_name().unwrap()),
+            src,
+            category: "documents".to_string(),
+            overwrite: true,
+            verify: false,
+            recipient: Some(1000),
+        };
+        let path = dir.join("journal.tsv");
+        let config = ConfigSource::File(dir.join("config.toml"));
+        Journal::create(
+            &path,
+            &dir,
+            std::slice::from_ref(&item),
+            "RUN",
+            Some(&config),
+        )
+        .unwrap();
+
+        let pending = load(&path).unwrap();
+        assert_eq!(pending.config, Some(config));
+        let read = &pending.plan[0];
+        assert_eq!((&read.src, &read.dest), (&item.src, &item.dest));
+        assert_eq!(read.recipient, Some(1000));
+        assert!(read.overwrite && !read.verify);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
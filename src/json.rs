@@ -0,0 +1,58 @@
+/// Quotes and escapes a string as a JSON string literal
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reads a JSON string literal at the start of `text` (after whitespace);
+/// returns it unescaped with the rest of the text
+pub fn parse_string(text: &str) -> Option<(String, &str)> {
+    let rest = text.trim_start().strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &rest[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'u' => {
+                    let hex: String = (0..4)
+                        .filter_map(|_| chars.next().map(|(_, c)| c))
+                        .collect();
+                    let unit = u16::from_str_radix(&hex, 16).ok()?;
+                    // Characters outside the BMP come as a surrogate pair
+                    let c = match char::from_u32(unit as u32) {
+                        Some(c) => c,
+                        None => {
+                            let low: String =
+                                chars.by_ref().skip(2).take(4).map(|(_, c)| c).collect();
+                            let low = u16::from_str_radix(&low, 16).ok()?;
+                            char::decode_utf16([unit, low]).next()?.ok()?
+                        }
+                    };
+                    out.push(c);
+                }
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
@@ -0,0 +1,38 @@
+use crate::config::CodeConfig;
+
+/// The folder web assets get when `[code] web = "web"`
+pub const WEB_CATEGORY: &str = "web";
+
+/// Source extensions by language folder, for splitting `code`
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("python", &["py", "pyw", "pyi", "ipynb"]),
+    (
+        "web",
+        &[
+            "html", "htm", "css", "scss", "js", "mjs", "ts", "jsx", "tsx", "json",
+        ],
+    ),
+    ("java", &["java", "kt", "kts", "scala"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh"]),
+    ("go", &["go"]),
+    ("ruby", &["rb"]),
+    ("php", &["php"]),
+    ("shell", &["sh", "bash", "zsh", "fish", "ps1"]),
+];
+
+/// Where a file classified as `code` goes: `web` for web assets when they are
+/// their own category, `code/<language>` when splitting, or `None` to stay in
+/// `code` (also for extensions no language claims)
+pub fn placement(ext: &str, config: &CodeConfig) -> Option<String> {
+    let language = LANGUAGES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&ext))
+        .map(|(language, _)| *language);
+    match language {
+        Some("web") if config.separate_web => Some(WEB_CATEGORY.to_string()),
+        Some(language) if config.split => Some(format!("code/{}", language)),
+        _ => None,
+    }
+}
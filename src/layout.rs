@@ -0,0 +1,267 @@
+use crate::date::{self, DateTime, MonthNames};
+use crate::owner;
+use std::time::SystemTime;
+
+/// Folder for files whose owner cannot be determined
+const UNKNOWN_OWNER: &str = "unknown";
+
+/// Folder for files with no recorded download site
+const UNKNOWN_ORIGIN: &str = "unknown-source";
+
+/// One level of the destination path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dimension {
+    /// The file's category, e.g. `images` (with any subfolders it brings)
+    Type,
+    /// The file's last-modified date (UTC) through a format such as `%Y-%m`
+    Date(String),
+    /// The account that owns the file, for shared drop folders
+    Owner,
+    /// The site the file was downloaded from, e.g. `github.com`
+    Origin,
+}
+
+/// What the dimensions need to know about a file
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub modified: SystemTime,
+    /// Only looked up when the layout sorts by owner (see `Layout::uses_owner`)
+    pub owner: Option<String>,
+    /// Only looked up when the layout sorts by origin (see `Layout::uses_origin`)
+    pub origin: Option<String>,
+}
+
+/// Ordered dimensions a file's destination folder is built from, given as
+/// `--by type,date` (`images/2024-05/`), `--by date:%Y,type` (`2024/images/`),
+/// `--by type,owner` (`scans/alice/`) or `--by origin,type` (`github.com/archives/`).
+///
+/// `date` takes a format after a colon: `%Y`, `%m` and `%d` (UTC), `%B` and
+/// `%b` for the month's full and short name in the time locale (`Mai` under
+/// `LC_TIME=de_DE.UTF-8`), `%G` and `%V` for the ISO week-numbering year and
+/// week, `%q` for the quarter, `%%` for a literal percent, `/` for nested
+/// folders; `year`, `month`, `day`, `week` and `quarter` are short for `%Y`,
+/// `%Y-%m`, `%Y-%m-%d`, `%G-W%V` and `%Y-Q%q`. Plain `date` means `month`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    dimensions: Vec<Dimension>,
+    /// For `%B` and `%b`, looked up when the layout is parsed
+    months: &'static MonthNames,
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Result<Layout, String> {
+        let mut dimensions = Vec::new();
+        for spec in text.split(',').map(str::trim) {
+            let (name, option) = match spec.split_once(':') {
+                Some((name, option)) => (name.trim(), Some(option.trim())),
+                None => (spec, None),
+            };
+            let dimension = match (name, option) {
+                ("type", None) => Dimension::Type,
+                ("date", option) => Dimension::Date(date_format(option.unwrap_or("month"))?),
+                ("owner", None) => Dimension::Owner,
+                ("origin", None) => Dimension::Origin,
+                ("type" | "owner" | "origin", Some(_)) => {
+                    return Err(format!("'{}' takes no format", name));
+                }
+                ("", _) => return Err("empty dimension".to_string()),
+                (other, _) => {
+                    return Err(format!(
+                        "unknown dimension '{}' (expected type, date, owner or origin)",
+                        other
+                    ));
+                }
+            };
+            if !matches!(dimension, Dimension::Date(_)) && dimensions.contains(&dimension) {
+                return Err(format!("'{}' given twice", name));
+            }
+            dimensions.push(dimension);
+        }
+        Ok(Layout {
+            dimensions,
+            months: date::month_names(&date::time_locale()),
+        })
+    }
+
+    /// Whether `Facts::owner` has to be filled in
+    pub fn uses_owner(&self) -> bool {
+        self.dimensions.contains(&Dimension::Owner)
+    }
+
+    /// Whether `Facts::origin` has to be filled in
+    pub fn uses_origin(&self) -> bool {
+        self.dimensions.contains(&Dimension::Origin)
+    }
+
+    /// The folder (relative to the target) a file of `category` goes into
+    pub fn folder(&self, category: &str, facts: &Facts) -> String {
+        self.segments(category, facts, self.dimensions.len())
+    }
+
+    /// The folder holding the category folder itself, e.g. `2024/images` for
+    /// `--by date:%Y,type,date`; `None` if the layout has no `type`
+    pub fn category_folder(&self, category: &str, facts: &Facts) -> Option<String> {
+        let end = self.dimensions.iter().position(|d| *d == Dimension::Type)? + 1;
+        Some(self.segments(category, facts, end))
+    }
+
+    /// Whether a top-level folder name is one this layout creates when it
+    /// starts with a date, owner or origin (so `2024-05`, `alice` or `github.com`
+    /// is not swept into Folders)
+    pub fn owns_folder(&self, name: &str) -> bool {
+        match self.dimensions.first() {
+            Some(Dimension::Date(format)) => {
+                let first = format.split('/').next().unwrap_or(format);
+                matches_format(first, name, self.months)
+            }
+            Some(Dimension::Owner) => name == UNKNOWN_OWNER || owner::is_account(name),
+            Some(Dimension::Origin) => name == UNKNOWN_ORIGIN || is_domain(name),
+            _ => false,
+        }
+    }
+
+    fn segments(&self, category: &str, facts: &Facts, end: usize) -> String {
+        let mut date = None;
+        self.dimensions[..end]
+            .iter()
+            .map(|dimension| match dimension {
+                Dimension::Type => category.to_string(),
+                Dimension::Date(format) => {
+                    let date =
+                        *date.get_or_insert_with(|| DateTime::from_system_time(facts.modified));
+                    render_date(format, date, self.months)
+                }
+                Dimension::Owner => folder_name(facts.owner.as_deref().unwrap_or(UNKNOWN_OWNER)),
+                Dimension::Origin => folder_name(facts.origin.as_deref().unwrap_or(UNKNOWN_ORIGIN)),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            dimensions: vec![Dimension::Type],
+            months: date::month_names(&date::time_locale()),
+        }
+    }
+}
+
+/// Expands the shorthands and checks a date format
+fn date_format(option: &str) -> Result<String, String> {
+    let format = match option {
+        "year" => "%Y",
+        "month" => "%Y-%m",
+        "day" => "%Y-%m-%d",
+        "week" => "%G-W%V",
+        "quarter" => "%Y-Q%q",
+        other => other,
+    };
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            match chars.next() {
+                Some('Y' | 'm' | 'd' | 'B' | 'b' | 'G' | 'V' | 'q' | '%') => {}
+                Some(other) => return Err(format!("unknown date field '%{}'", other)),
+                None => return Err("date format ends with '%'".to_string()),
+            }
+        } else if c == '\\' {
+            return Err("use '/' to nest date folders".to_string());
+        }
+    }
+    if format
+        .split('/')
+        .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(format!("'{}' is not usable as a folder name", format));
+    }
+    Ok(format.to_string())
+}
+
+fn render_date(format: &str, date: DateTime, months: &MonthNames) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", date.year)),
+            Some('m') => out.push_str(&format!("{:02}", date.month)),
+            Some('d') => out.push_str(&format!("{:02}", date.day)),
+            Some('B') => out.push_str(months.full[date.month as usize - 1]),
+            Some('b') => out.push_str(months.short[date.month as usize - 1]),
+            Some('G') => out.push_str(&format!("{:04}", date.iso_week().0)),
+            Some('V') => out.push_str(&format!("{:02}", date.iso_week().1)),
+            Some('q') => out.push_str(&date.quarter().to_string()),
+            _ => out.push('%'),
+        }
+    }
+    out
+}
+
+/// An owner or origin as one folder level: a domain-qualified account
+/// (`DOMAIN\user`), an odd name or an IPv6 host (colons, which Windows does
+/// not allow) must not nest folders or leave the target
+fn folder_name(name: &str) -> String {
+    match name.replace(['/', '\\', ':'], "_") {
+        name if name.is_empty() || name == "." || name == ".." => "_".to_string(),
+        name => name,
+    }
+}
+
+/// Whether `name` could have been rendered from `format` (one folder level)
+fn matches_format(format: &str, name: &str, months: &MonthNames) -> bool {
+    let mut name = name.chars();
+    let mut format = format.chars();
+    let digits = |name: &mut std::str::Chars, n: usize| {
+        (0..n).all(|_| name.next().is_some_and(|c| c.is_ascii_digit()))
+    };
+    while let Some(c) = format.next() {
+        let ok = match c {
+            '%' => match format.next() {
+                Some('Y' | 'G') => digits(&mut name, 4),
+                Some('m' | 'd' | 'V') => digits(&mut name, 2),
+                Some('q') => name.next().is_some_and(|c| ('1'..='4').contains(&c)),
+                Some(field @ ('B' | 'b')) => {
+                    let names = if field == 'B' {
+                        &months.full
+                    } else {
+                        &months.short
+                    };
+                    // The longest name that fits, should one start another
+                    let rest = name.as_str();
+                    match names
+                        .iter()
+                        .filter(|month| rest.starts_with(**month))
+                        .max_by_key(|month| month.len())
+                    {
+                        Some(month) => {
+                            name = rest[month.len()..].chars();
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                _ => name.next() == Some('%'),
+            },
+            c => name.next() == Some(c),
+        };
+        if !ok {
+            return false;
+        }
+    }
+    name.next().is_none()
+}
+
+/// Whether `name` looks like a host name (`github.com`, `dl.google.com`)
+fn is_domain(name: &str) -> bool {
+    name.contains('.')
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
@@ -0,0 +1,89 @@
+//! Sorting files into category folders: classification rules, planning,
+//! and carrying out moves. The `auto-organize` binary is one front-end;
+//! others can follow a run through an [`observer::Observer`], and teach
+//! the rules about more files with a [`classifier::Classifier`].
+
+pub mod actions;
+pub mod analyze;
+pub mod branding;
+pub mod budget;
+pub mod classifier;
+pub mod cloud;
+pub mod compress;
+pub mod config;
+pub mod control;
+pub mod corrections;
+pub mod csv;
+pub mod damaged;
+pub mod date;
+pub mod deflate;
+pub mod dirdiff;
+pub mod documents;
+pub mod downloads;
+pub mod email;
+pub mod encrypted;
+pub mod error;
+pub mod extensionless;
+pub mod glob;
+pub mod hash;
+pub mod history;
+pub mod index;
+pub mod installed;
+pub mod installers;
+pub mod integrate;
+pub mod interrupt;
+pub mod journal;
+pub mod json;
+pub mod languages;
+pub mod layout;
+pub mod lint;
+pub mod listing;
+pub mod loops;
+pub mod lzma;
+pub mod manifest;
+pub mod metrics;
+pub mod mime;
+pub mod mounts;
+pub mod mover;
+pub mod netfs;
+pub mod observer;
+pub mod origin;
+pub mod owner;
+pub mod pathlimit;
+pub mod paths;
+pub mod permissions;
+pub mod pipeline;
+pub mod plan;
+pub mod plist;
+pub mod preflight;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod profiles;
+pub mod records;
+pub mod regex;
+pub mod rules;
+pub mod schedule;
+pub mod script;
+pub mod series;
+pub mod service;
+pub mod shadow;
+pub mod shared;
+pub mod sidecars;
+pub mod snapshot;
+pub mod staging;
+pub mod stats;
+pub mod style;
+pub mod sync;
+pub mod tags;
+pub mod teach;
+pub mod template;
+pub mod throttle;
+pub mod trash;
+pub mod tsv;
+pub mod verify;
+pub mod vfs;
+pub mod video;
+pub mod wasm;
+pub mod watch;
+pub mod xattr;
+pub mod zip;
@@ -0,0 +1,205 @@
+use crate::config::{Config, Document};
+use crate::extensionless::{BUILTIN_FILENAMES, BUILTIN_SHEBANGS};
+use crate::rules::BUILTIN_CATEGORIES;
+use crate::sidecars::BUILTIN_SIDECAR_EXTENSIONS;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Two mappings for the same key where only one can win
+    Conflict,
+    /// A mapping hides another one, as intended or not
+    Shadowed,
+    /// Worth knowing, nothing to fix
+    Info,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Level::Conflict => "CONFLICT",
+            Level::Shadowed => "SHADOWED",
+            Level::Info => "INFO",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+pub struct Finding {
+    pub level: Level,
+    pub message: String,
+}
+
+/// Checks the built-in tables and a config for mappings that conflict with
+/// or shadow each other. `doc` is the raw config file, which still has the
+/// keys that normalize to the same extension (`.PDF` and `pdf`).
+pub fn lint(config: &Config, doc: Option<&Document>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut report = |level, message: String| findings.push(Finding { level, message });
+
+    // Built-in tables: an extension listed twice goes to the first category
+    let mut builtin: BTreeMap<&str, &str> = BTreeMap::new();
+    for (category, extensions) in BUILTIN_CATEGORIES {
+        for ext in *extensions {
+            match builtin.get(ext) {
+                Some(first) => report(
+                    Level::Conflict,
+                    format!(
+                        ".{}: built-in under both {} and {}; {} wins",
+                        ext, first, category, first
+                    ),
+                ),
+                None => {
+                    builtin.insert(ext, category);
+                }
+            }
+        }
+    }
+
+    // Config keys that collapse into one after normalizing: the last one wins
+    if let Some(doc) = doc {
+        let tables = [
+            ("extensions", "extension"),
+            ("origins", "domain"),
+            ("filenames", "file name"),
+            ("shebangs", "interpreter"),
+        ];
+        for (table, what) in tables {
+            let Some(entries) = doc.tables.get(table) else {
+                continue;
+            };
+            let mut seen: BTreeMap<String, (&str, usize)> = BTreeMap::new();
+            for entry in entries {
+                let key = match table {
+                    "extensions" => entry.key.trim_start_matches('.').to_lowercase(),
+                    "origins" => entry.key.trim_start_matches("www.").to_lowercase(),
+                    _ => entry.key.to_lowercase(),
+                };
+                if let Some((first, line)) = seen.insert(key.clone(), (&entry.key, entry.line)) {
+                    report(
+                        Level::Conflict,
+                        format!(
+                            "[{}] '{}' (line {}) and '{}' (line {}) are the same {}; line {} wins",
+                            table, first, line, entry.key, entry.line, what, entry.line
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    // Config mappings hiding built-in ones
+    for (ext, category) in &config.extensions {
+        match builtin.get(ext.as_str()) {
+            Some(&original) if original == category => report(
+                Level::Info,
+                format!(".{}: [extensions] repeats the built-in {}", ext, category),
+            ),
+            Some(&original) => report(
+                Level::Shadowed,
+                format!(
+                    ".{}: [extensions] sends it to {} instead of the built-in {}",
+                    ext, category, original
+                ),
+            ),
+            None => {}
+        }
+    }
+    let pairs = [
+        ("filenames", &config.filenames, BUILTIN_FILENAMES),
+        ("shebangs", &config.shebangs, BUILTIN_SHEBANGS),
+    ];
+    for (table, configured, builtins) in pairs {
+        for (key, category) in configured {
+            match builtins.iter().find(|(name, _)| name == key) {
+                Some((_, original)) if original == category => report(
+                    Level::Info,
+                    format!("'{}': [{}] repeats the built-in {}", key, table, category),
+                ),
+                Some((_, original)) => report(
+                    Level::Shadowed,
+                    format!(
+                        "'{}': [{}] sends it to {} instead of the built-in {}",
+                        key, table, category, original
+                    ),
+                ),
+                None => {}
+            }
+        }
+    }
+
+    // Group rules come before every extension mapping; equal priorities fall back to names
+    let mut by_priority: BTreeMap<u64, Vec<&str>> = BTreeMap::new();
+    for (name, rule) in &config.group_by {
+        by_priority.entry(rule.priority).or_default().push(name);
+    }
+    for (priority, names) in &by_priority {
+        if names.len() > 1 {
+            report(
+                Level::Info,
+                format!(
+                    "[group_by] {} share priority {}: a file matching several goes by the first name",
+                    names.join(", "),
+                    priority
+                ),
+            );
+        }
+    }
+
+    // A sidecar follows its primary file, whatever its own mapping says
+    let sidecars: HashSet<String> = match &config.sidecar_extensions {
+        Some(list) => list.iter().cloned().collect(),
+        None => BUILTIN_SIDECAR_EXTENSIONS
+            .iter()
+            .map(|e| e.to_string())
+            .collect(),
+    };
+    for (ext, category) in &config.extensions {
+        if sidecars.contains(ext) {
+            report(
+                Level::Shadowed,
+                format!(
+                    ".{}: is a sidecar, so it goes to {} only when it has no primary file",
+                    ext, category
+                ),
+            );
+        }
+    }
+
+    // Settings for a category no rule produces never apply
+    let produced: HashSet<&str> = builtin
+        .values()
+        .copied()
+        .chain(config.extensions.values().map(String::as_str))
+        .chain(config.filenames.values().map(String::as_str))
+        .chain(config.shebangs.values().map(String::as_str))
+        .chain(BUILTIN_FILENAMES.iter().map(|(_, c)| *c))
+        .chain(BUILTIN_SHEBANGS.iter().map(|(_, c)| *c))
+        .chain(
+            config
+                .group_by
+                .values()
+                .filter_map(|r| r.category.as_deref()),
+        )
+        .chain(
+            config
+                .code
+                .separate_web
+                .then_some(crate::languages::WEB_CATEGORY),
+        )
+        .chain(["Others", "Conflicts"])
+        .collect();
+    for name in config.categories.keys() {
+        let top = name.split('/').next().unwrap_or(name);
+        if !produced.contains(name.as_str()) && !produced.contains(top) {
+            report(
+                Level::Info,
+                format!("[categories.{}]: no rule sends files there", name),
+            );
+        }
+    }
+
+    findings.sort_by_key(|f| f.level);
+    findings
+}
@@ -0,0 +1,177 @@
+use clap::ValueEnum;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Something to organize: an entry of the target directory, or a path
+/// named by `--files-from`
+pub enum Entry {
+    Listed(fs::DirEntry),
+    Named(PathBuf),
+}
+
+impl Entry {
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Entry::Listed(entry) => entry.path(),
+            Entry::Named(path) => path.clone(),
+        }
+    }
+
+    pub fn file_name(&self) -> OsString {
+        match self {
+            Entry::Listed(entry) => entry.file_name(),
+            Entry::Named(path) => path.file_name().unwrap_or_default().to_owned(),
+        }
+    }
+
+    /// Like `DirEntry::file_type`: symlinks are not followed
+    pub fn file_type(&self) -> io::Result<fs::FileType> {
+        match self {
+            Entry::Listed(entry) => entry.file_type(),
+            Entry::Named(path) => fs::symlink_metadata(path).map(|m| m.file_type()),
+        }
+    }
+
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        match self {
+            Entry::Listed(entry) => entry.metadata(),
+            Entry::Named(path) => fs::symlink_metadata(path),
+        }
+    }
+
+    /// What kind of special file this is (or a symlink points to), if it is one
+    pub fn special(&self) -> Option<&'static str> {
+        match self.file_type() {
+            Ok(t) if t.is_symlink() => fs::metadata(self.path())
+                .ok()
+                .and_then(|m| special_kind(m.file_type())),
+            Ok(t) => special_kind(t),
+            Err(_) => None,
+        }
+    }
+}
+
+/// The order entries are organized in. Directories list their entries in
+/// whatever order the file system keeps them, so runs (and their output)
+/// would differ from one machine to the next without one.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// By path, byte by byte
+    Name,
+    /// Oldest modification first
+    Mtime,
+    /// Smallest first
+    Size,
+}
+
+/// Sorts `entries` by `order`; entries that tie, or whose metadata cannot be
+/// read, are sorted by path
+pub fn sort(entries: &mut [Entry], order: Order) {
+    entries.sort_by_cached_key(|entry| {
+        let metadata = match order {
+            Order::Name => None,
+            Order::Mtime | Order::Size => entry.metadata().ok(),
+        };
+        let key = match (order, metadata) {
+            (Order::Mtime, Some(m)) => (m.modified().unwrap_or(SystemTime::UNIX_EPOCH), 0),
+            (Order::Size, Some(m)) => (SystemTime::UNIX_EPOCH, m.len()),
+            _ => (SystemTime::UNIX_EPOCH, 0),
+        };
+        (key, entry.path())
+    });
+}
+
+/// Named pipes, sockets and device nodes: opening one to look inside can
+/// block forever or read a whole disk, so they are never organized
+#[cfg(unix)]
+pub fn special_kind(file_type: fs::FileType) -> Option<&'static str> {
+    use std::os::unix::fs::FileTypeExt;
+    if file_type.is_fifo() {
+        Some("named pipe")
+    } else if file_type.is_socket() {
+        Some("socket")
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        Some("device")
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+pub fn special_kind(_file_type: fs::FileType) -> Option<&'static str> {
+    None
+}
+
+/// Reads a list of paths from `source` (`-` for stdin): one per line, or
+/// NUL-separated with `null` (as written by `find -print0`)
+pub fn read_list(source: &Path, null: bool) -> io::Result<Vec<PathBuf>> {
+    let mut bytes = Vec::new();
+    if source == Path::new("-") {
+        io::stdin().lock().read_to_end(&mut bytes)?;
+    } else {
+        fs::File::open(source)?.read_to_end(&mut bytes)?;
+    }
+
+    let separator = if null { b'\0' } else { b'\n' };
+    let mut paths = Vec::new();
+    for item in bytes.split(|&b| b == separator) {
+        let item = match item {
+            [rest @ .., b'\r'] if !null => rest,
+            _ => item,
+        };
+        if !item.is_empty() {
+            paths.push(path_from_bytes(item));
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Resolves relative paths against `base` and drops repeats. Parents are
+/// canonicalized so the same file is recognized however it was written; the
+/// last component is kept as is, so a listed symlink stays a symlink.
+pub fn resolve(paths: Vec<PathBuf>, base: &Path) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut resolved = Vec::new();
+    for path in paths {
+        let path = base.join(path);
+        let path = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => match parent.canonicalize() {
+                Ok(parent) => parent.join(name),
+                Err(_) => path,
+            },
+            _ => path,
+        };
+        if seen.insert(path.clone()) {
+            resolved.push(path);
+        }
+    }
+    resolved
+}
+
+/// The folder of `base` (canonical) that `path` is nested in, if it is
+/// below one rather than directly in `base` or outside it
+pub fn top_folder(path: &Path, base: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base).ok()?;
+    let mut components = relative.components();
+    let top = match components.next()? {
+        Component::Normal(name) => name.to_string_lossy().into_owned(),
+        _ => return None,
+    };
+    components.next()?;
+    Some(top)
+}
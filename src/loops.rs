@@ -0,0 +1,85 @@
+use crate::actions::Action;
+use crate::rules::RuleSet;
+use std::path::{Component, Path, PathBuf};
+
+/// Destinations the config sends files to that later runs over `target_dir`
+/// would organize again: `copy-to` and `symlink-into` folders inside it
+/// (other than in the category's own folder or a protected folder), and with
+/// `shared` a `[shared] root` inside it. Each is described on one line.
+pub fn find(rules: &RuleSet, target_dir: &Path, shared: bool) -> Vec<String> {
+    let target = resolve(target_dir);
+    let categories = rules.category_folders();
+    let protected = rules.protected_folders();
+    let mut found = Vec::new();
+
+    for (category, action) in rules.configured_actions() {
+        let (verb, dir) = match action {
+            Action::CopyTo(dir) => ("copy-to", dir),
+            Action::SymlinkInto(dir) => ("symlink-into", dir),
+            _ => continue,
+        };
+        let setting = format!(
+            "[categories.{}] action = \"{} {}\"",
+            category,
+            verb,
+            dir.display()
+        );
+        let dest = resolve(&target_dir.join(dir));
+        let Ok(inside) = dest.strip_prefix(&target) else {
+            continue;
+        };
+        let Some(top) = inside.iter().next().and_then(|top| top.to_str()) else {
+            found.push(format!("{}: that is the folder being organized", setting));
+            continue;
+        };
+        let own = Path::new(category).iter().next().and_then(|c| c.to_str());
+        if own == Some(top) {
+            continue;
+        }
+        if categories.contains(top) {
+            found.push(format!(
+                "{}: that is inside the '{}' category folder, whose files are filed again",
+                setting, top
+            ));
+        } else if !protected.contains(top) {
+            found.push(format!(
+                "{}: that is inside the folder being organized, so later runs sweep it up \
+                 (use a folder outside it, or list '{}' in protected_folders)",
+                setting, top
+            ));
+        }
+    }
+
+    let root = &rules.shared().root;
+    if shared && resolve(root).starts_with(&target) {
+        found.push(format!(
+            "[shared] root = \"{}\": that is inside the folder being organized, \
+             so the users' trees would be organized again",
+            root.display()
+        ));
+    }
+    found
+}
+
+/// `path` made absolute with `.` and `..` taken out, and with the symlinks of
+/// the part that exists followed, so two names of one folder compare equal.
+/// Destinations need not exist yet.
+fn resolve(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or(path.to_path_buf());
+    let mut clean = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                clean.pop();
+            }
+            component => clean.push(component),
+        }
+    }
+    for existing in clean.ancestors() {
+        if let Ok(real) = existing.canonicalize() {
+            return real.join(clean.strip_prefix(existing).unwrap_or(Path::new("")));
+        }
+    }
+    clean
+}
@@ -0,0 +1,266 @@
+use std::io;
+
+const NUM_STATES: usize = 12;
+const POS_STATES_MAX: usize = 1 << 4;
+const LEN_LOW_SYMBOLS: usize = 1 << 3;
+const LEN_HIGH_SYMBOLS: usize = 1 << 8;
+const MATCH_MIN_LEN: usize = 2;
+const END_POS_MODEL_INDEX: u32 = 14;
+const FULL_DISTANCES: usize = 1 << (END_POS_MODEL_INDEX >> 1);
+const ALIGN_BITS: u32 = 4;
+/// Probabilities are 11-bit fixed point, starting at one half
+const PROB_INIT: u16 = 1 << 10;
+
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> io::Result<Self> {
+        let head = data.get(..5).ok_or_else(truncated)?;
+        if head[0] != 0 {
+            return Err(invalid("bad range coder start"));
+        }
+        Ok(RangeDecoder {
+            data,
+            pos: 5,
+            range: u32::MAX,
+            code: u32::from_be_bytes([head[1], head[2], head[3], head[4]]),
+        })
+    }
+
+    fn normalize(&mut self) -> io::Result<()> {
+        if self.range < 1 << 24 {
+            let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            self.range <<= 8;
+            self.code = (self.code << 8) | u32::from(byte);
+        }
+        Ok(())
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> io::Result<u32> {
+        let bound = (self.range >> 11) * u32::from(*prob);
+        let bit = if self.code < bound {
+            *prob += ((1 << 11) - *prob) >> 5;
+            self.range = bound;
+            0
+        } else {
+            *prob -= *prob >> 5;
+            self.code -= bound;
+            self.range -= bound;
+            1
+        };
+        self.normalize()?;
+        Ok(bit)
+    }
+
+    /// Bits with a fixed probability of one half, most significant first
+    fn direct_bits(&mut self, n: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            self.range >>= 1;
+            let bit = u32::from(self.code >= self.range);
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            value = (value << 1) | bit;
+            self.normalize()?;
+        }
+        Ok(value)
+    }
+
+    /// `n` bits through a tree of probabilities, most significant first
+    fn tree(&mut self, probs: &mut [u16], n: u32) -> io::Result<u32> {
+        let mut m = 1;
+        for _ in 0..n {
+            m = (m << 1) | self.bit(&mut probs[m as usize])?;
+        }
+        Ok(m - (1 << n))
+    }
+
+    /// `n` bits through a tree of probabilities, least significant first
+    fn reverse_tree(&mut self, probs: &mut [u16], n: u32) -> io::Result<u32> {
+        let (mut m, mut value) = (1, 0);
+        for i in 0..n {
+            let bit = self.bit(&mut probs[m as usize])?;
+            m = (m << 1) | bit;
+            value |= bit << i;
+        }
+        Ok(value)
+    }
+}
+
+struct LenDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; LEN_LOW_SYMBOLS]; POS_STATES_MAX],
+    mid: [[u16; LEN_LOW_SYMBOLS]; POS_STATES_MAX],
+    high: [u16; LEN_HIGH_SYMBOLS],
+}
+
+impl LenDecoder {
+    fn new() -> Self {
+        LenDecoder {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; LEN_LOW_SYMBOLS]; POS_STATES_MAX],
+            mid: [[PROB_INIT; LEN_LOW_SYMBOLS]; POS_STATES_MAX],
+            high: [PROB_INIT; LEN_HIGH_SYMBOLS],
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> io::Result<usize> {
+        if rc.bit(&mut self.choice)? == 0 {
+            return Ok(rc.tree(&mut self.low[pos_state], 3)? as usize);
+        }
+        if rc.bit(&mut self.choice2)? == 0 {
+            return Ok(8 + rc.tree(&mut self.mid[pos_state], 3)? as usize);
+        }
+        Ok(16 + rc.tree(&mut self.high, 8)? as usize)
+    }
+}
+
+/// Decompresses a raw LZMA stream (as 7-Zip stores it) of `size` bytes;
+/// `props` are the coder's 5 property bytes (lc/lp/pb, dictionary size).
+/// The whole output stays in memory, so the dictionary size is not needed.
+pub fn decompress(props: &[u8], data: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    let &d = props
+        .first()
+        .ok_or_else(|| invalid("missing LZMA properties"))?;
+    if d >= 9 * 5 * 5 {
+        return Err(invalid("bad LZMA properties"));
+    }
+    let (lc, lp, pb) = (u32::from(d % 9), u32::from(d / 9 % 5), u32::from(d / 45));
+
+    let mut rc = RangeDecoder::new(data)?;
+    let mut literals = vec![PROB_INIT; 0x300 << (lc + lp)];
+    let mut is_match = [PROB_INIT; NUM_STATES << 4];
+    let mut is_rep = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g0 = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g1 = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g2 = [PROB_INIT; NUM_STATES];
+    let mut is_rep0_long = [PROB_INIT; NUM_STATES << 4];
+    let mut pos_slots = [[PROB_INIT; 1 << 6]; 4];
+    let mut pos_decoders = [PROB_INIT; 1 + FULL_DISTANCES - END_POS_MODEL_INDEX as usize];
+    let mut align = [PROB_INIT; 1 << ALIGN_BITS];
+    let mut lengths = LenDecoder::new();
+    let mut rep_lengths = LenDecoder::new();
+
+    let mut out: Vec<u8> = Vec::with_capacity(size.min(1 << 20));
+    let mut state = 0usize;
+    let mut reps = [0usize; 4];
+
+    while out.len() < size {
+        let pos_state = out.len() & ((1 << pb) - 1);
+
+        if rc.bit(&mut is_match[(state << 4) + pos_state])? == 0 {
+            let prev = out.last().copied().unwrap_or(0);
+            let lit_state = ((out.len() & ((1 << lp) - 1)) << lc) + (usize::from(prev) >> (8 - lc));
+            let probs = &mut literals[0x300 * lit_state..0x300 * (lit_state + 1)];
+            let mut symbol = 1usize;
+            if state >= 7 {
+                // After a match the byte at the match distance guides the guess
+                let mut match_byte = out[out.len() - reps[0] - 1];
+                while symbol < 0x100 {
+                    let match_bit = usize::from(match_byte >> 7);
+                    match_byte <<= 1;
+                    let bit = rc.bit(&mut probs[((1 + match_bit) << 8) + symbol])? as usize;
+                    symbol = (symbol << 1) | bit;
+                    if match_bit != bit {
+                        break;
+                    }
+                }
+            }
+            while symbol < 0x100 {
+                symbol = (symbol << 1) | rc.bit(&mut probs[symbol])? as usize;
+            }
+            out.push(symbol as u8);
+            state = match state {
+                0..=3 => 0,
+                4..=9 => state - 3,
+                _ => state - 6,
+            };
+            continue;
+        }
+
+        let len;
+        if rc.bit(&mut is_rep[state])? == 1 {
+            if out.is_empty() {
+                return Err(invalid("repeated match at the start"));
+            }
+            if rc.bit(&mut is_rep_g0[state])? == 0 {
+                if rc.bit(&mut is_rep0_long[(state << 4) + pos_state])? == 0 {
+                    state = if state < 7 { 9 } else { 11 };
+                    out.push(out[out.len() - reps[0] - 1]);
+                    continue;
+                }
+            } else {
+                let dist = if rc.bit(&mut is_rep_g1[state])? == 0 {
+                    reps[1]
+                } else {
+                    let dist = if rc.bit(&mut is_rep_g2[state])? == 0 {
+                        reps[2]
+                    } else {
+                        let dist = reps[3];
+                        reps[3] = reps[2];
+                        dist
+                    };
+                    reps[2] = reps[1];
+                    dist
+                };
+                reps[1] = reps[0];
+                reps[0] = dist;
+            }
+            len = rep_lengths.decode(&mut rc, pos_state)?;
+            state = if state < 7 { 8 } else { 11 };
+        } else {
+            reps[3] = reps[2];
+            reps[2] = reps[1];
+            reps[1] = reps[0];
+            len = lengths.decode(&mut rc, pos_state)?;
+            state = if state < 7 { 7 } else { 10 };
+
+            let slot = rc.tree(&mut pos_slots[len.min(3)], 6)?;
+            let dist = if slot < 4 {
+                slot
+            } else {
+                let direct = (slot >> 1) - 1;
+                let base = (2 | (slot & 1)) << direct;
+                if slot < END_POS_MODEL_INDEX {
+                    let probs = &mut pos_decoders[(base - slot) as usize..];
+                    base + rc.reverse_tree(probs, direct)?
+                } else {
+                    let high = rc.direct_bits(direct - ALIGN_BITS)? << ALIGN_BITS;
+                    base.wrapping_add(high)
+                        .wrapping_add(rc.reverse_tree(&mut align, ALIGN_BITS)?)
+                }
+            };
+            if dist == u32::MAX {
+                // End marker
+                break;
+            }
+            reps[0] = dist as usize;
+        }
+
+        if reps[0] >= out.len() {
+            return Err(invalid("match reaches before the start"));
+        }
+        let start = out.len() - reps[0] - 1;
+        for k in 0..(len + MATCH_MIN_LEN).min(size - out.len()) {
+            out.push(out[start + k]);
+        }
+    }
+    Ok(out)
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "truncated LZMA stream")
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
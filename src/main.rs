@@ -1,26 +1,4009 @@
-use clap::Parser;
-use std::collections::{HashMap, HashSet};
+use actions::Action;
+use auto_organize::{
+    actions, analyze, branding, budget, cloud, compress, config, control, corrections, damaged,
+    date, dirdiff, downloads, encrypted, error, glob, history, index, installed, installers,
+    integrate, interrupt, journal, layout, lint, listing, loops, manifest, metrics, mime, mounts,
+    mover, netfs, observer, origin, owner, paths, permissions, pipeline, plan, preflight, profiles,
+    records, rules, schedule, script, series, service, shadow, shared, sidecars, snapshot, staging,
+    stats, style, sync, tags, teach, template, throttle, trash, verify, vfs, watch,
+};
+use budget::Budget;
+use clap::{ArgGroup, Parser, Subcommand};
+use cloud::CloudPlaceholders;
+use config::Config;
+use corrections::{Placement, Suggestion};
+use damaged::{Damage, EmptyFiles};
+use date::DateTime;
+use downloads::Downloads;
+use error::OrganizeError;
+use installed::InstalledPrograms;
+use installers::{InstalledInstallers, OldInstallers};
+use journal::{ConfigSource, Journal, Placed};
+use layout::{Facts, Layout};
+use listing::{Entry, Order};
+use mounts::MountTable;
+use mover::CopyOptions;
+use observer::{Observer, Step};
+use pipeline::Stage;
+use plan::{ConflictName, ConflictPolicy, DestCache, DirPolicy, PlannedMove};
+use profiles::Profile;
+use records::Records;
+use rules::RuleSet;
+use staging::Staging;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use style::{ColorMode, Style};
+use tags::{Store, Tagger};
+use teach::Teacher;
+use template::Template;
+use vfs::{FileSystem, OverlayFs, RealFs};
+use watch::{ChangePoller, ConfigWatcher, PollMode};
 
 /// A CLI tool to automatically organize files into folders by type.
 ///
 /// Moves unknown files to 'Others', apps to 'APPS', and loose folders to 'Folders'.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+#[command(group = ArgGroup::new("watching").args(["watch", "shadow"]).multiple(true))]
 struct Args {
-    /// The directory to organize (defaults to current directory)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The directory to organize, or a single file (defaults to current directory)
     path: Option<PathBuf>,
 
-    /// Dry run: preview changes without moving files
-    #[arg(short, long, default_value_t = false)]
-    dry_run: bool,
+    /// With a single file: the directory whose category folders it goes into
+    /// (defaults to the file's own folder)
+    #[arg(long, value_name = "DIR")]
+    dest: Option<PathBuf>,
+
+    /// Dry run: preview changes without moving files
+    #[arg(short, long, default_value_t = false)]
+    dry_run: bool,
+
+    /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Use a named config instead: ~/.config/auto-organize/profiles/NAME.toml, or a
+    /// preset that ships with the program, like "desktop-builtin" (see `profiles`)
+    #[arg(long, value_name = "NAME", conflicts_with = "config")]
+    profile: Option<String>,
+
+    /// Keep only the newest N versions of each installer in APPS
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    keep_installers: Option<u64>,
+
+    /// Where older installers go when --keep-installers is set
+    #[arg(long, value_enum, default_value_t = OldInstallers::Old, requires = "keep_installers")]
+    old_installers: OldInstallers,
+
+    /// Installers of programs already installed here (by the registry,
+    /// /Applications, dpkg, rpm...): move them to APPS/installed or the trash
+    #[arg(long, value_enum, value_name = "POLICY")]
+    installed_installers: Option<InstalledInstallers>,
+
+    /// What to do when a file of the same name is already in its category folder
+    /// (default: the category's `on_conflict` in the config, else version for documents, skip for the rest)
+    #[arg(long, value_enum, value_name = "POLICY")]
+    on_conflict: Option<ConflictPolicy>,
+
+    /// Never overwrite, delete or merge, whatever other flags and the config say:
+    /// conflicts are skipped and nothing is trashed, purged or compressed
+    #[arg(long, default_value_t = false)]
+    safe: bool,
+
+    /// After applying, check every moved file is at its destination (once, and
+    /// the same size) and nothing else went missing; exit non-zero if not
+    #[arg(long, default_value_t = false, conflicts_with = "dry_run")]
+    verify_run: bool,
+
+    /// For irreplaceable data: safe mode, every move verified (copies read back
+    /// and compared, renames checked before and after) and the journal written
+    /// to disk after every move
+    #[arg(long, default_value_t = false)]
+    paranoid: bool,
+
+    /// Loose folders: leave them, move them into Folders, or move each into the
+    /// category most of its contents belong to (default: the config's `dirs`, else folders)
+    #[arg(long, value_enum, value_name = "POLICY")]
+    dirs: Option<DirPolicy>,
+
+    /// Order to organize files in, so that runs on the same files print the
+    /// same lines (default: the config's `order`, else name; --files-from
+    /// keeps the list's order unless given)
+    #[arg(long, value_enum, value_name = "ORDER")]
+    order: Option<Order>,
+
+    /// Merge a loose folder into Folders/ when a folder of that name is already there
+    #[arg(long, default_value_t = false)]
+    merge_dirs: bool,
+
+    /// Leave files of this MIME type where they are, by their contents where
+    /// they tell, else their extension: video/*, application/pdf (repeatable)
+    #[arg(long, value_name = "TYPE", value_parser = mime::parse_pattern)]
+    skip_mime: Vec<String>,
+
+    /// Only organize files of this MIME type (repeatable; see --skip-mime)
+    #[arg(long, value_name = "TYPE", value_parser = mime::parse_pattern)]
+    only_mime: Vec<String>,
+
+    /// Online-only cloud files (OneDrive, Dropbox, iCloud): leave them, or download then organize
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = CloudPlaceholders::Skip)]
+    cloud_placeholders: CloudPlaceholders,
+
+    /// Empty files and files whose contents do not match their extension
+    /// (failed downloads): leave them, put them in Others, trash them, or quarantine them
+    #[arg(long, value_enum, value_name = "POLICY")]
+    empty_files: Option<EmptyFiles>,
+
+    /// Print each move with this template instead, e.g. "{category}\t{name}\t{size}"
+    /// (placeholders: category name stem ext size modified owner origin kind src dest run)
+    #[arg(long, value_name = "TEMPLATE", value_parser = Template::parse)]
+    format: Option<Template>,
+
+    /// Category tags in their color, with an emoji: on a terminal unless
+    /// NO_COLOR is set (auto), always or never (colors are set per category in the config)
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Destination folders built from these dimensions, in order: "type,date"
+    /// gives images/2024-05/, "date:%Y,type" gives 2024/images/, "type,owner"
+    /// gives documents/alice/, "origin,type" gives github.com/archives/
+    /// (date formats: %Y %m %d, %B %b month names in the LC_TIME language,
+    /// %G %V ISO week year and week, %q quarter; or year, month, day, week,
+    /// quarter)
+    #[arg(long, value_name = "DIMENSIONS", default_value = "type", value_parser = Layout::parse)]
+    by: Layout,
+
+    /// Set by `reorganize`: the files already in category folders are the ones
+    /// planned, and those where the rules put them stay
+    #[arg(skip)]
+    reorganize: bool,
+
+    /// Set by `plan --emit-shell`: the dry run's moves are written here as a
+    /// shell script
+    #[arg(skip)]
+    emit_shell: Option<PathBuf>,
+
+    /// Never move this folder into Folders (repeatable; adds to config's protected_folders)
+    #[arg(long, value_name = "NAME")]
+    protect: Vec<String>,
+
+    /// Ask which category unknown extensions belong to (answers are saved to the config)
+    #[arg(short, long, default_value_t = false, conflicts_with = "watching")]
+    interactive: bool,
+
+    /// In interactive mode, don't save answers to the config
+    #[arg(long, default_value_t = false, requires = "interactive")]
+    no_learn: bool,
+
+    /// Look for files you moved out of their category folder since earlier runs
+    /// (in watch mode, before every pass): they stay where you put them, and once
+    /// 3 of an extension went to the same folder, that rule is saved to the config
+    #[arg(long, default_value_t = false)]
+    learn: bool,
+
+    /// Keep running and re-organize periodically; config edits apply without restart
+    #[arg(short, long, default_value_t = false)]
+    watch: bool,
+
+    /// Watch without moving anything: log the moves each pass would make, to tune
+    /// the rules over a few days before trusting them (see `shadow report`)
+    #[arg(long, default_value_t = false)]
+    shadow: bool,
+
+    /// Seconds between passes in watch mode
+    #[arg(long, value_name = "SECS", default_value_t = 10, requires = "watching",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// Poll for changes instead of a pass every interval, for network shares
+    /// where other clients' writes may still be in progress
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = PollMode::Auto, requires = "watching")]
+    poll: PollMode,
+
+    /// Leave files where they are; record their category as a tag instead (an
+    /// extended attribute or Finder tag, else a .tags.json in their folder)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["shared", "watching"])]
+    tag_only: bool,
+
+    /// Serve /healthz and Prometheus metrics (/metrics) on this port (on 127.0.0.1)
+    /// or ADDRESS:PORT while watching
+    #[arg(long, value_name = "ADDR", requires = "watching", value_parser = metrics::parse_addr)]
+    metrics: Option<SocketAddr>,
+
+    /// Seconds between polls for changes (see --poll)
+    #[arg(long, value_name = "SECS", default_value_t = 30, requires = "watching",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    poll_interval: u64,
+
+    /// Record organized files in the index so `auto-organize find` can locate them later
+    #[arg(long, default_value_t = false)]
+    index: bool,
+
+    /// Keep the index and journals here instead of the platform data directory
+    #[arg(long, value_name = "DIR", global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Write a CSV manifest of this run's moves (check it later with `auto-organize verify`)
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// Organize only the files listed in FILE (`-` for stdin), one path per line;
+    /// relative paths are taken from the target directory
+    #[arg(long, value_name = "FILE", conflicts_with = "watching")]
+    files_from: Option<PathBuf>,
+
+    /// Organize the files inside loose folders too, instead of moving the folders to Folders
+    #[arg(short, long, default_value_t = false)]
+    recursive: bool,
+
+    /// Treat mount points (another drive, a bind mount, a cloud drive) like any
+    /// other folder; by default they are left alone and never descended into
+    #[arg(long, default_value_t = false)]
+    cross_filesystems: bool,
+
+    /// Keep the folders files came from under their category:
+    /// setups/tools/app.exe goes to APPS/setups/tools/app.exe
+    #[arg(long, default_value_t = false)]
+    keep_structure: bool,
+
+    /// Shared computer: sort each user's files into their own tree under the
+    /// `[shared] root` of the config (by owner or name prefix), owned by them
+    #[arg(long, default_value_t = false, conflicts_with = "keep_structure")]
+    shared: bool,
+
+    /// Move at most N files and folders per run; the rest are moved by later runs
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_files: Option<u64>,
+
+    /// Plan everything, then make only the first N planned moves; the rest
+    /// stay in the journal for `auto-organize resume --batch-size N`, batch by
+    /// batch (for a first run over a huge tree, across sessions or reboots)
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["dry_run", "watch"]
+    )]
+    batch_size: Option<u64>,
+
+    /// Move at most this much data per run, e.g. 500M or 2G
+    #[arg(long, value_name = "SIZE", value_parser = budget::parse_size)]
+    max_bytes: Option<u64>,
+
+    /// Stop starting new moves after this long, e.g. 60s or 5m
+    #[arg(long, value_name = "DURATION", value_parser = service::parse_interval)]
+    time_budget: Option<u64>,
+
+    /// Copy across filesystems (e.g. to a NAS) at most this fast, e.g. 10MB/s
+    #[arg(long, value_name = "RATE", value_parser = mover::parse_rate)]
+    bwlimit: Option<u64>,
+
+    /// Make at most this many changes (moves, removals, new folders) a
+    /// second, e.g. to spare a spinning disk or a NAS; overrides [limits.*]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    fs_ops_limit: Option<u64>,
+
+    /// Make at most this many moves at the same time onto a destination
+    /// filesystem, counting other running instances; overrides [limits.*]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    max_transfers: Option<u64>,
+
+    /// Copy across filesystems this much at a time, e.g. 4M
+    #[arg(long, value_name = "SIZE", default_value = "1M", value_parser = mover::parse_chunk_size)]
+    chunk_size: u64,
+
+    /// With --files-from: paths are separated by NUL, as from `find -print0`
+    #[arg(short = '0', long, default_value_t = false, requires = "files_from")]
+    null: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find where files ended up, using the index of organized files
+    Find {
+        /// File name pattern, e.g. "invoice*" (case-insensitive)
+        pattern: String,
+
+        /// Only show files in this category
+        #[arg(long)]
+        category: Option<String>,
+
+        /// Only show files whose SHA-256 starts with this prefix
+        #[arg(long, value_name = "PREFIX")]
+        hash: Option<String>,
+    },
+
+    /// Check that organized files are still where they were put, unchanged
+    Verify {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Check against this manifest instead of the index
+        #[arg(long, value_name = "FILE")]
+        manifest: Option<PathBuf>,
+
+        /// Only check what this run moved (its ID, or `last`)
+        #[arg(long, value_name = "ID")]
+        run: Option<String>,
+    },
+
+    /// Show what a directory holds: file types by count and size, largest and oldest files
+    Analyze {
+        /// The directory to analyze, recursively (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// How many of the largest and oldest files to list
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        top: usize,
+
+        /// Report format
+        #[arg(long, value_enum, default_value_t = analyze::Output::Text)]
+        output: analyze::Output,
+    },
+
+    /// Zip old files of categories with `compress_after_days` into monthly archives
+    Compress {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Preview which files would be archived without changing anything
+        #[arg(short, long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only compress this category (repeatable)
+        #[arg(long, value_name = "NAME", value_parser = config::parse_category)]
+        category: Vec<String>,
+
+        /// Archive files older than this many days, overriding compress_after_days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+    },
+
+    /// Finish a run that was interrupted (killed, laptop slept, power loss)
+    Resume {
+        /// The directory whose run was interrupted (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Make at most N of the moves left, then stop again
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        batch_size: Option<u64>,
+
+        /// Config file (defaults to the one the interrupted run used)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// The listing recorded before the first run on a directory, and what changed since
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+
+    /// What `--shadow` logged: the moves it would have made, and the files
+    /// moved or deleted by hand first
+    Shadow {
+        #[command(subcommand)]
+        command: ShadowCommand,
+    },
+
+    /// List the profiles --profile takes, or print one (`profiles show NAME`)
+    Profiles {
+        #[command(subcommand)]
+        command: Option<ProfilesCommand>,
+    },
+
+    /// Inspect the classification rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+
+    /// List past runs, or show the moves of one (`history show <ID>`)
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommand>,
+    },
+
+    /// Move what a past run moved back where it was (see `history`)
+    Undo {
+        /// The run's ID, or `last` for the most recent one
+        id: String,
+
+        /// Only undo moves into this category and its subfolders (repeatable)
+        #[arg(long, value_name = "CATEGORY")]
+        only: Vec<String>,
+
+        /// Show what would be moved back without moving anything
+        #[arg(short, long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Delete files replaced by overwrites from the staging area
+    Purge {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Delete every staged run, not only the expired ones
+        #[arg(long, default_value_t = false, conflicts_with = "older_than")]
+        all: bool,
+
+        /// Delete runs staged more than this many days ago, instead of staging_days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+
+        /// Show what would be deleted without deleting it
+        #[arg(short, long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Show when watch mode runs passes (the config's [schedule]), or pause and
+    /// resume a watching daemon
+    Schedule {
+        /// The watched directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Hold off passes until `--resume` (or for the time given with --for)
+        #[arg(long, default_value_t = false, conflicts_with = "resume")]
+        pause: bool,
+
+        /// With --pause: resume by itself after this long, e.g. 2h or 1d
+        #[arg(long = "for", value_name = "DURATION", requires = "pause",
+              value_parser = service::parse_interval)]
+        pause_for: Option<u64>,
+
+        /// Let passes run again
+        #[arg(long, default_value_t = false)]
+        resume: bool,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Tell a watching daemon to run a pass now, pause, resume, reload its
+    /// config, report its status, or shut down
+    Ctl {
+        #[arg(value_enum)]
+        command: control::Request,
+
+        /// The watched directory (defaults to current directory)
+        path: Option<PathBuf>,
+    },
+
+    /// Move files already in category folders to where the current rules and
+    /// layout put them (e.g. after adding a category); `undo` reverses it
+    Reorganize {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Show what would move without moving anything
+        #[arg(short, long, default_value_t = false)]
+        dry_run: bool,
+
+        /// The layout files should be in, as for a run: "type", "type,date"...
+        #[arg(long, value_name = "DIMENSIONS", default_value = "type", value_parser = Layout::parse)]
+        by: Layout,
+
+        /// What to do when a file of the same name is already where one goes
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// First look for files you moved to another folder by hand: leave them
+        /// there, and learn extension rules from them (see --learn)
+        #[arg(long, default_value_t = false)]
+        learn: bool,
+    },
+
+    /// Check that a directory is organized: lists what a run would move (loose
+    /// entries, files in the wrong category folder) and exits 1 if anything
+    Check {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// The layout files should be in, as for a run: "type", "type,date"...
+        #[arg(long, value_name = "DIMENSIONS", default_value = "type", value_parser = Layout::parse)]
+        by: Layout,
+
+        /// Config file (defaults to the directory's .auto-organize.toml, else
+        /// ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Plan a run without moving anything, and write its moves as a shell
+    /// script of `mkdir -p` and `mv` commands to inspect, edit and run yourself
+    Plan {
+        /// The directory to organize (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Where to write the script
+        #[arg(long, value_name = "FILE")]
+        emit_shell: PathBuf,
+
+        /// The layout files go in, as for a run: "type", "type,date"...
+        #[arg(long, value_name = "DIMENSIONS", default_value = "type", value_parser = Layout::parse)]
+        by: Layout,
+
+        /// What to do when a file of the same name is already where one goes
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_conflict: Option<ConflictPolicy>,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Go through the stages listed in the config's [pipeline] in order:
+    /// classify, dedupe, retention, compress, prune-empty
+    Run {
+        /// The directory to run on (defaults to current directory)
+        path: Option<PathBuf>,
+
+        /// Show what each stage would do without changing anything
+        #[arg(short, long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Run on a schedule: installs a systemd timer, launchd agent or Windows scheduled task
+    InstallService {
+        /// The directory to organize (defaults to current directory)
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Time between runs, e.g. 30m, 1h, 1d (with --watch: between passes)
+        #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = service::parse_interval)]
+        interval: u64,
+
+        /// Keep one `--watch` process running instead of starting a run each time
+        #[arg(short, long, default_value_t = false)]
+        watch: bool,
+
+        /// Config file for the scheduled runs
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only print what would be installed
+        #[arg(long, default_value_t = false)]
+        print: bool,
+    },
+
+    /// Remove the service installed for a directory
+    UninstallService {
+        /// The directory it organizes (defaults to current directory)
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+
+    /// Add "Organize this folder" and "Organize this file" to the file manager's
+    /// context menu (Nautilus, Dolphin, Finder or Explorer)
+    Integrate {
+        /// Add the menu entries
+        #[arg(long, default_value_t = false, required_unless_present = "uninstall")]
+        install: bool,
+
+        /// Remove the menu entries
+        #[arg(long, default_value_t = false, conflicts_with_all = ["install", "print", "config"])]
+        uninstall: bool,
+
+        /// Config file for runs started from the menu
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Only print what would be installed
+        #[arg(long, default_value_t = false)]
+        print: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RulesCommand {
+    /// Report mappings that conflict with or shadow each other (exits 1 on conflicts)
+    Lint {
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Show the category each file name in SAMPLES (`-` for stdin, one per line)
+    /// would get, without touching any file: rules that read contents or the
+    /// file (origins, owners, shebangs, video and document inspection) are left out
+    Test {
+        samples: PathBuf,
+
+        /// Config file (defaults to ~/.config/auto-organize/config.toml if it exists)
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// List the moves of a run
+    Show {
+        /// The run's ID, or `last` for the most recent one
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfilesCommand {
+    /// Print a profile's config, e.g. to start a profile of your own from a preset
+    Show {
+        /// The profile's name, e.g. desktop-builtin
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ShadowCommand {
+    /// Summarize the log: moves by category, extensions left to Others, files
+    /// handled by hand
+    Report {
+        /// The watched directory (defaults to current directory)
+        path: Option<PathBuf>,
+    },
+
+    /// Delete the log, to start over after changing the rules
+    Clear {
+        /// The watched directory (defaults to current directory)
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommand {
+    /// List the files the directory held before auto-organize first touched it
+    Show {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+    },
+
+    /// Show where those files are now: moved, gone, changed, and what is new
+    Diff {
+        /// The organized directory (defaults to current directory)
+        path: Option<PathBuf>,
+    },
+}
+
+/// Files and folders starting with this belong to the tool itself and are never organized
+const ARTIFACT_PREFIX: &str = ".auto-organize";
+
+fn main() {
+    let args = Args::parse();
+    if let Some(dir) = &args.data_dir {
+        paths::set_data_dir(dir.clone());
+    }
+
+    match args.command {
+        Some(Command::Find {
+            ref pattern,
+            ref category,
+            ref hash,
+        }) => run_find(pattern, category.as_deref(), hash.as_deref()),
+        Some(Command::Verify {
+            ref path,
+            ref manifest,
+            ref run,
+        }) => run_verify(path.as_deref(), manifest.as_deref(), run.as_deref()),
+        Some(Command::Analyze {
+            ref path,
+            top,
+            output,
+        }) => run_analyze(path.as_deref(), top, output),
+        Some(Command::Compress {
+            ref path,
+            dry_run,
+            ref config,
+            ref category,
+            older_than,
+        }) => run_compress(
+            path.as_deref(),
+            dry_run,
+            config.as_deref(),
+            category,
+            older_than,
+        ),
+        Some(Command::Resume {
+            ref path,
+            batch_size,
+            ref config,
+        }) => run_resume(path.as_deref(), batch_size, config.as_deref()),
+        Some(Command::Snapshot {
+            command: SnapshotCommand::Show { ref path },
+        }) => run_snapshot(path.as_deref(), false),
+        Some(Command::Snapshot {
+            command: SnapshotCommand::Diff { ref path },
+        }) => run_snapshot(path.as_deref(), true),
+        Some(Command::Shadow {
+            command: ShadowCommand::Report { ref path },
+        }) => run_shadow_report(path.as_deref()),
+        Some(Command::Shadow {
+            command: ShadowCommand::Clear { ref path },
+        }) => run_shadow_clear(path.as_deref()),
+        Some(Command::Rules {
+            command: RulesCommand::Lint { ref config },
+        }) => run_rules_lint(config.as_deref()),
+        Some(Command::Rules {
+            command:
+                RulesCommand::Test {
+                    ref samples,
+                    ref config,
+                },
+        }) => run_rules_test(samples, config.as_deref()),
+        Some(Command::Profiles { command: None }) => run_profiles(),
+        Some(Command::Profiles {
+            command: Some(ProfilesCommand::Show { ref name }),
+        }) => run_profiles_show(name),
+        Some(Command::History { command: None }) => run_history(),
+        Some(Command::History {
+            command: Some(HistoryCommand::Show { ref id }),
+        }) => run_history_show(id),
+        Some(Command::Undo {
+            ref id,
+            ref only,
+            dry_run,
+        }) => run_undo(id, only, dry_run),
+        Some(Command::Purge {
+            ref path,
+            all,
+            older_than,
+            dry_run,
+            ref config,
+        }) => run_purge(path.as_deref(), all, older_than, dry_run, config.as_deref()),
+        Some(Command::Plan {
+            ref path,
+            ref emit_shell,
+            ref by,
+            on_conflict,
+            ref config,
+        }) => run_plan(
+            path.as_deref(),
+            emit_shell,
+            by.clone(),
+            on_conflict,
+            config.as_deref(),
+        ),
+        Some(Command::Reorganize {
+            ref path,
+            dry_run,
+            ref by,
+            on_conflict,
+            ref config,
+            learn,
+        }) => run_reorganize(
+            path.as_deref(),
+            dry_run,
+            by.clone(),
+            on_conflict,
+            config.as_deref(),
+            learn,
+        ),
+        Some(Command::Check {
+            ref path,
+            ref by,
+            ref config,
+        }) => run_check(path.as_deref(), by.clone(), config.as_deref()),
+        Some(Command::Run {
+            ref path,
+            dry_run,
+            ref config,
+        }) => run_pipeline(path.as_deref(), dry_run, config.as_deref()),
+        Some(Command::Schedule {
+            ref path,
+            pause,
+            pause_for,
+            resume,
+            ref config,
+        }) => run_schedule(path.as_deref(), pause, pause_for, resume, config.as_deref()),
+        Some(Command::Ctl { command, ref path }) => run_ctl(command, path.as_deref()),
+        Some(Command::InstallService {
+            ref path,
+            interval,
+            watch,
+            ref config,
+            print,
+        }) => run_install_service(
+            path.as_deref(),
+            interval,
+            watch,
+            config.as_deref(),
+            args.data_dir.as_deref(),
+            print,
+        ),
+        Some(Command::UninstallService { ref path }) => run_uninstall_service(path.as_deref()),
+        Some(Command::Integrate {
+            uninstall: true, ..
+        }) => run_uninstall_integration(),
+        Some(Command::Integrate {
+            ref config, print, ..
+        }) => run_integrate(config.as_deref(), args.data_dir.as_deref(), print),
+        None => organize(args),
+    }
+}
+
+fn organize(mut args: Args) {
+    // Shadow mode is a dry run that keeps going, and keeps what it saw
+    if args.shadow {
+        args.watch = true;
+        args.dry_run = true;
+    }
+    // Paranoid runs never overwrite, delete or merge either
+    if args.paranoid {
+        args.safe = true;
+    }
+    let mut target_dir = args.path.clone().unwrap_or_else(|| PathBuf::from("."));
+
+    // A single file (from a file manager's context menu, say) goes into the
+    // category folders next to it, or those of --dest
+    let single_file = target_dir.is_file().then(|| target_dir.clone());
+    if let Some(file) = &single_file {
+        if args.watch || args.files_from.is_some() {
+            eprintln!("Error: --watch and --files-from need a directory, not a file.");
+            std::process::exit(1);
+        }
+        target_dir = match (&args.dest, file.parent()) {
+            (Some(dest), _) => dest.clone(),
+            (None, Some(parent)) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            (None, _) => PathBuf::from("."),
+        };
+    } else if args.dest.is_some() {
+        eprintln!("Error: --dest is for organizing a single file; give the file as the path.");
+        std::process::exit(1);
+    }
+
+    if !target_dir.is_dir() {
+        eprintln!(
+            "Error: '{}' is not a valid directory.",
+            target_dir.display()
+        );
+        std::process::exit(1);
+    }
+
+    // A profile file is a config file by another name; a preset has no file
+    let preset = match args.profile.as_deref().map(profiles::find) {
+        Some(Ok(Profile::File(path))) => {
+            args.config = Some(path);
+            None
+        }
+        Some(Ok(Profile::Builtin(text))) => Some(text),
+        Some(Err(e)) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+    let config = match preset {
+        Some(text) => parse_preset(args.profile.as_deref().unwrap_or_default(), text),
+        None => load_config(args.config.as_deref()),
+    };
+
+    refuse_loops(&RuleSet::new(config.clone()), &target_dir, args.shared);
+
+    if let Some(journal_path) = journal::find(&target_dir) {
+        eprintln!(
+            "{}: an interrupted run was found in '{}'.",
+            if args.dry_run { "Warning" } else { "Error" },
+            target_dir.display()
+        );
+        eprintln!(
+            "Run `auto-organize resume` to finish it, or delete '{}' to discard it.",
+            journal_path.display()
+        );
+        if !args.dry_run {
+            std::process::exit(1);
+        }
+    }
+
+    // The list is read up front: stdin is consumed once, before anything moves
+    let listed = match single_file {
+        _ if args.reorganize => Some(listing::resolve(
+            categorized_files(&target_dir, &RuleSet::new(config.clone()), &args.by),
+            Path::new("."),
+        )),
+        Some(file) => Some(listing::resolve(vec![file], Path::new("."))),
+        None => args.files_from.as_deref().map(|source| {
+            if source == Path::new("-") && args.interactive {
+                eprintln!("Error: --interactive reads answers from stdin; use --files-from FILE");
+                std::process::exit(1);
+            }
+            match listing::read_list(source, args.null) {
+                Ok(paths) => listing::resolve(paths, &target_dir),
+                Err(e) => {
+                    eprintln!("Error reading file list '{}': {}", source.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }),
+    };
+
+    println!(
+        "Target: {}",
+        target_dir
+            .canonicalize()
+            .unwrap_or(target_dir.clone())
+            .display()
+    );
+    if args.shadow {
+        println!("Mode:   SHADOW (nothing is moved; see `auto-organize shadow report`)");
+    } else if args.dry_run {
+        println!("Mode:   DRY RUN (No changes will be made)");
+    }
+    if args.reorganize {
+        println!("Mode:   REORGANIZE (files already in category folders)");
+    }
+    if args.paranoid {
+        println!("Mode:   PARANOID (safe, every move verified, journal synced after each)");
+    } else if args.safe || config.safe_mode {
+        println!("Mode:   SAFE (nothing is overwritten, deleted or merged)");
+    }
+    if let Some(listed) = &listed {
+        println!("Files:  {} listed", listed.len());
+    }
+    // Network shares are polled for changes: other clients' writes raise no
+    // events, and a file may still be arriving when a pass runs
+    let share = if args.watch {
+        netfs::network_type(&target_dir)
+    } else {
+        None
+    };
+    let polling = match args.poll {
+        PollMode::Auto => share.is_some(),
+        PollMode::Always => true,
+        PollMode::Never => false,
+    };
+    if args.watch && polling {
+        let on = share.map(|fs_type| format!(" on {}", fs_type));
+        println!(
+            "Mode:   WATCH (polling for changes every {}s{}, Ctrl-C to stop)",
+            args.poll_interval,
+            on.unwrap_or_default()
+        );
+    } else if args.watch {
+        println!("Mode:   WATCH (every {}s, Ctrl-C to stop)", args.interval);
+    }
+    if args.watch && !config.schedule.is_empty() {
+        println!("Hours:  {}", describe_schedule(&config.schedule));
+    }
+    println!("-----------------------------------------");
+
+    if !args.dry_run {
+        record_first_snapshot(&target_dir);
+    }
+
+    // 1. Setup rules (extension map + config)
+    let mut rules = RuleSet::new(config);
+    // Corrections are looked for up front; watch mode looks before every pass
+    if args.learn && !args.watch && learn_from_corrections(&args, &target_dir, &rules) {
+        rules = RuleSet::new(load_config(args.config.as_deref()));
+    }
+    // Dry runs never write the index or a manifest
+    let mut records = Records::default();
+    if !args.dry_run {
+        if args.index {
+            records.open_index();
+        }
+        if let Some(path) = &args.manifest {
+            records.open_manifest(path, &target_dir);
+        }
+    }
+    if args.shadow {
+        records.open_shadow(&target_dir);
+    }
+
+    // Ctrl-C lets the move in progress finish (or keeps a partial copy for
+    // `resume`) and skips the rest
+    interrupt::install();
+
+    if !args.watch {
+        let result = run_pass(&args, &target_dir, listed.as_deref(), &rules, &mut records);
+        match report_pass(result, &target_dir, args.dry_run) {
+            0 => return,
+            code => std::process::exit(code),
+        }
+    }
+
+    // Watch mode: re-run on an interval, picking up config edits between passes
+    let mut watcher = args
+        .config
+        .clone()
+        .or_else(config::default_path)
+        .filter(|_| preset.is_none())
+        .map(ConfigWatcher::new);
+    let mut poller = polling.then(|| ChangePoller::new(target_dir.clone(), ARTIFACT_PREFIX));
+    let interval = match poller {
+        Some(_) => args.poll_interval,
+        None => args.interval,
+    };
+
+    let metrics = Arc::new(metrics::Metrics::default());
+    if let Some(addr) = args.metrics {
+        match metrics::serve(addr, metrics.clone()) {
+            Ok(bound) => println!("Metrics: http://{}/metrics (health: /healthz)", bound),
+            Err(e) => {
+                eprintln!("Error: cannot serve metrics on {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // `auto-organize ctl` talks to this loop; a second daemon on the same
+    // directory is refused
+    let control = match control::Control::listen(&target_dir) {
+        Ok(control) => Some(control),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            eprintln!("Error: {} ('{}')", e, target_dir.display());
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!(
+                "Warning: no control channel, `ctl` will not reach this run: {}",
+                e
+            );
+            None
+        }
+    };
+    let sleep = |duration: Duration| match &control {
+        Some(control) => control.sleep(duration),
+        None => interrupt::sleep(duration),
+    };
+
+    // Why passes are held off (a pause, or the schedule), while they are
+    let mut held: Option<String> = None;
+    loop {
+        let reload = control.as_ref().is_some_and(|c| c.take_reload());
+        if reload && watcher.is_none() {
+            println!(
+                "[{}] No config file to reload; keeping current config.",
+                DateTime::now().to_iso()
+            );
+        }
+        if let Some(watcher) = watcher.as_mut()
+            && let Some(config) = if reload {
+                watcher.reload()
+            } else {
+                watcher.poll()
+            }
+        {
+            let reloaded = RuleSet::new(config);
+            let found = loops::find(&reloaded, &target_dir, args.shared);
+            if found.is_empty() {
+                rules = reloaded;
+            } else {
+                println!(
+                    "[{}] The new config sends files back into the folder being organized; keeping the current config:",
+                    DateTime::now().to_iso()
+                );
+                for line in &found {
+                    println!("    {}", line);
+                }
+            }
+        }
+
+        // `ctl run-now` runs a pass even when on hold or nothing changed
+        let forced = control.as_ref().is_some_and(|c| c.take_run_now());
+        if !forced && let Some(reason) = hold_reason(&target_dir, rules.schedule()) {
+            if held.as_ref() != Some(&reason) {
+                println!("[{}] On hold: {}.", DateTime::now().to_iso(), reason);
+                if let Some(control) = &control {
+                    control.set_state(&format!("on hold: {}", reason));
+                }
+            }
+            held = Some(reason);
+            if !sleep(Duration::from_secs(interval)) {
+                break;
+            }
+            continue;
+        }
+        if !forced && held.take().is_some() {
+            println!("[{}] Passes resume.", DateTime::now().to_iso());
+            if let Some(control) = &control {
+                control.set_state("idle");
+            }
+        }
+
+        if !forced
+            && let Some(poller) = poller.as_mut()
+            && !poller.ready()
+        {
+            if !sleep(Duration::from_secs(interval)) {
+                break;
+            }
+            continue;
+        }
+
+        if let Some(control) = &control {
+            control.set_state("running a pass");
+        }
+        // A rule learned here is picked up with the config change next time
+        if args.learn && !args.dry_run {
+            learn_from_corrections(&args, &target_dir, &rules);
+        }
+        let started = Instant::now();
+        let pass = run_pass(&args, &target_dir, None, &rules, &mut records);
+        match &pass {
+            Ok(summary) => metrics.record_pass(
+                summary.files,
+                summary.dirs,
+                summary.errors,
+                started.elapsed(),
+            ),
+            Err(e) => metrics.record_failure(&e.to_string(), started.elapsed()),
+        }
+        if let Some(control) = &control {
+            let outcome = match &pass {
+                Ok(summary) => format!(
+                    "{} file(s), {} folder(s), {} error(s)",
+                    summary.files, summary.dirs, summary.errors
+                ),
+                Err(e) => format!("failed: {}", e),
+            };
+            control.pass_done(&schedule::local_iso(DateTime::now().to_unix()), &outcome);
+            if let Some(reason) = &held {
+                control.set_state(&format!("on hold: {}", reason));
+            }
+        }
+        match pass {
+            Ok(summary) if args.shadow => {
+                if summary.shadowed + summary.handled > 0 {
+                    println!(
+                        "[{}] Logged {} new move(s); {} logged file(s) were moved or deleted by hand.",
+                        DateTime::now().to_iso(),
+                        summary.shadowed,
+                        summary.handled
+                    );
+                }
+            }
+            Ok(summary) if summary.is_empty() && !summary.cancelled => {}
+            Ok(summary) => {
+                print!("[{}] ", DateTime::now().to_iso());
+                summary.print();
+                if summary.cancelled {
+                    print_stopped(&summary, &target_dir, args.dry_run);
+                    std::process::exit(130);
+                }
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        }
+        if let Some(poller) = poller.as_mut() {
+            poller.passed();
+        }
+
+        if !sleep(Duration::from_secs(interval)) {
+            break;
+        }
+    }
+    println!("Stopped watching {}.", target_dir.display());
+}
+
+/// Looks for files moved by hand since earlier runs (see `corrections`),
+/// records them so runs leave them there, and suggests the extension rules
+/// they argue for, or with `--learn` saves them to the config. Returns
+/// whether the config changed.
+fn learn_from_corrections(args: &Args, target_dir: &Path, rules: &RuleSet) -> bool {
+    let known = match corrections::load(target_dir) {
+        Ok(known) => known,
+        Err(e) => {
+            eprintln!("Warning: cannot read the corrections: {}", e);
+            return false;
+        }
+    };
+    let found = match corrections::detect(target_dir, &known) {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("Warning: cannot look for corrections: {}", e);
+            return false;
+        }
+    };
+    for correction in &found {
+        let moved = match &correction.to {
+            Placement::Root => format!("{} -> back here", correction.from),
+            Placement::Category(to) => format!("{} -> {}", correction.from, to),
+            Placement::Gone => continue,
+        };
+        println!(
+            "[CORRECTED] {:?} ({}; it stays there)",
+            correction.name, moved
+        );
+    }
+    if !args.dry_run
+        && !found.is_empty()
+        && let Err(e) = corrections::save(target_dir, &found)
+    {
+        eprintln!("Warning: cannot record the corrections: {}", e);
+    }
+    // Suggestions are made when there is something new to go on
+    let new = found.iter().any(|c| matches!(c.to, Placement::Category(_)));
+    if !new && !args.learn {
+        return false;
+    }
+
+    let preset = args.profile.as_deref().is_some_and(profiles::is_builtin);
+    let config_path = (!args.dry_run && args.learn && !preset)
+        .then(|| args.config.clone().or_else(config::default_path))
+        .flatten();
+    let mut changed = false;
+    for Suggestion {
+        extension,
+        category,
+        count,
+    } in corrections::suggestions(&[known, found].concat())
+    {
+        if rules.classify_name(&format!("file.{}", extension)) == category {
+            continue;
+        }
+        match &config_path {
+            Some(path) => match config::set_string(path, "extensions", &extension, &category) {
+                Ok(()) => {
+                    println!(
+                        "    Learned: .{} -> {} ({} correction(s); saved to {})",
+                        extension,
+                        category,
+                        count,
+                        path.display()
+                    );
+                    changed = true;
+                }
+                Err(e) => eprintln!("Error saving to config '{}': {}", path.display(), e),
+            },
+            None => println!(
+                "    Suggestion: .{} -> {} ({} correction(s); --learn saves it to the config)",
+                extension, category, count
+            ),
+        }
+    }
+    changed
+}
+
+/// Stops before anything moves when the config sends files somewhere later
+/// runs over `target_dir` would organize again (see `loops::find`)
+fn refuse_loops(rules: &RuleSet, target_dir: &Path, shared: bool) {
+    let found = loops::find(rules, target_dir, shared);
+    if found.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Error: the config sends files back into the folder being organized ('{}'):",
+        target_dir.display()
+    );
+    for line in &found {
+        eprintln!("  {}", line);
+    }
+    std::process::exit(1);
+}
+
+/// Why watch passes on `target_dir` should not run now, if they should not
+fn hold_reason(target_dir: &Path, schedule: &schedule::Schedule) -> Option<String> {
+    match schedule::paused(target_dir) {
+        Some(Some(until)) => {
+            return Some(format!(
+                "paused until {} local time",
+                schedule::local_iso(until)
+            ));
+        }
+        Some(None) => {
+            return Some(format!(
+                "paused (`auto-organize schedule --resume {}` to resume)",
+                target_dir.display()
+            ));
+        }
+        None => {}
+    }
+    let now = DateTime::now_local();
+    if schedule.allows(now) {
+        return None;
+    }
+    Some(match schedule.next_allowed(now) {
+        Some(next) => format!("outside the schedule until {} local time", next.to_iso()),
+        None => "the schedule allows no time at all".to_string(),
+    })
+}
+
+/// "02:00-05:00; never Mon-Fri 09:00-18:00"
+fn describe_schedule(schedule: &schedule::Schedule) -> String {
+    let list = |windows: &[schedule::Window]| {
+        windows
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match (schedule.active.is_empty(), schedule.quiet.is_empty()) {
+        (false, true) => list(&schedule.active),
+        (true, false) => format!("any time but {}", list(&schedule.quiet)),
+        (false, false) => format!(
+            "{}, but never {}",
+            list(&schedule.active),
+            list(&schedule.quiet)
+        ),
+        (true, true) => "any time".to_string(),
+    }
+}
+
+/// After a `--batch-size` batch: what is left, and how to move the next one
+fn print_next_batch(summary: &PassSummary, target_dir: &Path) {
+    if let Some(size) = summary.batch {
+        println!(
+            "Batch done: {} planned move(s) left. Make the next {} with `auto-organize resume --batch-size {} {}`.",
+            summary.remaining,
+            size,
+            size,
+            target_dir.display()
+        );
+    }
+}
+
+/// After Ctrl-C: what was left undone, and how to finish it
+fn print_stopped(summary: &PassSummary, target_dir: &Path, dry_run: bool) {
+    if summary.remaining == 0 || dry_run {
+        return;
+    }
+    if journal::find(target_dir).is_some() {
+        println!(
+            "Stopped by Ctrl-C: {} planned move(s) left. Finish them with `auto-organize resume {}`.",
+            summary.remaining,
+            target_dir.display()
+        );
+    } else {
+        println!(
+            "Stopped by Ctrl-C: {} planned move(s) left for the next run.",
+            summary.remaining
+        );
+    }
+}
+
+/// `--tag-only`: records each file's category as a tag and moves nothing
+fn tag_files(files: &[(PathBuf, (String, Facts))], dry_run: bool) -> PassSummary {
+    let mut tagger = Tagger::default();
+    let mut summary = PassSummary::default();
+    for (path, (category, _)) in files {
+        let name = path.file_name().unwrap_or_default();
+        if dry_run {
+            println!("[{:<12}] {:?} (tag)", category, name);
+            summary.files += 1;
+            continue;
+        }
+        match tagger.tag(path, category) {
+            Ok(Store::Attribute) => println!("[{:<12}] {:?} (tagged)", category, name),
+            Ok(Store::Sidecar) => println!(
+                "[{:<12}] {:?} (tagged in {})",
+                category,
+                name,
+                tags::SIDECAR
+            ),
+            Err(e) => {
+                eprintln!("Error tagging {:?}: {}", name, e);
+                continue;
+            }
+        }
+        summary.files += 1;
+    }
+    if let Err(e) = tagger.finish() {
+        eprintln!("Error writing {}: {}", tags::SIDECAR, e);
+    }
+    summary
+}
+
+/// Counts of what one organize pass did
+#[derive(Debug, Default)]
+struct PassSummary {
+    files: usize,
+    dirs: usize,
+    retired: usize,
+    /// Installers of programs already installed, moved out of APPS
+    installed: usize,
+    compressed: usize,
+    archives: usize,
+    /// Files left where they are because their folder may not be created
+    missing_folder: usize,
+    /// Files left where they are because of their category's `skip_larger_than`
+    too_large: usize,
+    /// Files and folders left for later because a download into them is in progress
+    deferred: usize,
+    /// Folders left in place because one of their name is there, and how
+    /// many of those would merge without a conflict
+    unmerged: usize,
+    mergeable: usize,
+    /// Files replaced by overwrites, kept in this staging folder
+    staged: usize,
+    staging_dir: Option<PathBuf>,
+    /// Planned moves not made because this budget ran out, or Ctrl-C
+    remaining: usize,
+    budget: Option<String>,
+    cancelled: bool,
+    /// `--batch-size`: the batch ended with moves left in the journal
+    batch: Option<u64>,
+    /// The ID of the run, when it is journaled (and so in the history)
+    run: Option<String>,
+    /// Moves (or other steps) that failed
+    errors: usize,
+    /// `--verify-run`: problems found after applying the plan
+    discrepancies: Option<usize>,
+    /// `plan --emit-shell`: where the moves were written
+    script: Option<PathBuf>,
+    /// Files trashed, copied, linked or handed to a program by their category's action
+    acted: usize,
+    /// How the category folders changed since the previous run
+    changes: Vec<String>,
+    /// Shadow mode: moves logged for the first time, and logged files that
+    /// were moved or deleted by hand since
+    shadowed: usize,
+    handled: usize,
+    /// Numbered copies of downloads trashed as duplicates of another copy
+    collapsed: usize,
+    /// Files found empty or not matching their extension, and what became of them
+    empty: usize,
+    corrupt: usize,
+    damaged_outcome: &'static str,
+}
+
+impl PassSummary {
+    fn is_empty(&self) -> bool {
+        self.files == 0
+            && self.dirs == 0
+            && self.retired == 0
+            && self.installed == 0
+            && self.compressed == 0
+            && self.acted == 0
+            && self.budget.is_none()
+    }
+
+    fn print(&self) {
+        println!(
+            "{}. {} files and {} folders processed.",
+            if self.cancelled { "Stopped" } else { "Done" },
+            self.files,
+            self.dirs
+        );
+        if let Some(run) = &self.run {
+            println!("Run ID: {} (`auto-organize history show {}`).", run, run);
+        }
+        if self.acted > 0 {
+            println!(
+                "Dealt with {} file(s) by their category's action.",
+                self.acted
+            );
+        }
+        if self.retired > 0 {
+            println!("Retired {} old installer(s).", self.retired);
+        }
+        if self.installed > 0 {
+            println!(
+                "Moved {} installer(s) of installed programs out of APPS.",
+                self.installed
+            );
+        }
+        if self.compressed > 0 {
+            println!(
+                "Compressed {} old file(s) into {} archive(s).",
+                self.compressed, self.archives
+            );
+        }
+        if self.staged > 0
+            && let Some(dir) = &self.staging_dir
+        {
+            println!(
+                "Kept {} replaced file(s) in {} (removed automatically, or now with `auto-organize purge`).",
+                self.staged,
+                dir.display()
+            );
+        }
+        if self.missing_folder > 0 {
+            println!(
+                "Left {} file(s) in place: their category folder does not exist (create_if_missing = false).",
+                self.missing_folder
+            );
+        }
+        if self.too_large > 0 {
+            println!(
+                "Left {} file(s) in place: larger than their category's skip_larger_than.",
+                self.too_large
+            );
+        }
+        if self.deferred > 0 {
+            println!(
+                "Left {} file(s) of downloads in progress for a later run.",
+                self.deferred
+            );
+        }
+        if self.unmerged > 0 {
+            println!(
+                "Left {} folder(s) in place: a folder of the same name is there ({} would merge \
+                 without conflicts; see --merge-dirs).",
+                self.unmerged, self.mergeable
+            );
+        }
+        if self.collapsed > 0 {
+            println!(
+                "Trashed {} numbered copy(ies) of downloads with the same contents as another.",
+                self.collapsed
+            );
+        }
+        if self.empty + self.corrupt > 0 {
+            println!(
+                "Found {} empty and {} corrupt file(s), likely failed downloads: {}.",
+                self.empty, self.corrupt, self.damaged_outcome
+            );
+        }
+        if let Some(budget) = &self.budget {
+            println!(
+                "Stopped at {}: {} planned move(s) left for the next run.",
+                budget, self.remaining
+            );
+        }
+        match self.discrepancies {
+            Some(0) => println!("Verified: every move landed and nothing went missing."),
+            Some(found) => println!("Verification found {} problem(s) (listed above).", found),
+            None => {}
+        }
+        if let Some(script) = &self.script {
+            println!(
+                "Wrote the moves to {} (run it with `sh {}`, or on a copy: `sh {} DIR`).",
+                script.display(),
+                script.display(),
+                script.display()
+            );
+        }
+        if !self.changes.is_empty() {
+            println!("Since last run: {}", self.changes.join(", "));
+        }
+    }
+}
+
+/// Entries of each kind listed when a folder is compared with the one in its way
+const DIFF_LINES: usize = 20;
+
+/// Prints a run as it is planned and applied: one line per move or skip
+/// on `out` (through `format` if given), errors on stderr
+struct Console<'a, W: Write> {
+    out: W,
+    format: Option<&'a Template>,
+    /// The ID of the run, for `{run}` in `format`
+    run: &'a str,
+    /// What moves are applied to (the overlay in a dry run)
+    fs: Arc<dyn FileSystem>,
+    /// Steps that failed so far
+    errors: usize,
+    /// Folders left in place because one of their name is there, and how
+    /// many of those would merge without a conflict
+    unmerged: usize,
+    mergeable: usize,
+    /// Shadow mode: the pass prints only moves it has not logged before
+    quiet: bool,
+    /// How category tags look
+    style: &'a Style,
+}
+
+impl<W: Write> Console<'_, W> {
+    fn print_move(&mut self, item: &PlannedMove) {
+        let name = item.src.file_name().unwrap_or_default();
+        let _ = match self.format {
+            Some(template) => writeln!(self.out, "{}", plan::render(template, item, self.run)),
+            None => match item.kind {
+                plan::EntryKind::File => {
+                    let dest_name = item.dest.file_name().unwrap_or_default();
+                    if dest_name == name {
+                        writeln!(self.out, "{} {:?}", self.style.tag(&item.category), name)
+                    } else {
+                        writeln!(
+                            self.out,
+                            "{} {:?} -> {:?}",
+                            self.style.tag(&item.category),
+                            name,
+                            dest_name
+                        )
+                    }
+                }
+                plan::EntryKind::Dir => {
+                    let tag = self.style.tag(&item.category);
+                    writeln!(self.out, "{} (Directory) {:?}", tag, name)
+                }
+                plan::EntryKind::Merge => {
+                    let tag = self.style.tag(&item.category);
+                    writeln!(self.out, "{} (Merged) {:?}", tag, name)
+                }
+            },
+        };
+    }
+}
+
+impl<W: Write> Observer for Console<'_, W> {
+    fn on_skip(&mut self, name: &OsStr, is_dir: bool, reason: &str) {
+        if self.quiet {
+            return;
+        }
+        let tag = if is_dir { "SKIP DIR" } else { "SKIP" };
+        let _ = writeln!(self.out, "[{}] {:?} ({})", tag, name, reason);
+    }
+
+    fn on_dir_exists(&mut self, src: &Path, dest: &Path) {
+        let diff = match dirdiff::compare(self.fs.as_ref(), src, dest) {
+            Ok(diff) => diff,
+            Err(e) => {
+                if !self.quiet {
+                    let _ = writeln!(self.out, "    (cannot compare it with {:?}: {})", dest, e);
+                }
+                return;
+            }
+        };
+        self.unmerged += 1;
+        if diff.merges_cleanly() {
+            self.mergeable += 1;
+        }
+        if self.quiet {
+            return;
+        }
+        let _ = writeln!(
+            self.out,
+            "    {} file(s) the same; {} only in this folder (+), {} only in {:?} (-), {} different (~)",
+            diff.same,
+            diff.only_in_src.len(),
+            diff.only_in_dest.len(),
+            dest,
+            diff.differing.len()
+        );
+        for (mark, paths) in [
+            ('+', &diff.only_in_src),
+            ('-', &diff.only_in_dest),
+            ('~', &diff.differing),
+        ] {
+            for path in paths.iter().take(DIFF_LINES) {
+                let _ = writeln!(self.out, "      {} {}", mark, path.display());
+            }
+            if paths.len() > DIFF_LINES {
+                let _ = writeln!(
+                    self.out,
+                    "      {} ... and {} more",
+                    mark,
+                    paths.len() - DIFF_LINES
+                );
+            }
+        }
+        let verdict = if diff.merges_cleanly() {
+            "--merge-dirs would only add entries"
+        } else {
+            "with --merge-dirs, the files that differ go through the conflict policy"
+        };
+        let _ = writeln!(self.out, "    {}", verdict);
+    }
+
+    fn on_shortened(&mut self, src: &Path, _dest: &Path) {
+        if self.quiet {
+            return;
+        }
+        let _ = writeln!(
+            self.out,
+            "[SHORTEN] {:?} (its destination is too long for the filesystem; the name is cut)",
+            src.file_name().unwrap_or_default()
+        );
+    }
+
+    fn on_move_start(&mut self, item: &PlannedMove) {
+        if !self.quiet {
+            self.print_move(item);
+        }
+    }
+
+    fn on_move_complete(&mut self, item: &PlannedMove) {
+        if item.kind == plan::EntryKind::Merge && self.fs.metadata(&item.src).is_ok() {
+            let name = item.src.file_name().unwrap_or_default();
+            let _ = writeln!(
+                self.out,
+                "    {:?} kept: some entries were not merged",
+                name
+            );
+        }
+    }
+
+    fn on_error(&mut self, error: &OrganizeError) {
+        let name = error.path().file_name().unwrap_or_default();
+        if let OrganizeError::Cancelled { .. } = error {
+            let _ = writeln!(
+                self.out,
+                "[STOPPED] {:?} (what was copied is kept for resume)",
+                name
+            );
+            return;
+        }
+        self.errors += 1;
+        // Keep the error next to the line of the move it belongs to
+        let _ = self.out.flush();
+        match error.step() {
+            Step::ReadDir => eprintln!("Error reading directory {:?}: {}", name, error),
+            Step::CreateFolder => eprintln!("Error creating dir: {}", error),
+            Step::Stage => eprintln!("Error staging the file {:?} replaces: {}", name, error),
+            Step::MoveFile => eprintln!("Error moving {:?}: {}", name, error),
+            Step::MoveDir => eprintln!("Error moving directory {:?}: {}", name, error),
+            Step::RemoveMerged => {
+                eprintln!("Error removing merged directory {:?}: {}", name, error)
+            }
+        }
+    }
+}
+
+/// What the layout may sort an entry by besides its category
+fn facts_for(args: &Args, path: &Path, meta: Option<&fs::Metadata>) -> Facts {
+    Facts {
+        modified: meta
+            .and_then(|m| m.modified().ok())
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+        owner: match meta {
+            Some(meta) if args.by.uses_owner() => owner::owner_of(path, meta),
+            _ => None,
+        },
+        origin: if args.by.uses_origin() {
+            origin::domain_of(path)
+        } else {
+            None
+        },
+    }
+}
+
+/// The folder categories are created in for `path`: the target, or with
+/// `--shared` the tree of the user it belongs to (`None` if there is none),
+/// with the user id to give it to
+fn destination_root(
+    args: &Args,
+    rules: &RuleSet,
+    target_dir: &Path,
+    path: &Path,
+) -> Option<(PathBuf, Option<u32>)> {
+    if !args.shared {
+        return Some((target_dir.to_path_buf(), None));
+    }
+    let meta = fs::symlink_metadata(path).ok()?;
+    let user = shared::user_for(path, &meta, rules.shared())?;
+    Some((shared::user_root(rules.shared(), &user.name), user.uid))
+}
+
+/// Gives an entry moved by `--shared` to the user it was planned for; their
+/// tree is the folder it went into under the shared root
+fn hand_over(config: &config::SharedConfig, item: &PlannedMove, out: &mut impl Write) {
+    let Some(folder) = item
+        .dest
+        .strip_prefix(&config.root)
+        .ok()
+        .and_then(|rest| rest.iter().next())
+    else {
+        return;
+    };
+    // Windows has no user ids: the ACLs of the shared root do this
+    let Some(uid) = item.recipient else {
+        if cfg!(unix) {
+            let _ = out.flush();
+            eprintln!(
+                "Warning: cannot give {:?} to {}: no account of that name",
+                item.dest,
+                folder.to_string_lossy()
+            );
+        }
+        return;
+    };
+    if let Err(e) = shared::hand_over(config, &config.root.join(folder), uid, &item.dest) {
+        let _ = out.flush();
+        eprintln!(
+            "Warning: cannot give {:?} to user {}: {}",
+            item.dest, uid, e
+        );
+    }
+}
+
+/// Prints how a single pass went; returns the exit status it calls for: 130
+/// when Ctrl-C stopped it, 1 after errors or discrepancies, else 0
+fn report_pass(result: io::Result<PassSummary>, target_dir: &Path, dry_run: bool) -> i32 {
+    let summary = match result {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    println!("-----------------------------------------");
+    summary.print();
+    if summary.cancelled {
+        print_stopped(&summary, target_dir, dry_run);
+        return 130;
+    }
+    print_next_batch(&summary, target_dir);
+    if summary.errors > 0 || summary.discrepancies.is_some_and(|found| found > 0) {
+        return 1;
+    }
+    0
+}
+
+/// Organizes the top level of `target_dir` once
+fn run_pass(
+    args: &Args,
+    target_dir: &Path,
+    listed: Option<&[PathBuf]>,
+    rules: &RuleSet,
+    records: &mut Records,
+) -> io::Result<PassSummary> {
+    // These folders will NOT be moved if they already exist
+    let mut protected_folders = rules.protected_folders();
+    protected_folders.extend(args.protect.iter().cloned());
+    // A shared root inside the target is where files go, not a loose folder
+    if args.shared
+        && let Ok(inside) = rules.shared().root.strip_prefix(target_dir)
+        && let Some(top) = inside.iter().next()
+    {
+        protected_folders.insert(top.to_string_lossy().into_owned());
+    }
+
+    let safe = safe_mode(args, rules);
+    // Broken downloads are left alone rather than trashed in safe mode
+    let empty_files = args.empty_files.map(|policy| match policy {
+        EmptyFiles::Delete if safe => EmptyFiles::Skip,
+        policy => policy,
+    });
+
+    // Files replaced by earlier runs are deleted once they have been staged long enough
+    let keep_days = rules.staging_days();
+    if !args.dry_run && !safe && keep_days > 0 {
+        match staging::purge(target_dir, keep_days, false) {
+            Ok(purged) => {
+                for run in purged {
+                    println!("[PURGE] {:?} (staged over {} days ago)", run, keep_days);
+                }
+            }
+            Err(e) => eprintln!("Warning: cannot purge the staging area: {}", e),
+        }
+    }
+
+    // 2. Read directory (or the given list) and plan every move before touching anything
+    let mut entries: VecDeque<Entry> = match listed {
+        Some(paths) => paths.iter().cloned().map(Entry::Named).collect(),
+        None => fs::read_dir(target_dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot read directory: {}", e)))?
+            .flatten()
+            .map(Entry::Listed)
+            .collect(),
+    };
+    let order = args.order.or(rules.order()).unwrap_or(Order::Name);
+    if listed.is_none() || args.files_from.is_none() || args.order.is_some() {
+        listing::sort(entries.make_contiguous(), order);
+    }
+    // Listed paths are canonical; the base must be too to compare them
+    let base = match listed {
+        Some(_) => target_dir.canonicalize()?,
+        None => target_dir.to_path_buf(),
+    };
+
+    // Every artifact of the pass (journal, index and manifest rows, programs
+    // run by actions) carries its ID
+    let run_id = history::new_id();
+    let mut plan: Vec<PlannedMove> = Vec::new();
+    // Folders of categories with permissions set, and whether they were there
+    // before the run
+    let mut guarded: BTreeMap<PathBuf, (String, bool)> = BTreeMap::new();
+    // Each file with its category and what else the layout may sort it by
+    let mut files: Vec<(PathBuf, (String, Facts))> = Vec::new();
+    // Broken files for `--empty-files delete`, trashed once the plan is applied
+    let mut doomed: Vec<(PathBuf, String)> = Vec::new();
+    let (mut empty, mut corrupt) = (0, 0);
+    // Numbered copies of downloads trashed as duplicates
+    let mut collapsed = 0;
+    // A dry run plays the plan out on an overlay of the disk, so it reports
+    // the same folders, names and failures a real run would
+    let limits = rules.fs_limits(throttle::Limits {
+        ops_per_second: args.fs_ops_limit,
+        transfers: args.max_transfers,
+    });
+    let real = RealFs {
+        copy: CopyOptions {
+            chunk_size: args.chunk_size as usize,
+            bwlimit: args.bwlimit,
+        },
+    };
+    let fs: Arc<dyn FileSystem> = if args.dry_run {
+        Arc::new(OverlayFs::new())
+    } else if limits.is_empty() {
+        Arc::new(real)
+    } else {
+        Arc::new(throttle::Throttled::new(real, limits))
+    };
+    let mut dests = DestCache::new(Box::new(fs.clone()));
+
+    // One line per entry adds up on large runs: write them in blocks, and
+    // flush before anything else (prompts, errors) reaches the terminal
+    let style = Style::new(args.color.enabled(), rules.labels());
+    let mut console = Console {
+        out: BufWriter::new(io::stdout().lock()),
+        format: args.format.as_ref(),
+        run: &run_id,
+        fs,
+        errors: 0,
+        unmerged: 0,
+        mergeable: 0,
+        quiet: args.shadow,
+        style: &style,
+    };
+
+    let mut teacher = args.interactive.then(|| {
+        // Answers are only saved for real runs, and only when learning is on
+        // (nowhere with a preset, which has no file)
+        let preset = args.profile.as_deref().is_some_and(profiles::is_builtin);
+        let config_path = (!args.dry_run && !args.no_learn && !preset)
+            .then(|| args.config.clone().or_else(config::default_path))
+            .flatten();
+        Teacher::new(config_path, rules.categories())
+    });
+
+    // Folders --recursive emptied (or will), parents first
+    let mut walked: Vec<PathBuf> = Vec::new();
+    // Mount points are skipped unless --cross-filesystems
+    let mounts = (!args.cross_filesystems).then(MountTable::load);
+    // Downloads still in progress wait for a later run
+    let mut downloads = Downloads::new(&rules.temp_extensions());
+    let mime_filter = mime::MimeFilter::new(&args.skip_mime, &args.only_mime);
+    let mut deferred = 0;
+    let pinned = match corrections::load(target_dir) {
+        Ok(known) => corrections::pinned(&known),
+        Err(e) => {
+            eprintln!("Warning: cannot read the corrections: {}", e);
+            HashMap::new()
+        }
+    };
+
+    while let Some(entry) = entries.pop_front() {
+        if interrupt::requested() {
+            break;
+        }
+        let path = entry.path();
+        let file_name = entry.file_name();
+
+        // Listed paths can be anything: only files not already organized are
+        // moved, and nothing from our own folders (staged files, say), as
+        // the walk of the directory never goes there
+        if let Entry::Named(_) = entry {
+            let ours = path
+                .strip_prefix(&base)
+                .unwrap_or(&path)
+                .iter()
+                .any(|part| part.to_string_lossy().starts_with(ARTIFACT_PREFIX));
+            let reason = match entry.file_type() {
+                Err(_) => Some("not found".to_string()),
+                Ok(t) if t.is_dir() => Some("a folder; list files only".to_string()),
+                Ok(_) if ours => Some("kept by auto-organize itself".to_string()),
+                // Reorganizing lists files of the category folders themselves
+                Ok(_) if args.reorganize => None,
+                Ok(_) => listing::top_folder(&path, &base)
+                    .filter(|top| protected_folders.contains(top) || args.by.owns_folder(top))
+                    .map(|top| format!("already in {}", top)),
+            };
+            if let Some(reason) = reason {
+                console.on_skip(path.as_os_str(), false, &reason);
+                continue;
+            }
+        }
+
+        // Our own journal/state files stay where they are
+        if file_name.to_string_lossy().starts_with(ARTIFACT_PREFIX) || file_name == tags::SIDECAR {
+            continue;
+        }
+
+        // Where the user put a file after an earlier run organized it
+        if !pinned.is_empty() && corrections::is_pinned(&pinned, &path) {
+            console.on_skip(&file_name, false, "put there by you");
+            continue;
+        }
+
+        // Files a sync tool is still writing (or keeps for itself) stay put
+        let sync_state = rules.sync_file(&file_name.to_string_lossy());
+        if sync_state == Some(sync::SyncFile::Ignored) {
+            console.on_skip(&file_name, false, "sync tool file");
+            continue;
+        }
+
+        // The directory listing already carries the type; only symlinks need
+        // a stat to see where they point
+        let is_dir = match entry.file_type() {
+            Ok(t) if t.is_symlink() => path.is_dir(),
+            Ok(t) => t.is_dir(),
+            Err(_) => path.is_dir(),
+        };
+
+        // --- Handle Directories ---
+        if is_dir {
+            // Get the folder name (e.g., "images" from "/Downloads/images")
+            if let Some(folder_name) = path.file_name().and_then(|n| n.to_str()) {
+                // If the folder is one of our categories (or dates), SKIP it.
+                let top_level = path.parent() == Some(target_dir);
+                if top_level
+                    && (protected_folders.contains(folder_name) || args.by.owns_folder(folder_name))
+                {
+                    continue;
+                }
+
+                // A torrent still filling its folder (or Safari's .download)
+                if let Some(reason) = downloads.in_progress(&path, true) {
+                    console.on_skip(&file_name, true, &reason);
+                    deferred += 1;
+                    continue;
+                }
+
+                // A mounted backup drive is not a loose folder, nor part of this one
+                if let Some(mounts) = &mounts
+                    && mounts.is_mount_point(&path)
+                {
+                    console.on_skip(&file_name, true, "a mount point; see --cross-filesystems");
+                    continue;
+                }
+
+                // An app or library bundle is one file to the user: it goes where
+                // a file of its kind would, whole, even with --recursive
+                if rules.is_bundle(folder_name) {
+                    if args.tag_only {
+                        continue;
+                    }
+                    let Some((root, recipient)) = destination_root(args, rules, target_dir, &path)
+                    else {
+                        console.on_skip(&file_name, true, "no user to give it to");
+                        continue;
+                    };
+                    let category = rules.classify_name(folder_name);
+                    let facts = facts_for(args, &path, entry.metadata().ok().as_ref());
+                    if let Some(required) = rules.existing_folder_required(&category)
+                        && let Some(folder) = args.by.category_folder(required, &facts)
+                        && dests.lookup(&root.join(&folder)) != Some(true)
+                    {
+                        let reason = format!("folder {:?} does not exist", folder);
+                        console.on_skip(&file_name, true, &reason);
+                        continue;
+                    }
+                    let mut folder = args.by.folder(&category, &facts);
+                    if args.keep_structure
+                        && let Some(from) = path.parent().and_then(|p| p.strip_prefix(&base).ok())
+                        && !from.as_os_str().is_empty()
+                    {
+                        folder = Path::new(&folder).join(from).to_string_lossy().into_owned();
+                    }
+                    // Merging would mix the insides of two apps
+                    let items = plan::plan_directory(
+                        &path,
+                        &root,
+                        &folder,
+                        conflict_policy(args, rules, &category),
+                        false,
+                        &mut dests,
+                        &mut console,
+                    );
+                    plan.extend(
+                        items
+                            .into_iter()
+                            .map(|item| PlannedMove { recipient, ..item }),
+                    );
+                    continue;
+                }
+
+                // --recursive organizes what is inside instead (symlinks are never followed)
+                if args.recursive && entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    match fs::read_dir(&path) {
+                        Ok(children) => {
+                            let before = entries.len();
+                            let mut children: Vec<Entry> =
+                                children.flatten().map(Entry::Listed).collect();
+                            listing::sort(&mut children, order);
+                            entries.extend(children);
+                            if entries.len() > before {
+                                walked.push(path);
+                            }
+                        }
+                        Err(e) => {
+                            let _ = console.out.flush();
+                            eprintln!("Error reading {:?}: {}", path, e);
+                        }
+                    }
+                    continue;
+                }
+
+                // Otherwise, it's a loose folder. Move it to "Folders" (or its category)
+                let policy = args
+                    .dirs
+                    .or(rules.dir_policy())
+                    .unwrap_or(DirPolicy::Folders);
+                if args.tag_only || policy == DirPolicy::Skip {
+                    continue;
+                }
+                let Some((root, recipient)) = destination_root(args, rules, target_dir, &path)
+                else {
+                    console.on_skip(&file_name, true, "no user to give it to");
+                    continue;
+                };
+                let container = match policy {
+                    DirPolicy::Classify => rules
+                        .classify_folder(&path)
+                        .filter(|category| match rules.existing_folder_required(category) {
+                            Some(required) => dests.lookup(&root.join(required)) == Some(true),
+                            None => true,
+                        })
+                        .unwrap_or_else(|| "Folders".to_string()),
+                    _ => "Folders".to_string(),
+                };
+                let items = plan::plan_directory(
+                    &path,
+                    &root,
+                    &container,
+                    conflict_policy(args, rules, &container),
+                    args.merge_dirs && !safe,
+                    &mut dests,
+                    &mut console,
+                );
+                plan.extend(
+                    items
+                        .into_iter()
+                        .map(|item| PlannedMove { recipient, ..item }),
+                );
+            }
+            continue;
+        }
+
+        // Named pipes, sockets and devices are never moved, nor opened to classify them
+        if let Some(kind) = entry.special() {
+            console.on_skip(&file_name, false, &format!("a {}", kind));
+            continue;
+        }
+
+        // --- Handle Files ---
+        // Browsers write into x.crdownload (Firefox into x.part beside x): the
+        // file moves once its download is done
+        if let Some(reason) = downloads.in_progress(&path, false) {
+            console.on_skip(&file_name, false, &reason);
+            deferred += 1;
+            continue;
+        }
+        let meta = entry.metadata().ok();
+        let facts = facts_for(args, &path, meta.as_ref());
+
+        // Checked before classifying: sniffing contents would trigger a download
+        if meta.as_ref().is_some_and(cloud::is_placeholder_metadata) {
+            let name = &file_name;
+            match args.cloud_placeholders {
+                CloudPlaceholders::Skip => {
+                    console.on_skip(name, false, "online-only cloud file");
+                    continue;
+                }
+                CloudPlaceholders::Hydrate if args.dry_run => {
+                    let _ = writeln!(
+                        console.out,
+                        "[DOWNLOAD] {:?} (online-only cloud file)",
+                        name
+                    );
+                }
+                CloudPlaceholders::Hydrate => {
+                    // Downloads take a while: show what is happening now
+                    let _ = writeln!(
+                        console.out,
+                        "[DOWNLOAD] {:?} (online-only cloud file)",
+                        name
+                    );
+                    let _ = console.out.flush();
+                    match cloud::hydrate(&path) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            console.on_skip(name, false, "still online-only after download");
+                            continue;
+                        }
+                        Err(e) => {
+                            eprintln!("Error downloading {:?}: {}", name, e);
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(reason) = mime_filter.excludes(&path) {
+            console.on_skip(&file_name, false, &reason);
+            continue;
+        }
+
+        if let Some(policy) = empty_files
+            && let Some(len) = meta.as_ref().map(fs::Metadata::len)
+        {
+            match damaged::inspect(&path, len) {
+                Ok(Some(damage)) => {
+                    match damage {
+                        Damage::Empty => empty += 1,
+                        Damage::Corrupt => corrupt += 1,
+                    }
+                    match policy {
+                        EmptyFiles::Skip => console.on_skip(&file_name, false, damage.describe()),
+                        EmptyFiles::Others => files.push((path, ("Others".to_string(), facts))),
+                        EmptyFiles::Quarantine => {
+                            files.push((path, (damaged::QUARANTINE_CATEGORY.to_string(), facts)))
+                        }
+                        EmptyFiles::Delete => doomed.push((path, damage.describe().to_string())),
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    let _ = console.out.flush();
+                    eprintln!("Error reading {:?}: {}", file_name, e);
+                }
+            }
+        }
+
+        if sync_state == Some(sync::SyncFile::Conflict) {
+            files.push((path, (sync::CONFLICTS_CATEGORY.to_string(), facts)));
+            continue;
+        }
+
+        let (mut category, ambiguity) = rules.classify_checked(&path);
+        if let Some(ambiguity) = ambiguity {
+            let _ = writeln!(
+                console.out,
+                "[AMBIGUOUS] {:?} (a {} named like {}: {} and {} both claim it, so Others)",
+                file_name,
+                ambiguity.contents,
+                ambiguity.by_name,
+                ambiguity.by_name,
+                ambiguity.by_contents
+            );
+        }
+
+        if let Some(teacher) = teacher.as_mut() {
+            let ext = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_lowercase())
+                .unwrap_or_default();
+            if !ext.is_empty() && !rules.knows_extension(&ext) {
+                let _ = console.out.flush();
+                category = teacher.category_for(&ext, &path);
+            }
+        }
+
+        files.push((path, (category, facts)));
+    }
+
+    // Many files of one unknown extension deserve better than Others (shadow
+    // mode leaves that to `shadow report` rather than repeating it every pass)
+    let threshold = rules.others_threshold();
+    if threshold > 0 && !args.shadow {
+        let mut unknown: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (i, (path, (category, _))) in files.iter().enumerate() {
+            let ext = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_lowercase())
+                .unwrap_or_default();
+            if category == "Others" && !ext.is_empty() && !rules.knows_extension(&ext) {
+                unknown.entry(ext).or_default().push(i);
+            }
+        }
+        for (ext, indices) in unknown {
+            if (indices.len() as u64) <= threshold {
+                continue;
+            }
+            if let Some(teacher) = teacher.as_mut() {
+                let _ = console.out.flush();
+                if let Some(category) = teacher.offer_category(&ext, indices.len()) {
+                    for i in indices {
+                        files[i].1.0 = category.clone();
+                    }
+                }
+            } else {
+                let _ = writeln!(
+                    console.out,
+                    "[SUGGEST] {} files with extension '.{}' go to Others; for a category of their own, \
+                     add {} = {} under [extensions] in the config (or run with -i)",
+                    indices.len(),
+                    ext,
+                    config::format_key(&ext),
+                    config::Value::String(ext.clone())
+                );
+            }
+        }
+    }
+
+    // A download saved more than once (report.pdf, report (1).pdf) keeps
+    // one copy; the others go to the trash (not in safe mode, nor out of
+    // category folders)
+    let mut copy_names = HashMap::new();
+    if let Some(keep) = rules.numbered_duplicates()
+        && !safe
+        && !args.reorganize
+    {
+        let (dropped, names) = downloads::collapse(&mut files, keep);
+        for (path, kept) in dropped {
+            let reason = format!("same as {:?}", kept.file_name().unwrap_or_default());
+            doomed.push((path, reason));
+            collapsed += 1;
+        }
+        copy_names = names;
+    }
+
+    // Numbered episodes (and their cover art) stay together in a folder of
+    // their own, before sidecars follow them
+    series::group(&mut files, rules.series_min_files());
+
+    // Sidecars (photo.jpg.xmp, video.srt) follow their primary file's category
+    // (and date and owner, so a pair is never split across months)
+    sidecars::group(&mut files, rules.sidecar_extensions());
+    if args.tag_only {
+        drop(console);
+        return Ok(tag_files(&files, args.dry_run));
+    }
+    let mut missing_folder = 0;
+    let mut too_large = 0;
+    // Files whose category does something else with them than a move
+    let mut acted: Vec<(PathBuf, String, Action)> = Vec::new();
+    for (path, (category, facts)) in &files {
+        let mut action = rules.action(category);
+        // An extractor would only stop at the password prompt: the archive is
+        // moved like any other instead
+        if let Action::Run(program) = &action
+            && encrypted::is_encrypted(path).unwrap_or(false)
+        {
+            let _ = writeln!(
+                console.out,
+                "[ENCRYPTED] {:?} (needs a password, so not handed to {})",
+                path.file_name().unwrap_or_default(),
+                program.display()
+            );
+            action = Action::Move;
+        }
+        match action {
+            Action::Move => {}
+            // Files sorted before are never trashed or handed out by `reorganize`
+            _ if args.reorganize => continue,
+            Action::Ignore => {
+                let reason = format!("{} is set to ignore", category);
+                console.on_skip(path.file_name().unwrap_or_default(), false, &reason);
+                continue;
+            }
+            Action::Trash if safe => {
+                console.on_skip(path.file_name().unwrap_or_default(), false, "safe mode");
+                continue;
+            }
+            action => {
+                if !actions::done(&action, path, target_dir) {
+                    acted.push((path.clone(), category.clone(), action));
+                }
+                continue;
+            }
+        }
+        let Some((root, recipient)) = destination_root(args, rules, target_dir, path) else {
+            console.on_skip(
+                path.file_name().unwrap_or_default(),
+                false,
+                "no user to give it to",
+            );
+            continue;
+        };
+        if let Some(required) = rules.existing_folder_required(category)
+            && let Some(folder) = args.by.category_folder(required, facts)
+            && dests.lookup(&root.join(&folder)) != Some(true)
+        {
+            let reason = format!("folder {:?} does not exist", folder);
+            console.on_skip(path.file_name().unwrap_or_default(), false, &reason);
+            missing_folder += 1;
+            continue;
+        }
+        if let Some(limit) = rules.size_limit(category)
+            && fs::symlink_metadata(path).is_ok_and(|m| m.len() > limit)
+        {
+            let reason = format!(
+                "larger than the {} limit of {}",
+                category,
+                analyze::human_size(limit)
+            );
+            console.on_skip(path.file_name().unwrap_or_default(), false, &reason);
+            too_large += 1;
+            continue;
+        }
+        let mut folder = args.by.folder(category, facts);
+        if args.keep_structure
+            && let Some(from) = path.parent().and_then(|p| p.strip_prefix(&base).ok())
+            && !from.as_os_str().is_empty()
+        {
+            folder = Path::new(&folder).join(from).to_string_lossy().into_owned();
+        }
+        // Already in the folder it belongs in (or a subfolder of it)
+        if args.reorganize
+            && root
+                .join(&folder)
+                .canonicalize()
+                .is_ok_and(|dir| path.starts_with(dir))
+        {
+            continue;
+        }
+        let verify = args.paranoid || rules.verify_copies(category);
+        let policy = conflict_policy(args, rules, category);
+        let naming = rules
+            .conflict_name(category)
+            .unwrap_or_else(|| ConflictName::default_for(policy));
+        // A plugin may name the file by what it holds
+        let renamed = rules
+            .rename(path, category)
+            .or_else(|| copy_names.get(path).cloned())
+            .map(OsString::from);
+        let Some(name) = renamed.as_deref().or(path.file_name()) else {
+            continue;
+        };
+        let Some(item) = plan::plan_file(
+            path,
+            name,
+            &root,
+            &folder,
+            policy,
+            &naming,
+            &mut dests,
+            &mut console,
+        ) else {
+            continue;
+        };
+        // The category's folder and those below it that the file goes into
+        if let Some((configured, _)) = rules.folder_permissions(category)
+            && let Some(top) = args.by.category_folder(configured, facts)
+        {
+            let top = root.join(top);
+            let mut dir = item.dest.parent();
+            while let Some(folder) = dir
+                && folder.starts_with(&top)
+            {
+                guarded
+                    .entry(folder.to_path_buf())
+                    .or_insert_with(|| (configured.to_string(), folder.exists()));
+                dir = folder.parent();
+            }
+        }
+        plan.push(PlannedMove {
+            verify,
+            recipient,
+            ..item
+        });
+    }
+
+    // Stop now rather than halfway through: read-only folders, no room for copies
+    if !plan.is_empty() {
+        let report = preflight::check(&plan, target_dir, args.dry_run);
+        // Copies that fit are worth knowing about too: they take a while
+        for copies in &report.copies {
+            if copies.free.is_some_and(|free| copies.needed > free) {
+                continue;
+            }
+            let free = copies
+                .free
+                .map(|free| format!(", {} free", analyze::human_size(free)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                console.out,
+                "[COPY] {:?}: {} from another filesystem ({}){}",
+                copies.dir,
+                analyze::human_size(copies.needed),
+                copies.breakdown(),
+                free
+            );
+        }
+        let problems = report.problems;
+        if !problems.is_empty() {
+            let _ = console.out.flush();
+            for problem in &problems {
+                eprintln!(
+                    "{}: {}",
+                    if args.dry_run { "Warning" } else { "Error" },
+                    problem
+                );
+            }
+            if !args.dry_run {
+                return Err(io::Error::other(
+                    "preflight checks failed; nothing was moved",
+                ));
+            }
+        }
+    }
+
+    // 3. Apply the plan, journaling progress so an interrupted run can be
+    // resumed (and, once finished, undone from the history)
+    let mut journal = None;
+    if !args.dry_run && !plan.is_empty() {
+        let journal_path = journal::path_for(target_dir);
+        let config = match (&args.config, &args.profile) {
+            (Some(path), _) => Some(ConfigSource::File(path.clone())),
+            (None, Some(name)) => Some(ConfigSource::Profile(name.clone())),
+            (None, None) => None,
+        };
+        match Journal::create(&journal_path, target_dir, &plan, &run_id, config.as_ref()) {
+            Ok(mut j) => {
+                if args.paranoid
+                    && let Err(e) = j.make_durable()
+                {
+                    return Err(io::Error::other(format!(
+                        "cannot write the journal to disk: {}",
+                        e
+                    )));
+                }
+                journal = Some(j);
+            }
+            Err(e) if args.batch_size.is_some() => {
+                return Err(io::Error::other(format!(
+                    "cannot write journal, which keeps the batches: {}",
+                    e
+                )));
+            }
+            Err(e) if args.paranoid => {
+                return Err(io::Error::other(format!(
+                    "cannot write journal (--paranoid runs need one): {}",
+                    e
+                )));
+            }
+            Err(e) => eprintln!(
+                "Warning: cannot write journal (run won't be resumable): {}",
+                e
+            ),
+        }
+    }
+
+    let mut staging = Staging::new(target_dir, rules.staging_days(), &run_id);
+    let mut summary = PassSummary {
+        missing_folder,
+        too_large,
+        deferred,
+        collapsed,
+        empty,
+        corrupt,
+        damaged_outcome: empty_files.map_or("", EmptyFiles::outcome),
+        run: journal.is_some().then(|| run_id.clone()),
+        ..PassSummary::default()
+    };
+
+    // Installers that would land in APPS during a dry run (they aren't there yet)
+    let mut pending_apps = Vec::new();
+
+    // --verify-run: what the tree held before, and which moves were made
+    let run_check = if args.verify_run && !args.dry_run {
+        match verify::RunCheck::scan(target_dir, ARTIFACT_PREFIX) {
+            Ok(check) => Some(check),
+            Err(e) => {
+                return Err(io::Error::other(format!(
+                    "cannot list files to verify the run: {}",
+                    e
+                )));
+            }
+        }
+    } else {
+        None
+    };
+    let mut moved_items = vec![false; plan.len()];
+
+    let mut budget = Budget::new(args.max_files, args.max_bytes, args.time_budget);
+    for (i, item) in plan.iter().enumerate() {
+        if args.batch_size.is_some_and(|n| i as u64 >= n) {
+            summary.remaining = plan.len() - i;
+            summary.batch = args.batch_size;
+            break;
+        }
+        let size = match item.kind {
+            plan::EntryKind::File => fs::symlink_metadata(&item.src).map_or(0, |m| m.len()),
+            plan::EntryKind::Dir | plan::EntryKind::Merge => 0,
+        };
+        if let Some(reason) = budget.exceeded_by(size) {
+            summary.remaining = plan.len() - i;
+            summary.budget = Some(reason);
+            break;
+        }
+        budget.spend(size);
+        if interrupt::requested() {
+            summary.remaining = plan.len() - i;
+            summary.cancelled = true;
+            break;
+        }
+
+        let moved = plan::apply(item, &mut dests, &mut staging, &mut console);
+        // A copy stopped halfway is left to `resume`, like the moves after it
+        if !moved && interrupt::requested() {
+            summary.remaining = plan.len() - i;
+            summary.cancelled = true;
+            break;
+        }
+        moved_items[i] = moved;
+        if moved {
+            match item.kind {
+                plan::EntryKind::File => summary.files += 1,
+                plan::EntryKind::Dir | plan::EntryKind::Merge => summary.dirs += 1,
+            }
+            if !args.dry_run {
+                records.record(&item.src, &item.dest, &item.category, &run_id);
+            }
+            if args.shadow && records.shadow(item) {
+                console.print_move(item);
+                summary.shadowed += 1;
+            }
+            if args.shared && !args.dry_run {
+                hand_over(rules.shared(), item, &mut console.out);
+            }
+            if args.dry_run && item.category == "APPS" {
+                pending_apps.push(item.src.clone());
+            }
+        }
+
+        let placed = (moved && item.kind == plan::EntryKind::File)
+            .then(|| Placed::of(&item.dest))
+            .flatten();
+        if let Some(j) = journal.as_mut()
+            && let Err(e) = j.mark_done(i, placed)
+        {
+            // The journal stays behind for `resume`
+            if args.paranoid {
+                return Err(io::Error::other(format!(
+                    "cannot write the journal to disk: {}",
+                    e
+                )));
+            }
+            eprintln!("Warning: cannot update journal: {}", e);
+        }
+    }
+
+    if let Some(check) = &run_check {
+        let _ = console.out.flush();
+        summary.discrepancies = Some(check.check(&plan, &moved_items));
+    }
+    if let Some(path) = &args.emit_shell {
+        let moves: Vec<&PlannedMove> = plan
+            .iter()
+            .zip(&moved_items)
+            .filter(|(_, moved)| **moved)
+            .map(|(item, _)| item)
+            .collect();
+        let _ = console.out.flush();
+        if let Err(e) = script::write(path, target_dir, &moves) {
+            eprintln!("Error writing script '{}': {}", path.display(), e);
+            std::process::exit(1);
+        }
+        summary.script = Some(path.clone());
+    }
+
+    // A stopped run keeps its journal for `resume`, and so does a batch
+    // (on disk for good: the next one may come after a reboot)
+    if let Some(mut j) = journal {
+        if summary.batch.is_some() {
+            if let Err(e) = j.checkpoint() {
+                eprintln!("Warning: cannot write the journal to disk: {}", e);
+            }
+        } else if !summary.cancelled
+            && let Err(e) = j.finish()
+        {
+            eprintln!("Warning: cannot record the run in the history: {}", e);
+        }
+    }
+    // Folders --recursive moved everything out of go too; anything left keeps them
+    if !args.dry_run && !safe {
+        for dir in walked.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+    // New category folders get the category's permissions; existing ones too
+    // with `enforce_permissions`
+    if !args.dry_run {
+        for (dir, (category, existed)) in &guarded {
+            let Some((_, permissions)) = rules.folder_permissions(category) else {
+                continue;
+            };
+            if (*existed && !permissions.enforce) || !dir.is_dir() {
+                continue;
+            }
+            if let Err(e) = permissions::apply(dir, permissions) {
+                let _ = console.out.flush();
+                eprintln!("Warning: cannot set permissions of {:?}: {}", dir, e);
+            }
+        }
+    }
+    // Subfolders `reorganize` emptied go too; the category folders stay
+    if args.reorganize && !args.dry_run && !safe {
+        for (item, _) in plan.iter().zip(&moved_items).filter(|(_, moved)| **moved) {
+            let mut dir = item.src.parent();
+            while let Some(parent) = dir
+                && parent.parent().is_some_and(|up| up != base)
+                && parent.starts_with(&base)
+                && fs::remove_dir(parent).is_ok()
+            {
+                dir = parent.parent();
+            }
+        }
+    }
+    // Category folders get their file-manager icon (and name) once they exist
+    let branding = rules.folder_branding();
+    if branding.enabled && !args.dry_run {
+        let mut names: Vec<&String> = protected_folders.iter().collect();
+        names.sort();
+        for name in names {
+            let dir = target_dir.join(name);
+            if !dir.is_dir() {
+                continue;
+            }
+            match branding::brand(&dir, name, branding) {
+                Ok(true) => {
+                    let _ = writeln!(console.out, "[BRAND] {:?}", name);
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    let _ = console.out.flush();
+                    eprintln!("Warning: cannot brand {:?}: {}", name, e);
+                }
+            }
+        }
+    }
+    summary.errors = console.errors;
+    summary.unmerged = console.unmerged;
+    summary.mergeable = console.mergeable;
+    drop(console);
+    // Files a dry run staged only exist on its overlay
+    if !args.dry_run {
+        summary.staged = staging.staged;
+    }
+    summary.staging_dir = staging.dir().map(Path::to_path_buf);
+
+    // Broken downloads go to the trash rather than into a category (after
+    // Ctrl-C, no new work is started: the next run trashes them)
+    let doomed = if summary.cancelled {
+        Vec::new()
+    } else {
+        doomed
+    };
+    for (path, reason) in doomed {
+        let name = path.file_name().unwrap_or_default();
+        println!("{} {:?} ({})", style.tag("Trash"), name, reason);
+        if args.dry_run {
+            continue;
+        }
+        match trash::move_to_trash(&path) {
+            Ok(dest) => records.record(&path, &dest, "Trash", &run_id),
+            Err(e) => eprintln!("Error trashing {:?}: {}", name, e),
+        }
+    }
+
+    // Then the files of categories with another action: trashed, copied,
+    // linked or handed to a program (not after Ctrl-C either)
+    if summary.cancelled {
+        acted.clear();
+    }
+    for (path, category, action) in acted {
+        let name = path.file_name().unwrap_or_default();
+        let tag = style.tag(&category);
+        println!("{} {:?} ({})", tag, name, action.describe());
+        summary.acted += 1;
+        if args.dry_run {
+            continue;
+        }
+        match actions::perform(&action, &path, &category, target_dir, &run_id) {
+            Ok(Some(dest)) if action == Action::Trash => {
+                records.record(&path, &dest, "Trash", &run_id)
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!(
+                    "Error: {:?} could not be {}: {}",
+                    name,
+                    action.describe(),
+                    e
+                );
+                summary.errors += 1;
+            }
+        }
+    }
+
+    // 4. Installer cleanup: set aside installers of installed programs, then
+    // retire older versions piling up in APPS
+    if (args.keep_installers.is_some() || args.installed_installers.is_some()) && !summary.cancelled
+    {
+        let apps_dir = target_dir.join("APPS");
+        let mut candidates: Vec<PathBuf> = fs::read_dir(&apps_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.is_file())
+                    .collect()
+            })
+            .unwrap_or_default();
+        candidates.extend(pending_apps);
+
+        if let Some(policy) = args.installed_installers {
+            let policy = match policy {
+                InstalledInstallers::Trash if safe => InstalledInstallers::Installed,
+                policy => policy,
+            };
+            let retired = installers::retire_installed(
+                &candidates,
+                &apps_dir,
+                &InstalledPrograms::load(),
+                policy,
+                args.dry_run,
+            );
+            summary.installed = retired.len();
+            candidates.retain(|path| !retired.iter().any(|(src, _, _)| src == path));
+            for (src, dest, label) in retired {
+                if !args.dry_run {
+                    records.record(&src, &dest, label, &run_id);
+                }
+            }
+        }
+
+        if let Some(keep) = args.keep_installers {
+            let old_installers = match args.old_installers {
+                OldInstallers::Trash if safe => OldInstallers::Old,
+                policy => policy,
+            };
+            let retired = installers::cleanup(
+                &candidates,
+                &apps_dir,
+                keep as usize,
+                old_installers,
+                args.dry_run,
+            );
+            summary.retired = retired.len();
+            for (src, dest, label) in retired {
+                if !args.dry_run {
+                    records.record(&src, &dest, label, &run_id);
+                }
+            }
+        }
+    }
+
+    // 5. Zip up files that have sat in a category longer than compress_after_days
+    // (not when the budget ran out: that is more work of the same kind, nor
+    // after Ctrl-C, nor in safe mode, as the originals are deleted once archived)
+    let compress_after = match summary.budget {
+        Some(_) => Vec::new(),
+        None if safe || summary.cancelled => Vec::new(),
+        None => rules.compress_after_days(),
+    };
+    for (category, after_days) in compress_after {
+        match compress::compress_category(target_dir, category, after_days, args.dry_run) {
+            Ok(archives) => {
+                summary.archives += archives.len();
+                summary.compressed += archives.iter().map(|a| a.members.len()).sum::<usize>();
+                if !args.dry_run {
+                    for archive in &archives {
+                        records.record(&archive.path, &archive.path, category, &run_id);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Error compressing {}: {}", category, e),
+        }
+    }
+
+    if args.shadow {
+        summary.handled = records.sweep_shadow();
+    }
+    records.flush();
+
+    // 6. Compare the category folders with the previous run (in watch mode,
+    // only after passes that moved something: a full count is not free)
+    if !args.dry_run
+        && (!args.watch || !summary.is_empty())
+        && let Some(stats_path) = stats::path_for(target_dir)
+    {
+        let after = stats::snapshot(target_dir, &protected_folders, ARTIFACT_PREFIX);
+        match stats::load(&stats_path) {
+            Ok(Some(before)) => summary.changes = stats::diff(&before, &after),
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: cannot read the previous run's stats: {}", e),
+        }
+        if let Err(e) = stats::save(&stats_path, &after) {
+            eprintln!("Warning: cannot save run stats: {}", e);
+        }
+    }
+
+    Ok(summary)
+}
+
+/// `--safe` or `safe_mode` in the config (re-read between watch passes)
+fn safe_mode(args: &Args, rules: &RuleSet) -> bool {
+    args.safe || rules.safe_mode()
+}
+
+/// Skip in safe mode; `--on-conflict` when given, else what the config says for the category
+fn conflict_policy(args: &Args, rules: &RuleSet, category: &str) -> ConflictPolicy {
+    if safe_mode(args, rules) {
+        return ConflictPolicy::Skip;
+    }
+    args.on_conflict
+        .or_else(|| rules.conflict_policy(category))
+        .unwrap_or(ConflictPolicy::Skip)
+}
+
+/// Loads the user config; an explicit path must exist, the default one is optional
+fn load_config(explicit: Option<&Path>) -> Config {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => match config::default_path() {
+            Some(path) if path.is_file() => path,
+            _ => return Config::default(),
+        },
+    };
+
+    match Config::load(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error in config '{}': {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parses a preset that ships with the program; one that does not parse is a bug
+fn parse_preset(name: &str, text: &str) -> Config {
+    match Config::parse(text) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error in profile '{}': {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Lists the profiles `--profile` takes
+fn run_profiles() {
+    for name in profiles::names() {
+        match profiles::find(&name) {
+            Ok(Profile::File(path)) => println!("{:<20} {}", name, path.display()),
+            _ => println!("{:<20} (built in)", name),
+        }
+    }
+    if let Some(dir) = profiles::dir() {
+        println!();
+        println!("Profiles of your own go in {}/<name>.toml.", dir.display());
+    }
+}
+
+/// Prints a profile's config
+fn run_profiles_show(name: &str) {
+    match profiles::find(name) {
+        Ok(Profile::Builtin(text)) => print!("{}", text),
+        Ok(Profile::File(path)) => match fs::read_to_string(&path) {
+            Ok(text) => print!("{}", text),
+            Err(e) => {
+                eprintln!("Error reading '{}': {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints every indexed file whose name matches `pattern`
+fn run_find(pattern: &str, category: Option<&str>, hash: Option<&str>) {
+    let Some(path) = index::default_path() else {
+        eprintln!("Error: cannot determine data directory (HOME is not set)");
+        std::process::exit(1);
+    };
+    let records = match index::load(&path) {
+        Ok(records) => records,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No index yet. Run with --index to start recording organized files.");
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error reading index '{}': {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut found = 0;
+    for record in records {
+        if !glob::matches(pattern, &record.name) {
+            continue;
+        }
+        if category.is_some_and(|c| !record.category.eq_ignore_ascii_case(c)) {
+            continue;
+        }
+        if hash.is_some_and(|h| !record.hash.starts_with(&h.to_lowercase())) {
+            continue;
+        }
+
+        found += 1;
+        let missing = if record.path.exists() {
+            ""
+        } else {
+            " (missing)"
+        };
+        println!(
+            "[{:<12}] {}{}  ({})",
+            record.category,
+            record.path.display(),
+            missing,
+            record.organized_at
+        );
+    }
+
+    if found == 0 {
+        println!("No organized files match {:?}.", pattern);
+    }
+}
+
+/// Prints the file-type breakdown and largest/oldest files of a directory
+fn run_analyze(path: Option<&Path>, top: usize, output: analyze::Output) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !root.is_dir() {
+        eprintln!("Error: '{}' is not a valid directory.", root.display());
+        std::process::exit(1);
+    }
+
+    let analysis = match analyze::analyze(&root, top, ARTIFACT_PREFIX) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            eprintln!("Error reading directory: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match output {
+        analyze::Output::Json => println!("{}", analysis.to_json()),
+        analyze::Output::Text => {
+            println!("Analyzing: {}", root.display());
+            println!("-----------------------------------------");
+            analysis.print_text();
+        }
+    }
+}
+
+/// Records what `target_dir` holds before the first real run on it, so the
+/// original layout can still be looked up later
+fn record_first_snapshot(target_dir: &Path) {
+    let Some(path) = snapshot::path_for(target_dir) else {
+        return;
+    };
+    if path.exists() {
+        return;
+    }
+    let saved = snapshot::take(target_dir, ARTIFACT_PREFIX).and_then(|listing| {
+        snapshot::save(&path, &listing)?;
+        Ok(listing.files.len())
+    });
+    match saved {
+        Ok(count) => println!(
+            "Snapshot: {} file(s) listed before the first run (see `auto-organize snapshot show`)",
+            count
+        ),
+        Err(e) => eprintln!("Warning: cannot record a snapshot of the directory: {}", e),
+    }
+}
+
+/// Prints the first-run snapshot of a directory, or how it compares to now
+fn run_snapshot(path: Option<&Path>, diff: bool) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !root.is_dir() {
+        eprintln!("Error: '{}' is not a valid directory.", root.display());
+        std::process::exit(1);
+    }
+    let Some(snapshot_path) = snapshot::path_for(&root) else {
+        eprintln!("Error: no data directory (set HOME or use --data-dir)");
+        std::process::exit(1);
+    };
+    let before = match snapshot::load(&snapshot_path) {
+        Ok(Some(before)) => before,
+        Ok(None) => {
+            println!(
+                "No snapshot of '{}' (it is taken on the first run that moves files).",
+                root.display()
+            );
+            return;
+        }
+        Err(e) => {
+            eprintln!(
+                "Error reading snapshot '{}': {}",
+                snapshot_path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+
+    println!("Snapshot: {}", root.display());
+    println!(
+        "Taken:    {} ({} files)",
+        DateTime::from_unix(before.taken).to_iso(),
+        before.files.len()
+    );
+    println!("-----------------------------------------");
+
+    if !diff {
+        for (path, info) in &before.files {
+            println!(
+                "{:>10}  {}  {}",
+                analyze::human_size(info.size),
+                DateTime::from_unix(info.modified).to_iso(),
+                path.display()
+            );
+        }
+        return;
+    }
+
+    let now = match snapshot::take(&root, ARTIFACT_PREFIX) {
+        Ok(now) => now,
+        Err(e) => {
+            eprintln!("Error reading directory: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let changes = snapshot::diff(&before, &now);
+    for (from, to) in &changes.moved {
+        println!("[MOVED  ] {:?} -> {:?}", from, to);
+    }
+    for path in &changes.gone {
+        println!("[GONE   ] {:?}", path);
+    }
+    for path in &changes.changed {
+        println!("[CHANGED] {:?}", path);
+    }
+    for path in &changes.added {
+        println!("[NEW    ] {:?}", path);
+    }
+    println!("-----------------------------------------");
+    println!(
+        "{} moved, {} gone, {} changed, {} new.",
+        changes.moved.len(),
+        changes.gone.len(),
+        changes.changed.len(),
+        changes.added.len()
+    );
+}
+
+/// Archives old files in categories, on demand rather than after an organize pass
+fn run_compress(
+    path: Option<&Path>,
+    dry_run: bool,
+    config_path: Option<&Path>,
+    only: &[String],
+    older_than: Option<u64>,
+) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !target_dir.is_dir() {
+        eprintln!(
+            "Error: '{}' is not a valid directory.",
+            target_dir.display()
+        );
+        std::process::exit(1);
+    }
+
+    let rules = RuleSet::new(load_config(config_path));
+    if rules.safe_mode() && !dry_run {
+        eprintln!("Error: safe_mode is on in the config; compressing deletes the originals.");
+        std::process::exit(1);
+    }
+    let configured = rules.compress_after_days();
+
+    let categories: Vec<(&str, u64)> = if only.is_empty() {
+        configured
+            .iter()
+            .map(|&(category, days)| (category, older_than.unwrap_or(days)))
+            .collect()
+    } else {
+        let mut categories = Vec::new();
+        for category in only {
+            let days = older_than.or_else(|| {
+                configured
+                    .iter()
+                    .find(|(c, _)| c == category)
+                    .map(|&(_, days)| days)
+            });
+            let Some(days) = days else {
+                eprintln!(
+                    "Error: '{}' has no compress_after_days in the config; pass --older-than.",
+                    category
+                );
+                std::process::exit(1);
+            };
+            categories.push((category.as_str(), days));
+        }
+        categories
+    };
+
+    if categories.is_empty() {
+        println!("Nothing to compress: no category sets compress_after_days.");
+        return;
+    }
+
+    println!("Target: {}", target_dir.display());
+    if dry_run {
+        println!("Mode:   DRY RUN (No changes will be made)");
+    }
+    println!("-----------------------------------------");
+
+    let mut summary = PassSummary::default();
+    for (category, days) in categories {
+        match compress::compress_category(&target_dir, category, days, dry_run) {
+            Ok(archives) => {
+                summary.archives += archives.len();
+                summary.compressed += archives.iter().map(|a| a.members.len()).sum::<usize>();
+            }
+            Err(e) => eprintln!("Error compressing {}: {}", category, e),
+        }
+    }
+
+    println!("-----------------------------------------");
+    if summary.is_empty() {
+        println!("Done. No files old enough to compress.");
+    } else {
+        println!(
+            "Done. Compressed {} old file(s) into {} archive(s).",
+            summary.compressed, summary.archives
+        );
+    }
+}
+
+/// Lints the built-in rules and the config file (explicit, or the default one)
+fn run_rules_lint(explicit: Option<&Path>) {
+    let path = explicit
+        .map(Path::to_path_buf)
+        .or_else(config::default_path)
+        .filter(|path| explicit.is_some() || path.is_file());
+
+    let (config, doc) = match &path {
+        Some(path) => {
+            let parsed = fs::read_to_string(path)
+                .map_err(|e| config::ConfigError::Io(path.clone(), e))
+                .and_then(|text| config::parse_document(&text))
+                .and_then(|doc| Ok((Config::from_document(&doc)?, doc)));
+            match parsed {
+                Ok((config, doc)) => (config, Some(doc)),
+                Err(e) => {
+                    eprintln!("Error in config '{}': {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => (Config::default(), None),
+    };
+
+    match &path {
+        Some(path) => println!("Config: {}", path.display()),
+        None => println!("Config: none (built-in rules only)"),
+    }
+    println!(
+        "Order: [origins] > group_by (by priority) > [extensions] > built-in extensions > file names > shebangs > Others"
+    );
+    println!("-----------------------------------------");
+
+    let findings = lint::lint(&config, doc.as_ref());
+    for finding in &findings {
+        println!("[{}] {}", finding.level, finding.message);
+    }
+    let conflicts = findings
+        .iter()
+        .filter(|f| f.level == lint::Level::Conflict)
+        .count();
+    if findings.is_empty() {
+        println!("No conflicting or shadowed rules.");
+    } else {
+        println!("-----------------------------------------");
+        println!("{} finding(s), {} conflict(s).", findings.len(), conflicts);
+    }
+    if conflicts > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// Prints the category of each file name listed in `samples`, by name alone
+fn run_rules_test(samples: &Path, config: Option<&Path>) {
+    let names = match listing::read_list(samples, false) {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("Error reading samples '{}': {}", samples.display(), e);
+            std::process::exit(1);
+        }
+    };
+    let rules = RuleSet::new(load_config(config));
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for path in &names {
+        // Only the name counts; a path's folders are ignored
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let category = rules.classify_name(&name.to_string_lossy());
+        println!("[{:<12}] {:?}", category, name);
+        *counts.entry(category).or_default() += 1;
+    }
+
+    println!("-----------------------------------------");
+    let routed: Vec<String> = counts
+        .iter()
+        .map(|(category, n)| format!("{} {}", n, category))
+        .collect();
+    println!(
+        "{} name(s): {}.",
+        counts.values().sum::<usize>(),
+        if routed.is_empty() {
+            "nothing to route".to_string()
+        } else {
+            routed.join(", ")
+        }
+    );
+}
+
+/// Empties the staging area of a directory: runs older than `older_than`
+/// days (staging_days by default), or all of them
+fn run_purge(
+    path: Option<&Path>,
+    all: bool,
+    older_than: Option<u64>,
+    dry_run: bool,
+    config: Option<&Path>,
+) {
+    let target_dir = path.unwrap_or(Path::new("."));
+    let rules = RuleSet::new(load_config(config));
+    if rules.safe_mode() && !dry_run {
+        eprintln!("Error: safe_mode is on in the config; nothing is deleted.");
+        std::process::exit(1);
+    }
+    let days = match (all, older_than) {
+        (true, _) => 0,
+        (false, Some(days)) => days,
+        (false, None) => rules.staging_days(),
+    };
+
+    match staging::purge(target_dir, days, dry_run) {
+        Ok(runs) if runs.is_empty() => println!("Nothing to purge."),
+        Ok(runs) => {
+            for run in &runs {
+                println!("[PURGE] {:?}", run);
+            }
+            if dry_run {
+                println!("Would delete {} staged run(s).", runs.len());
+            } else {
+                println!("Deleted {} staged run(s).", runs.len());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error purging '{}': {}", target_dir.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Re-files what is already in category folders: a run over those files,
+/// where files in (or below) the folder the rules give them stay
+fn run_reorganize(
+    path: Option<&Path>,
+    dry_run: bool,
+    by: Layout,
+    on_conflict: Option<ConflictPolicy>,
+    config: Option<&Path>,
+    learn: bool,
+) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut args = Args::parse_from([
+        OsStr::new("auto-organize"),
+        OsStr::new("--"),
+        target_dir.as_os_str(),
+    ]);
+    args.reorganize = true;
+    args.dry_run = dry_run;
+    args.by = by;
+    args.on_conflict = on_conflict;
+    args.config = config.map(Path::to_path_buf);
+    args.learn = learn;
+    organize(args);
+}
+
+/// A directory's own layout: the config `check` holds it to unless given one
+const LAYOUT_FILE: &str = ".auto-organize.toml";
+
+/// Two dry runs, one over the directory and one (as `reorganize`) over its
+/// category folders; whatever either would move deviates from the layout
+fn run_check(path: Option<&Path>, by: Layout, config: Option<&Path>) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !target_dir.is_dir() {
+        eprintln!(
+            "Error: '{}' is not a valid directory.",
+            target_dir.display()
+        );
+        std::process::exit(1);
+    }
+    let declared = target_dir.join(LAYOUT_FILE);
+    let config_path = match config {
+        Some(path) => Some(path.to_path_buf()),
+        None => declared.is_file().then_some(declared),
+    };
+    let rules = RuleSet::new(load_config(config_path.as_deref()));
+
+    let mut args = Args::parse_from([
+        OsStr::new("auto-organize"),
+        OsStr::new("--"),
+        target_dir.as_os_str(),
+    ]);
+    args.dry_run = true;
+    args.by = by;
+    args.config = config_path.clone();
+
+    println!("Checking: {}", target_dir.display());
+    if let Some(path) = &config_path {
+        println!("Layout:   {}", path.display());
+    }
+    println!("-----------------------------------------");
+    let mut records = Records::default();
+    let loose = match run_pass(&args, &target_dir, None, &rules, &mut records) {
+        Ok(summary) => summary.files + summary.dirs + summary.acted,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    args.reorganize = true;
+    let listed = listing::resolve(
+        categorized_files(&target_dir, &rules, &args.by),
+        Path::new("."),
+    );
+    let misfiled = match run_pass(&args, &target_dir, Some(&listed), &rules, &mut records) {
+        Ok(summary) => summary.files + summary.dirs,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("-----------------------------------------");
+    if loose + misfiled == 0 {
+        println!("Organized: nothing would move.");
+        return;
+    }
+    println!(
+        "Not organized: {} loose and {} misfiled item(s) (listed above).",
+        loose, misfiled
+    );
+    std::process::exit(1);
+}
+
+/// A dry run whose moves are written to `script` instead of being made
+fn run_plan(
+    path: Option<&Path>,
+    script: &Path,
+    by: Layout,
+    on_conflict: Option<ConflictPolicy>,
+    config: Option<&Path>,
+) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut args = Args::parse_from([
+        OsStr::new("auto-organize"),
+        OsStr::new("--"),
+        target_dir.as_os_str(),
+    ]);
+    args.dry_run = true;
+    args.emit_shell = Some(script.to_path_buf());
+    args.by = by;
+    args.on_conflict = on_conflict;
+    args.config = config.map(Path::to_path_buf);
+    organize(args);
+}
+
+/// Every file in the category folders of `target_dir` (and the folders the
+/// layout makes at the top, like 2024/), for `reorganize`. Bundles are left
+/// whole, and our own files alone.
+fn categorized_files(target_dir: &Path, rules: &RuleSet, by: &Layout) -> Vec<PathBuf> {
+    let folders = rules.category_folders();
+    let mut files = Vec::new();
+    let mut stack: Vec<PathBuf> = fs::read_dir(target_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .filter(|e| {
+                    let name = e.file_name().to_string_lossy().into_owned();
+                    folders.contains(&name) || by.owns_folder(&name)
+                })
+                .map(|e| e.path())
+                .collect()
+        })
+        .unwrap_or_default();
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            eprintln!("Error reading {:?}", dir);
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(ARTIFACT_PREFIX)
+                || name == tags::SIDECAR
+                || branding::FILES.contains(&name.as_str())
+            {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(t) if t.is_dir() && !rules.is_bundle(&name) => stack.push(entry.path()),
+                Ok(t) if t.is_dir() => {}
+                Ok(_) => files.push(entry.path()),
+                Err(_) => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Runs the stages of `[pipeline]` one after the other; a stage that cannot
+/// run stops the rest
+fn run_pipeline(path: Option<&Path>, dry_run: bool, config_path: Option<&Path>) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !target_dir.is_dir() {
+        eprintln!(
+            "Error: '{}' is not a valid directory.",
+            target_dir.display()
+        );
+        std::process::exit(1);
+    }
+
+    let config = load_config(config_path);
+    let pipeline = config.pipeline.clone();
+    if pipeline.stages.is_empty() {
+        eprintln!("Error: the config lists no [pipeline] stages.");
+        std::process::exit(1);
+    }
+    if config.safe_mode
+        && !dry_run
+        && let Some(stage) = pipeline.stages.iter().find(|s| s.destructive())
+    {
+        eprintln!(
+            "Error: safe_mode is on in the config; the {} stage deletes files.",
+            stage.name()
+        );
+        std::process::exit(1);
+    }
+
+    // Before any stage runs, not when the classify stage gets to it
+    if pipeline.stages.contains(&Stage::Classify) {
+        refuse_loops(&RuleSet::new(config.clone()), &target_dir, false);
+    }
+
+    let names: Vec<&str> = pipeline.stages.iter().map(|s| s.name()).collect();
+    println!("Pipeline: {}", names.join(" -> "));
+    if dry_run {
+        // Nothing moves, so each stage sees the directory as it is now
+        println!("Mode:     DRY RUN (No changes will be made)");
+    }
+    interrupt::install();
+
+    let shown = |path: &Path| path.strip_prefix(&target_dir).unwrap_or(path).to_path_buf();
+    // Whether the file is in the trash now (or would be, in a dry run)
+    let trash = |path: &Path| {
+        if dry_run {
+            return true;
+        }
+        match trash::move_to_trash(path) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Error trashing {:?}: {}", shown(path), e);
+                false
+            }
+        }
+    };
+    // Stages that left files they should have trashed
+    let mut failed = false;
+
+    for (i, &stage) in pipeline.stages.iter().enumerate() {
+        if interrupt::requested() {
+            println!("Stopped by Ctrl-C before the {} stage.", stage.name());
+            std::process::exit(130);
+        }
+        println!();
+        println!("== {}/{}: {}", i + 1, names.len(), stage.name());
+        match stage {
+            Stage::Classify => {
+                let mut args = Args::parse_from([
+                    OsStr::new("auto-organize"),
+                    OsStr::new("--"),
+                    target_dir.as_os_str(),
+                ]);
+                args.dry_run = dry_run;
+                args.config = config_path.map(Path::to_path_buf);
+                args.recursive = pipeline.classify.recursive;
+                args.dirs = pipeline.classify.dirs;
+                args.on_conflict = pipeline.classify.on_conflict;
+                let code = classify_stage(&args, &config, &target_dir);
+                if code != 0 {
+                    let rest = &names[i + 1..];
+                    if rest.is_empty() {
+                        println!("Pipeline stopped in the classify stage.");
+                    } else {
+                        println!(
+                            "Pipeline stopped in the classify stage; not run: {}.",
+                            rest.join(", ")
+                        );
+                    }
+                    std::process::exit(code);
+                }
+            }
+            Stage::Dedupe => {
+                let options = &pipeline.dedupe;
+                let groups = pipeline::duplicates(
+                    &target_dir,
+                    &options.categories,
+                    options.keep,
+                    ARTIFACT_PREFIX,
+                );
+                let (mut count, mut errors) = (0, 0);
+                for group in &groups {
+                    for copy in &group.copies {
+                        if interrupt::requested() {
+                            break;
+                        }
+                        println!(
+                            "[{:<12}] {:?} (same as {:?})",
+                            "Trash",
+                            shown(copy),
+                            shown(&group.kept)
+                        );
+                        if trash(copy) {
+                            count += 1;
+                        } else {
+                            errors += 1;
+                        }
+                    }
+                }
+                println!("{} duplicate(s) of {} file(s).", count, groups.len());
+                if errors > 0 {
+                    println!("{} duplicate(s) could not be trashed.", errors);
+                    failed = true;
+                }
+            }
+            Stage::Retention => {
+                let options = &pipeline.retention;
+                let days = options.after_days.unwrap_or_default();
+                let expired =
+                    pipeline::expired(&target_dir, &options.categories, days, ARTIFACT_PREFIX);
+                let (mut count, mut errors) = (0, 0);
+                for path in expired.iter().take_while(|_| !interrupt::requested()) {
+                    println!(
+                        "[{:<12}] {:?} (older than {} days)",
+                        "Trash",
+                        shown(path),
+                        days
+                    );
+                    if trash(path) {
+                        count += 1;
+                    } else {
+                        errors += 1;
+                    }
+                }
+                println!("{} of {} file(s) past retention.", count, expired.len());
+                if errors > 0 {
+                    println!("{} file(s) could not be trashed.", errors);
+                    failed = true;
+                }
+            }
+            Stage::Compress => {
+                let options = &pipeline.compress;
+                run_compress(
+                    Some(&target_dir),
+                    dry_run,
+                    config_path,
+                    &options.categories,
+                    options.older_than_days,
+                );
+            }
+            Stage::PruneEmpty => match pipeline::prune_empty(
+                &target_dir,
+                &config.protected_folders,
+                ARTIFACT_PREFIX,
+                dry_run,
+            ) {
+                Ok(removed) => {
+                    for dir in &removed {
+                        println!("[PRUNE] {:?}", shown(dir));
+                    }
+                    println!("{} empty folder(s).", removed.len());
+                }
+                Err(e) => {
+                    eprintln!("Error pruning '{}': {}", target_dir.display(), e);
+                    std::process::exit(1);
+                }
+            },
+        }
+    }
+    if interrupt::requested() {
+        std::process::exit(130);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// The classify stage of a pipeline: one pass, as `auto-organize` without a
+/// subcommand would make; returns its exit status (see `report_pass`)
+fn classify_stage(args: &Args, config: &Config, target_dir: &Path) -> i32 {
+    if let Some(journal_path) = journal::find(target_dir)
+        && !args.dry_run
+    {
+        eprintln!(
+            "Error: an interrupted run was found in '{}'.",
+            target_dir.display()
+        );
+        eprintln!(
+            "Run `auto-organize resume` to finish it, or delete '{}' to discard it.",
+            journal_path.display()
+        );
+        return 1;
+    }
+    if !args.dry_run {
+        record_first_snapshot(target_dir);
+    }
+    let rules = RuleSet::new(config.clone());
+    let mut records = Records::default();
+    let result = run_pass(args, target_dir, None, &rules, &mut records);
+    report_pass(result, target_dir, args.dry_run)
+}
+
+/// Pauses or resumes watch passes on a directory, or shows whether they run now
+fn run_schedule(
+    path: Option<&Path>,
+    pause: bool,
+    pause_for: Option<u64>,
+    resume: bool,
+    config: Option<&Path>,
+) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !root.is_dir() {
+        eprintln!("Error: '{}' is not a valid directory.", root.display());
+        std::process::exit(1);
+    }
+
+    if pause {
+        let until = pause_for.map(|secs| DateTime::now().to_unix() + secs as i64);
+        if let Err(e) = schedule::pause(&root, until) {
+            eprintln!("Error pausing '{}': {}", root.display(), e);
+            std::process::exit(1);
+        }
+        match until {
+            Some(until) => println!(
+                "Paused passes on '{}' until {} local time.",
+                root.display(),
+                schedule::local_iso(until)
+            ),
+            None => println!(
+                "Paused passes on '{}' until `auto-organize schedule --resume {}`.",
+                root.display(),
+                root.display()
+            ),
+        }
+        println!("A watching daemon finishes the pass it is in, then waits.");
+        return;
+    }
+    if resume {
+        match schedule::unpause(&root) {
+            Ok(true) => println!("Passes on '{}' resume.", root.display()),
+            Ok(false) => println!("'{}' was not paused.", root.display()),
+            Err(e) => {
+                eprintln!("Error resuming '{}': {}", root.display(), e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let rules = RuleSet::new(load_config(config));
+    println!("Hours: {}", describe_schedule(rules.schedule()));
+    match hold_reason(&root, rules.schedule()) {
+        Some(reason) => println!("Now:   on hold, {}.", reason),
+        None => println!("Now:   passes run."),
+    }
+}
+
+/// Sends a request to the daemon watching a directory and prints its answer
+fn run_ctl(request: control::Request, path: Option<&Path>) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    match control::send(&root, request) {
+        Ok(reply) => print!("{}", reply),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            eprintln!(
+                "Error: no daemon is watching '{}' (start one with `auto-organize --watch {}`).",
+                root.display(),
+                root.display()
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!(
+                "Error reaching the daemon watching '{}': {}",
+                root.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// What shadow mode saw over time: what the rules would have done with
+/// which files, and which ones people dealt with before the tool would have
+fn run_shadow_report(path: Option<&Path>) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let Some(log_path) = shadow::path_for(&root) else {
+        eprintln!("Error: no data directory (set HOME or use --data-dir)");
+        std::process::exit(1);
+    };
+    let entries = match shadow::load(&log_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!(
+                "No shadow log for '{}' (start one with `auto-organize --shadow {}`).",
+                root.display(),
+                root.display()
+            );
+            return;
+        }
+        Err(e) => {
+            eprintln!("Error reading shadow log '{}': {}", log_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Shadow log: {}", root.display());
+    if let Some(first) = entries.first() {
+        let days = (DateTime::now().to_unix() - first.seen) / 86_400;
+        println!(
+            "Since:      {} ({} day(s))",
+            DateTime::from_unix(first.seen).to_iso(),
+            days
+        );
+    }
+    println!("-----------------------------------------");
+    if entries.is_empty() {
+        println!("Nothing would have been moved yet.");
+        return;
+    }
+
+    let mut by_category: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut to_others: BTreeMap<String, usize> = BTreeMap::new();
+    for entry in &entries {
+        *by_category.entry(&entry.category).or_default() += 1;
+        if entry.category == "Others" && entry.kind == plan::EntryKind::File {
+            let ext = entry
+                .src
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy().to_lowercase()))
+                .unwrap_or_else(|| "(none)".to_string());
+            *to_others.entry(ext).or_default() += 1;
+        }
+    }
+    println!("{} move(s) would have been made:", entries.len());
+    let mut by_category: Vec<_> = by_category.into_iter().collect();
+    by_category.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (category, count) in by_category {
+        println!("  {:<20} {:>6}", category, count);
+    }
+
+    if !to_others.is_empty() {
+        println!();
+        println!(
+            "Sent to Others, by extension (map them under [extensions] if they belong somewhere):"
+        );
+        let mut to_others: Vec<_> = to_others.into_iter().collect();
+        to_others.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (ext, count) in to_others {
+            println!("  {:<20} {:>6}", ext, count);
+        }
+    }
+
+    // Files people moved or deleted before a real run would have: either the
+    // tool would have helped, or its category was not where they wanted them
+    let handled: Vec<_> = entries.iter().filter(|e| e.gone.is_some()).collect();
+    if !handled.is_empty() {
+        println!();
+        println!("Moved or deleted by hand before a run would have moved them:");
+        for entry in &handled {
+            let name = entry.src.file_name().unwrap_or_default();
+            let after = entry.gone.unwrap_or(entry.seen) - entry.seen;
+            println!(
+                "[{:<12}] {:?} (after {})",
+                entry.category,
+                name,
+                human_duration(after)
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "{} would be moved now, {} were dealt with by hand.",
+        entries.len() - handled.len(),
+        handled.len()
+    );
 }
 
-fn main() {
-    let args = Args::parse();
-    let target_dir = args.path.unwrap_or_else(|| PathBuf::from("."));
+/// "3 day(s)", "5 hour(s)" or "12 minute(s)"
+fn human_duration(secs: i64) -> String {
+    match secs {
+        s if s >= 86_400 => format!("{} day(s)", s / 86_400),
+        s if s >= 3600 => format!("{} hour(s)", s / 3600),
+        s => format!("{} minute(s)", s / 60),
+    }
+}
+
+fn run_shadow_clear(path: Option<&Path>) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let Some(log_path) = shadow::path_for(&root) else {
+        eprintln!("Error: no data directory (set HOME or use --data-dir)");
+        std::process::exit(1);
+    };
+    match fs::remove_file(&log_path) {
+        Ok(()) => println!("Deleted the shadow log of '{}'.", root.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No shadow log for '{}'.", root.display())
+        }
+        Err(e) => {
+            eprintln!("Error deleting '{}': {}", log_path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Absolute form of a path given on the command line, or exits
+fn absolute_or_exit(path: &Path) -> PathBuf {
+    match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Error: '{}': {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
 
+/// Installs (or with `print`, shows) the scheduler entry for a directory
+fn run_install_service(
+    path: Option<&Path>,
+    interval: u64,
+    watch: bool,
+    config: Option<&Path>,
+    data_dir: Option<&Path>,
+    print: bool,
+) {
+    let target_dir = absolute_or_exit(path.unwrap_or(Path::new(".")));
     if !target_dir.is_dir() {
         eprintln!(
             "Error: '{}' is not a valid directory.",
@@ -29,214 +4012,775 @@ fn main() {
         std::process::exit(1);
     }
 
-    println!(
-        "Target: {}",
-        target_dir
-            .canonicalize()
-            .unwrap_or(target_dir.clone())
-            .display()
-    );
-    if args.dry_run {
-        println!("Mode:   DRY RUN (No changes will be made)");
+    // Scheduled runs start elsewhere, so every path they get is absolute
+    let mut extra_args = Vec::new();
+    if let Some(config) = config {
+        extra_args.push("--config".to_string());
+        extra_args.push(absolute_or_exit(config).to_string_lossy().into_owned());
+    }
+    if let Some(dir) = data_dir {
+        let _ = fs::create_dir_all(dir);
+        extra_args.push("--data-dir".to_string());
+        extra_args.push(absolute_or_exit(dir).to_string_lossy().into_owned());
     }
-    println!("-----------------------------------------");
 
-    // 1. Setup extension map and protected folder names
-    let extension_map = get_extension_map();
+    let service = service::Service {
+        path: target_dir,
+        interval,
+        watch,
+        extra_args,
+    };
 
-    // These folders will NOT be moved if they already exist
-    let protected_folders = get_protected_folder_names();
+    if print {
+        let shown = if cfg!(windows) {
+            service::schtasks_preview(&service).map(|line| println!("{}", line))
+        } else {
+            service.files().map(|files| {
+                for (path, contents) in files {
+                    println!("# {}", path.display());
+                    println!("{}", contents);
+                }
+            })
+        };
+        if let Err(e) = shown {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    // 2. Read directory
-    let entries = match fs::read_dir(&target_dir) {
-        Ok(e) => e,
+    match service.install() {
+        Ok(files) => {
+            for file in files {
+                println!("Wrote {}", file.display());
+            }
+            println!(
+                "Installed {}: organizing {} {}.",
+                service::name_for(&service.path),
+                service.path.display(),
+                if watch {
+                    "continuously".to_string()
+                } else {
+                    format!("every {}s", interval)
+                }
+            );
+        }
         Err(e) => {
-            eprintln!("Error reading directory: {}", e);
+            eprintln!("Error installing service: {}", e);
             std::process::exit(1);
         }
-    };
+    }
+}
 
-    let mut files_count = 0;
-    let mut dirs_count = 0;
+fn run_uninstall_service(path: Option<&Path>) {
+    // The directory may be gone already; its service can still be removed
+    let path = path.unwrap_or(Path::new("."));
+    let target_dir = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    match service::uninstall(&target_dir) {
+        Ok(removed) if removed.is_empty() => {
+            println!("No service installed for {}.", target_dir.display());
+        }
+        Ok(removed) => {
+            for item in removed {
+                println!("Removed {}", item.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error removing service: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    for entry in entries.flatten() {
-        let path = entry.path();
+fn run_integrate(config: Option<&Path>, data_dir: Option<&Path>, print: bool) {
+    // Menu entries run from wherever the file manager is, so paths are absolute
+    let mut extra_args = Vec::new();
+    if let Some(config) = config {
+        extra_args.push("--config".to_string());
+        extra_args.push(absolute_or_exit(config).to_string_lossy().into_owned());
+    }
+    if let Some(dir) = data_dir {
+        let _ = fs::create_dir_all(dir);
+        extra_args.push("--data-dir".to_string());
+        extra_args.push(absolute_or_exit(dir).to_string_lossy().into_owned());
+    }
+    let integration = integrate::Integration { extra_args };
 
-        // --- Handle Directories ---
-        if path.is_dir() {
-            // Get the folder name (e.g., "images" from "/Downloads/images")
-            if let Some(folder_name) = path.file_name().and_then(|n| n.to_str()) {
-                // If the folder is one of our categories, SKIP it.
-                if protected_folders.contains(folder_name) {
-                    continue;
+    if print {
+        let shown = if cfg!(windows) {
+            integrate::reg_preview(&integration).map(|text| print!("{}", text))
+        } else {
+            integration.files().map(|files| {
+                for (path, contents) in files {
+                    println!("# {}", path.display());
+                    println!("{}", contents);
                 }
+            })
+        };
+        if let Err(e) = shown {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-                // Otherwise, it's a loose folder. Move it to "Folders"
-                if process_directory(&path, &target_dir, "Folders", args.dry_run) {
-                    dirs_count += 1;
-                }
+    match integration.install() {
+        Ok(written) => {
+            for item in written {
+                println!("Wrote {}", item.display());
             }
-            continue;
+            println!(
+                "Installed \"Organize this folder\" and \"Organize this file\" in {}.",
+                integrate::where_shown()
+            );
         }
+        Err(e) => {
+            eprintln!("Error installing the menu entries: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-        // --- Handle Files ---
-        let ext = path
-            .extension()
-            .and_then(|s| s.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
+fn run_uninstall_integration() {
+    match integrate::uninstall() {
+        Ok(removed) if removed.is_empty() => println!("No menu entries installed."),
+        Ok(removed) => {
+            for item in removed {
+                println!("Removed {}", item.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("Error removing the menu entries: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-        // Check if extension is known
-        let category = match extension_map.get(&ext) {
-            Some(cat) => cat.clone(),     // Known category (images, apps, etc.)
-            None => "Others".to_string(), // Unknown extension (ini, sw, meme) -> Others
+/// Lists the runs in the history, oldest first
+fn run_history() {
+    let runs = match history::list() {
+        Ok(runs) => runs,
+        Err(e) => {
+            eprintln!("Error reading the history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if runs.is_empty() {
+        println!("No runs recorded yet.");
+        return;
+    }
+    for run in &runs {
+        let mut categories: BTreeMap<&str, usize> = BTreeMap::new();
+        for (_, item) in &run.moves {
+            if item.kind != plan::EntryKind::Merge {
+                *categories.entry(item.category.as_str()).or_default() += 1;
+            }
+        }
+        let moved: Vec<String> = categories
+            .iter()
+            .map(|(category, n)| format!("{} {}", n, category))
+            .collect();
+        let undone = match run.undone.len() {
+            0 => String::new(),
+            n if n >= run.moves.len() => " [undone]".to_string(),
+            n => format!(" [{} undone]", n),
         };
+        println!(
+            "{}  {}  {}{}",
+            run.id,
+            run.target.display(),
+            moved.join(", "),
+            undone
+        );
+    }
+}
 
-        if process_file(&path, &target_dir, &category, args.dry_run) {
-            files_count += 1;
+/// The run `id` names (`last`: the most recent), or exits
+fn load_run(id: &str) -> history::Run {
+    let run = if id == "last" {
+        history::list().and_then(|runs| {
+            runs.into_iter()
+                .next_back()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no runs recorded yet"))
+        })
+    } else {
+        history::load(id)
+    };
+    match run {
+        Ok(run) => run,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
         }
     }
+}
 
+/// Lists the moves of one run
+fn run_history_show(id: &str) {
+    let run = load_run(id);
+    println!("Run:    {}", run.id);
+    println!("Target: {}", run.target.display());
     println!("-----------------------------------------");
-    println!(
-        "Done. {} files and {} folders processed.",
-        files_count, dirs_count
-    );
+    for (i, item) in &run.moves {
+        let name = item.src.file_name().unwrap_or_default();
+        let dest = item.dest.strip_prefix(&run.target).unwrap_or(&item.dest);
+        let note = if run.undone.contains(i) {
+            " (undone)"
+        } else {
+            ""
+        };
+        match item.kind {
+            plan::EntryKind::File => {
+                println!("[{:<12}] {:?} -> {:?}{}", item.category, name, dest, note)
+            }
+            plan::EntryKind::Dir => println!(
+                "[{:<12}] (Directory) {:?} -> {:?}{}",
+                item.category, name, dest, note
+            ),
+            plan::EntryKind::Merge => println!(
+                "[{:<12}] (Merged) {:?} into {:?}{}",
+                item.category, name, dest, note
+            ),
+        }
+    }
 }
 
-/// Moves a file to a category folder
-fn process_file(file_path: &Path, base_dir: &Path, category: &str, dry_run: bool) -> bool {
-    let category_dir = base_dir.join(category);
+/// Moves what run `id` moved back to where it was, newest move first.
+/// Entries that are gone from their destination, or whose old place is
+/// taken again, are left as they are.
+fn run_undo(id: &str, only: &[String], dry_run: bool) {
+    let run = load_run(id);
+    println!("Undoing: {} ({})", run.id, run.target.display());
+    if dry_run {
+        println!("Mode:    DRY RUN (No changes will be made)");
+    }
+    println!("-----------------------------------------");
+
+    let wanted = |category: &str| {
+        only.is_empty()
+            || only.iter().any(|o| {
+                category == o
+                    || category
+                        .strip_prefix(o.as_str())
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+    };
+    let (mut moved_back, mut left) = (0, 0);
+    for (i, item) in run.moves.iter().rev() {
+        if run.undone.contains(i) || !wanted(&item.category) {
+            continue;
+        }
+        let name = item.src.file_name().unwrap_or_default();
 
-    if !dry_run && !category_dir.exists() {
-        if let Err(e) = fs::create_dir_all(&category_dir) {
+        // A merged folder was removed once emptied; its files bring it back
+        if item.kind == plan::EntryKind::Merge {
+            if !dry_run && let Err(e) = history::mark_undone(&run.id, *i) {
+                eprintln!("Warning: cannot update the history: {}", e);
+            }
+            continue;
+        }
+        if fs::symlink_metadata(&item.dest).is_err() {
+            println!("[MISSING] {:?} (no longer in {})", name, item.category);
+            left += 1;
+            continue;
+        }
+        // Another file took the name since, or the one moved was changed
+        if let Some(placed) = run.placed.get(i)
+            && Placed::of(&item.dest) != Some(*placed)
+        {
+            println!(
+                "[CHANGED] {:?} (not the file the run put in {})",
+                name, item.category
+            );
+            left += 1;
+            continue;
+        }
+        if fs::symlink_metadata(&item.src).is_ok() {
+            println!("[SKIP] {:?} (its old place is taken)", name);
+            left += 1;
+            continue;
+        }
+        println!("[UNDO] {:?} (back from {})", name, item.category);
+        if dry_run {
+            moved_back += 1;
+            continue;
+        }
+        if let Some(parent) = item.src.parent()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
             eprintln!("Error creating dir: {}", e);
-            return false;
+            continue;
+        }
+        if let Err(e) = mover::move_path(&item.dest, &item.src, &CopyOptions::default()) {
+            eprintln!("Error moving {:?} back: {}", name, e);
+            continue;
         }
+        // The file an overwrite replaced returns, if it is still staged
+        if item.overwrite
+            && let Some(staged) = staging::staged_file(&run.target, &run.id, &item.dest)
+        {
+            match mover::move_path(&staged, &item.dest, &CopyOptions::default()) {
+                Ok(()) => println!("[RESTORE] {:?} (the file it replaced)", name),
+                Err(e) => eprintln!("Error restoring the file {:?} replaced: {}", name, e),
+            }
+        }
+        if let Err(e) = history::mark_undone(&run.id, *i) {
+            eprintln!("Warning: cannot update the history: {}", e);
+        }
+        // Folders left empty go too, up to the organized directory
+        for dir in item.dest.ancestors().skip(1) {
+            if dir == run.target || !dir.starts_with(&run.target) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+        moved_back += 1;
     }
 
-    let file_name = file_path.file_name().unwrap_or_default();
-    let dest_path = category_dir.join(file_name);
-
-    if dest_path.exists() {
-        println!("[SKIP] {:?} (already exists in {})", file_name, category);
-        return false;
+    println!("-----------------------------------------");
+    if dry_run {
+        println!("Would move {} back; {} left as they are.", moved_back, left);
+    } else {
+        println!(
+            "Done. {} moved back, {} left as they are.",
+            moved_back, left
+        );
     }
+}
 
-    println!("[{:<12}] {:?}", category, file_name);
+/// Continues the remaining moves of an interrupted run from its journal
+fn run_resume(path: Option<&Path>, batch_size: Option<u64>, explicit_config: Option<&Path>) {
+    let target_dir = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let Some(journal_path) = journal::find(&target_dir) else {
+        println!(
+            "Nothing to resume in '{}' (no interrupted run).",
+            target_dir.display()
+        );
+        return;
+    };
 
-    if !dry_run {
-        if let Err(e) = fs::rename(file_path, &dest_path) {
-            eprintln!("Error moving {:?}: {}", file_name, e);
-            return false;
+    let pending = match journal::load(&journal_path) {
+        Ok(pending) => pending,
+        Err(e) => {
+            eprintln!("Error reading journal '{}': {}", journal_path.display(), e);
+            std::process::exit(1);
         }
-    }
-    true
-}
+    };
 
-/// Moves a directory into a parent folder (e.g., "Folders")
-fn process_directory(
-    dir_path: &Path,
-    base_dir: &Path,
-    dest_container: &str,
-    dry_run: bool,
-) -> bool {
-    let container_dir = base_dir.join(dest_container);
+    let remaining = pending.plan.len() - pending.done.len().min(pending.plan.len());
+    println!("Resuming: {}", target_dir.display());
+    println!(
+        "Journal: {} of {} moves remaining",
+        remaining,
+        pending.plan.len()
+    );
+    println!("-----------------------------------------");
 
-    if !dry_run && !container_dir.exists() {
-        if let Err(e) = fs::create_dir_all(&container_dir) {
-            eprintln!("Error creating container dir: {}", e);
-            return false;
+    // Journals from before runs had IDs get one now
+    let run_id = pending.run.clone().unwrap_or_else(history::new_id);
+    let mut journal = match Journal::reopen(&journal_path, &run_id) {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("Error opening journal '{}': {}", journal_path.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    // Replaced files are staged as in a normal run (the run's config decides
+    // for how long), and its [limits.*] pace the moves
+    let config = match (explicit_config, &pending.config) {
+        (Some(path), _) => load_config(Some(path)),
+        (None, Some(ConfigSource::File(path))) => load_config(Some(path)),
+        (None, Some(ConfigSource::Profile(name))) => match profiles::find(name) {
+            Ok(Profile::File(path)) => load_config(Some(&path)),
+            Ok(Profile::Builtin(text)) => parse_preset(name, text),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => load_config(None),
+    };
+    let rules = RuleSet::new(config);
+    let mut staging = Staging::new(&target_dir, rules.staging_days(), &run_id);
+    let limits = rules.fs_limits(throttle::Limits::default());
+    let fs: Arc<dyn FileSystem> = if limits.is_empty() {
+        Arc::new(RealFs::default())
+    } else {
+        Arc::new(throttle::Throttled::new(RealFs::default(), limits))
+    };
+    let mut summary = PassSummary {
+        run: Some(run_id.clone()),
+        ..PassSummary::default()
+    };
+    let mut dests = DestCache::default();
+    let style = Style::new(ColorMode::Auto.enabled(), rules.labels());
+    let mut console = Console {
+        out: io::stdout(),
+        format: None,
+        run: &run_id,
+        fs,
+        errors: 0,
+        unmerged: 0,
+        mergeable: 0,
+        quiet: false,
+        style: &style,
+    };
+    interrupt::install();
+    // Moves from `i` on that are still to do, when stopped there
+    let left = |i: usize| {
+        (i..pending.plan.len())
+            .filter(|i| !pending.done.contains(i))
+            .count()
+    };
+    let mut started = 0;
+    for (i, item) in pending.plan.iter().enumerate() {
+        if pending.done.contains(&i) {
+            continue;
+        }
+        if batch_size.is_some_and(|n| started >= n) {
+            summary.remaining = left(i);
+            summary.batch = batch_size;
+            break;
+        }
+        started += 1;
+        if interrupt::requested() {
+            summary.remaining = left(i);
+            summary.cancelled = true;
+            break;
         }
-    }
 
-    let dir_name = dir_path.file_name().unwrap_or_default();
-    let dest_path = container_dir.join(dir_name);
+        // A half-finished file copy from the interrupted run is continued
+        // below; a folder copy is discarded and starts over
+        if mover::has_partial_file(&item.dest) && item.src.exists() {
+            println!("[RESUME] partial copy of {:?}", item.dest);
+        } else {
+            match mover::remove_stale_part(&item.dest) {
+                Ok(true) => println!("[CLEANUP] partial copy of {:?}", item.dest),
+                Ok(false) => {}
+                Err(e) => eprintln!("Error removing partial copy of {:?}: {}", item.dest, e),
+            }
+        }
 
-    // Safety check: ensure we aren't trying to move the container into itself
-    if dir_path == container_dir {
-        return false;
+        let name = item.src.file_name().unwrap_or_default();
+        let mut placed = None;
+        // Whether the entry is at its destination now, by this run or the
+        // interrupted one
+        let mut arrived = true;
+        // Copied to another filesystem before the interruption, but the
+        // original was not removed yet
+        let finished = item.kind != plan::EntryKind::Merge
+            && item.src.exists()
+            && item.dest.exists()
+            && match mover::finish_interrupted(&item.src, &item.dest) {
+                Ok(finished) => finished,
+                Err(e) => {
+                    eprintln!("Error finishing the move of {:?}: {}", name, e);
+                    console.errors += 1;
+                    false
+                }
+            };
+        if finished {
+            println!(
+                "[FINISH] {:?} (copied before; the original is removed now)",
+                name
+            );
+            match item.kind {
+                plan::EntryKind::File => {
+                    summary.files += 1;
+                    placed = Placed::of(&item.dest);
+                }
+                plan::EntryKind::Dir | plan::EntryKind::Merge => summary.dirs += 1,
+            }
+        } else if !item.src.exists() {
+            if !item.dest.exists() {
+                println!("[MISSING] {:?} (no longer at its source)", name);
+                arrived = false;
+            } else if item.kind == plan::EntryKind::File {
+                // The move finished just before the interruption
+                placed = Placed::of(&item.dest);
+            }
+        } else if item.dest.exists() && !item.overwrite && item.kind != plan::EntryKind::Merge {
+            println!("[SKIP] {:?} (already exists in {})", name, item.category);
+            arrived = false;
+        } else if plan::apply(item, &mut dests, &mut staging, &mut console) {
+            match item.kind {
+                plan::EntryKind::File => {
+                    summary.files += 1;
+                    placed = Placed::of(&item.dest);
+                }
+                plan::EntryKind::Dir | plan::EntryKind::Merge => summary.dirs += 1,
+            }
+        } else if interrupt::requested() {
+            // Stopped halfway through a copy: the next resume continues it
+            summary.remaining = left(i);
+            summary.cancelled = true;
+            break;
+        } else {
+            arrived = false;
+        }
+        // A `--shared` run gives what it moved to the user it was planned for
+        if arrived && item.recipient.is_some() {
+            hand_over(rules.shared(), item, &mut console.out);
+        }
+
+        if let Err(e) = journal.mark_done(i, placed) {
+            eprintln!("Warning: cannot update journal: {}", e);
+        }
     }
 
-    if dest_path.exists() {
-        println!(
-            "[SKIP DIR] {:?} (already exists in {})",
-            dir_name, dest_container
-        );
-        return false;
+    if summary.batch.is_some() {
+        if let Err(e) = journal.checkpoint() {
+            eprintln!("Warning: cannot write the journal to disk: {}", e);
+        }
+    } else if !summary.cancelled
+        && let Err(e) = journal.finish()
+    {
+        eprintln!("Warning: cannot record the run in the history: {}", e);
     }
 
-    println!("[{:<12}] (Directory) {:?}", dest_container, dir_name);
+    summary.staged = staging.staged;
+    summary.staging_dir = staging.dir().map(Path::to_path_buf);
+    summary.errors = console.errors;
 
-    if !dry_run {
-        if let Err(e) = fs::rename(dir_path, &dest_path) {
-            eprintln!("Error moving directory {:?}: {}", dir_name, e);
-            return false;
-        }
+    println!("-----------------------------------------");
+    summary.print();
+    if summary.cancelled {
+        print_stopped(&summary, &target_dir, false);
+        std::process::exit(130);
+    }
+    print_next_batch(&summary, &target_dir);
+    if summary.errors > 0 {
+        std::process::exit(1);
     }
-    true
 }
 
-/// Returns a set of folder names that should not be moved
-fn get_protected_folder_names() -> HashSet<String> {
-    let mut set = HashSet::new();
-    set.insert("images".to_string());
-    set.insert("documents".to_string());
-    set.insert("spreadsheets".to_string());
-    set.insert("presentations".to_string());
-    set.insert("archives".to_string());
-    set.insert("audio".to_string());
-    set.insert("video".to_string());
-    set.insert("code".to_string());
-    set.insert("APPS".to_string()); // New category
-    set.insert("Others".to_string()); // Catch-all for files
-    set.insert("Folders".to_string()); // Catch-all for directories
-    set
+/// Compares what the manifest (or index) recorded with what is on disk now
+fn run_verify(path: Option<&Path>, manifest_path: Option<&Path>, run: Option<&str>) {
+    let root = path
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if !root.is_dir() {
+        eprintln!("Error: '{}' is not a valid directory.", root.display());
+        std::process::exit(1);
+    }
+
+    let run = run.map(|id| match id {
+        "last" => load_run(id).id,
+        id => id.to_ascii_uppercase(),
+    });
+    let of_run = |row_run: &Option<String>| run.is_none() || *row_run == run;
+    let expected: Vec<verify::Expected> = match manifest_path {
+        Some(manifest_path) => match manifest::load(manifest_path) {
+            Ok(rows) => rows
+                .into_iter()
+                .filter(|row| of_run(&row.run))
+                .map(|row| verify::Expected {
+                    label: row.destination.display().to_string(),
+                    path: root.join(&row.destination),
+                    size: row.size,
+                    sha256: row.sha256,
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!(
+                    "Error reading manifest '{}': {}",
+                    manifest_path.display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        },
+        None => {
+            let Some(index_path) = index::default_path() else {
+                eprintln!("Error: cannot determine data directory (HOME is not set)");
+                std::process::exit(1);
+            };
+            let records = match index::load(&index_path) {
+                Ok(records) => records,
+                Err(e) => {
+                    eprintln!("Error reading index '{}': {}", index_path.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            // Only what was organized inside this directory
+            let root_abs = root.canonicalize().unwrap_or_else(|_| root.clone());
+            records
+                .into_iter()
+                .filter(|r| r.path.starts_with(&root_abs) && of_run(&r.run))
+                .map(|r| verify::Expected {
+                    label: r.path.display().to_string(),
+                    sha256: (r.hash != "-").then_some(r.hash),
+                    size: None,
+                    path: r.path,
+                })
+                .collect()
+        }
+    };
+
+    println!("Verifying: {}", root.display());
+    if let Some(run) = &run {
+        println!("Run:       {}", run);
+    }
+    println!("-----------------------------------------");
+    let report = verify::verify(&expected, &root);
+    println!("-----------------------------------------");
+    println!(
+        "Checked {}: {} ok, {} altered, {} moved, {} missing.",
+        expected.len(),
+        report.ok,
+        report.altered,
+        report.moved,
+        report.missing
+    );
+
+    if !report.is_clean() {
+        std::process::exit(1);
+    }
 }
 
-fn get_extension_map() -> HashMap<String, String> {
-    let mut map = HashMap::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let categories = [
-        (
-            "images",
-            vec![
-                "jpg", "jpeg", "png", "gif", "bmp", "svg", "webp", "ico", "tiff", "heic",
-            ],
-        ),
-        (
-            "documents",
-            vec!["pdf", "doc", "docx", "txt", "rtf", "odt", "md"],
-        ),
-        ("spreadsheets", vec!["xls", "xlsx", "csv", "ods"]),
-        ("presentations", vec!["ppt", "pptx", "odp", "key"]),
-        (
-            "archives",
-            vec!["zip", "rar", "tar", "gz", "bz2", "7z", "iso"],
-        ),
-        ("audio", vec!["mp3", "wav", "flac", "aac", "ogg", "m4a"]),
-        ("video", vec!["mp4", "mkv", "avi", "mov", "wmv", "webm"]),
-        (
-            "code",
-            vec![
-                "rs", "py", "js", "ts", "java", "c", "cpp", "go", "rb", "php", "html", "css",
-                "json",
-            ],
-        ),
-        // New "APPS" category for executables
-        (
-            "APPS",
-            vec![
-                "exe", "msi", "dmg", "app", "deb", "rpm", "apk", "appimage", "sh", "bat",
-            ],
-        ),
-    ];
+    /// An empty folder for one test; journals and history go to a data
+    /// folder of the tests' own, never the user's
+    fn scratch(name: &str) -> PathBuf {
+        paths::set_data_dir(std::env::temp_dir().join("auto-organize-main-data"));
+        let dir = std::env::temp_dir().join(format!("auto-organize-main-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
 
-    for (category, extensions) in categories {
-        for ext in extensions {
-            map.insert(ext.to_string(), category.to_string());
-        }
+    fn args(flags: &[&str], target_dir: &Path) -> Args {
+        let mut argv = vec![OsStr::new("auto-organize")];
+        argv.extend(flags.iter().map(OsStr::new));
+        argv.extend([OsStr::new("--"), target_dir.as_os_str()]);
+        Args::try_parse_from(argv).unwrap()
+    }
+
+    fn rules(config: &str) -> RuleSet {
+        RuleSet::new(Config::parse(config).unwrap())
     }
 
-    map
+    #[test]
+    fn safe_mode_forces_skip_on_conflict() {
+        let dir = Path::new("/nonexistent");
+        let overwrite = ["--on-conflict", "overwrite"];
+        assert_eq!(
+            conflict_policy(&args(&overwrite, dir), &rules(""), "documents"),
+            ConflictPolicy::Overwrite
+        );
+        let flag = args(&["--safe", "--on-conflict", "overwrite"], dir);
+        assert_eq!(
+            conflict_policy(&flag, &rules(""), "documents"),
+            ConflictPolicy::Skip
+        );
+        let config = rules("safe_mode = true\n");
+        assert_eq!(
+            conflict_policy(&args(&overwrite, dir), &config, "documents"),
+            ConflictPolicy::Skip
+        );
+    }
+
+    #[test]
+    fn safe_runs_replace_trash_and_prune_nothing() {
+        let dir = scratch("safe");
+        fs::create_dir_all(dir.join("documents")).unwrap();
+        fs::write(dir.join("documents/notes.txt"), "old").unwrap();
+        fs::write(dir.join("notes.txt"), "new").unwrap();
+        fs::write(dir.join("broken.pdf"), "").unwrap();
+
+        let flags = [
+            "--safe",
+            "--on-conflict",
+            "overwrite",
+            "--empty-files",
+            "delete",
+        ];
+        let summary = run_pass(
+            &args(&flags, &dir),
+            &dir,
+            None,
+            &rules(""),
+            &mut Records::default(),
+        )
+        .unwrap();
+        assert_eq!(summary.errors, 0);
+        assert_eq!(
+            fs::read_to_string(dir.join("documents/notes.txt")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "new");
+        assert!(dir.join("broken.pdf").exists());
+        assert!(!dir.join(staging::STAGING_DIR).exists());
+
+        // A misfiled photo leaves its subfolder empty; reorganize keeps it in safe mode
+        fs::create_dir_all(dir.join("documents/trip")).unwrap();
+        fs::write(dir.join("documents/trip/beach.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let mut reorganize = args(&[], &dir);
+        reorganize.reorganize = true;
+        let config = rules("safe_mode = true\n");
+        let listed = categorized_files(&dir, &config, &reorganize.by);
+        run_pass(
+            &reorganize,
+            &dir,
+            Some(&listed),
+            &config,
+            &mut Records::default(),
+        )
+        .unwrap();
+        assert!(dir.join("images/beach.png").exists());
+        assert!(dir.join("documents/trip").is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn undo_restores_the_file_an_overwrite_staged() {
+        let dir = scratch("undo");
+        fs::create_dir_all(dir.join("documents")).unwrap();
+        fs::write(dir.join("documents/notes.txt"), "old").unwrap();
+        fs::write(dir.join("notes.txt"), "new").unwrap();
+        fs::write(dir.join("todo.txt"), "todo").unwrap();
+
+        let flags = ["--on-conflict", "overwrite"];
+        run_pass(
+            &args(&flags, &dir),
+            &dir,
+            None,
+            &rules(""),
+            &mut Records::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("documents/notes.txt")).unwrap(),
+            "new"
+        );
+        assert!(!dir.join("notes.txt").exists());
+        let run = history::list()
+            .unwrap()
+            .into_iter()
+            .rfind(|run| run.target == dir)
+            .unwrap();
+        assert!(staging::staged_file(&dir, &run.id, &dir.join("documents/notes.txt")).is_some());
+
+        // A file changed since the run is left where it is
+        fs::write(dir.join("documents/todo.txt"), "done").unwrap();
+        run_undo(&run.id, &[], false);
+        assert_eq!(fs::read_to_string(dir.join("notes.txt")).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(dir.join("documents/notes.txt")).unwrap(),
+            "old"
+        );
+        assert!(!dir.join("todo.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("documents/todo.txt")).unwrap(),
+            "done"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
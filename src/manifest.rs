@@ -0,0 +1,116 @@
+use crate::csv;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER: [&str; 6] = ["source", "destination", "category", "size", "sha256", "run"];
+
+/// CSV record of one run's moves. Destinations are relative to the organized
+/// directory, so the manifest stays valid after the tree is synced elsewhere.
+pub struct Manifest {
+    target_dir: PathBuf,
+    writer: BufWriter<File>,
+}
+
+/// One manifest line read back for verification
+#[derive(Debug, Clone)]
+pub struct ManifestRow {
+    pub source: String,
+    /// Relative to the organized directory
+    pub destination: PathBuf,
+    /// `None` for directories
+    pub size: Option<u64>,
+    /// Hex SHA-256, or `None` for directories
+    pub sha256: Option<String>,
+    /// The run that made the move (see `history`); `None` in manifests of
+    /// older versions
+    pub run: Option<String>,
+}
+
+impl Manifest {
+    pub fn create(path: &Path, target_dir: &Path) -> io::Result<Manifest> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", csv::line(&HEADER))?;
+        Ok(Manifest {
+            target_dir: target_dir.to_path_buf(),
+            writer,
+        })
+    }
+
+    /// `hash` is `None` for directories
+    pub fn record(
+        &mut self,
+        src: &Path,
+        dest: &Path,
+        category: &str,
+        hash: Option<&str>,
+        run: &str,
+    ) -> io::Result<()> {
+        let relative = dest.strip_prefix(&self.target_dir).unwrap_or(dest);
+        // The source no longer exists, so it can't be canonicalized
+        let source = std::path::absolute(src).unwrap_or_else(|_| src.to_path_buf());
+        let size = match hash {
+            Some(_) => fs::metadata(dest)?.len().to_string(),
+            None => "-".to_string(),
+        };
+        writeln!(
+            self.writer,
+            "{}",
+            csv::line(&[
+                &source.to_string_lossy(),
+                &relative.to_string_lossy(),
+                category,
+                &size,
+                hash.unwrap_or("-"),
+                run,
+            ])
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Loads a manifest. When the same source appears twice (moved, then retired
+/// to APPS/old), the later row wins.
+pub fn load(path: &Path) -> io::Result<Vec<ManifestRow>> {
+    let text = fs::read_to_string(path)?;
+    let mut rows: Vec<ManifestRow> = Vec::new();
+    let mut by_source: HashMap<String, usize> = HashMap::new();
+
+    for record in csv::parse(&text).into_iter().skip(1) {
+        // Manifests of older versions have no run column
+        let (source, destination, size, sha256, run) = match &record[..] {
+            [source, destination, _category, size, sha256] => {
+                (source, destination, size, sha256, None)
+            }
+            [source, destination, _category, size, sha256, run] => {
+                (source, destination, size, sha256, Some(run))
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed manifest row: {}", record.join(",")),
+                ));
+            }
+        };
+        let row = ManifestRow {
+            source: source.clone(),
+            destination: PathBuf::from(destination),
+            size: size.parse().ok(),
+            sha256: (sha256 != "-").then(|| sha256.clone()),
+            run: run.cloned(),
+        };
+
+        match by_source.get(&row.source) {
+            Some(&i) => rows[i] = row,
+            None => {
+                by_source.insert(row.source.clone(), rows.len());
+                rows.push(row);
+            }
+        }
+    }
+    Ok(rows)
+}
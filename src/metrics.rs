@@ -0,0 +1,186 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// What a `--watch` daemon has done since it started, served over HTTP
+/// (`/healthz`, and `/metrics` in the Prometheus text format) for monitoring
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    runs: u64,
+    failed_runs: u64,
+    files: u64,
+    folders: u64,
+    errors: u64,
+    last_run: Option<SystemTime>,
+    last_duration: Duration,
+    /// Why the last pass failed; cleared by the next one that works
+    last_failure: Option<String>,
+}
+
+/// Parses "9184" (on 127.0.0.1) or "ADDRESS:PORT"
+pub fn parse_addr(text: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = text.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    text.parse()
+        .map_err(|_| format!("'{}': use a port, or an address and port", text))
+}
+
+impl Metrics {
+    /// A pass finished: what it moved, and how many moves failed
+    pub fn record_pass(&self, files: usize, folders: usize, errors: usize, took: Duration) {
+        let mut c = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        c.runs += 1;
+        c.files += files as u64;
+        c.folders += folders as u64;
+        c.errors += errors as u64;
+        c.last_run = Some(SystemTime::now());
+        c.last_duration = took;
+        c.last_failure = None;
+    }
+
+    /// A pass could not run at all (e.g. the directory is unreadable)
+    pub fn record_failure(&self, error: &str, took: Duration) {
+        let mut c = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        c.runs += 1;
+        c.failed_runs += 1;
+        c.errors += 1;
+        c.last_run = Some(SystemTime::now());
+        c.last_duration = took;
+        c.last_failure = Some(error.to_string());
+    }
+
+    /// Healthy unless the last pass failed
+    fn health(&self) -> Result<String, String> {
+        let c = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        match &c.last_failure {
+            Some(error) => Err(format!("last run failed: {}\n", error)),
+            None => Ok("ok\n".to_string()),
+        }
+    }
+
+    fn render(&self) -> String {
+        let c = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let last_run = c
+            .last_run
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP auto_organize_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE auto_organize_{} {}", name, kind);
+            let _ = writeln!(out, "auto_organize_{} {}", name, value);
+        };
+        metric(
+            "runs_total",
+            "counter",
+            "Passes run since the daemon started.",
+            c.runs.to_string(),
+        );
+        metric(
+            "failed_runs_total",
+            "counter",
+            "Passes that could not run.",
+            c.failed_runs.to_string(),
+        );
+        metric(
+            "files_moved_total",
+            "counter",
+            "Files moved into a category.",
+            c.files.to_string(),
+        );
+        metric(
+            "folders_moved_total",
+            "counter",
+            "Folders moved or merged.",
+            c.folders.to_string(),
+        );
+        metric(
+            "errors_total",
+            "counter",
+            "Moves that failed, and passes that could not run.",
+            c.errors.to_string(),
+        );
+        metric(
+            "last_run_timestamp_seconds",
+            "gauge",
+            "When the last pass finished (Unix time; 0 before the first).",
+            last_run.to_string(),
+        );
+        metric(
+            "last_run_duration_seconds",
+            "gauge",
+            "How long the last pass took.",
+            format!("{:.3}", c.last_duration.as_secs_f64()),
+        );
+        out
+    }
+}
+
+/// Starts answering on `addr` in the background; returns the bound address
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let bound = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // One client at a time is plenty for a scraper; a stuck one is dropped
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+            let _ = respond(stream, &metrics);
+        }
+    });
+    Ok(bound)
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers say nothing we need, but are read so the client sees a clean close
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = target.split('?').next().unwrap_or("");
+    let (status, content_type, body) = match (method, path) {
+        ("GET" | "HEAD", "/healthz") => match metrics.health() {
+            Ok(body) => ("200 OK", "text/plain; charset=utf-8", body),
+            Err(body) => ("503 Service Unavailable", "text/plain; charset=utf-8", body),
+        },
+        ("GET" | "HEAD", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        ("GET" | "HEAD", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "use GET\n".to_string(),
+        ),
+    };
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
@@ -0,0 +1,239 @@
+use crate::damaged;
+use crate::glob::GlobSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// MIME types of extensions: the contents' signature picks one of these
+/// when it has one (see `damaged::signature`), else the file's extension
+const EXTENSION_TYPES: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("webp", "image/webp"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("svg", "image/svg+xml"),
+    ("heic", "image/heic"),
+    ("avif", "image/avif"),
+    ("raw", "image/x-raw"),
+    ("cr2", "image/x-canon-cr2"),
+    ("nef", "image/x-nikon-nef"),
+    ("dng", "image/x-adobe-dng"),
+    ("psd", "image/vnd.adobe.photoshop"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/x-m4v"),
+    ("mov", "video/quicktime"),
+    ("3gp", "video/3gpp"),
+    ("avi", "video/x-msvideo"),
+    ("mkv", "video/x-matroska"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("wmv", "video/x-ms-wmv"),
+    ("flv", "video/x-flv"),
+    ("mpg", "video/mpeg"),
+    ("mpeg", "video/mpeg"),
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("flac", "audio/flac"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("wma", "audio/x-ms-wma"),
+    ("aiff", "audio/aiff"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    (
+        "xlsx",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "pptx",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+    ("rtf", "application/rtf"),
+    ("eml", "message/rfc822"),
+    ("emlx", "message/rfc822"),
+    ("msg", "application/vnd.ms-outlook"),
+    ("mbox", "application/mbox"),
+    ("epub", "application/epub+zip"),
+    ("mobi", "application/x-mobipocket-ebook"),
+    ("txt", "text/plain"),
+    ("log", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("tsv", "text/tab-separated-values"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("py", "text/x-python"),
+    ("rs", "text/x-rust"),
+    ("c", "text/x-c"),
+    ("h", "text/x-c"),
+    ("cpp", "text/x-c++"),
+    ("java", "text/x-java"),
+    ("sh", "application/x-sh"),
+    ("zip", "application/zip"),
+    ("jar", "application/java-archive"),
+    ("apk", "application/vnd.android.package-archive"),
+    ("gz", "application/gzip"),
+    ("tgz", "application/gzip"),
+    ("bz2", "application/x-bzip2"),
+    ("xz", "application/x-xz"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/vnd.rar"),
+    ("tar", "application/x-tar"),
+    ("exe", "application/vnd.microsoft.portable-executable"),
+    ("dll", "application/vnd.microsoft.portable-executable"),
+    ("msi", "application/x-msi"),
+    ("dmg", "application/x-apple-diskimage"),
+    ("pkg", "application/x-newton-compatible-pkg"),
+    ("deb", "application/vnd.debian.binary-package"),
+    ("rpm", "application/x-rpm"),
+    ("appimage", "application/vnd.appimage"),
+    ("iso", "application/x-iso9660-image"),
+    ("img", "application/x-raw-disk-image"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("torrent", "application/x-bittorrent"),
+    ("sqlite", "application/vnd.sqlite3"),
+    ("db", "application/vnd.sqlite3"),
+    ("stl", "model/stl"),
+    ("obj", "model/obj"),
+    ("gltf", "model/gltf+json"),
+    ("glb", "model/gltf-binary"),
+];
+
+/// Contents without a known type
+const UNKNOWN: &str = "application/octet-stream";
+
+/// How much of a file without a signature or a known extension is read to
+/// tell text from binary
+const TEXT_BYTES: u64 = 1024;
+
+/// The MIME type of `path`: by its contents where they have a known
+/// signature, else by its extension, else `text/plain` for what reads as
+/// UTF-8 text and `application/octet-stream` for the rest. Contents of a
+/// family (ZIP, MP4) keep the type of the extension when it is one of it
+/// (a `.docx` stays a Word document, a `.m4a` audio).
+pub fn detect(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if let Ok(Some(exts)) = damaged::signature(path) {
+        let ext = if exts.contains(&ext.as_str()) {
+            ext.as_str()
+        } else {
+            exts[0]
+        };
+        if let Some(mime) = of_extension(ext) {
+            return mime;
+        }
+    }
+    if let Some(mime) = of_extension(&ext) {
+        return mime;
+    }
+    if looks_like_text(path) {
+        "text/plain"
+    } else {
+        UNKNOWN
+    }
+}
+
+fn of_extension(ext: &str) -> Option<&'static str> {
+    EXTENSION_TYPES
+        .iter()
+        .find(|(e, _)| *e == ext)
+        .map(|(_, mime)| *mime)
+}
+
+/// Non-empty, no NUL bytes and UTF-8 (a character may be cut at the end)
+fn looks_like_text(path: &Path) -> bool {
+    let mut head = Vec::new();
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    if file.take(TEXT_BYTES).read_to_end(&mut head).is_err() || head.is_empty() {
+        return false;
+    }
+    !head.contains(&0)
+        && match std::str::from_utf8(&head) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+}
+
+/// `--skip-mime` and `--only-mime`: files whose MIME type matches one of
+/// `skip`, or none of `only`, are left where they are. Patterns are globs
+/// like `video/*` or `application/pdf`.
+pub struct MimeFilter {
+    skip: GlobSet,
+    only: Option<GlobSet>,
+}
+
+impl MimeFilter {
+    pub fn new(skip: &[String], only: &[String]) -> Self {
+        MimeFilter {
+            skip: GlobSet::new(skip),
+            only: (!only.is_empty()).then(|| GlobSet::new(only)),
+        }
+    }
+
+    /// Why the file at `path` is left out, if it is. Reads its first bytes,
+    /// unless there are no patterns.
+    pub fn excludes(&self, path: &Path) -> Option<String> {
+        if self.skip.is_empty() && self.only.is_none() {
+            return None;
+        }
+        let mime = detect(path);
+        if self.skip.matches_any(mime) {
+            return Some(format!("{} is skipped by --skip-mime", mime));
+        }
+        match &self.only {
+            Some(only) if !only.matches_any(mime) => {
+                Some(format!("{} is not in --only-mime", mime))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses a `--skip-mime` or `--only-mime` pattern: `type/subtype`, either
+/// of which may be a glob
+pub fn parse_pattern(s: &str) -> Result<String, String> {
+    match s.split_once('/') {
+        Some((kind, sub)) if !kind.is_empty() && !sub.is_empty() && !sub.contains('/') => {
+            Ok(s.to_lowercase())
+        }
+        _ => Err(format!(
+            "{:?} is not a MIME type pattern like video/* or application/pdf",
+            s
+        )),
+    }
+}
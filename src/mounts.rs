@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+
+/// Tells mount points (another drive, a bind mount, a FUSE cloud drive)
+/// apart from ordinary folders, so `--recursive` stays on one filesystem
+pub struct MountTable {
+    points: Vec<PathBuf>,
+}
+
+impl MountTable {
+    /// Reads the system's mount table, where it has one (Linux)
+    pub fn load() -> Self {
+        MountTable {
+            points: sys::mount_points(),
+        }
+    }
+
+    /// Whether `dir` is on another filesystem than its parent, or is
+    /// mounted over (bind mounts keep the device of their source)
+    pub fn is_mount_point(&self, dir: &Path) -> bool {
+        if sys::other_device(dir) {
+            return true;
+        }
+        !self.points.is_empty()
+            && dir
+                .canonicalize()
+                .is_ok_and(|dir| self.points.contains(&dir))
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::{Path, PathBuf};
+
+    pub fn other_device(dir: &Path) -> bool {
+        let Some(parent) = dir.parent() else {
+            return false;
+        };
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        match (fs::symlink_metadata(dir), fs::metadata(parent)) {
+            (Ok(dir), Ok(parent)) => dir.dev() != parent.dev(),
+            _ => false,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn mount_points() -> Vec<PathBuf> {
+        let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+            return Vec::new();
+        };
+        mounts
+            .lines()
+            .filter_map(|line| line.split(' ').nth(1))
+            .map(|field| PathBuf::from(super::unescape(field)))
+            .collect()
+    }
+
+    /// Without bind mounts, a change of device is all there is to see
+    #[cfg(not(target_os = "linux"))]
+    pub fn mount_points() -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Mounted folders and junctions are reparse points, which are never
+/// followed like symlinks
+#[cfg(not(unix))]
+mod sys {
+    use std::path::{Path, PathBuf};
+
+    pub fn other_device(_dir: &Path) -> bool {
+        false
+    }
+
+    pub fn mount_points() -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Mount points in `/proc/mounts` escape spaces and tabs as octal (`\040`)
+#[cfg(target_os = "linux")]
+pub fn unescape(field: &str) -> String {
+    let mut out = Vec::new();
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(octal) = field.get(i + 1..i + 4)
+            && let Ok(value) = u8::from_str_radix(octal, 8)
+        {
+            out.push(value);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
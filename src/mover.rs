@@ -0,0 +1,675 @@
+use crate::budget;
+use crate::dirdiff;
+use crate::error::CrossDeviceError;
+use crate::hash::Sha256;
+use crate::interrupt;
+use crate::listing;
+use crate::vfs::RealFs;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Files at least this big show a progress line while being copied
+const PROGRESS_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// How much of the end of a partial copy is compared with the source before
+/// the copy continues from it
+const RESUME_CHECK_BYTES: u64 = 64 * 1024;
+
+/// The largest `--chunk-size`: each copy holds one chunk in memory
+pub const MAX_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// How copies across filesystems are made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyOptions {
+    /// Bytes read and written at a time
+    pub chunk_size: usize,
+    /// Bytes per second written at most (`--bwlimit`), e.g. to spare a NAS link
+    pub bwlimit: Option<u64>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            chunk_size: 1024 * 1024,
+            bwlimit: None,
+        }
+    }
+}
+
+/// Parses a `--chunk-size` like `4M`, up to `MAX_CHUNK_SIZE`
+pub fn parse_chunk_size(text: &str) -> Result<u64, String> {
+    match budget::parse_size(text)? {
+        size if size > MAX_CHUNK_SIZE => Err(format!("'{}': at most 64M at a time", text)),
+        size => Ok(size),
+    }
+}
+
+/// Parses a transfer rate like `10MB/s`, `500K` or `1G/s` into bytes per second
+pub fn parse_rate(text: &str) -> Result<u64, String> {
+    let size = text.trim();
+    let size = size.strip_suffix("/s").unwrap_or(size);
+    budget::parse_size(size).map_err(|_| format!("'{}': use a rate like 10MB/s or 500K/s", text))
+}
+
+/// Moves a file or directory, falling back to copy + delete across filesystems.
+///
+/// Copies are written to `<dest>.part`, flushed to disk, and renamed into place
+/// once complete; the source goes only after the rename is on disk too. So an
+/// interrupted copy, or a crash or power loss, never leaves a half-written
+/// file under the final name, nor loses the source. A file copy picks up
+/// where a `.part` left by an interrupted run stopped, if that still matches
+/// the source; any other stale `.part` is discarded and the copy starts over.
+pub fn move_path(src: &Path, dest: &Path, options: &CopyOptions) -> io::Result<()> {
+    match rename_new(src, dest) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_then_remove(src, dest, false, options).map_err(crossed)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Like `move_path`, but a copied file (every file of a copied folder) is
+/// read back and compared (SHA-256) with what was read from the source
+/// before the source is removed, and a rename is checked to have left the
+/// source gone and its size and modification time as they were
+pub fn move_path_verified(src: &Path, dest: &Path, options: &CopyOptions) -> io::Result<()> {
+    let before = Fingerprint::of(src)?;
+    match rename_new(src, dest) {
+        Ok(()) => check_renamed(src, dest, &before),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_then_remove(src, dest, true, options).map_err(crossed)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Renames `src` to `dest` unless something is already there, in one step
+/// where the system allows. Names are reserved while planning; this keeps
+/// whatever took one since (another run, a sync client) from being replaced.
+fn rename_new(src: &Path, dest: &Path) -> io::Result<()> {
+    sys::rename_noreplace(src, dest).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => taken(dest),
+        _ => e,
+    })
+}
+
+/// What a rename must not change about a file or folder
+#[derive(Debug, PartialEq, Eq)]
+struct Fingerprint {
+    is_dir: bool,
+    /// Files only: a folder's size and time may change as it is re-linked
+    len: Option<u64>,
+    modified: Option<SystemTime>,
+}
+
+impl Fingerprint {
+    fn of(path: &Path) -> io::Result<Self> {
+        let meta = fs::symlink_metadata(path)?;
+        let is_dir = meta.is_dir();
+        Ok(Fingerprint {
+            is_dir,
+            len: (!is_dir).then_some(meta.len()),
+            modified: if is_dir { None } else { meta.modified().ok() },
+        })
+    }
+}
+
+/// A rename is one step, but a network filesystem or FUSE driver can still
+/// get it wrong: the source must be gone, and the destination look the same
+fn check_renamed(src: &Path, dest: &Path, before: &Fingerprint) -> io::Result<()> {
+    if fs::symlink_metadata(src).is_ok() {
+        return Err(io::Error::other(format!(
+            "{:?} is still there after the rename",
+            src
+        )));
+    }
+    if Fingerprint::of(dest)? != *before {
+        return Err(io::Error::other(format!(
+            "{:?} differs in size or time from the original after the rename",
+            dest
+        )));
+    }
+    Ok(())
+}
+
+fn taken(dest: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{:?} was taken since the run was planned", dest),
+    )
+}
+
+/// Marks a failed copy to another filesystem as such (see
+/// `OrganizeError::CrossDevice`); a taken name stays a conflict
+fn crossed(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::AlreadyExists {
+        return e;
+    }
+    io::Error::new(e.kind(), CrossDeviceError(e))
+}
+
+/// Check, then rename: for systems and filesystems without an exclusive rename
+#[cfg(not(windows))]
+fn rename_checked(src: &Path, dest: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(dest).is_ok() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+    fs::rename(src, dest)
+}
+
+/// Where an in-progress copy of `dest` is written
+pub fn part_path(dest: &Path) -> PathBuf {
+    let mut name: OsString = dest.file_name().unwrap_or_default().to_owned();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Removes a leftover `.part` from an interrupted copy; returns whether one existed
+pub fn remove_stale_part(dest: &Path) -> io::Result<bool> {
+    let part = part_path(dest);
+    match fs::symlink_metadata(&part) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(&part).map(|_| true),
+        Ok(_) => fs::remove_file(&part).map(|_| true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `dest` has a `.part` a file copy can continue from
+pub fn has_partial_file(dest: &Path) -> bool {
+    fs::symlink_metadata(part_path(dest)).is_ok_and(|meta| meta.is_file())
+}
+
+fn copy_then_remove(
+    src: &Path,
+    dest: &Path,
+    verify: bool,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    // Not worth copying only to find the name taken at the end
+    if fs::symlink_metadata(dest).is_ok() {
+        return Err(taken(dest));
+    }
+    let part = part_path(dest);
+    let mut throttle = Throttle::new(options.bwlimit);
+
+    if src.is_dir() {
+        remove_stale_part(dest)?;
+        copy_dir(src, &part, verify, options, &mut throttle)?;
+        rename_new(&part, dest)?;
+        sync_parent(dest)?;
+        fs::remove_dir_all(src)
+    } else {
+        if !has_partial_file(dest) {
+            remove_stale_part(dest)?;
+        }
+        let digest = copy_file(src, &part, verify, true, options, &mut throttle)?;
+        if let Some(digest) = digest
+            && hash_copy(&part)? != digest
+        {
+            fs::remove_file(&part)?;
+            return Err(io::Error::other(
+                "copy does not match the original; the original was kept",
+            ));
+        }
+        rename_new(&part, dest)?;
+        sync_parent(dest)?;
+        fs::remove_file(src)
+    }
+}
+
+/// Finishes a copy to another filesystem that was interrupted after the copy
+/// took its place at `dest` but before `src` was removed: if `dest` holds
+/// what is left of `src` (a file with the same contents; a folder with every
+/// file still in `src`, the same), `src` goes now. Returns whether it did;
+/// anything else at `dest` is not that copy.
+pub fn finish_interrupted(src: &Path, dest: &Path) -> io::Result<bool> {
+    let (src_meta, dest_meta) = (fs::symlink_metadata(src)?, fs::symlink_metadata(dest)?);
+    if src_meta.is_dir() != dest_meta.is_dir() {
+        return Ok(false);
+    }
+    if src_meta.is_dir() {
+        let diff = dirdiff::compare(&RealFs::default(), src, dest)?;
+        if !diff.only_in_src.is_empty() || !diff.differing.is_empty() {
+            return Ok(false);
+        }
+        fs::remove_dir_all(src)?;
+    } else {
+        if src_meta.len() != dest_meta.len() || hash_copy(src)? != hash_copy(dest)? {
+            return Ok(false);
+        }
+        fs::remove_file(src)?;
+    }
+    Ok(true)
+}
+
+/// Writes the entries of `path`'s folder (a rename into it) to disk
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync_dir(parent),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+/// Writes a folder's entries to disk, so files created or renamed in it
+/// survive a crash
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Folders cannot be opened to flush them here; NTFS journals renames
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// SHA-256 of a finished copy, with a progress line for big files on a terminal
+fn hash_copy(path: &Path) -> io::Result<[u8; 32]> {
+    let total = fs::metadata(path)?.len();
+    let show_progress = total >= PROGRESS_THRESHOLD && io::stderr().is_terminal();
+    let mut reader = File::open(path)?;
+    let mut hasher = Sha256::default();
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut read = 0u64;
+    let mut last_percent = None;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        read += n as u64;
+        if show_progress {
+            let percent = read * 100 / total.max(1);
+            if last_percent != Some(percent) {
+                eprint!("\r    verifying copy: {:>3}%", percent);
+                last_percent = Some(percent);
+            }
+        }
+    }
+    if show_progress {
+        eprintln!();
+    }
+    Ok(hasher.finish())
+}
+
+fn copy_dir(
+    src: &Path,
+    dest: &Path,
+    verify: bool,
+    options: &CopyOptions,
+    throttle: &mut Throttle,
+) -> io::Result<()> {
+    fs::create_dir(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target, verify, options, throttle)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&entry.path(), &target)?;
+        } else if let Some(kind) = listing::special_kind(file_type) {
+            // Reading a pipe or device would hang or never end; the folder stays
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{:?} is a {} and cannot be copied", entry.path(), kind),
+            ));
+        } else if let Some(digest) =
+            copy_file(&entry.path(), &target, verify, false, options, throttle)?
+            && hash_copy(&target)? != digest
+        {
+            return Err(io::Error::other(format!(
+                "the copy of {:?} does not match the original; the original was kept",
+                entry.path()
+            )));
+        }
+    }
+    fs::set_permissions(dest, fs::metadata(src)?.permissions())?;
+    sync_dir(dest)
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dest)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::copy(src, dest).map(|_| ())
+}
+
+/// Copies contents and permissions in chunks, printing progress for big
+/// files on a terminal. With `resume`, continues a partial `dest` that
+/// matches the start of `src`. With `hash`, returns the SHA-256 of the
+/// whole source, read from the source even for the part an earlier run
+/// copied (which must then match it byte for byte, or the copy starts over).
+fn copy_file(
+    src: &Path,
+    dest: &Path,
+    hash: bool,
+    resume: bool,
+    options: &CopyOptions,
+    throttle: &mut Throttle,
+) -> io::Result<Option<[u8; 32]>> {
+    let meta = fs::metadata(src)?;
+    let total = meta.len();
+    let show_progress = total >= PROGRESS_THRESHOLD && io::stderr().is_terminal();
+
+    let mut reader = File::open(src)?;
+    let mut hasher = hash.then(Sha256::default);
+    let mut buf = vec![0u8; options.chunk_size.max(1)];
+    let mut start = if resume {
+        resume_offset(&mut reader, &meta, dest)?
+    } else {
+        0
+    };
+    if start > 0
+        && let Some(hasher) = hasher.as_mut()
+    {
+        // The hash covers the whole source, so the check of the finished copy
+        // covers what an earlier run wrote too; that must be the same already
+        reader.seek(SeekFrom::Start(0))?;
+        let ours = hash_prefix(&mut reader, start, &mut buf, Some(hasher))?;
+        let theirs = hash_prefix(&mut File::open(dest)?, start, &mut buf, None)?;
+        if ours != theirs {
+            *hasher = Sha256::default();
+            start = 0;
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    let mut writer = if start > 0 {
+        if show_progress {
+            eprintln!(
+                "    resuming {:?} at {}%",
+                src.file_name().unwrap_or_default(),
+                start * 100 / total.max(1)
+            );
+        }
+        OpenOptions::new().append(true).open(dest)?
+    } else {
+        File::create(dest)?
+    };
+    let mut copied = start;
+    let mut last_percent = None;
+
+    loop {
+        // What was copied so far stays in the `.part` for `resume`
+        if interrupt::requested() {
+            if show_progress {
+                eprintln!();
+            }
+            return Err(interrupt::cancelled());
+        }
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buf[..n])?;
+        throttle.spend(n);
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buf[..n]);
+        }
+        copied += n as u64;
+
+        if show_progress {
+            let percent = copied * 100 / total.max(1);
+            if last_percent != Some(percent) {
+                eprint!(
+                    "\r    copying {:?}: {:>3}%",
+                    src.file_name().unwrap_or_default(),
+                    percent
+                );
+                last_percent = Some(percent);
+            }
+        }
+    }
+    if show_progress {
+        eprintln!();
+    }
+
+    writer.flush()?;
+    fs::set_permissions(dest, meta.permissions())?;
+    // On disk before the source may be removed
+    writer.sync_all()?;
+    Ok(hasher.map(Sha256::finish))
+}
+
+/// The SHA-256 of the first `len` bytes of `file`, also fed to `also`
+fn hash_prefix(
+    file: &mut File,
+    len: u64,
+    buf: &mut [u8],
+    mut also: Option<&mut Sha256>,
+) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::default();
+    let mut prefix = file.take(len);
+    loop {
+        let n = prefix.read(buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        if let Some(also) = also.as_mut() {
+            also.update(&buf[..n]);
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// How much of `src` an interrupted copy at `part` already holds: all of it,
+/// if it is no longer than the source, was written after the source last
+/// changed, and ends with the same bytes as that stretch of the source.
+/// Otherwise 0 and the copy starts over.
+fn resume_offset(reader: &mut File, meta: &fs::Metadata, part: &Path) -> io::Result<u64> {
+    let Ok(part_meta) = fs::symlink_metadata(part) else {
+        return Ok(0);
+    };
+    let len = part_meta.len();
+    let written_after = match (part_meta.modified(), meta.modified()) {
+        (Ok(part), Ok(src)) => part >= src,
+        _ => false,
+    };
+    if !part_meta.is_file() || len == 0 || len > meta.len() || !written_after {
+        return Ok(0);
+    }
+
+    let tail = len.min(RESUME_CHECK_BYTES) as usize;
+    let mut ours = vec![0u8; tail];
+    let mut theirs = vec![0u8; tail];
+    reader.seek(SeekFrom::Start(len - tail as u64))?;
+    reader.read_exact(&mut ours)?;
+    let mut partial = File::open(part)?;
+    partial.seek(SeekFrom::Start(len - tail as u64))?;
+    partial.read_exact(&mut theirs)?;
+    Ok(if ours == theirs { len } else { 0 })
+}
+
+/// Spaces out writes so they average at most `limit` bytes per second
+struct Throttle {
+    limit: Option<u64>,
+    start: Instant,
+    sent: u64,
+}
+
+impl Throttle {
+    fn new(limit: Option<u64>) -> Self {
+        Throttle {
+            limit,
+            start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Records `n` bytes written, sleeping if that is ahead of the limit
+    fn spend(&mut self, n: usize) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        self.sent += n as u64;
+        let due = Duration::from_secs_f64(self.sent as f64 / limit as f64);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sys {
+    use std::ffi::{CString, c_char, c_int, c_uint};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// The filesystem cannot rename exclusively (EINVAL, ENOSYS, ENOTSUP)
+    #[cfg(target_os = "linux")]
+    const UNSUPPORTED: &[i32] = &[22, 38, 95];
+    #[cfg(target_os = "macos")]
+    const UNSUPPORTED: &[i32] = &[22, 45, 78];
+
+    #[cfg(target_os = "linux")]
+    unsafe extern "C" {
+        fn renameat2(
+            olddirfd: c_int,
+            oldpath: *const c_char,
+            newdirfd: c_int,
+            newpath: *const c_char,
+            flags: c_uint,
+        ) -> c_int;
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe extern "C" {
+        fn renamex_np(from: *const c_char, to: *const c_char, flags: c_uint) -> c_int;
+    }
+
+    pub fn rename_noreplace(src: &Path, dest: &Path) -> io::Result<()> {
+        let from = CString::new(src.as_os_str().as_bytes())?;
+        let to = CString::new(dest.as_os_str().as_bytes())?;
+        // SAFETY: both paths are NUL-terminated
+        #[cfg(target_os = "linux")]
+        let result = unsafe {
+            const AT_FDCWD: c_int = -100;
+            const RENAME_NOREPLACE: c_uint = 1;
+            renameat2(
+                AT_FDCWD,
+                from.as_ptr(),
+                AT_FDCWD,
+                to.as_ptr(),
+                RENAME_NOREPLACE,
+            )
+        };
+        // SAFETY: both paths are NUL-terminated
+        #[cfg(target_os = "macos")]
+        let result = unsafe {
+            const RENAME_EXCL: c_uint = 0x4;
+            renamex_np(from.as_ptr(), to.as_ptr(), RENAME_EXCL)
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        match error.raw_os_error() {
+            Some(code) if UNSUPPORTED.contains(&code) => super::rename_checked(src, dest),
+            _ => Err(error),
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn MoveFileExW(from: *const u16, to: *const u16, flags: u32) -> i32;
+    }
+
+    fn wide(path: &Path) -> Vec<u16> {
+        path.as_os_str().encode_wide().chain(Some(0)).collect()
+    }
+
+    /// Without MOVEFILE_REPLACE_EXISTING, an existing `dest` is an error; without
+    /// MOVEFILE_COPY_ALLOWED, another volume is too (ERROR_NOT_SAME_DEVICE)
+    pub fn rename_noreplace(src: &Path, dest: &Path) -> io::Result<()> {
+        let (from, to) = (wide(src), wide(dest));
+        // SAFETY: both paths are NUL-terminated wide strings
+        if unsafe { MoveFileExW(from.as_ptr(), to.as_ptr(), 0) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    pub fn rename_noreplace(src: &Path, dest: &Path) -> io::Result<()> {
+        super::rename_checked(src, dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verified_resume_rereads_what_an_earlier_run_copied() {
+        let dir = std::env::temp_dir().join("auto-organize-mover-resume");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (src, part) = (dir.join("big.bin"), dir.join("big.bin.part"));
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&src, &data).unwrap();
+        // Same length and end as the source's first 200000 bytes, but one
+        // byte off near the start: the tail check alone takes it
+        let mut partial = data[..200_000].to_vec();
+        partial[10] ^= 0xFF;
+        thread::sleep(Duration::from_millis(20));
+        fs::write(&part, &partial).unwrap();
+
+        let options = CopyOptions::default();
+        let digest = copy_file(&src, &part, true, true, &options, &mut Throttle::new(None))
+            .unwrap()
+            .unwrap();
+        assert_eq!(fs::read(&part).unwrap(), data);
+        assert_eq!(hash_copy(&part).unwrap(), digest);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chunk_sizes_are_capped() {
+        assert_eq!(parse_chunk_size("4M"), Ok(4 * 1024 * 1024));
+        assert!(parse_chunk_size("1T").is_err());
+    }
+
+    #[test]
+    fn interrupted_moves_finish_only_over_their_own_copy() {
+        let dir = std::env::temp_dir().join("auto-organize-mover-finish");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("dest")).unwrap();
+        let (src, dest) = (dir.join("a.txt"), dir.join("dest/a.txt"));
+        fs::write(&src, "copied").unwrap();
+        fs::write(&dest, "another").unwrap();
+        assert!(!finish_interrupted(&src, &dest).unwrap());
+        assert!(src.exists());
+
+        fs::write(&dest, "copied").unwrap();
+        assert!(finish_interrupted(&src, &dest).unwrap());
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "copied");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
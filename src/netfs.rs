@@ -0,0 +1,143 @@
+use std::path::Path;
+
+/// Filesystem types of network shares (as `/proc/mounts` and `statfs` name them)
+const NETWORK_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afpfs",
+    "webdav",
+    "davfs",
+    "fuse.sshfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "fuse.glusterfs",
+];
+
+/// The filesystem type of `path` if it is a network share (SMB, NFS, AFP,
+/// WebDAV, sshfs...), where change notifications from other clients are
+/// unreliable
+pub fn network_type(path: &Path) -> Option<String> {
+    filesystem_type(path).filter(|fs| is_network(fs))
+}
+
+/// The filesystem type of `path` (`ext4`, `apfs`, `nfs4`, `cifs`...), where
+/// the platform tells; on Windows only network drives have one (`smb3`)
+pub fn filesystem_type(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    sys::filesystem_type(&path)
+}
+
+/// Whether a filesystem type is a network share's
+pub fn is_network(fs_type: &str) -> bool {
+    NETWORK_TYPES.contains(&fs_type)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use crate::mounts::unescape;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// The type of the deepest mount point containing `path`
+    pub fn filesystem_type(path: &Path) -> Option<String> {
+        let mounts = fs::read_to_string("/proc/self/mounts").ok()?;
+        mounts
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let _device = fields.next()?;
+                let mount_point = PathBuf::from(unescape(fields.next()?));
+                let fs_type = fields.next()?;
+                path.starts_with(&mount_point)
+                    .then(|| (mount_point, fs_type.to_string()))
+            })
+            .max_by_key(|(mount_point, _)| mount_point.components().count())
+            .map(|(_, fs_type)| fs_type)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use std::ffi::{CStr, CString, c_char};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// `struct statfs` (64-bit inodes) up to and including `f_fstypename`
+    #[repr(C)]
+    struct StatFs {
+        _counts: [u32; 2],
+        _blocks: [u64; 5],
+        _fsid: [i32; 2],
+        _owner: u32,
+        _type: u32,
+        _flags: u32,
+        _subtype: u32,
+        fstypename: [c_char; 16],
+        _rest: [u8; 2 * 1024 + 32],
+    }
+
+    unsafe extern "C" {
+        #[cfg_attr(target_arch = "x86_64", link_name = "statfs$INODE64")]
+        fn statfs(path: *const c_char, buf: *mut StatFs) -> i32;
+    }
+
+    pub fn filesystem_type(path: &Path) -> Option<String> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut buf = std::mem::MaybeUninit::<StatFs>::zeroed();
+        // SAFETY: `path` is NUL-terminated and `buf` is a full `struct statfs`
+        if unsafe { statfs(path.as_ptr(), buf.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: filled in by a successful statfs
+        let buf = unsafe { buf.assume_init() };
+        // SAFETY: the kernel NUL-terminates the name within its 16 bytes
+        let name = unsafe { CStr::from_ptr(buf.fstypename.as_ptr()) };
+        Some(name.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Component, Path, Prefix};
+
+    const DRIVE_REMOTE: u32 = 4;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDriveTypeW(root: *const u16) -> u32;
+    }
+
+    /// Mapped drives and UNC paths are reported as `smb3`
+    pub fn filesystem_type(path: &Path) -> Option<String> {
+        let Some(Component::Prefix(prefix)) = path.components().next() else {
+            return None;
+        };
+        if matches!(prefix.kind(), Prefix::UNC(..) | Prefix::VerbatimUNC(..)) {
+            return Some("smb3".to_string());
+        }
+        let root: Vec<u16> = prefix
+            .as_os_str()
+            .encode_wide()
+            .chain("\\".encode_utf16())
+            .chain(Some(0))
+            .collect();
+        // SAFETY: `root` is a NUL-terminated wide string
+        let remote = unsafe { GetDriveTypeW(root.as_ptr()) } == DRIVE_REMOTE;
+        remote.then(|| "smb3".to_string())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod sys {
+    use std::path::Path;
+
+    pub fn filesystem_type(_path: &Path) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,66 @@
+use crate::error::OrganizeError;
+use crate::plan::{ConflictPolicy, PlannedMove};
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// A destination that is already taken while planning
+pub struct Conflict<'a> {
+    pub src: &'a Path,
+    pub dest: &'a Path,
+    pub category: &'a str,
+    /// What is in the way is a folder
+    pub existing_is_dir: bool,
+    /// The policy that applies unless the observer picks another
+    pub policy: ConflictPolicy,
+}
+
+/// The part of a run that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Listing a folder being merged
+    ReadDir,
+    /// Creating the destination folder
+    CreateFolder,
+    /// Setting aside the file an overwrite replaces
+    Stage,
+    MoveFile,
+    MoveDir,
+    /// Removing what is left of a merged folder
+    RemoveMerged,
+}
+
+/// Follows a run as it is planned and applied, and may change its decisions.
+/// Every callback has a default, so front-ends implement only what they show;
+/// the command-line tool prints its output from one of these.
+pub trait Observer {
+    /// A move was planned; returning false leaves the entry where it is
+    fn on_plan_item(&mut self, _item: &PlannedMove) -> bool {
+        true
+    }
+
+    /// A destination is taken; returns the policy to apply to this file
+    fn on_conflict(&mut self, conflict: &Conflict) -> ConflictPolicy {
+        conflict.policy
+    }
+
+    /// A destination was too long for the filesystem, and `dest` is the
+    /// shortened one the file goes to instead
+    fn on_shortened(&mut self, _src: &Path, _dest: &Path) {}
+
+    /// An entry is left where it is while planning, e.g. its name is taken
+    fn on_skip(&mut self, _name: &OsStr, _is_dir: bool, _reason: &str) {}
+
+    /// A folder was left where it is (after `on_skip`) because a folder of
+    /// its name is already at `dest` and merging is off; see
+    /// `dirdiff::compare` to tell whether merging would be safe
+    fn on_dir_exists(&mut self, _src: &Path, _dest: &Path) {}
+
+    /// A planned move is about to be carried out
+    fn on_move_start(&mut self, _item: &PlannedMove) {}
+
+    /// A move finished (in a dry run: on the overlay)
+    fn on_move_complete(&mut self, _item: &PlannedMove) {}
+
+    /// A step failed; the entry is left where it is
+    fn on_error(&mut self, _error: &OrganizeError) {}
+}
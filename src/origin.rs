@@ -0,0 +1,93 @@
+use std::path::Path;
+
+/// The site a file was downloaded from, e.g. `github.com` (without `www.`),
+/// as recorded by the browser: the `Zone.Identifier` stream on Windows,
+/// `kMDItemWhereFroms` on macOS, `user.xdg.origin.url` elsewhere (Chromium).
+pub fn domain_of(path: &Path) -> Option<String> {
+    sys::urls(path).iter().find_map(|url| host(url))
+}
+
+/// Whether `domain` is `pattern` or one of its subdomains
+/// (`codeload.github.com` is from `github.com`)
+pub fn matches(domain: &str, pattern: &str) -> bool {
+    let pattern = pattern.trim_start_matches("www.").to_lowercase();
+    domain == pattern
+        || domain
+            .strip_suffix(&pattern)
+            .is_some_and(|sub| sub.ends_with('.'))
+}
+
+/// The lowercased host of a URL, without `www.`, user info or port;
+/// `None` for URLs without one (`about:blank`, `data:`)
+fn host(url: &str) -> Option<String> {
+    let url = url.trim().strip_prefix("blob:").unwrap_or(url.trim());
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next()?,
+        None => authority.split(':').next()?,
+    };
+    let host = host.trim_end_matches('.').to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs;
+    use std::path::Path;
+
+    /// `[ZoneTransfer]` with `HostUrl=` (the download) and `ReferrerUrl=` (the page)
+    pub fn urls(path: &Path) -> Vec<String> {
+        let mut stream = path.as_os_str().to_owned();
+        stream.push(":Zone.Identifier");
+        let Ok(text) = fs::read_to_string(&stream) else {
+            return Vec::new();
+        };
+        let mut urls = Vec::new();
+        for key in ["HostUrl=", "ReferrerUrl="] {
+            urls.extend(
+                text.lines()
+                    .filter_map(|line| line.trim().strip_prefix(key))
+                    .map(str::to_string),
+            );
+        }
+        urls
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use crate::xattr;
+    use std::path::Path;
+
+    #[cfg(target_os = "macos")]
+    pub fn urls(path: &Path) -> Vec<String> {
+        attribute(path, "com.apple.metadata:kMDItemWhereFroms")
+            .map(|data| crate::plist::strings(&data))
+            .unwrap_or_default()
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn urls(path: &Path) -> Vec<String> {
+        ["user.xdg.origin.url", "user.xdg.referrer.url"]
+            .iter()
+            .filter_map(|name| attribute(path, name))
+            .map(|data| String::from_utf8_lossy(&data).into_owned())
+            .collect()
+    }
+
+    fn attribute(path: &Path, name: &str) -> Option<Vec<u8>> {
+        xattr::get(path, name).ok().flatten()
+    }
+}
+
+#[cfg(not(any(windows, unix)))]
+mod sys {
+    use std::path::Path;
+
+    pub fn urls(_path: &Path) -> Vec<String> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Owner names already looked up, by user id (or SID string on Windows);
+/// a shared scan folder has thousands of files but only a few owners
+static NAMES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The account name of the file's owner: the Unix user (or its uid if it has
+/// no name), the owner in the file's security descriptor on Windows
+pub fn owner_of(path: &Path, meta: &fs::Metadata) -> Option<String> {
+    let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    sys::owner(path, meta, names.get_or_insert_with(HashMap::new))
+}
+
+/// Makes the account `uid` the owner (and its primary group the group) of
+/// `path`, without following symlinks. Needs root; a no-op on Windows, where
+/// moved files keep the ACLs of the folder they land in.
+pub fn set_owner(path: &Path, uid: u32) -> io::Result<()> {
+    sys::set_owner(path, uid)
+}
+
+/// The user id of the file's owner (Unix only)
+pub fn uid_of(meta: &fs::Metadata) -> Option<u32> {
+    sys::uid_of(meta)
+}
+
+/// The user id of the account `name` (Unix only)
+pub fn account_id(name: &str) -> Option<u32> {
+    sys::account_id(name)
+}
+
+/// Gives `path` to the account `owner` and/or the group `group` (names or
+/// numeric ids), without following symlinks. Changing the owner needs root;
+/// the group, membership of it. Unsupported on Windows.
+pub fn set_owner_and_group(
+    path: &Path,
+    owner: Option<&str>,
+    group: Option<&str>,
+) -> io::Result<()> {
+    sys::set_owner_and_group(path, owner, group)
+}
+
+/// Whether the account `account` (a name, or a uid on Unix) owns `path`
+pub fn owns(account: &str, path: &Path) -> bool {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return false;
+    };
+    owner_of(path, &meta).is_some_and(|name| name == account) || sys::has_uid(&meta, account)
+}
+
+/// Whether `name` is an account on this system (or a bare uid), i.e. a
+/// folder an owner-first layout may have created
+pub fn is_account(name: &str) -> bool {
+    name.parse::<u32>().is_ok() || sys::account_exists(name)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::collections::HashMap;
+    use std::ffi::{CStr, CString, c_char};
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    /// The leading fields, laid out the same on every Unix
+    #[repr(C)]
+    struct Passwd {
+        pw_name: *const c_char,
+        _pw_passwd: *const c_char,
+        pw_uid: u32,
+        pw_gid: u32,
+    }
+
+    /// Likewise for groups
+    #[repr(C)]
+    struct Group {
+        _gr_name: *const c_char,
+        _gr_passwd: *const c_char,
+        gr_gid: u32,
+    }
+
+    unsafe extern "C" {
+        fn getpwuid(uid: u32) -> *const Passwd;
+        fn getpwnam(name: *const c_char) -> *const Passwd;
+        fn getgrnam(name: *const c_char) -> *const Group;
+    }
+
+    /// Callers hold the NAMES lock, which also keeps the non-reentrant
+    /// `getpw*` calls from running on two threads at once
+    pub fn owner(
+        _path: &Path,
+        meta: &fs::Metadata,
+        names: &mut HashMap<String, String>,
+    ) -> Option<String> {
+        let uid = meta.uid().to_string();
+        if let Some(name) = names.get(&uid) {
+            return Some(name.clone());
+        }
+        // SAFETY: `getpwuid` takes any uid; its result, static storage the
+        // next `getpw*` call reuses, is read while the caller holds the lock
+        let entry = unsafe { getpwuid(meta.uid()) };
+        let name = if entry.is_null() {
+            uid.clone()
+        } else {
+            // SAFETY: a non-null entry has a NUL-terminated `pw_name`, copied
+            // out before the lock goes
+            let name = unsafe { CStr::from_ptr((*entry).pw_name) };
+            name.to_string_lossy().into_owned()
+        };
+        names.insert(uid, name.clone());
+        Some(name)
+    }
+
+    pub fn has_uid(meta: &fs::Metadata, account: &str) -> bool {
+        account.parse() == Ok(meta.uid())
+    }
+
+    pub fn account_exists(name: &str) -> bool {
+        let Ok(name) = CString::new(name) else {
+            return false;
+        };
+        let _guard = super::NAMES.lock();
+        // SAFETY: a NUL-terminated name; only whether there is an entry is read
+        !unsafe { getpwnam(name.as_ptr()) }.is_null()
+    }
+
+    pub fn set_owner(path: &Path, uid: u32) -> io::Result<()> {
+        let gid = {
+            let _guard = super::NAMES.lock();
+            // SAFETY: `getpwuid` takes any uid; the entry is read before the lock goes
+            let entry = unsafe { getpwuid(uid) };
+            // SAFETY: checked for null; `Passwd` matches its leading fields
+            (!entry.is_null()).then(|| unsafe { (*entry).pw_gid })
+        };
+        // An id without an account keeps the group
+        std::os::unix::fs::lchown(path, Some(uid), gid)
+    }
+
+    pub fn uid_of(meta: &fs::Metadata) -> Option<u32> {
+        Some(meta.uid())
+    }
+
+    pub fn account_id(name: &str) -> Option<u32> {
+        user_id(name).ok()
+    }
+
+    pub fn set_owner_and_group(
+        path: &Path,
+        owner: Option<&str>,
+        group: Option<&str>,
+    ) -> io::Result<()> {
+        let uid = owner.map(user_id).transpose()?;
+        let gid = group.map(group_id).transpose()?;
+        std::os::unix::fs::lchown(path, uid, gid)
+    }
+
+    fn user_id(name: &str) -> io::Result<u32> {
+        if let Ok(id) = name.parse() {
+            return Ok(id);
+        }
+        let c_name = CString::new(name).map_err(io::Error::other)?;
+        let _guard = super::NAMES.lock();
+        // SAFETY: a NUL-terminated name; the entry is read before the lock goes
+        let entry = unsafe { getpwnam(c_name.as_ptr()) };
+        if entry.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no account named {:?}", name),
+            ));
+        }
+        // SAFETY: checked for null; `Passwd` matches its leading fields
+        Ok(unsafe { (*entry).pw_uid })
+    }
+
+    fn group_id(name: &str) -> io::Result<u32> {
+        if let Ok(id) = name.parse() {
+            return Ok(id);
+        }
+        let c_name = CString::new(name).map_err(io::Error::other)?;
+        let _guard = super::NAMES.lock();
+        // SAFETY: as for `user_id`
+        let entry = unsafe { getgrnam(c_name.as_ptr()) };
+        if entry.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no group named {:?}", name),
+            ));
+        }
+        // SAFETY: checked for null; `Group` matches its leading fields
+        Ok(unsafe { (*entry).gr_gid })
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::collections::HashMap;
+    use std::ffi::c_void;
+    use std::fs;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    const SE_FILE_OBJECT: u32 = 1;
+    const OWNER_SECURITY_INFORMATION: u32 = 1;
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn GetNamedSecurityInfoW(
+            name: *const u16,
+            object_type: u32,
+            info: u32,
+            owner: *mut *mut c_void,
+            group: *mut *mut c_void,
+            dacl: *mut *mut c_void,
+            sacl: *mut *mut c_void,
+            descriptor: *mut *mut c_void,
+        ) -> u32;
+        fn ConvertSidToStringSidW(sid: *mut c_void, string: *mut *mut u16) -> i32;
+        fn LookupAccountSidW(
+            system: *const u16,
+            sid: *mut c_void,
+            name: *mut u16,
+            name_len: *mut u32,
+            domain: *mut u16,
+            domain_len: *mut u32,
+            sid_use: *mut u32,
+        ) -> i32;
+        fn LookupAccountNameW(
+            system: *const u16,
+            name: *const u16,
+            sid: *mut c_void,
+            sid_len: *mut u32,
+            domain: *mut u16,
+            domain_len: *mut u32,
+            sid_use: *mut u32,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn LocalFree(mem: *mut c_void) -> *mut c_void;
+    }
+
+    fn wide(s: &std::ffi::OsStr) -> Vec<u16> {
+        s.encode_wide().chain(Some(0)).collect()
+    }
+
+    /// Names are cached by the owner's SID in string form
+    pub fn owner(
+        path: &Path,
+        _meta: &fs::Metadata,
+        names: &mut HashMap<String, String>,
+    ) -> Option<String> {
+        let path = wide(path.as_os_str());
+        let mut sid = ptr::null_mut();
+        let mut descriptor = ptr::null_mut();
+        // SAFETY: a NUL-terminated wide path and out-pointers to locals; on
+        // success the descriptor is ours to free with `LocalFree`
+        let status = unsafe {
+            GetNamedSecurityInfoW(
+                path.as_ptr(),
+                SE_FILE_OBJECT,
+                OWNER_SECURITY_INFORMATION,
+                &mut sid,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut descriptor,
+            )
+        };
+        if status != 0 || sid.is_null() {
+            return None;
+        }
+
+        // `sid` points into the descriptor, which is freed once done with it
+        let owner = sid_key(sid).map(|key| match names.get(&key) {
+            Some(name) => name.clone(),
+            None => {
+                let name = account_name(sid).unwrap_or_else(|| key.clone());
+                names.insert(key, name.clone());
+                name
+            }
+        });
+        // SAFETY: allocated by `GetNamedSecurityInfoW`, and `sid` (which
+        // points into it) is not used after this
+        unsafe { LocalFree(descriptor) };
+        owner
+    }
+
+    pub fn has_uid(_meta: &fs::Metadata, _account: &str) -> bool {
+        false
+    }
+
+    fn sid_key(sid: *mut c_void) -> Option<String> {
+        let mut text = ptr::null_mut();
+        // SAFETY: `sid` is a valid SID from the caller's descriptor
+        if unsafe { ConvertSidToStringSidW(sid, &mut text) } == 0 {
+            return None;
+        }
+        let key = from_wide_ptr(text);
+        // SAFETY: allocated by `ConvertSidToStringSidW`, and copied out above
+        unsafe { LocalFree(text.cast()) };
+        Some(key)
+    }
+
+    fn account_name(sid: *mut c_void) -> Option<String> {
+        let mut name = [0u16; 256];
+        let mut domain = [0u16; 256];
+        let (mut name_len, mut domain_len, mut sid_use) = (256u32, 256u32, 0u32);
+        // SAFETY: the buffers are as long as the lengths passed with them,
+        // which the call sets to what it wrote
+        let ok = unsafe {
+            LookupAccountSidW(
+                ptr::null(),
+                sid,
+                name.as_mut_ptr(),
+                &mut name_len,
+                domain.as_mut_ptr(),
+                &mut domain_len,
+                &mut sid_use,
+            )
+        };
+        (ok != 0).then(|| String::from_utf16_lossy(&name[..name_len as usize]))
+    }
+
+    /// `text` must be a NUL-terminated wide string
+    fn from_wide_ptr(text: *const u16) -> String {
+        let mut len = 0;
+        // SAFETY: reads stop at the terminating NUL
+        while unsafe { *text.add(len) } != 0 {
+            len += 1;
+        }
+        // SAFETY: the `len` units before the NUL, read above
+        String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(text, len) })
+    }
+
+    pub fn account_exists(name: &str) -> bool {
+        let name: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let (mut sid_len, mut domain_len, mut sid_use) = (0u32, 0u32, 0u32);
+        // With no buffers this fails either way; the sizes say whether it was found
+        // SAFETY: a NUL-terminated wide name; null buffers with zero lengths
+        unsafe {
+            LookupAccountNameW(
+                ptr::null(),
+                name.as_ptr(),
+                ptr::null_mut(),
+                &mut sid_len,
+                ptr::null_mut(),
+                &mut domain_len,
+                &mut sid_use,
+            )
+        };
+        sid_len > 0
+    }
+
+    pub fn set_owner(_path: &Path, _uid: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn uid_of(_meta: &fs::Metadata) -> Option<u32> {
+        None
+    }
+
+    pub fn account_id(_name: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn set_owner_and_group(
+        _path: &Path,
+        _owner: Option<&str>,
+        _group: Option<&str>,
+    ) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "owner and group cannot be set on this platform",
+        ))
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    pub fn owner(
+        _path: &Path,
+        _meta: &fs::Metadata,
+        _names: &mut HashMap<String, String>,
+    ) -> Option<String> {
+        None
+    }
+
+    pub fn has_uid(_meta: &fs::Metadata, _account: &str) -> bool {
+        false
+    }
+
+    pub fn account_exists(_name: &str) -> bool {
+        false
+    }
+
+    pub fn set_owner(_path: &Path, _uid: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn uid_of(_meta: &fs::Metadata) -> Option<u32> {
+        None
+    }
+
+    pub fn account_id(_name: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn set_owner_and_group(
+        _path: &Path,
+        _owner: Option<&str>,
+        _group: Option<&str>,
+    ) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "owner and group cannot be set on this platform",
+        ))
+    }
+}
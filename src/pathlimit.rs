@@ -0,0 +1,138 @@
+use crate::hash::{self, Sha256};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+/// Longest file or folder name (bytes; UTF-16 units on Windows, which a
+/// byte count never undercounts)
+pub const MAX_NAME: usize = 255;
+
+/// Longest whole path: PATH_MAX on Linux, 1024 on macOS, MAX_PATH on
+/// Windows (Explorer and many programs still stop there)
+#[cfg(target_os = "macos")]
+pub const MAX_PATH: usize = 1024;
+#[cfg(windows)]
+pub const MAX_PATH: usize = 260;
+#[cfg(not(any(target_os = "macos", windows)))]
+pub const MAX_PATH: usize = 4096;
+
+/// Kept free in names and paths for what is added later: " (12)" or a date
+/// for a conflict, ".part" while copying
+const MARGIN: usize = 32;
+
+/// Generated folder names that are too long are cut to this, so every file
+/// of the folder ends up in the same shortened one
+const FOLDER_NAME: usize = 48;
+
+/// A file name is never cut below this, before its hash and extension
+const MIN_STEM: usize = 16;
+
+/// Extensions longer than this are part of the name
+const MAX_EXTENSION: usize = 16;
+
+/// Cuts `name` to at most `max` bytes, the same way every time: what is
+/// kept of the stem, `~`, a hash of the whole name (so names that start
+/// alike stay apart) and the extension. Names that fit come back as they are.
+pub fn shorten_name(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() <= MAX_EXTENSION => {
+            (stem, format!(".{}", ext))
+        }
+        _ => (name, String::new()),
+    };
+    let mut hasher = Sha256::default();
+    hasher.update(name.as_bytes());
+    let tag = hash::to_hex(&hasher.finish()[..4]);
+
+    let room = max.saturating_sub(ext.len() + 1 + tag.len());
+    let mut end = room.min(stem.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    // Windows drops trailing dots and spaces from names
+    let kept = stem[..end].trim_end_matches(['.', ' ']);
+    format!("{}~{}{}", kept, tag, ext)
+}
+
+/// Fits a destination under `base` within the name and path limits, cutting
+/// overlong names with `shorten_name`: the file name first, then generated
+/// folders (never `base` itself). `None` when it fits as it is; an error
+/// when even shortened names leave it too long.
+pub fn fit(base: &Path, dest: &Path) -> Result<Option<PathBuf>, String> {
+    let Ok(rel) = dest.strip_prefix(base) else {
+        return Ok(None);
+    };
+    let mut names: Vec<&OsStr> = Vec::new();
+    for component in rel.components() {
+        match component {
+            Component::Normal(name) => names.push(name),
+            // Nothing generated, nothing to shorten
+            _ => return Ok(None),
+        }
+    }
+    let Some(last) = names.len().checked_sub(1) else {
+        return Ok(None);
+    };
+    let original: Vec<String> = names
+        .iter()
+        .map(|name| name.to_string_lossy().into_owned())
+        .collect();
+    let mut parts = original.clone();
+
+    // The entry moved keeps room for a conflict suffix and `.part`; folders
+    // only need to fit
+    for (i, part) in parts.iter_mut().enumerate() {
+        let (limit, max) = if i == last {
+            (MAX_NAME - MARGIN, MAX_NAME - MARGIN)
+        } else {
+            (MAX_NAME, FOLDER_NAME)
+        };
+        if part.len() > limit {
+            *part = shorten_name(part, max);
+        }
+    }
+
+    let base_len = std::path::absolute(base)
+        .unwrap_or(base.to_path_buf())
+        .as_os_str()
+        .as_encoded_bytes()
+        .len();
+    let total = |parts: &[String]| base_len + parts.iter().map(|p| p.len() + 1).sum::<usize>();
+    let budget = MAX_PATH - MARGIN;
+
+    // Too deep: the file name gives way first, then the longest folders
+    if total(&parts) > budget {
+        let over = total(&parts) - budget;
+        let floor = MIN_STEM + 1 + 8 + MAX_EXTENSION + 1;
+        let max = parts[last].len().saturating_sub(over).max(floor);
+        parts[last] = shorten_name(&parts[last], max);
+    }
+    while total(&parts) > budget {
+        let Some(longest) = (0..last)
+            .filter(|&i| parts[i].len() > FOLDER_NAME)
+            .max_by_key(|&i| parts[i].len())
+        else {
+            return Err(format!(
+                "the destination path is over the limit of {} even with shortened names",
+                MAX_PATH
+            ));
+        };
+        parts[longest] = shorten_name(&parts[longest], FOLDER_NAME);
+    }
+
+    if parts == original {
+        return Ok(None);
+    }
+    // Names left alone keep their exact bytes, even when not valid Unicode
+    let mut fitted = base.to_path_buf();
+    for ((name, part), was) in names.iter().zip(&parts).zip(&original) {
+        if part == was {
+            fitted.push(name);
+        } else {
+            fitted.push(part);
+        }
+    }
+    Ok(Some(fitted))
+}
@@ -0,0 +1,66 @@
+use crate::hash::{self, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const APP_DIR: &str = "auto-organize";
+
+/// Set once from `--data-dir`; replaces the platform data directory
+static DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_data_dir(dir: PathBuf) {
+    let _ = DATA_DIR_OVERRIDE.set(dir);
+}
+
+/// Where the tool keeps its own state (index, journals of interrupted runs):
+/// `--data-dir` if given, else `$XDG_DATA_HOME/auto-organize`
+/// (`~/.local/share`), `~/Library/Application Support/auto-organize` on macOS,
+/// `%APPDATA%\auto-organize` on Windows
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = DATA_DIR_OVERRIDE.get() {
+        return Some(dir.clone());
+    }
+    platform_dir(
+        "XDG_DATA_HOME",
+        ".local/share",
+        "Library/Application Support",
+        "APPDATA",
+    )
+}
+
+/// Where the config file lives: `$XDG_CONFIG_HOME/auto-organize` (`~/.config`,
+/// also on macOS), `%APPDATA%\auto-organize` on Windows
+pub fn config_dir() -> Option<PathBuf> {
+    platform_dir("XDG_CONFIG_HOME", ".config", ".config", "APPDATA")
+}
+
+/// The XDG variable wins wherever it is set; otherwise the platform convention
+fn platform_dir(xdg_var: &str, unix: &str, macos: &str, windows_var: &str) -> Option<PathBuf> {
+    if let Some(dir) = non_empty_var(xdg_var) {
+        return Some(dir.join(APP_DIR));
+    }
+    if cfg!(windows) {
+        return Some(non_empty_var(windows_var)?.join(APP_DIR));
+    }
+    let relative = if cfg!(target_os = "macos") {
+        macos
+    } else {
+        unix
+    };
+    Some(non_empty_var("HOME")?.join(relative).join(APP_DIR))
+}
+
+fn non_empty_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// A short, stable file name for state about `dir` (e.g. its journal), so
+/// nothing has to be written inside the organized directory itself
+pub fn key_for(dir: &Path) -> String {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let mut hasher = Sha256::default();
+    hasher.update(dir.to_string_lossy().as_bytes());
+    hash::to_hex(&hasher.finish()[..8])
+}
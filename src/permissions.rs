@@ -0,0 +1,35 @@
+use crate::config::FolderPermissions;
+use crate::owner;
+use std::io;
+use std::path::Path;
+
+/// Sets a category's owner, group and mode on one of its folders. The owner
+/// and group go first: changing them clears the setgid bit on some systems.
+pub fn apply(dir: &Path, permissions: &FolderPermissions) -> io::Result<()> {
+    if permissions.owner.is_some() || permissions.group.is_some() {
+        owner::set_owner_and_group(
+            dir,
+            permissions.owner.as_deref(),
+            permissions.group.as_deref(),
+        )?;
+    }
+    if let Some(mode) = permissions.mode {
+        set_mode(dir, mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(dir: &Path, mode: u32) -> io::Result<()> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(dir, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_dir: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "permission bits cannot be set on this platform",
+    ))
+}
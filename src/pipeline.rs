@@ -0,0 +1,196 @@
+use crate::analyze::{self, FileInfo};
+use crate::hash;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// One step of `auto-organize run`, as named in `[pipeline] stages`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Sort loose files into category folders (a normal run)
+    Classify,
+    /// Trash files with the same contents as another, keeping one
+    Dedupe,
+    /// Trash files older than `after_days` in some categories
+    Retention,
+    /// Zip old files into monthly archives (as `compress`)
+    Compress,
+    /// Remove folders left empty
+    PruneEmpty,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [
+        Stage::Classify,
+        Stage::Dedupe,
+        Stage::Retention,
+        Stage::Compress,
+        Stage::PruneEmpty,
+    ];
+
+    pub fn parse(name: &str) -> Option<Stage> {
+        Stage::ALL.into_iter().find(|stage| stage.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Classify => "classify",
+            Stage::Dedupe => "dedupe",
+            Stage::Retention => "retention",
+            Stage::Compress => "compress",
+            Stage::PruneEmpty => "prune-empty",
+        }
+    }
+
+    /// Whether the stage deletes (or trashes) files, which safe mode forbids
+    pub fn destructive(self) -> bool {
+        !matches!(self, Stage::Classify | Stage::PruneEmpty)
+    }
+}
+
+/// Which copy of a duplicate the dedupe stage keeps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Keep {
+    /// The one modified longest ago (usually the original download)
+    #[default]
+    Oldest,
+    Newest,
+}
+
+/// Files with the same contents: the one kept, and the others
+pub struct Duplicates {
+    pub kept: PathBuf,
+    pub copies: Vec<PathBuf>,
+}
+
+/// Every file under the given categories of `root` (all of it when none are
+/// given), skipping entries that start with `skip_prefix`
+fn files_in(root: &Path, categories: &[String], skip_prefix: &str) -> Vec<FileInfo> {
+    let dirs: Vec<PathBuf> = if categories.is_empty() {
+        vec![root.to_path_buf()]
+    } else {
+        categories.iter().map(|c| root.join(c)).collect()
+    };
+    let mut files = Vec::new();
+    for dir in dirs {
+        match analyze::files(&dir, skip_prefix) {
+            Ok(found) => files.extend(found.into_iter().map(|mut file| {
+                file.path = dir.join(&file.path);
+                file
+            })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Error reading {:?}: {}", dir, e),
+        }
+    }
+    files
+}
+
+/// Groups files with the same contents. Only files of equal size are hashed;
+/// empty files are never duplicates of each other.
+pub fn duplicates(
+    root: &Path,
+    categories: &[String],
+    keep: Keep,
+    skip_prefix: &str,
+) -> Vec<Duplicates> {
+    let mut by_size: BTreeMap<u64, Vec<FileInfo>> = BTreeMap::new();
+    for file in files_in(root, categories, skip_prefix) {
+        if file.size > 0 {
+            by_size.entry(file.size).or_default().push(file);
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (_, same_size) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        let mut by_hash: BTreeMap<String, Vec<FileInfo>> = BTreeMap::new();
+        for file in same_size {
+            match hash::hash_file(&file.path) {
+                Ok(digest) => by_hash.entry(digest).or_default().push(file),
+                Err(e) => eprintln!("Error reading {:?}: {}", file.path, e),
+            }
+        }
+        for (_, mut same) in by_hash.into_iter().filter(|(_, files)| files.len() > 1) {
+            same.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.path.cmp(&b.path)));
+            if keep == Keep::Newest {
+                same.rotate_right(1);
+            }
+            let mut paths = same.into_iter().map(|file| file.path);
+            let kept = paths.next().expect("a group has two files or more");
+            groups.push(Duplicates {
+                kept,
+                copies: paths.collect(),
+            });
+        }
+    }
+    groups
+}
+
+/// Files in `categories` last modified more than `after_days` days ago
+pub fn expired(
+    root: &Path,
+    categories: &[String],
+    after_days: u64,
+    skip_prefix: &str,
+) -> Vec<PathBuf> {
+    let cutoff = SystemTime::now() - Duration::from_secs(after_days.saturating_mul(86_400));
+    let mut files: Vec<PathBuf> = files_in(root, categories, skip_prefix)
+        .into_iter()
+        .filter(|file| file.modified < cutoff)
+        .map(|file| file.path)
+        .collect();
+    files.sort();
+    files
+}
+
+/// Removes folders under `root` that hold nothing, deepest first, and returns
+/// them; a folder holding only empty folders goes too. `root` itself and the
+/// top-level folders named in `keep` stay.
+pub fn prune_empty(
+    root: &Path,
+    keep: &[String],
+    skip_prefix: &str,
+    dry_run: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    prune(root, root, keep, skip_prefix, dry_run, &mut removed)?;
+    Ok(removed)
+}
+
+/// Whether `dir` is empty once its empty subfolders are gone
+fn prune(
+    root: &Path,
+    dir: &Path,
+    keep: &[String],
+    skip_prefix: &str,
+    dry_run: bool,
+    removed: &mut Vec<PathBuf>,
+) -> io::Result<bool> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let kept = dir == root && keep.contains(&name);
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        if !is_dir || kept || name.starts_with(skip_prefix) {
+            empty = false;
+            continue;
+        }
+        match prune(root, &entry.path(), keep, skip_prefix, dry_run, removed) {
+            Ok(true) => {}
+            Ok(false) => empty = false,
+            Err(e) => {
+                eprintln!("Error reading {:?}: {}", entry.path(), e);
+                empty = false;
+            }
+        }
+    }
+    if !empty || dir == root {
+        return Ok(false);
+    }
+    if !dry_run {
+        fs::remove_dir(dir)?;
+    }
+    removed.push(dir.to_path_buf());
+    Ok(true)
+}
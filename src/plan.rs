@@ -0,0 +1,656 @@
+use crate::date::DateTime;
+use crate::error::OrganizeError;
+use crate::hash;
+use crate::observer::{Conflict, Observer, Step};
+use crate::origin;
+use crate::owner;
+use crate::pathlimit;
+use crate::staging::Staging;
+use crate::template::Template;
+use crate::vfs::{FileSystem, RealFs};
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Whether a planned move is a single file or a whole directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    /// A folder whose contents were merged into an existing one: the step
+    /// removes what is left of the source once its entries have moved
+    Merge,
+}
+
+/// What to do when the destination of a file already exists
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave the file where it is
+    Skip,
+    /// Move it under a free name, e.g. "report (1).pdf"
+    Rename,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep both if their contents differ, the new one named after its
+    /// modification date, e.g. "report (2024-05-01).docx"; skip an identical copy
+    Version,
+}
+
+/// What happens to loose folders in the target directory
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirPolicy {
+    /// Leave them where they are
+    Skip,
+    /// Move them into Folders
+    Folders,
+    /// Move each into the category most of its contents belong to (by size),
+    /// or into Folders when none has most of it
+    Classify,
+}
+
+/// How the file moved in is named when a conflict keeps both: `{stem}`,
+/// `{ext}` (with its dot), `{date}` (the file's modification date,
+/// YYYY-MM-DD) and `{n}` (a counter). Without `{n}`, a taken name gets
+/// " (1)", " (2)"... as well.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictName {
+    pattern: String,
+}
+
+const CONFLICT_NAME_FIELDS: &[&str] = &["stem", "ext", "date", "n"];
+
+impl ConflictName {
+    pub fn parse(pattern: &str) -> Result<ConflictName, String> {
+        if pattern.contains(['/', '\\']) {
+            return Err("a conflict name cannot contain '/' or '\\'".to_string());
+        }
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                return Err(format!("unclosed '{}'", &rest[start..]));
+            };
+            let field = &rest[start + 1..start + len];
+            if !CONFLICT_NAME_FIELDS.contains(&field) {
+                return Err(format!(
+                    "unknown placeholder {{{}}} (available: {})",
+                    field,
+                    CONFLICT_NAME_FIELDS.join(", ")
+                ));
+            }
+            rest = &rest[start + len + 1..];
+        }
+        if !pattern.contains("{stem}") {
+            return Err("a conflict name needs {stem}".to_string());
+        }
+        Ok(ConflictName {
+            pattern: pattern.to_string(),
+        })
+    }
+
+    /// The built-in naming of a policy: a counter, or the date for `Version`
+    pub fn default_for(policy: ConflictPolicy) -> ConflictName {
+        let pattern = match policy {
+            ConflictPolicy::Version => "{stem} ({date}){ext}",
+            _ => "{stem} ({n}){ext}",
+        };
+        ConflictName {
+            pattern: pattern.to_string(),
+        }
+    }
+
+    fn render(&self, stem: &str, ext: &str, date: &str, n: u64) -> String {
+        self.pattern
+            .replace("{stem}", stem)
+            .replace("{ext}", ext)
+            .replace("{date}", date)
+            .replace("{n}", &n.to_string())
+    }
+}
+
+/// One move decided during planning, before anything touches the disk
+#[derive(Debug, Clone)]
+pub struct PlannedMove {
+    pub kind: EntryKind,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    /// Category (or container such as "Folders") the entry goes into
+    pub category: String,
+    /// The destination exists and is replaced (`--on-conflict overwrite`)
+    pub overwrite: bool,
+    /// A copy across filesystems is read back and checked before the source goes
+    pub verify: bool,
+    /// With `--shared`, the user id the entry is given to once moved
+    pub recipient: Option<u32>,
+}
+
+/// What is already in the destination folders of one pass.
+///
+/// Each folder is listed once, the first time a move targets it, instead of
+/// a stat per file; planned destinations are claimed so that two files of
+/// the same pass never pick the same name. Folders created while applying
+/// are remembered so each is created once.
+///
+/// Planning and applying see the disk through the cache's filesystem, so a
+/// run can be simulated against a `vfs::MemoryFs`.
+pub struct DestCache {
+    fs: Box<dyn FileSystem>,
+    /// Folder -> entry name (see `name_key`) -> whether it is a directory
+    listings: HashMap<PathBuf, HashMap<OsString, bool>>,
+    /// Folders known to exist on disk
+    ready: HashSet<PathBuf>,
+}
+
+impl Default for DestCache {
+    fn default() -> Self {
+        DestCache::new(Box::new(RealFs::default()))
+    }
+}
+
+impl DestCache {
+    pub fn new(fs: Box<dyn FileSystem>) -> Self {
+        DestCache {
+            fs,
+            listings: HashMap::new(),
+            ready: HashSet::new(),
+        }
+    }
+
+    /// The filesystem moves are planned against and carried out on
+    pub fn fs(&self) -> &dyn FileSystem {
+        self.fs.as_ref()
+    }
+
+    /// `Some(is_dir)` if something is (or is planned to be) at `path`
+    pub fn lookup(&mut self, path: &Path) -> Option<bool> {
+        let (dir, name) = (path.parent()?, path.file_name()?);
+        self.listing(dir).get(&name_key(name)).copied()
+    }
+
+    /// Records that a planned move will put something at `path`
+    fn claim(&mut self, path: &Path, is_dir: bool) {
+        if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+            self.listing(dir).insert(name_key(name), is_dir);
+        }
+    }
+
+    fn listing(&mut self, dir: &Path) -> &mut HashMap<OsString, bool> {
+        let fs = &self.fs;
+        self.listings.entry(dir.to_path_buf()).or_insert_with(|| {
+            // A folder that cannot be read (usually: not created yet) is empty
+            fs.read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .into_iter()
+                        .map(|e| (name_key(&e.name), e.is_dir))
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    /// Creates `dir` (and its parents) unless it is known to exist
+    fn ensure_dir(&mut self, dir: &Path) -> io::Result<()> {
+        if !self.ready.contains(dir) {
+            self.fs.create_dir_all(dir)?;
+            self.ready.insert(dir.to_path_buf());
+        }
+        Ok(())
+    }
+}
+
+/// Names compare case-insensitively where the filesystem usually does, so a
+/// cached "Report.pdf" still blocks "report.pdf"
+fn name_key(name: &OsStr) -> OsString {
+    if cfg!(any(windows, target_os = "macos")) {
+        name.to_string_lossy().to_lowercase().into()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Plans moving a file into a category folder, as `name`. When the
+/// destination is already taken, `policy` (or the observer) decides; a skip
+/// returns `None`.
+#[allow(clippy::too_many_arguments)]
+pub fn plan_file(
+    file_path: &Path,
+    name: &OsStr,
+    base_dir: &Path,
+    category: &str,
+    policy: ConflictPolicy,
+    naming: &ConflictName,
+    dests: &mut DestCache,
+    observer: &mut impl Observer,
+) -> Option<PlannedMove> {
+    let dest_path = base_dir.join(category).join(name);
+    // Nested date and template folders plus a long name can exceed the limits
+    let dest_path = match pathlimit::fit(base_dir, &dest_path) {
+        Ok(None) => dest_path,
+        Ok(Some(shortened)) => {
+            observer.on_shortened(file_path, &shortened);
+            shortened
+        }
+        Err(reason) => {
+            observer.on_skip(name, false, &reason);
+            return None;
+        }
+    };
+    plan_file_to(
+        file_path, dest_path, category, policy, naming, dests, observer,
+    )
+}
+
+fn plan_file_to(
+    file_path: &Path,
+    dest_path: PathBuf,
+    category: &str,
+    policy: ConflictPolicy,
+    naming: &ConflictName,
+    dests: &mut DestCache,
+    observer: &mut impl Observer,
+) -> Option<PlannedMove> {
+    let (dest, overwrite) = resolve_conflict(
+        file_path, dest_path, category, policy, naming, dests, observer,
+    )?;
+    let item = PlannedMove {
+        kind: EntryKind::File,
+        src: file_path.to_path_buf(),
+        dest,
+        category: category.to_string(),
+        overwrite,
+        verify: false,
+        recipient: None,
+    };
+    if !observer.on_plan_item(&item) {
+        return None;
+    }
+    dests.claim(&item.dest, false);
+    Some(item)
+}
+
+/// Applies the conflict policy to a file destination. Returns where the
+/// file should go and whether that replaces an existing file.
+fn resolve_conflict(
+    src: &Path,
+    dest: PathBuf,
+    category: &str,
+    policy: ConflictPolicy,
+    naming: &ConflictName,
+    dests: &mut DestCache,
+    observer: &mut impl Observer,
+) -> Option<(PathBuf, bool)> {
+    let Some(existing_is_dir) = dests.lookup(&dest) else {
+        return Some((dest, false));
+    };
+    let file_name = dest.file_name().unwrap_or_default().to_owned();
+    let policy = observer.on_conflict(&Conflict {
+        src,
+        dest: &dest,
+        category,
+        existing_is_dir,
+        policy,
+    });
+
+    match policy {
+        ConflictPolicy::Skip => {
+            let reason = format!("already exists in {}", category);
+            observer.on_skip(&file_name, false, &reason);
+            None
+        }
+        ConflictPolicy::Rename => Some((free_name(src, &dest, naming, dests), false)),
+        ConflictPolicy::Overwrite | ConflictPolicy::Version if existing_is_dir => {
+            let reason = format!("a folder with that name exists in {}", category);
+            observer.on_skip(&file_name, false, &reason);
+            None
+        }
+        ConflictPolicy::Overwrite => Some((dest, true)),
+        ConflictPolicy::Version if same_contents(src, &dest, dests) => {
+            let reason = format!("an identical copy is already in {}", category);
+            observer.on_skip(&file_name, false, &reason);
+            None
+        }
+        ConflictPolicy::Version => Some((free_name(src, &dest, naming, dests), false)),
+    }
+}
+
+/// First free name for `src` next to `dest`, following `naming`
+fn free_name(src: &Path, dest: &Path, naming: &ConflictName, dests: &mut DestCache) -> PathBuf {
+    let stem = dest.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = dest
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let date = dests
+        .fs()
+        .metadata(src)
+        .map(|meta| {
+            let date = DateTime::from_system_time(meta.modified);
+            format!("{:04}-{:02}-{:02}", date.year, date.month, date.day)
+        })
+        .unwrap_or_default();
+
+    let counted = naming.pattern.contains("{n}");
+    let mut candidates = (0..).map(|n| {
+        if counted {
+            return dest.with_file_name(naming.render(&stem, &ext, &date, n + 1));
+        }
+        let name = naming.render(&stem, &ext, &date, 0);
+        if n == 0 {
+            return dest.with_file_name(name);
+        }
+        // "report (2024-05-01).docx" is taken too: "report (2024-05-01) (1).docx"
+        let named = Path::new(&name);
+        let stem = named.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = named
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        dest.with_file_name(format!("{} ({}){}", stem, n, ext))
+    });
+    candidates
+        .find(|candidate| dests.lookup(candidate).is_none())
+        .unwrap_or_else(|| dest.to_path_buf())
+}
+
+/// Whether the file at `dest` has the same contents as `src` (same size,
+/// then same SHA-256); a destination that is only planned is never the same
+fn same_contents(src: &Path, dest: &Path, dests: &DestCache) -> bool {
+    let (Ok(a), Ok(b)) = (dests.fs().metadata(src), dests.fs().metadata(dest)) else {
+        return false;
+    };
+    a.len == b.len
+        && matches!(
+            (hash::hash_file(src), hash::hash_file(dest)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// Plans moving a directory into a parent folder (e.g., "Folders").
+///
+/// If a folder of the same name is already there, it is skipped, or with
+/// `merge` its contents are moved in entry by entry (files go through the
+/// conflict policy) and the emptied source folder is removed afterwards.
+pub fn plan_directory(
+    dir_path: &Path,
+    base_dir: &Path,
+    dest_container: &str,
+    policy: ConflictPolicy,
+    merge: bool,
+    dests: &mut DestCache,
+    observer: &mut impl Observer,
+) -> Vec<PlannedMove> {
+    let container_dir = base_dir.join(dest_container);
+    let dir_name = dir_path.file_name().unwrap_or_default();
+    let dest_path = container_dir.join(dir_name);
+
+    // Safety check: ensure we aren't trying to move the container into itself
+    if dir_path == container_dir {
+        return Vec::new();
+    }
+
+    // Folders (and bundles) get the same room for a conflict suffix as files
+    let dest_path = match pathlimit::fit(base_dir, &dest_path) {
+        Ok(None) => dest_path,
+        Ok(Some(shortened)) => {
+            observer.on_shortened(dir_path, &shortened);
+            shortened
+        }
+        Err(reason) => {
+            observer.on_skip(dir_name, true, &reason);
+            return Vec::new();
+        }
+    };
+
+    if let Some(is_dir) = dests.lookup(&dest_path) {
+        if merge && is_dir {
+            let mut plan = Vec::new();
+            if let Err(e) = plan_merge(
+                dir_path,
+                &dest_path,
+                dest_container,
+                policy,
+                &mut plan,
+                dests,
+                observer,
+            ) {
+                observer.on_error(&OrganizeError::new(dir_path, None, Step::ReadDir, e));
+                return Vec::new();
+            }
+            return plan;
+        }
+        let reason = format!("already exists in {}", dest_container);
+        observer.on_skip(dir_name, true, &reason);
+        if is_dir {
+            observer.on_dir_exists(dir_path, &dest_path);
+        }
+        return Vec::new();
+    }
+
+    let item = PlannedMove {
+        kind: EntryKind::Dir,
+        src: dir_path.to_path_buf(),
+        dest: dest_path,
+        category: dest_container.to_string(),
+        overwrite: false,
+        verify: false,
+        recipient: None,
+    };
+    if !observer.on_plan_item(&item) {
+        return Vec::new();
+    }
+    dests.claim(&item.dest, true);
+    vec![item]
+}
+
+/// Plans merging `src` into the existing folder `dest`: entries missing from
+/// `dest` move as they are, sub-folders present in both are merged recursively
+fn plan_merge(
+    src: &Path,
+    dest: &Path,
+    category: &str,
+    policy: ConflictPolicy,
+    plan: &mut Vec<PlannedMove>,
+    dests: &mut DestCache,
+    observer: &mut impl Observer,
+) -> io::Result<()> {
+    let mut entries = dests.fs().read_dir(src)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for entry in entries {
+        let path = entry.path;
+        let target = dest.join(&entry.name);
+        let is_dir = entry.is_dir;
+
+        if is_dir {
+            match dests.lookup(&target) {
+                None => {
+                    let item = PlannedMove {
+                        kind: EntryKind::Dir,
+                        src: path,
+                        dest: target,
+                        category: category.to_string(),
+                        overwrite: false,
+                        verify: false,
+                        recipient: None,
+                    };
+                    if observer.on_plan_item(&item) {
+                        dests.claim(&item.dest, true);
+                        plan.push(item);
+                    }
+                }
+                Some(true) => plan_merge(&path, &target, category, policy, plan, dests, observer)?,
+                Some(false) => {
+                    let reason = format!("a file with that name exists in {}", category);
+                    observer.on_skip(&entry.name, true, &reason);
+                }
+            }
+        } else {
+            let naming = ConflictName::default_for(policy);
+            plan.extend(plan_file_to(
+                &path, target, category, policy, &naming, dests, observer,
+            ));
+        }
+    }
+
+    plan.push(PlannedMove {
+        kind: EntryKind::Merge,
+        src: src.to_path_buf(),
+        dest: dest.to_path_buf(),
+        category: category.to_string(),
+        overwrite: false,
+        verify: false,
+        recipient: None,
+    });
+    Ok(())
+}
+
+/// Carries out a planned move on the filesystem of `dests`, telling
+/// `observer` as it goes. A file it replaces goes to `staging` first.
+/// A dry run applies its plan to a `vfs::OverlayFs`.
+/// Returns false if it failed; the observer has been told why.
+pub fn apply(
+    item: &PlannedMove,
+    dests: &mut DestCache,
+    staging: &mut Staging,
+    observer: &mut impl Observer,
+) -> bool {
+    observer.on_move_start(item);
+
+    if item.kind == EntryKind::Merge {
+        // Whatever was skipped on conflict stays behind in the source folder
+        if let Err(e) = remove_empty_dirs(dests.fs(), &item.src) {
+            observer.on_error(&OrganizeError::new(&item.src, None, Step::RemoveMerged, e));
+            return false;
+        }
+        observer.on_move_complete(item);
+        return true;
+    }
+
+    if let Some(parent) = item.dest.parent()
+        && let Err(e) = dests.ensure_dir(parent)
+    {
+        observer.on_error(&OrganizeError::new(parent, None, Step::CreateFolder, e));
+        return false;
+    }
+
+    // The file being replaced stays recoverable until the staging area expires
+    if item.overwrite
+        && dests.fs().metadata(&item.dest).is_ok_and(|m| !m.is_dir)
+        && let Err(e) = staging.stage(&item.dest, dests.fs())
+    {
+        observer.on_error(&OrganizeError::new(&item.src, None, Step::Stage, e));
+        return false;
+    }
+
+    if let Err(e) = dests.fs().move_path(&item.src, &item.dest, item.verify) {
+        let step = match item.kind {
+            EntryKind::File => Step::MoveFile,
+            _ => Step::MoveDir,
+        };
+        observer.on_error(&OrganizeError::new(&item.src, Some(&item.dest), step, e));
+        return false;
+    }
+    observer.on_move_complete(item);
+    true
+}
+
+/// Fills in an output template for a planned move (before it happens)
+pub fn render(template: &Template, item: &PlannedMove, run: &str) -> String {
+    let meta = fs::symlink_metadata(&item.src).ok();
+    template.render(|field| match field {
+        "category" => item.category.clone(),
+        "name" => lossy(item.src.file_name()),
+        "stem" => lossy(item.src.file_stem()),
+        "ext" => lossy(item.src.extension()),
+        "size" => match (&meta, item.kind) {
+            (Some(meta), EntryKind::File) => meta.len().to_string(),
+            _ => "-".to_string(),
+        },
+        "modified" => meta
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .map(|t| DateTime::from_system_time(t).to_iso())
+            .unwrap_or_else(|| "-".to_string()),
+        "owner" => meta
+            .as_ref()
+            .and_then(|m| owner::owner_of(&item.src, m))
+            .unwrap_or_else(|| "-".to_string()),
+        "origin" => origin::domain_of(&item.src).unwrap_or_else(|| "-".to_string()),
+        "kind" => match item.kind {
+            EntryKind::File => "file",
+            EntryKind::Dir => "dir",
+            EntryKind::Merge => "merge",
+        }
+        .to_string(),
+        "src" => item.src.display().to_string(),
+        "dest" => item.dest.display().to_string(),
+        "run" => run.to_string(),
+        _ => String::new(),
+    })
+}
+
+fn lossy(part: Option<&std::ffi::OsStr>) -> String {
+    part.map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Removes `dir` and every folder under it that is (or becomes) empty
+fn remove_empty_dirs(fs: &dyn FileSystem, dir: &Path) -> io::Result<()> {
+    for entry in fs.read_dir(dir)? {
+        if entry.is_dir {
+            remove_empty_dirs(fs, &entry.path)?;
+        }
+    }
+    if fs.read_dir(dir)?.is_empty() {
+        fs.remove_dir(dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathlimit::MAX_NAME;
+    use crate::vfs::MemoryFs;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    struct Quiet;
+    impl Observer for Quiet {}
+
+    #[test]
+    fn long_name_with_a_conflict_stays_within_the_name_limit() {
+        let fs = Arc::new(MemoryFs::new());
+        let name = format!("{}.pdf", "r".repeat(236));
+        assert_eq!(name.len(), 240);
+        fs.add_file(format!("/in/{}", name), 1, SystemTime::UNIX_EPOCH);
+        let mut dests = DestCache::new(Box::new(fs.clone()));
+        let policy = ConflictPolicy::Version;
+        let naming = ConflictName::default_for(policy);
+        let plan = |dests: &mut DestCache| {
+            plan_file(
+                Path::new(&format!("/in/{}", name)),
+                OsStr::new(&name),
+                Path::new("/out"),
+                "documents",
+                policy,
+                &naming,
+                dests,
+                &mut Quiet,
+            )
+            .unwrap()
+        };
+
+        // The first one takes the shortened name, the second a dated one
+        let first = plan(&mut dests);
+        let second = plan(&mut dests);
+        assert_ne!(first.dest, second.dest);
+        for item in [first, second] {
+            let file_name = item.dest.file_name().unwrap().len();
+            assert!(file_name + ".part".len() <= MAX_NAME, "{:?}", item.dest);
+        }
+    }
+}
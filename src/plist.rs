@@ -0,0 +1,128 @@
+/// Every string in a binary (`bplist00`) or XML property list, in order:
+/// enough for arrays of strings such as `kMDItemWhereFroms` and Finder tags
+pub fn strings(data: &[u8]) -> Vec<String> {
+    if data.starts_with(b"bplist00") {
+        return binary_strings(data).unwrap_or_default();
+    }
+    let text = String::from_utf8_lossy(data);
+    text.split("<string>")
+        .skip(1)
+        .filter_map(|part| part.split_once("</string>"))
+        .map(|(value, _)| value.replace("&amp;", "&"))
+        .collect()
+}
+
+fn binary_strings(data: &[u8]) -> Option<Vec<String>> {
+    let trailer = data.get(data.len().checked_sub(32)?..)?;
+    let offset_size = trailer[6] as usize;
+    let objects = be(&trailer[8..16]) as usize;
+    let table = be(&trailer[24..32]) as usize;
+    // Lengths and offsets come from the file: any sum of them can overflow
+    if offset_size == 0 {
+        return None;
+    }
+    let mut strings = Vec::new();
+    for i in 0..objects {
+        let at = i.checked_mul(offset_size)?.checked_add(table)?;
+        let offset = be(data.get(at..at.checked_add(offset_size)?)?) as usize;
+        let marker = *data.get(offset)?;
+        let (len, start) = length(data, offset)?;
+        match marker >> 4 {
+            // ASCII
+            0x5 => strings.push(
+                String::from_utf8_lossy(data.get(start..start.checked_add(len)?)?).into_owned(),
+            ),
+            // UTF-16, big-endian
+            0x6 => {
+                let units: Vec<u16> = data
+                    .get(start..len.checked_mul(2)?.checked_add(start)?)?
+                    .chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                strings.push(String::from_utf16_lossy(&units));
+            }
+            _ => {}
+        }
+    }
+    Some(strings)
+}
+
+/// An object's length and where its contents start: the marker's low
+/// nibble, or an integer object after it when that is 0xF
+fn length(data: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let nibble = (data.get(offset)? & 0x0F) as usize;
+    if nibble != 0x0F {
+        return Some((nibble, offset + 1));
+    }
+    let int_marker = *data.get(offset + 1)?;
+    let size = 1usize << (int_marker & 0x0F);
+    let start = offset + 2;
+    let end = start.checked_add(size)?;
+    Some((be(data.get(start..end)?) as usize, end))
+}
+
+fn be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, &b| n << 8 | b as u64)
+}
+
+/// A binary property list holding an array of strings, as macOS stores
+/// Finder tags (`_kMDItemUserTags`)
+pub fn string_array(strings: &[String]) -> Vec<u8> {
+    let objects = strings.len() + 1;
+    let ref_size = if objects < 256 { 1 } else { 2 };
+    let mut out = b"bplist00".to_vec();
+    let mut offsets = Vec::with_capacity(objects);
+
+    offsets.push(out.len());
+    marker(&mut out, 0xA, strings.len());
+    for i in 1..objects {
+        out.extend_from_slice(&(i as u64).to_be_bytes()[8 - ref_size..]);
+    }
+    for string in strings {
+        offsets.push(out.len());
+        if string.is_ascii() {
+            marker(&mut out, 0x5, string.len());
+            out.extend_from_slice(string.as_bytes());
+        } else {
+            let units: Vec<u16> = string.encode_utf16().collect();
+            marker(&mut out, 0x6, units.len());
+            out.extend(units.iter().flat_map(|u| u.to_be_bytes()));
+        }
+    }
+
+    let table = out.len();
+    let offset_size = match table {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        _ => 4,
+    };
+    for offset in offsets {
+        out.extend_from_slice(&(offset as u64).to_be_bytes()[8 - offset_size..]);
+    }
+    out.extend_from_slice(&[0; 6]);
+    out.push(offset_size as u8);
+    out.push(ref_size as u8);
+    out.extend_from_slice(&(objects as u64).to_be_bytes());
+    out.extend_from_slice(&0u64.to_be_bytes());
+    out.extend_from_slice(&(table as u64).to_be_bytes());
+    out
+}
+
+/// An object marker with its length: in the low nibble, or as an integer
+/// object after it when 15 or more
+fn marker(out: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 15 {
+        out.push(kind << 4 | len as u8);
+        return;
+    }
+    out.push(kind << 4 | 0x0F);
+    if len <= 0xFF {
+        out.extend_from_slice(&[0x10, len as u8]);
+    } else if len <= 0xFFFF {
+        out.push(0x11);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0x12);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
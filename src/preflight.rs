@@ -0,0 +1,311 @@
+use crate::analyze::human_size;
+use crate::plan::{EntryKind, PlannedMove};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the file written (and removed at once) to see whether a folder is writable
+const PROBE_NAME: &str = ".auto-organize-probe";
+
+/// What a plan copies onto one filesystem: moves across filesystems (to
+/// another drive, a NAS) are copies and need room there
+pub struct Copies {
+    /// A folder on that filesystem
+    pub dir: PathBuf,
+    pub needed: u64,
+    /// `None` where free space cannot be told
+    pub free: Option<u64>,
+    /// Bytes by destination folder (the category of the moves)
+    pub by_dest: BTreeMap<String, u64>,
+}
+
+impl Copies {
+    /// "images 1.2 GB, Folders 300 MB", largest first
+    pub fn breakdown(&self) -> String {
+        let mut dests: Vec<(&String, &u64)> = self.by_dest.iter().collect();
+        dests.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        dests
+            .iter()
+            .map(|(dest, size)| format!("{} {}", dest, human_size(**size)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// What `check` found
+pub struct Report {
+    pub problems: Vec<String>,
+    /// Copies onto each other filesystem, whether they fit or not
+    pub copies: Vec<Copies>,
+}
+
+/// Checks, before anything moves, that the plan can finish: every folder
+/// files leave or land in is writable (not on a read-only mount), and
+/// filesystems receiving copies have room for them. A `dry_run` writes
+/// nothing, so it only reads folders' permissions instead of trying a write.
+pub fn check(plan: &[PlannedMove], target_dir: &Path, dry_run: bool) -> Report {
+    let mut problems = Vec::new();
+    let mut devices: HashMap<PathBuf, Option<u64>> = HashMap::new();
+    let mut device_of = |dir: &Path| {
+        *devices
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| device(dir))
+    };
+
+    // Folders written to: where entries leave from, and the closest existing
+    // folder of each destination (what is missing below it gets created)
+    let mut writable: BTreeSet<PathBuf> = BTreeSet::new();
+    writable.insert(target_dir.to_path_buf());
+    let mut landing: HashMap<PathBuf, PathBuf> = HashMap::new();
+    // Bytes copied onto each filesystem, with a folder on it to ask for free space
+    let mut copies: BTreeMap<u64, Copies> = BTreeMap::new();
+
+    for item in plan {
+        if item.kind == EntryKind::Merge {
+            continue;
+        }
+        let Some(src_dir) = item.src.parent() else {
+            continue;
+        };
+        let Some(dest_dir) = item.dest.parent() else {
+            continue;
+        };
+        let existing = landing
+            .entry(dest_dir.to_path_buf())
+            .or_insert_with(|| existing_ancestor(dest_dir))
+            .clone();
+        writable.insert(src_dir.to_path_buf());
+        writable.insert(existing.clone());
+
+        // A move within one filesystem is a rename and needs no space
+        let (Some(from), Some(to)) = (device_of(src_dir), device_of(&existing)) else {
+            continue;
+        };
+        if from != to {
+            let size = match item.kind {
+                EntryKind::File => fs::symlink_metadata(&item.src).map_or(0, |m| m.len()),
+                _ => tree_size(&item.src),
+            };
+            let copies = copies.entry(to).or_insert_with(|| Copies {
+                dir: existing.clone(),
+                needed: 0,
+                free: None,
+                by_dest: BTreeMap::new(),
+            });
+            copies.needed += size;
+            *copies.by_dest.entry(item.category.clone()).or_default() += size;
+        }
+    }
+
+    for dir in &writable {
+        let found = if dry_run { read_only(dir) } else { probe(dir) };
+        if let Err(problem) = found {
+            problems.push(problem);
+        }
+    }
+    let mut copies: Vec<Copies> = copies.into_values().collect();
+    for copies in &mut copies {
+        copies.free = sys::free_space(&copies.dir);
+        if let Some(free) = copies.free
+            && copies.needed > free
+        {
+            problems.push(format!(
+                "{:?}: {} to copy from another filesystem ({}), but only {} free",
+                copies.dir,
+                human_size(copies.needed),
+                copies.breakdown(),
+                human_size(free)
+            ));
+        }
+    }
+    Report { problems, copies }
+}
+
+/// `dir`, or the closest parent of it that exists
+fn existing_ancestor(dir: &Path) -> PathBuf {
+    let mut dir = dir;
+    while !dir.is_dir() {
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => dir = parent,
+            _ => return PathBuf::from("."),
+        }
+    }
+    dir.to_path_buf()
+}
+
+/// Writes and removes a small file in `dir`
+fn probe(dir: &Path) -> Result<(), String> {
+    let path = dir.join(format!("{}-{}", PROBE_NAME, std::process::id()));
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(_) => {
+            let _ = fs::remove_file(&path);
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::ReadOnlyFilesystem => {
+            Err(format!("{:?} is on a read-only filesystem", dir))
+        }
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Err(format!("{:?} is not writable (permission denied)", dir))
+        }
+        Err(e) => Err(format!("{:?} cannot be written to: {}", dir, e)),
+    }
+}
+
+/// Whether `dir`'s permissions leave it writable to anyone; `probe` without
+/// the write, so it misses what only writing tells (a read-only mount, ACLs)
+fn read_only(dir: &Path) -> Result<(), String> {
+    match fs::metadata(dir) {
+        Ok(meta) if meta.permissions().readonly() => {
+            Err(format!("{:?} is not writable (read-only permissions)", dir))
+        }
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{:?} cannot be read: {}", dir, e)),
+    }
+}
+
+/// Total size of the files below `path`
+fn tree_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return fs::symlink_metadata(path).map_or(0, |m| m.len());
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => tree_size(&entry.path()),
+            _ => entry.metadata().map_or(0, |m| m.len()),
+        })
+        .sum()
+}
+
+/// Which filesystem `dir` is on, where that can be told
+#[cfg(unix)]
+fn device(dir: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(dir).ok().map(|m| m.dev())
+}
+
+/// Windows: the drive (or share) the absolute path starts with
+#[cfg(windows)]
+fn device(dir: &Path) -> Option<u64> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use std::path::Component;
+    let absolute = std::path::absolute(dir).ok()?;
+    let Some(Component::Prefix(prefix)) = absolute.components().next() else {
+        return None;
+    };
+    let mut hasher = DefaultHasher::new();
+    prefix.as_os_str().to_ascii_lowercase().hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn device(_dir: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{CString, c_char, c_int, c_ulong};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// `struct statvfs`, up to the fields read here; the tail is padding so
+    /// the call never writes past the end, whatever the libc adds
+    #[cfg(target_os = "linux")]
+    #[repr(C)]
+    struct StatVfs {
+        f_bsize: c_ulong,
+        f_frsize: c_ulong,
+        f_blocks: u64,
+        f_bfree: u64,
+        f_bavail: u64,
+        _rest: [u64; 16],
+    }
+
+    /// macOS block counts are 32-bit
+    #[cfg(not(target_os = "linux"))]
+    #[repr(C)]
+    struct StatVfs {
+        f_bsize: c_ulong,
+        f_frsize: c_ulong,
+        f_blocks: u32,
+        f_bfree: u32,
+        f_bavail: u32,
+        _rest: [u64; 16],
+    }
+
+    unsafe extern "C" {
+        fn statvfs(path: *const c_char, buf: *mut StatVfs) -> c_int;
+    }
+
+    /// Bytes available to unprivileged users on the filesystem of `dir`.
+    /// Only 64-bit Linux and macOS layouts are known; elsewhere nothing is checked.
+    // The conversions widen the 32-bit macOS fields
+    #[allow(clippy::useless_conversion)]
+    pub fn free_space(dir: &Path) -> Option<u64> {
+        if !(cfg!(target_os = "macos")
+            || cfg!(all(target_os = "linux", target_pointer_width = "64")))
+        {
+            return None;
+        }
+        let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mut buf = StatVfs {
+            f_bsize: 0,
+            f_frsize: 0,
+            f_blocks: 0,
+            f_bfree: 0,
+            f_bavail: 0,
+            _rest: [0; 16],
+        };
+        if unsafe { statvfs(path.as_ptr(), &mut buf) } != 0 {
+            return None;
+        }
+        let block = if buf.f_frsize > 0 {
+            buf.f_frsize
+        } else {
+            buf.f_bsize
+        };
+        u64::from(buf.f_bavail).checked_mul(u64::from(block))
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(
+            directory: *const u16,
+            free_to_caller: *mut u64,
+            total: *mut u64,
+            total_free: *mut u64,
+        ) -> i32;
+    }
+
+    /// Bytes available to this user on the volume of `dir` (quotas included)
+    pub fn free_space(dir: &Path) -> Option<u64> {
+        let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut free = 0u64;
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                path.as_ptr(),
+                &mut free,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        (ok != 0).then_some(free)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    use std::path::Path;
+
+    pub fn free_space(_dir: &Path) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,213 @@
+use crate::analyze;
+use crate::damaged;
+use crate::video;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes read from the start of a file: image headers, a WAV's chunks and a
+/// text's first lines are all in here (a JPEG's frame header nearly always)
+const HEADER_BYTES: u64 = 64 * 1024;
+
+/// Text lines shown, and how much of each
+const TEXT_LINES: usize = 8;
+const LINE_CHARS: usize = 100;
+
+/// A few lines on what `path` holds, to pick a category by: the first lines
+/// of a text, an image's size in pixels, a video's or a WAV's duration, or
+/// else its size and what its contents look like. Reads only the start of
+/// the file (and a video's `moov` box).
+pub fn describe(path: &Path) -> io::Result<Vec<String>> {
+    let size = path.metadata()?.len();
+    let mut header = Vec::new();
+    File::open(path)?
+        .take(HEADER_BYTES)
+        .read_to_end(&mut header)?;
+
+    let mut lines = vec![format!("Size:     {}", analyze::human_size(size))];
+    if let Some((format, width, height)) = image_size(&header) {
+        lines.push(format!(
+            "Image:    {} x {} pixels ({})",
+            width, height, format
+        ));
+    } else if is_mp4(&header) {
+        let info = video::probe(path)?;
+        if let Some(secs) = info.duration_secs {
+            lines.push(format!("Duration: {}", clock(secs)));
+        }
+        if let (Some(width), Some(height)) = (info.width, info.height) {
+            lines.push(format!("Video:    {} x {} pixels", width, height));
+        }
+    } else if let Some(secs) = wav_duration(&header) {
+        lines.push(format!("Audio:    WAV, {}", clock(secs)));
+    } else if let Some(text) = text_lines(&header) {
+        lines.push("Text:".to_string());
+        lines.extend(text.into_iter().map(|line| format!("  | {}", line)));
+    } else {
+        match damaged::sniff(path)? {
+            Some(exts) => lines.push(format!("Contents: look like .{}", exts[0])),
+            None => lines.push("Contents: binary".to_string()),
+        }
+    }
+    Ok(lines)
+}
+
+/// "1:02:03" or "4:05"
+fn clock(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// Format and size of a PNG, GIF, JPEG, BMP or WebP, from its header
+fn image_size(data: &[u8]) -> Option<(&'static str, u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.get(12..16) == Some(b"IHDR") {
+        return Some(("PNG", be_u32(data, 16)?, be_u32(data, 20)?));
+    }
+    if data.starts_with(b"GIF8") {
+        return Some(("GIF", le_u16(data, 6)? as u32, le_u16(data, 8)? as u32));
+    }
+    if data.starts_with(b"BM") && data.len() >= 26 {
+        let height = le_u32(data, 22)? as i32;
+        return Some(("BMP", le_u32(data, 18)?, height.unsigned_abs()));
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return webp_size(data).map(|(w, h)| ("WebP", w, h));
+    }
+    if data.starts_with(b"\xff\xd8") {
+        return jpeg_size(data).map(|(w, h)| ("JPEG", w, h));
+    }
+    None
+}
+
+fn webp_size(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        // Lossy: 14-bit sizes after the key frame's start code
+        b"VP8 " => Some((
+            (le_u16(data, 26)? & 0x3fff) as u32,
+            (le_u16(data, 28)? & 0x3fff) as u32,
+        )),
+        // Lossless: two 14-bit sizes minus one, after the signature byte
+        b"VP8L" => {
+            let bits = le_u32(data, 21)?;
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        // Extended: 24-bit sizes minus one
+        b"VP8X" => {
+            let width = le_u32(data, 24)? & 0xff_ffff;
+            let height = le_u32(data, 26)? >> 8;
+            Some((width + 1, height + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Walks the markers up to the first start-of-frame, which holds the size
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+    while offset + 4 <= data.len() {
+        if data[offset] != 0xff {
+            return None;
+        }
+        let marker = data[offset + 1];
+        // Fill bytes before a marker
+        if marker == 0xff {
+            offset += 1;
+            continue;
+        }
+        let len = be_u16(data, offset + 2)? as usize;
+        // SOF0..SOF15, but not DHT, JPG or DAC, which share the range
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let height = be_u16(data, offset + 5)? as u32;
+            let width = be_u16(data, offset + 7)? as u32;
+            return Some((width, height));
+        }
+        offset += 2 + len;
+    }
+    None
+}
+
+/// MP4, MOV and M4V start with an `ftyp` box (older QuickTime with `moov`
+/// or `mdat`)
+fn is_mp4(data: &[u8]) -> bool {
+    matches!(
+        data.get(4..8),
+        Some(b"ftyp") | Some(b"moov") | Some(b"mdat") | Some(b"wide")
+    )
+}
+
+/// From the `fmt ` chunk's byte rate and the `data` chunk's size
+fn wav_duration(data: &[u8]) -> Option<u64> {
+    if !data.starts_with(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+        return None;
+    }
+    let mut byte_rate = None;
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let id = &data[offset..offset + 4];
+        let len = le_u32(data, offset + 4)? as usize;
+        match id {
+            b"fmt " => byte_rate = le_u32(data, offset + 16),
+            b"data" => {
+                let rate = byte_rate.filter(|&rate| rate > 0)?;
+                return Some(len as u64 / rate as u64);
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        offset += 8 + len + (len & 1);
+    }
+    None
+}
+
+/// The first lines, if `data` reads as UTF-8 text (cut anywhere, even
+/// inside a character)
+fn text_lines(data: &[u8]) -> Option<Vec<String>> {
+    if data.is_empty() || data.contains(&0) {
+        return None;
+    }
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&data[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    Some(
+        text.lines()
+            .take(TEXT_LINES)
+            .map(|line| {
+                let mut shown: String = line.chars().take(LINE_CHARS).collect();
+                if shown.len() < line.len() {
+                    shown.push_str(" ...");
+                }
+                shown
+            })
+            .collect(),
+    )
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
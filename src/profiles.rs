@@ -0,0 +1,83 @@
+use crate::paths;
+use std::fs;
+use std::path::PathBuf;
+
+/// Ends the names of the presets that ship with the program, so a profile
+/// file of the same name never hides one
+pub const BUILTIN_SUFFIX: &str = "-builtin";
+
+/// Presets that ship with the program: name (without the suffix) and config
+const BUILTIN: &[(&str, &str)] = &[("desktop", include_str!("../profiles/desktop.toml"))];
+
+/// A config chosen by name with `--profile`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Profile {
+    /// A preset that ships with the program (`desktop-builtin`)
+    Builtin(&'static str),
+    /// `<name>.toml` in the profiles folder (see `dir`)
+    File(PathBuf),
+}
+
+/// `profiles` in the config directory (see `paths::config_dir`)
+pub fn dir() -> Option<PathBuf> {
+    Some(paths::config_dir()?.join("profiles"))
+}
+
+/// Whether `name` is one of the presets' (whether or not it exists)
+pub fn is_builtin(name: &str) -> bool {
+    name.ends_with(BUILTIN_SUFFIX)
+}
+
+/// Looks a profile up by name
+pub fn find(name: &str) -> Result<Profile, String> {
+    if let Some(preset) = name.strip_suffix(BUILTIN_SUFFIX) {
+        return BUILTIN
+            .iter()
+            .find(|(builtin, _)| *builtin == preset)
+            .map(|(_, text)| Profile::Builtin(text))
+            .ok_or_else(|| {
+                format!(
+                    "no built-in profile '{}' (try: {})",
+                    name,
+                    names().join(", ")
+                )
+            });
+    }
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("'{}' is not a profile name", name));
+    }
+    let path = dir()
+        .ok_or("cannot determine the config directory (HOME is not set)")?
+        .join(format!("{}.toml", name));
+    if !path.is_file() {
+        return Err(format!(
+            "no profile '{}' ({} does not exist; try: {})",
+            name,
+            path.display(),
+            names().join(", ")
+        ));
+    }
+    Ok(Profile::File(path))
+}
+
+/// Every profile `--profile` takes: the presets, then the profile files
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN
+        .iter()
+        .map(|(name, _)| format!("{}{}", name, BUILTIN_SUFFIX))
+        .collect();
+    let mut files: Vec<String> = dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let stem = name.strip_suffix(".toml")?;
+            (!stem.starts_with('.')).then(|| stem.to_string())
+        })
+        .collect();
+    files.sort();
+    names.extend(files);
+    names
+}
@@ -0,0 +1,108 @@
+use crate::hash;
+use crate::index::{self, Index};
+use crate::manifest::Manifest;
+use crate::plan::PlannedMove;
+use crate::shadow::ShadowLog;
+use std::path::Path;
+
+/// The optional logs kept of every move: the long-lived index and a per-run
+/// manifest, or in shadow mode the moves that would have been made
+#[derive(Default)]
+pub struct Records {
+    pub index: Option<Index>,
+    pub manifest: Option<Manifest>,
+    pub shadow: Option<ShadowLog>,
+}
+
+impl Records {
+    /// Opens the index for recording; dry runs never touch it
+    pub fn open_index(&mut self) {
+        let Some(path) = index::default_path() else {
+            eprintln!("Error opening index: cannot determine data directory (HOME is not set)");
+            return;
+        };
+        match Index::open(&path) {
+            Ok(index) => self.index = Some(index),
+            Err(e) => eprintln!("Error opening index '{}': {}", path.display(), e),
+        }
+    }
+
+    pub fn open_manifest(&mut self, path: &Path, target_dir: &Path) {
+        match Manifest::create(path, target_dir) {
+            Ok(manifest) => self.manifest = Some(manifest),
+            Err(e) => eprintln!("Error creating manifest '{}': {}", path.display(), e),
+        }
+    }
+
+    pub fn open_shadow(&mut self, target_dir: &Path) {
+        match ShadowLog::open(target_dir) {
+            Ok(log) => self.shadow = Some(log),
+            Err(e) => eprintln!("Error opening shadow log: {}", e),
+        }
+    }
+
+    /// Logs a move a shadow pass would have made; true the first time
+    pub fn shadow(&mut self, item: &PlannedMove) -> bool {
+        let Some(log) = self.shadow.as_mut() else {
+            return false;
+        };
+        log.record(item).unwrap_or_else(|e| {
+            eprintln!("Error updating shadow log for {:?}: {}", item.src, e);
+            false
+        })
+    }
+
+    /// How many logged files were moved or deleted by hand since the last pass
+    pub fn sweep_shadow(&mut self) -> usize {
+        let Some(log) = self.shadow.as_mut() else {
+            return 0;
+        };
+        log.sweep().unwrap_or_else(|e| {
+            eprintln!("Error updating shadow log: {}", e);
+            0
+        })
+    }
+
+    /// Records a completed move from `src` to `dest`, made by run `run`
+    pub fn record(&mut self, src: &Path, dest: &Path, category: &str, run: &str) {
+        if self.index.is_none() && self.manifest.is_none() {
+            return;
+        }
+
+        let hash = if dest.is_dir() {
+            None
+        } else {
+            match hash::hash_file(dest) {
+                Ok(hash) => Some(hash),
+                Err(e) => {
+                    eprintln!("Error hashing {:?}: {}", dest, e);
+                    return;
+                }
+            }
+        };
+
+        if let Some(index) = self.index.as_mut()
+            && let Err(e) = index.record(dest, category, hash.as_deref(), run)
+        {
+            eprintln!("Error updating index for {:?}: {}", dest, e);
+        }
+        if let Some(manifest) = self.manifest.as_mut()
+            && let Err(e) = manifest.record(src, dest, category, hash.as_deref(), run)
+        {
+            eprintln!("Error updating manifest for {:?}: {}", dest, e);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(index) = self.index.as_mut()
+            && let Err(e) = index.flush()
+        {
+            eprintln!("Error writing index: {}", e);
+        }
+        if let Some(manifest) = self.manifest.as_mut()
+            && let Err(e) = manifest.flush()
+        {
+            eprintln!("Error writing manifest: {}", e);
+        }
+    }
+}
@@ -0,0 +1,593 @@
+use std::cell::Cell;
+use std::fmt;
+
+/// A small backtracking regular expression, enough for matching file names.
+///
+/// Supports literals, `.`, classes (`[A-Z0-9_-]`, `[^x]`), `\d \w \s` and their
+/// negations, `\b`, anchors `^ $`, groups `( )` and `(?: )`, alternation `|`,
+/// and the quantifiers `* + ? {n} {n,} {n,m}` (append `?` for lazy).
+/// A leading `(?i)` makes the whole pattern case-insensitive.
+///
+/// Groups nest at most [`MAX_DEPTH`] deep, counts go up to [`MAX_REPEAT`],
+/// and a search gives up after [`STEP_BUDGET`] steps, so a pattern that
+/// would backtrack for ages just does not match.
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    node: Node,
+    groups: usize,
+    ignore_case: bool,
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Regex({:?})", self.source)
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Group(Box<Node>, Option<usize>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => {
+                (lo..=hi).contains(&c)
+                    || ignore_case
+                        && c.to_lowercase()
+                            .chain(c.to_uppercase())
+                            .any(|c| (lo..=hi).contains(&c))
+            }
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        self.items.iter().any(|item| item.matches(c, ignore_case)) != self.negated
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+/// How deep groups may nest
+pub const MAX_DEPTH: usize = 32;
+/// The largest count in `{n}`, `{n,}` and `{n,m}`
+pub const MAX_REPEAT: usize = 1000;
+/// How many steps one search may take before it counts as no match
+pub const STEP_BUDGET: usize = 1_000_000;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let (ignore_case, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+            groups: 0,
+            depth: 0,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err("unmatched ')'".to_string());
+        }
+        Ok(Regex {
+            source: pattern.to_string(),
+            node,
+            groups: parser.groups,
+            ignore_case,
+        })
+    }
+
+    /// Finds the leftmost match. Index 0 is the whole match, then one entry per group.
+    /// `None` also when the search runs out of its [`STEP_BUDGET`].
+    pub fn captures(&self, text: &str) -> Option<Vec<Option<String>>> {
+        let chars: Vec<char> = text.chars().collect();
+        let matcher = Matcher {
+            text: &chars,
+            ignore_case: self.ignore_case,
+            steps: Cell::new(STEP_BUDGET),
+        };
+
+        for start in 0..=chars.len() {
+            let mut caps: Captures = vec![None; self.groups + 1];
+            let mut end = None;
+            let found = matcher.node(&self.node, start, &mut caps, &mut |pos, _| {
+                end = Some(pos);
+                true
+            });
+            if found && let Some(end) = end {
+                caps[0] = Some((start, end));
+                return Some(
+                    caps.into_iter()
+                        .map(|span| span.map(|(s, e)| chars[s..e].iter().collect()))
+                        .collect(),
+                );
+            }
+        }
+        None
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap_or(Node::Empty)
+        } else {
+            Node::Alt(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            items.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap_or(Node::Empty),
+            _ => Node::Concat(items),
+        })
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(format!("groups nested more than {} deep", MAX_DEPTH));
+                }
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                self.depth += 1;
+                let inner = self.parse_alt()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return Err("unclosed '('".to_string());
+                }
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            Some('[') => self.parse_class(),
+            Some('\\') => self.parse_escape(),
+            Some(c @ ('*' | '+' | '?')) => Err(format!("'{}' has nothing to repeat", c)),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err("unexpected end of pattern".to_string()),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("pattern ends with '\\'")?;
+        let class = |item| {
+            Node::Class(Class {
+                negated: false,
+                items: vec![item],
+            })
+        };
+        Ok(match c {
+            'd' => class(ClassItem::Digit(false)),
+            'D' => class(ClassItem::Digit(true)),
+            'w' => class(ClassItem::Word(false)),
+            'W' => class(ClassItem::Word(true)),
+            's' => class(ClassItem::Space(false)),
+            'S' => class(ClassItem::Space(true)),
+            'b' => Node::WordBoundary,
+            't' => Node::Char('\t'),
+            'n' => Node::Char('\n'),
+            c if c.is_alphanumeric() => return Err(format!("unknown escape '\\{}'", c)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self.next().ok_or("unclosed '['")?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                match self.next().ok_or("unclosed '['")? {
+                    'd' => {
+                        items.push(ClassItem::Digit(false));
+                        continue;
+                    }
+                    'D' => {
+                        items.push(ClassItem::Digit(true));
+                        continue;
+                    }
+                    'w' => {
+                        items.push(ClassItem::Word(false));
+                        continue;
+                    }
+                    'W' => {
+                        items.push(ClassItem::Word(true));
+                        continue;
+                    }
+                    's' => {
+                        items.push(ClassItem::Space(false));
+                        continue;
+                    }
+                    'S' => {
+                        items.push(ClassItem::Space(true));
+                        continue;
+                    }
+                    't' => '\t',
+                    'n' => '\n',
+                    c => c,
+                }
+            } else {
+                c
+            };
+
+            // A '-' between two characters is a range; anywhere else it is literal
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let mut hi = self.next().ok_or("unclosed '['")?;
+                if hi == '\\' {
+                    hi = self.next().ok_or("unclosed '['")?;
+                }
+                if hi < lo {
+                    return Err(format!("invalid range '{}-{}'", lo, hi));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+
+        Ok(Node::Class(Class { negated, items }))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => {
+                self.pos += 1;
+                (0, None)
+            }
+            Some('+') => {
+                self.pos += 1;
+                (1, None)
+            }
+            Some('?') => {
+                self.pos += 1;
+                (0, Some(1))
+            }
+            // Not a quantifier unless well-formed: then '{' is matched literally later
+            Some('{') => match self.parse_braces() {
+                Some(bounds) => bounds,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary) {
+            return Err("anchors cannot be repeated".to_string());
+        }
+        if let Some(max) = max
+            && max < min
+        {
+            return Err(format!("invalid repetition {{{},{}}}", min, max));
+        }
+        if min.max(max.unwrap_or(0)) > MAX_REPEAT {
+            return Err(format!("repetition counts go up to {}", MAX_REPEAT));
+        }
+        let greedy = !self.eat('?');
+        // `a**` or `a{2}{3}` would repeat a repetition; wrap it in a group instead
+        let start = self.pos;
+        let stacked = match self.peek() {
+            Some('*' | '+' | '?') => true,
+            Some('{') => self.parse_braces().is_some(),
+            _ => false,
+        };
+        if stacked {
+            let quantifier: String = self.chars[start..self.pos.max(start + 1)].iter().collect();
+            return Err(format!("'{}' follows another quantifier", quantifier));
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}` and moves past it; `None` leaves the position alone
+    fn parse_braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos..].iter().collect();
+        let close = rest.find('}')?;
+        let inner = &rest[1..close];
+        let bounds = match inner.split_once(',') {
+            None => {
+                let n = inner.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((lo, "")) => (lo.parse().ok()?, None),
+            Some((lo, hi)) => (lo.parse().ok()?, Some(hi.parse().ok()?)),
+        };
+        self.pos += rest[..=close].chars().count();
+        Some(bounds)
+    }
+}
+
+struct Matcher<'a> {
+    text: &'a [char],
+    ignore_case: bool,
+    /// Steps left before the search gives up
+    steps: Cell<usize>,
+}
+
+type Continuation<'k> = dyn FnMut(usize, &mut Captures) -> bool + 'k;
+
+impl Matcher<'_> {
+    /// Matches `node` at `pos`, then hands the end position to `k`; backtracks
+    /// into alternatives and repetitions until `k` accepts
+    fn node(&self, node: &Node, pos: usize, caps: &mut Captures, k: &mut Continuation) -> bool {
+        let steps = self.steps.get();
+        if steps == 0 {
+            return false;
+        }
+        self.steps.set(steps - 1);
+        match node {
+            Node::Empty => k(pos, caps),
+            Node::Char(c) => match self.text.get(pos) {
+                Some(t) if t == c || self.ignore_case && chars_eq_ignore_case(*t, *c) => {
+                    k(pos + 1, caps)
+                }
+                _ => false,
+            },
+            Node::Any => pos < self.text.len() && k(pos + 1, caps),
+            Node::Class(class) => match self.text.get(pos) {
+                Some(&t) if class.matches(t, self.ignore_case) => k(pos + 1, caps),
+                _ => false,
+            },
+            Node::Start => pos == 0 && k(pos, caps),
+            Node::End => pos == self.text.len() && k(pos, caps),
+            Node::WordBoundary => {
+                let before = pos > 0 && is_word(self.text[pos - 1]);
+                let after = self.text.get(pos).is_some_and(|&c| is_word(c));
+                before != after && k(pos, caps)
+            }
+            Node::Concat(items) => self.concat(items, pos, caps, k),
+            Node::Alt(branches) => branches.iter().any(|b| self.node(b, pos, caps, k)),
+            Node::Group(inner, None) => self.node(inner, pos, caps, k),
+            Node::Group(inner, Some(index)) => {
+                let index = *index;
+                self.node(inner, pos, caps, &mut |end, caps: &mut Captures| {
+                    let previous = caps[index];
+                    caps[index] = Some((pos, end));
+                    if k(end, caps) {
+                        return true;
+                    }
+                    caps[index] = previous;
+                    false
+                })
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.repeat(node, *min, *max, *greedy, 0, pos, caps, k),
+        }
+    }
+
+    fn concat(
+        &self,
+        items: &[Node],
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut Continuation,
+    ) -> bool {
+        match items.split_first() {
+            None => k(pos, caps),
+            Some((first, rest)) => self.node(first, pos, caps, &mut |next, caps: &mut Captures| {
+                self.concat(rest, next, caps, k)
+            }),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        count: usize,
+        pos: usize,
+        caps: &mut Captures,
+        k: &mut Continuation,
+    ) -> bool {
+        let can_stop = count >= min;
+        let can_continue = max.is_none_or(|max| count < max);
+
+        if !greedy && can_stop && k(pos, caps) {
+            return true;
+        }
+        if can_continue {
+            let more = self.node(node, pos, caps, &mut |next, caps: &mut Captures| {
+                // An iteration that consumed nothing can't make progress
+                if next == pos && can_stop {
+                    return false;
+                }
+                self.repeat(node, min, max, greedy, count + 1, next, caps, k)
+            });
+            if more {
+                return true;
+            }
+        }
+        greedy && can_stop && k(pos, caps)
+    }
+}
+
+fn chars_eq_ignore_case(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(pattern: &str, text: &str) -> Option<Vec<Option<String>>> {
+        Regex::new(pattern).unwrap().captures(text)
+    }
+
+    fn owned(parts: &[Option<&str>]) -> Vec<Option<String>> {
+        parts.iter().map(|p| p.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn captures_groups_and_the_whole_match() {
+        assert_eq!(
+            caps(r"(\d{4})-(\d\d)", "IMG 2024-05-01.jpg"),
+            Some(owned(&[Some("2024-05"), Some("2024"), Some("05")]))
+        );
+        assert_eq!(
+            caps(r"^(?:IMG|DSC)_(\w+?)(x)?\.", "DSC_0042.JPG"),
+            Some(owned(&[Some("DSC_0042."), Some("0042"), None]))
+        );
+        assert_eq!(
+            caps(r"(?i)\binvoice\b", "Scan INVOICE march"),
+            Some(owned(&[Some("INVOICE")]))
+        );
+        assert_eq!(
+            caps(r"[^a-z]+$", "report_FINAL"),
+            Some(owned(&[Some("_FINAL")]))
+        );
+        assert_eq!(caps(r"a{2,3}", "caaaat"), Some(owned(&[Some("aaa")])));
+        assert_eq!(caps(r"^\d+$", "12a"), None);
+        assert_eq!(caps(r"x{", "x{"), Some(owned(&[Some("x{")])));
+    }
+
+    #[test]
+    fn bad_patterns_fail_to_compile() {
+        for pattern in [
+            "(abc",
+            "abc)",
+            "[abc",
+            "*a",
+            r"\q",
+            "[z-a]",
+            "^*",
+            "a{3,1}",
+            "a{2}{3}",
+            "a**",
+            "a+*",
+            "a??+",
+            "a{1001}",
+            "a{1,5000}",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{:?} compiled", pattern);
+        }
+        assert!(Regex::new("a*?").is_ok());
+        assert!(Regex::new("(?:a{2}){3}").is_ok());
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        let deep = "(".repeat(100_000);
+        assert!(Regex::new(&deep).unwrap_err().contains("nested"));
+        let fine = format!("{}a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(Regex::new(&fine).unwrap().captures("a").is_some());
+    }
+
+    #[test]
+    fn pathological_patterns_give_up_quickly() {
+        let started = std::time::Instant::now();
+        assert_eq!(caps("(a*)*b", &"a".repeat(28)), None);
+        assert_eq!(caps(".*.*.*.*.*x", &"y".repeat(110)), None);
+        assert_eq!(caps("(?:a|a)*c", &"a".repeat(40)), None);
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,862 @@
+use crate::actions::Action;
+use crate::classifier::{self, Classifier, Input, Renamer, Subprocess, Verdict, Wasm};
+use crate::config::{
+    BrandingConfig, CategoryConfig, Config, FolderPermissions, SharedConfig, Trust,
+};
+use crate::damaged;
+use crate::documents;
+use crate::downloads::{self, KeepCopy};
+use crate::email;
+use crate::encrypted::{self, Routing};
+use crate::extensionless;
+use crate::languages;
+use crate::listing::Order;
+use crate::origin;
+use crate::owner;
+use crate::paths;
+use crate::plan::{ConflictName, ConflictPolicy, DirPolicy};
+use crate::regex::Regex;
+use crate::schedule::Schedule;
+use crate::series;
+use crate::sidecars;
+use crate::staging;
+use crate::style::Label;
+use crate::sync::{self, SyncFile, SyncRules};
+use crate::throttle::{Limits, Policy};
+use crate::video;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Everything needed to classify a file: the built-in tables plus the user config.
+/// Watch mode builds a fresh one on config reload and swaps it in between passes.
+pub struct RuleSet {
+    extensions: HashMap<String, String>,
+    /// `[group_by]` rule names in the order they are tried
+    group_order: Vec<String>,
+    sidecar_extensions: HashSet<String>,
+    sync: SyncRules,
+    /// Some category trusts contents: files are sniffed
+    sniff: bool,
+    /// Asked after the built-in rules, in order (see `classify`)
+    classifiers: Vec<Box<dyn Classifier>>,
+    /// Asked in order for a new name of each file moved (see `rename`)
+    renamers: Vec<Box<dyn Renamer>>,
+    config: Config,
+}
+
+/// A file whose contents belong to another category than its name, where
+/// both categories claim it (see `Trust`); it goes to `Others`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ambiguity {
+    /// The category its name says
+    pub by_name: String,
+    /// The category its contents say
+    pub by_contents: String,
+    /// What its contents are, e.g. "png"
+    pub contents: &'static str,
+}
+
+impl RuleSet {
+    pub fn new(config: Config) -> Self {
+        let mut extensions = get_extension_map();
+        // User mappings override (or add to) the built-in table
+        for (ext, category) in &config.extensions {
+            extensions.insert(ext.clone(), category.clone());
+        }
+
+        let sidecar_extensions = match &config.sidecar_extensions {
+            Some(list) => list.iter().cloned().collect(),
+            None => sidecars::BUILTIN_SIDECAR_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        };
+
+        let mut group_order: Vec<String> = config.group_by.keys().cloned().collect();
+        group_order.sort_by_key(|name| std::cmp::Reverse(config.group_by[name].priority));
+
+        let sniff = config
+            .categories
+            .values()
+            .any(|c| c.trust == Some(Trust::Contents));
+
+        let mut classifiers: Vec<Box<dyn Classifier>> = config
+            .classifiers
+            .iter()
+            .map(|(name, command)| Box::new(Subprocess::new(name, command)) as Box<dyn Classifier>)
+            .collect();
+        let mut renamers: Vec<Box<dyn Renamer>> = Vec::new();
+        for (name, path) in &config.plugins {
+            let path = match paths::config_dir() {
+                Some(dir) if path.is_relative() => dir.join(path),
+                _ => path.clone(),
+            };
+            match Wasm::load(name, &path) {
+                Ok(plugin) => {
+                    let plugin = Arc::new(plugin);
+                    if plugin.classifies() {
+                        classifiers.push(Box::new(Arc::clone(&plugin)));
+                    }
+                    if plugin.renames() {
+                        renamers.push(Box::new(plugin));
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Warning: cannot load plugin '{}' from {:?}: {}",
+                    name, path, e
+                ),
+            }
+        }
+
+        RuleSet {
+            extensions,
+            group_order,
+            sidecar_extensions,
+            sync: SyncRules::new(&config.sync),
+            sniff,
+            classifiers,
+            renamers,
+            config,
+        }
+    }
+
+    /// Adds a classifier, asked after the built-in rules and those added
+    /// before it (`[classifiers]` of the config come first, by name, then
+    /// `[plugins]`)
+    pub fn add_classifier(&mut self, classifier: Box<dyn Classifier>) {
+        self.classifiers.push(classifier);
+    }
+
+    /// Adds a renamer, asked after those added before it (`[plugins]` of
+    /// the config come first, by name)
+    pub fn add_renamer(&mut self, renamer: Box<dyn Renamer>) {
+        self.renamers.push(renamer);
+    }
+
+    /// The name a file going to `category` is given instead of its own: the
+    /// first renamer's answer that is a usable file name. Sidecars are asked
+    /// about on their own, so a renamer should name them to match.
+    pub fn rename(&self, path: &Path, category: &str) -> Option<String> {
+        if self.renamers.is_empty() {
+            return None;
+        }
+        let input = Input::new(path);
+        let current = path.file_name()?;
+        for renamer in &self.renamers {
+            let Some(name) = renamer.rename(&input, category) else {
+                continue;
+            };
+            if let Err(e) = validate_file_name(&name) {
+                eprintln!(
+                    "Warning: renamer '{}' answered {:?} for {:?}: {}",
+                    renamer.name(),
+                    name,
+                    path,
+                    e
+                );
+                continue;
+            }
+            return (*current != *name).then_some(name);
+        }
+        None
+    }
+
+    /// Whether the extension maps to a category (built-in or configured)
+    pub fn knows_extension(&self, ext: &str) -> bool {
+        self.extensions.contains_key(ext)
+    }
+
+    /// Every category this rule set can route files into
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = self.extensions.values().cloned().collect();
+        categories.push("Others".to_string());
+        categories
+    }
+
+    /// Extensions of companion files that follow their primary file
+    pub fn sidecar_extensions(&self) -> &HashSet<String> {
+        &self.sidecar_extensions
+    }
+
+    /// Extensions of the files downloads are written into until they complete
+    pub fn temp_extensions(&self) -> Vec<String> {
+        match &self.config.downloads.temp_extensions {
+            Some(list) => list.clone(),
+            None => downloads::BUILTIN_TEMP_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+
+    /// What becomes of numbered copies of a download with the same contents,
+    /// if anything
+    pub fn numbered_duplicates(&self) -> Option<KeepCopy> {
+        self.config.downloads.numbered_duplicates
+    }
+
+    /// `[limits.<backend>]`, with `all` from the command line over them
+    pub fn fs_limits(&self, all: Limits) -> Policy {
+        Policy {
+            backends: self.config.limits.clone(),
+            all,
+        }
+    }
+
+    /// Whether a file or folder belongs to a sync tool: in progress (never
+    /// moved) or a conflict copy. Conflict copies are reported as ignored too
+    /// when `skip_conflicts` is set.
+    pub fn sync_file(&self, name: &str) -> Option<SyncFile> {
+        match self.sync.check(name)? {
+            SyncFile::Conflict if self.config.sync.skip_conflicts => Some(SyncFile::Ignored),
+            state => Some(state),
+        }
+    }
+
+    /// Categories with `compress_after_days` set, and that age in days
+    pub fn compress_after_days(&self) -> Vec<(&str, u64)> {
+        self.config
+            .categories
+            .iter()
+            .filter_map(|(name, settings)| Some((name.as_str(), settings.compress_after_days?)))
+            .collect()
+    }
+
+    /// Icons and display names for category folders
+    pub fn folder_branding(&self) -> &BrandingConfig {
+        &self.config.folder_branding
+    }
+
+    /// Where `--shared` sends each user's files
+    pub fn shared(&self) -> &SharedConfig {
+        &self.config.shared
+    }
+
+    /// Days files replaced by an overwrite are kept before being purged
+    pub fn staging_days(&self) -> u64 {
+        self.config
+            .staging_days
+            .unwrap_or(staging::DEFAULT_KEEP_DAYS)
+    }
+
+    /// How many files of one unknown extension a run sends to Others before
+    /// suggesting a category for them (0: never)
+    pub fn others_threshold(&self) -> u64 {
+        self.config
+            .others_threshold
+            .unwrap_or(DEFAULT_OTHERS_THRESHOLD)
+    }
+
+    /// How many numbered files make a series (see `series::group`; 0: never)
+    pub fn series_min_files(&self) -> u64 {
+        self.config
+            .series_min_files
+            .unwrap_or(series::DEFAULT_MIN_FILES)
+    }
+
+    /// Whether the config turns on safe mode (`--safe` does too)
+    pub fn safe_mode(&self) -> bool {
+        self.config.safe_mode
+    }
+
+    /// What the config does with loose folders, unless `--dirs` says otherwise
+    pub fn dir_policy(&self) -> Option<DirPolicy> {
+        self.config.dirs
+    }
+
+    /// The order the config organizes files in, unless `--order` says otherwise
+    pub fn order(&self) -> Option<Order> {
+        self.config.order
+    }
+
+    /// The category whose folder permissions apply to files of `category`
+    /// (itself or the closest parent that sets any), and those permissions
+    pub fn folder_permissions<'a>(
+        &self,
+        category: &'a str,
+    ) -> Option<(&'a str, &FolderPermissions)> {
+        let mut folder = category;
+        loop {
+            if let Some(settings) = self.config.categories.get(folder)
+                && !settings.permissions.is_empty()
+            {
+                return Some((folder, &settings.permissions));
+            }
+            folder = &folder[..folder.rfind(['/', '\\'])?];
+        }
+    }
+
+    /// The folder that must already exist for files of `category` to move:
+    /// the category itself or the closest parent with `create_if_missing = false`
+    /// (so "documents/finance" can still be created inside an existing "documents")
+    pub fn existing_folder_required<'a>(&self, category: &'a str) -> Option<&'a str> {
+        let mut folder = category;
+        loop {
+            if self
+                .config
+                .categories
+                .get(folder)
+                .is_some_and(|settings| !settings.create_if_missing)
+            {
+                return Some(folder);
+            }
+            folder = &folder[..folder.rfind(['/', '\\'])?];
+        }
+    }
+
+    /// A `[categories]` setting for `category`, or for its closest parent
+    /// that has one ("documents/finance" follows "documents")
+    fn category_setting<T>(
+        &self,
+        category: &str,
+        setting: impl Fn(&CategoryConfig) -> Option<T>,
+    ) -> Option<T> {
+        let mut folder = category;
+        loop {
+            if let Some(value) = self.config.categories.get(folder).and_then(&setting) {
+                return Some(value);
+            }
+            folder = &folder[..folder.rfind(['/', '\\'])?];
+        }
+    }
+
+    /// Whether the contents or the extension of a file decide for `category`
+    pub fn trust(&self, category: &str) -> Trust {
+        self.category_setting(category, |c| c.trust)
+            .unwrap_or(Trust::Extension)
+    }
+
+    /// The conflict policy configured for `category`; documents keep both
+    /// versions by default, since two reports of one name are rarely the same
+    pub fn conflict_policy(&self, category: &str) -> Option<ConflictPolicy> {
+        self.category_setting(category, |settings| settings.on_conflict)
+            .or((top_level_folder(category) == "documents").then_some(ConflictPolicy::Version))
+    }
+
+    /// How `category` names a file kept next to one of the same name
+    pub fn conflict_name(&self, category: &str) -> Option<ConflictName> {
+        self.category_setting(category, |settings| settings.conflict_name.clone())
+    }
+
+    /// Files of `category` bigger than this are left where they are
+    pub fn size_limit(&self, category: &str) -> Option<u64> {
+        self.category_setting(category, |settings| settings.skip_larger_than)
+    }
+
+    /// Whether copies of `category` files are checked; on by default for disk
+    /// images, which are big enough for a bad copy to be worth a second read
+    pub fn verify_copies(&self, category: &str) -> bool {
+        self.category_setting(category, |settings| settings.verify_copies)
+            .unwrap_or(top_level_folder(category) == DISK_IMAGES_CATEGORY)
+    }
+
+    /// When watch mode may run passes
+    pub fn schedule(&self) -> &Schedule {
+        &self.config.schedule
+    }
+
+    /// What happens to files of `category` (or of a parent category)
+    pub fn action(&self, category: &str) -> Action {
+        self.category_setting(category, |settings| settings.action.clone())
+            .unwrap_or_default()
+    }
+
+    /// The categories the config gives a color or emoji of their own
+    pub fn labels(&self) -> BTreeMap<String, Label> {
+        self.config
+            .categories
+            .iter()
+            .filter(|(_, settings)| settings.label != Label::default())
+            .map(|(category, settings)| (category.clone(), settings.label.clone()))
+            .collect()
+    }
+
+    /// The categories the config gives an action of their own
+    pub fn configured_actions(&self) -> impl Iterator<Item = (&str, &Action)> {
+        self.config
+            .categories
+            .iter()
+            .filter_map(|(category, settings)| Some((category.as_str(), settings.action.as_ref()?)))
+    }
+
+    /// Returns a set of folder names that should not be moved.
+    ///
+    /// Derived from every category this rule set can produce (built-in and
+    /// configured), so a custom category is protected as soon as it exists,
+    /// plus the catch-all folders and any user-specified extras.
+    pub fn protected_folders(&self) -> HashSet<String> {
+        let builtin = BUILTIN_CATEGORIES.iter().map(|(category, _)| *category);
+        let configured = self
+            .extensions
+            .values()
+            .chain(self.config.filenames.values())
+            .chain(self.config.shebangs.values())
+            .chain(self.config.origins.values())
+            .chain(self.config.categories.keys())
+            .chain(
+                self.config
+                    .group_by
+                    .values()
+                    .filter_map(|r| r.category.as_ref()),
+            )
+            .map(String::as_str);
+
+        let mut set: HashSet<String> = builtin
+            .chain(configured)
+            .map(|category| top_level_folder(category).to_string())
+            .collect();
+        if self.config.code.separate_web {
+            set.insert(languages::WEB_CATEGORY.to_string());
+        }
+        set.insert("Others".to_string()); // Catch-all for files
+        set.insert(sync::CONFLICTS_CATEGORY.to_string());
+        set.insert(damaged::QUARANTINE_CATEGORY.to_string());
+        set.insert("Folders".to_string()); // Catch-all for directories
+        set.extend(self.config.protected_folders.iter().cloned());
+        set
+    }
+
+    /// The top-level folders files are sorted into: the protected folders,
+    /// less the catch-alls (`Folders`, `Quarantine`, `Conflicts`) and the
+    /// user's own extras, whose contents are not ours to re-file
+    pub fn category_folders(&self) -> HashSet<String> {
+        let mut set = self.protected_folders();
+        set.remove("Folders");
+        set.remove(damaged::QUARANTINE_CATEGORY);
+        set.remove(sync::CONFLICTS_CATEGORY);
+        for extra in &self.config.protected_folders {
+            set.remove(extra);
+        }
+        set
+    }
+
+    /// Returns the category folder a file belongs in.
+    ///
+    /// Resolution order, first match wins:
+    /// 1. `[origins]`: the site the file was downloaded from (most specific domain)
+    /// 2. `[group_by]` rules, highest `priority` first, ties by name (those
+    ///    with an `owner`, only for files it owns)
+    /// 3. `[extensions]` from the config
+    /// 4. the built-in extension table (first category listing the extension)
+    /// 5. for files without an extension: config `[filenames]`, built-in names,
+    ///    then the shebang (config `[shebangs]` before built-in interpreters)
+    /// 6. `Others`
+    ///
+    /// When a category has `trust = "contents"`, a file whose contents are of
+    /// another known type than its extension goes by its contents instead,
+    /// unless the category of its name trusts extensions: then it is
+    /// ambiguous and goes to `Others` (see `classify_checked`).
+    ///
+    /// Outside of origin and group rules, `video`, `code` and `documents` may then
+    /// be split into subfolders (`[video] split`, `[code] split` and `web`,
+    /// `[documents] inspect`), and archives that need a password set apart
+    /// (`[archives] encrypted`).
+    ///
+    /// The result then stands against added classifiers (`add_classifier`,
+    /// `[classifiers]`), asked in order: the rules answer with a confidence of
+    /// `RULES_CONFIDENCE` (none for `Others`), the highest confidence wins,
+    /// and on a tie the earlier answer, so the rules' own.
+    ///
+    /// `auto-organize rules lint` reports where one of these shadows another.
+    pub fn classify(&self, path: &Path) -> String {
+        self.route(path, true).0
+    }
+
+    /// Like `classify`, also telling why a file went to `Others` when its
+    /// contents and name disagree
+    pub fn classify_checked(&self, path: &Path) -> (String, Option<Ambiguity>) {
+        self.route(path, true)
+    }
+
+    /// The category a file of this name would get, from the name alone: no
+    /// origin, shebang, video or document contents (`rules test`)
+    pub fn classify_name(&self, name: &str) -> String {
+        self.route(Path::new(name), false).0
+    }
+
+    /// Whether a folder of this name is a bundle (see `BUNDLE_EXTENSIONS`)
+    pub fn is_bundle(&self, name: &str) -> bool {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| BUNDLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    }
+
+    /// The top-level category holding more than half of what is in `dir`
+    /// (by size, subfolders included), judged by file names; `None` when no
+    /// category but Others does. Only the first `FOLDER_SAMPLE` files count.
+    pub fn classify_folder(&self, dir: &Path) -> Option<String> {
+        let mut sizes: HashMap<String, u64> = HashMap::new();
+        let mut total = 0u64;
+        let mut seen = 0;
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    pending.push(entry.path());
+                    continue;
+                }
+                if !file_type.is_file() {
+                    continue;
+                }
+                // Empty files still have a say
+                let size = entry.metadata().map_or(0, |m| m.len()).max(1);
+                let category = self.route(&entry.path(), false).0;
+                let top = category.split('/').next().unwrap_or_default().to_string();
+                *sizes.entry(top).or_default() += size;
+                total += size;
+                seen += 1;
+                if seen == FOLDER_SAMPLE {
+                    pending.clear();
+                    break;
+                }
+            }
+        }
+        sizes
+            .into_iter()
+            .filter(|(category, size)| category != "Others" && *size * 2 > total)
+            .map(|(category, _)| category)
+            .next()
+    }
+
+    /// `classify_checked`, reading the file (and asking classifiers) only if
+    /// `read` is set
+    fn route(&self, path: &Path, read: bool) -> (String, Option<Ambiguity>) {
+        let (category, ambiguity) = self.route_by_rules(path, read);
+        if !read || self.classifiers.is_empty() {
+            return (category, ambiguity);
+        }
+        let rules = (category != "Others").then(|| Verdict {
+            category: category.clone(),
+            confidence: classifier::RULES_CONFIDENCE,
+        });
+        match classifier::resolve(rules, &self.classifiers, &Input::new(path)) {
+            Some(verdict) if verdict.category != category => (verdict.category, None),
+            _ => (category, ambiguity),
+        }
+    }
+
+    /// What the built-in rules and the config's tables say
+    fn route_by_rules(&self, path: &Path, read: bool) -> (String, Option<Ambiguity>) {
+        // Only read when rules use it: an extended attribute per file is not free
+        if read
+            && !self.config.origins.is_empty()
+            && let Some(domain) = origin::domain_of(path)
+            && let Some((_, category)) = self
+                .config
+                .origins
+                .iter()
+                .filter(|(pattern, _)| origin::matches(&domain, pattern))
+                .max_by_key(|(pattern, _)| pattern.len())
+        {
+            return (category.clone(), None);
+        }
+
+        let mut category = self.classify_by_name(path, read);
+
+        if read
+            && self.sniff
+            && let Ok(Some(exts)) = damaged::sniff(path)
+            && let Some(sniffed) = exts.iter().find_map(|ext| self.extensions.get(*ext))
+            && *sniffed != category
+        {
+            // An unknown extension says nothing; Others keeps nothing
+            let keeps = category != "Others" && self.trust(&category) == Trust::Extension;
+            let claims = self.trust(sniffed) == Trust::Contents;
+            if keeps && claims {
+                let ambiguity = Ambiguity {
+                    by_name: category,
+                    by_contents: sniffed.clone(),
+                    contents: exts[0],
+                };
+                return ("Others".to_string(), Some(ambiguity));
+            }
+            if !keeps {
+                category = sniffed.clone();
+            }
+        }
+        (self.refine(path, category, read), None)
+    }
+
+    /// Group rules, encrypted archives and the optional splits of `video`,
+    /// `code` and `documents`
+    fn refine(&self, path: &Path, category: String, read: bool) -> String {
+        // Related work files (PROJ-1234_spec.pdf, PROJ-1234_notes.txt) stay together
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            for rule in self
+                .group_order
+                .iter()
+                .map(|name| &self.config.group_by[name])
+            {
+                // The owner is only known when reading the file
+                if let Some(account) = &rule.owner
+                    && !(read && owner::owns(account, path))
+                {
+                    continue;
+                }
+                let group = match (&rule.folder, &rule.pattern) {
+                    (Some(folder), Some(pattern)) => pattern.captures(name).map(|_| folder.clone()),
+                    (Some(folder), None) => Some(folder.clone()),
+                    (None, Some(pattern)) => group_token(pattern, name),
+                    (None, None) => None,
+                };
+                if let Some(group) = group {
+                    let parent = rule.category.as_deref().unwrap_or(&category);
+                    return format!("{}/{}", parent, group);
+                }
+            }
+        }
+
+        // Archives that need a password, told apart when configured (off by default)
+        if category == "archives"
+            && read
+            && self.config.archives.encrypted != Routing::Keep
+            && encrypted::is_encrypted(path).unwrap_or(false)
+        {
+            return match self.config.archives.encrypted {
+                Routing::Quarantine => damaged::QUARANTINE_CATEGORY.to_string(),
+                _ => format!("archives/{}", encrypted::SUBFOLDER),
+            };
+        }
+
+        // Optional split of video into recordings/movies (off by default)
+        if category == "video" && self.config.video.split {
+            let sub = if read {
+                video::subcategory(path, &self.config.video)
+            } else {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .filter(|name| video::is_recording_name(name, &self.config.video))
+                    .map(|_| "recordings")
+            };
+            if let Some(sub) = sub {
+                return format!("video/{}", sub);
+            }
+        }
+
+        // Optional split of code by language, and web assets in their own folder
+        if category == "code"
+            && let Some(ext) = path.extension().and_then(|s| s.to_str())
+            && let Some(placement) = languages::placement(&ext.to_lowercase(), &self.config.code)
+        {
+            return placement;
+        }
+
+        // Optional keyword matching on the first pages (documents/finance)
+        if category == "documents"
+            && read
+            && self.config.documents.inspect
+            && let Some(sub) = documents::subfolder(path, &self.config.documents)
+        {
+            return format!("documents/{}", sub);
+        }
+
+        // Messages by the year they were sent and who sent them (email/2024/a@example.com)
+        if category == "email"
+            && read
+            && self.config.email.split
+            && let Some(sub) = email::subfolder(path, &self.config.email)
+        {
+            return format!("email/{}", sub);
+        }
+        category
+    }
+
+    fn classify_by_name(&self, path: &Path, read: bool) -> String {
+        let ext = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+
+        // Check if extension is known
+        match self.extensions.get(&ext) {
+            Some(cat) => cat.clone(), // Known category (images, apps, etc.)
+            // No extension: try well-known names (Makefile, README) and shebangs
+            None if ext.is_empty() => {
+                let category = if read {
+                    extensionless::classify(path, &self.config)
+                } else {
+                    path.file_name()
+                        .and_then(|n| n.to_str())
+                        .and_then(|name| extensionless::classify_name(name, &self.config))
+                };
+                category.unwrap_or_else(|| "Others".to_string())
+            }
+            None => "Others".to_string(), // Unknown extension (ini, sw, meme) -> Others
+        }
+    }
+}
+
+/// The folder name a group-by pattern extracts from a file name: its first
+/// capture group (or the whole match), if that is usable as a folder name
+fn group_token(pattern: &Regex, name: &str) -> Option<String> {
+    let captures = pattern.captures(name)?;
+    let token = captures.get(1).unwrap_or(&captures[0]).as_deref()?.trim();
+    let usable = !token.is_empty() && token != "." && token != ".." && !token.contains(['/', '\\']);
+    usable.then(|| token.to_string())
+}
+
+/// "documents/finance" lives under the "documents" folder
+fn top_level_folder(category: &str) -> &str {
+    category.split(['/', '\\']).next().unwrap_or(category)
+}
+
+/// CD/DVD and virtual machine images: huge, so copies are verified
+/// `others_threshold` unless the config sets it
+pub const DEFAULT_OTHERS_THRESHOLD: u64 = 10;
+
+/// Folders macOS shows and handles as one file (apps, libraries, documents
+/// saved as packages): classified by extension like a file and moved whole,
+/// never descended into or swept into `Folders`
+pub const BUNDLE_EXTENSIONS: &[&str] = &[
+    "app",
+    "photoslibrary",
+    "framework",
+    "pkg",
+    "mpkg",
+    "xcodeproj",
+    "xcworkspace",
+    "playground",
+    "pages",
+    "numbers",
+    "key",
+    "rtfd",
+    "logicx",
+    "band",
+    "fcpbundle",
+    "imovielibrary",
+];
+
+/// Files looked at to classify a folder: enough to tell, and bounded for
+/// folders like a checked-out repository
+const FOLDER_SAMPLE: usize = 1000;
+
+pub const DISK_IMAGES_CATEGORY: &str = "disk-images";
+
+/// Built-in categories and the extensions that belong to them.
+/// Every category here is also a protected folder (see `RuleSet::protected_folders`).
+/// An extension listed twice belongs to its first category.
+pub const BUILTIN_CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "images",
+        &[
+            "jpg",
+            "jpeg",
+            "png",
+            "gif",
+            "bmp",
+            "svg",
+            "webp",
+            "ico",
+            "tiff",
+            "heic",
+            "photoslibrary",
+        ],
+    ),
+    (
+        "documents",
+        &[
+            "pdf", "doc", "docx", "txt", "rtf", "odt", "md", "pages", "rtfd",
+        ],
+    ),
+    ("spreadsheets", &["xls", "xlsx", "csv", "ods", "numbers"]),
+    ("presentations", &["ppt", "pptx", "odp", "key"]),
+    ("email", &["eml", "emlx", "msg", "mbox"]),
+    ("archives", &["zip", "rar", "tar", "gz", "bz2", "7z"]),
+    (
+        DISK_IMAGES_CATEGORY,
+        &[
+            "iso", "img", "vmdk", "vdi", "qcow2", "vhd", "vhdx", "ova", "ovf",
+        ],
+    ),
+    (
+        "audio",
+        &["mp3", "wav", "flac", "aac", "ogg", "m4a", "logicx", "band"],
+    ),
+    (
+        "video",
+        &[
+            "mp4",
+            "mkv",
+            "avi",
+            "mov",
+            "wmv",
+            "webm",
+            "fcpbundle",
+            "imovielibrary",
+        ],
+    ),
+    (
+        "code",
+        &[
+            "rs",
+            "py",
+            "js",
+            "ts",
+            "java",
+            "c",
+            "cpp",
+            "go",
+            "rb",
+            "php",
+            "html",
+            "css",
+            "json",
+            "framework",
+            "xcodeproj",
+            "xcworkspace",
+            "playground",
+        ],
+    ),
+    // "APPS" category for executables
+    (
+        "APPS",
+        &[
+            "exe", "msi", "dmg", "app", "deb", "rpm", "apk", "appimage", "sh", "bat", "pkg", "mpkg",
+        ],
+    ),
+    ("fonts", &["ttf", "otf", "woff", "woff2"]),
+    ("ebooks", &["epub", "mobi", "azw3"]),
+    ("databases", &["sqlite", "db", "sql"]),
+    ("3d", &["stl", "obj", "fbx", "blend"]),
+    ("torrents", &["torrent"]),
+];
+
+fn get_extension_map() -> HashMap<String, String> {
+    let mut map = HashMap::new();
+
+    for (category, extensions) in BUILTIN_CATEGORIES {
+        for ext in *extensions {
+            map.entry(ext.to_string())
+                .or_insert_with(|| category.to_string());
+        }
+    }
+
+    map
+}
+
+/// A new name a renamer gives must stay a name, in the folder it is moved to
+fn validate_file_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err("not a file name".to_string());
+    }
+    if name.contains(['/', '\\']) {
+        return Err("a path, not a file name".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("control characters in a file name".to_string());
+    }
+    if name.len() > 255 {
+        return Err("longer than 255 bytes".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use crate::date::{self, DateTime};
+use crate::paths;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A stretch of the week in local time: some days (all by default), from
+/// `start` to `end` (minutes after midnight). A window that ends before it
+/// starts, like 22:00-06:00, runs past midnight into the next day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Monday first
+    days: [bool; 7],
+    start: u32,
+    end: u32,
+    text: String,
+}
+
+/// `[schedule]`: when watch mode may run passes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    /// Passes only run inside one of these; any time when empty
+    pub active: Vec<Window>,
+    /// Passes never run inside one of these, even when active says so
+    pub quiet: Vec<Window>,
+}
+
+impl Window {
+    /// Parses "02:00-05:00", "Mon-Fri 09:00-18:00" or "Sat,Sun 10:00-12:00"
+    pub fn parse(text: &str) -> Result<Window, String> {
+        let invalid = || {
+            format!(
+                "'{}': use HH:MM-HH:MM, after days like Mon-Fri or Sat,Sun if not every day",
+                text
+            )
+        };
+        let (days, hours) = match text.trim().rsplit_once(char::is_whitespace) {
+            Some((days, hours)) => (parse_days(days.trim()).ok_or_else(invalid)?, hours),
+            None => ([true; 7], text.trim()),
+        };
+        let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+        let (start, end) = (
+            parse_time(start).ok_or_else(invalid)?,
+            parse_time(end).ok_or_else(invalid)?,
+        );
+        if start == end {
+            return Err(format!("'{}': the window is empty", text));
+        }
+        Ok(Window {
+            days,
+            start,
+            end,
+            text: text.trim().to_string(),
+        })
+    }
+
+    /// Whether the local time `now` falls inside the window
+    pub fn contains(&self, now: DateTime) -> bool {
+        let day = now.weekday() as usize;
+        let minute = now.hour * 60 + now.minute;
+        if self.start < self.end {
+            return self.days[day] && (self.start..self.end).contains(&minute);
+        }
+        // Past midnight: the evening part is on a listed day, the morning
+        // part on the day after one
+        (self.days[day] && minute >= self.start) || (self.days[(day + 6) % 7] && minute < self.end)
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// "Mon-Fri", "Sat,Sun" or "Mon,Wed-Fri" (case-insensitive, three letters)
+fn parse_days(text: &str) -> Option<[bool; 7]> {
+    let day = |name: &str| {
+        DAYS.iter()
+            .position(|d| d.eq_ignore_ascii_case(name.trim()))
+    };
+    let mut days = [false; 7];
+    for part in text.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (day(from)?, day(to)?);
+                // Fri-Mon wraps over the weekend
+                let mut d = from;
+                loop {
+                    days[d] = true;
+                    if d == to {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days[day(part)?] = true,
+        }
+    }
+    Some(days)
+}
+
+/// "HH:MM" as minutes after midnight; "24:00" is the end of the day
+fn parse_time(text: &str) -> Option<u32> {
+    let (hour, minute) = text.trim().split_once(':')?;
+    let (hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    match (hour, minute) {
+        (24, 0) => Some(24 * 60),
+        (0..24, 0..60) => Some(hour * 60 + minute),
+        _ => None,
+    }
+}
+
+impl Schedule {
+    /// Whether a pass may run at the local time `now`
+    pub fn allows(&self, now: DateTime) -> bool {
+        (self.active.is_empty() || self.active.iter().any(|w| w.contains(now)))
+            && !self.quiet.iter().any(|w| w.contains(now))
+    }
+
+    /// The next local time, at most a week from `now`, when a pass may run
+    pub fn next_allowed(&self, now: DateTime) -> Option<DateTime> {
+        let start = now.to_unix() - i64::from(now.second);
+        (0..=7 * 24 * 60)
+            .map(|minute| DateTime::from_unix(start + minute * 60))
+            .find(|&t| self.allows(t))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty() && self.quiet.is_empty()
+    }
+}
+
+/// Where `schedule --pause` marks `target_dir` as paused:
+/// `<data dir>/paused/<key>`, keyed like journals, holding when the pause
+/// ends (seconds since the epoch) or nothing for "until resumed"
+pub fn pause_path(target_dir: &Path) -> Option<PathBuf> {
+    Some(
+        paths::data_dir()?
+            .join("paused")
+            .join(paths::key_for(target_dir)),
+    )
+}
+
+/// Pauses watch passes on `target_dir` until `until` (Unix time), or until resumed
+pub fn pause(target_dir: &Path, until: Option<i64>) -> io::Result<()> {
+    let path = pause_path(target_dir)
+        .ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, until.map(|u| u.to_string()).unwrap_or_default())
+}
+
+/// Lifts a pause; false if there was none
+pub fn unpause(target_dir: &Path) -> io::Result<bool> {
+    let Some(path) = pause_path(target_dir) else {
+        return Ok(false);
+    };
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `target_dir` is paused: `Some(None)` until resumed, `Some(Some(t))`
+/// until Unix time `t`. A pause that has run out is lifted.
+pub fn paused(target_dir: &Path) -> Option<Option<i64>> {
+    let path = pause_path(target_dir)?;
+    let text = fs::read_to_string(&path).ok()?;
+    let Ok(until) = text.trim().parse::<i64>() else {
+        return Some(None);
+    };
+    if until <= DateTime::now().to_unix() {
+        let _ = fs::remove_file(path);
+        return None;
+    }
+    Some(Some(until))
+}
+
+/// A Unix time as local wall-clock time, for messages
+pub fn local_iso(unix: i64) -> String {
+    DateTime::from_unix(unix + date::utc_offset(unix)).to_iso()
+}
@@ -0,0 +1,102 @@
+use crate::date::DateTime;
+use crate::plan::{EntryKind, PlannedMove};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Writes `moves` as a POSIX shell script of `mkdir -p` and `mv` commands
+/// that does what a run would, to be read, edited and run by hand (or where
+/// auto-organize is not installed). Paths inside `target_dir` are relative
+/// to it, and the script runs in the folder given as its argument, so it
+/// works on a copy of the folder elsewhere too.
+///
+/// Moves never replace a file (`mv -n`) unless the plan overwrites it, and
+/// the script stops at the first command that fails.
+pub fn write(path: &Path, target_dir: &Path, moves: &[&PlannedMove]) -> io::Result<()> {
+    let base = std::path::absolute(target_dir)?;
+    let mut out = Vec::new();
+    out.extend_from_slice(b"#!/bin/sh\n");
+    out.extend_from_slice(
+        format!(
+            "# {} move(s) planned by auto-organize on {}\n",
+            moves.len(),
+            DateTime::now_local().to_iso()
+        )
+        .as_bytes(),
+    );
+    out.extend_from_slice(
+        b"# Runs in the folder given as its argument, else where it was planned\n",
+    );
+    out.extend_from_slice(b"set -e\nif [ $# -gt 0 ]; then cd -- \"$1\"; else cd -- ");
+    out.extend_from_slice(&quote(&base));
+    out.extend_from_slice(b"; fi\n\n");
+
+    let relative = |p: &Path| -> PathBuf {
+        let p = std::path::absolute(p).unwrap_or(p.to_path_buf());
+        match p.strip_prefix(&base) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.to_path_buf(),
+            _ => p,
+        }
+    };
+    let mut made: HashSet<PathBuf> = HashSet::new();
+    for item in moves {
+        let src = relative(&item.src);
+        if item.kind == EntryKind::Merge {
+            // The folder whose contents were moved into an existing one
+            out.extend_from_slice(b"rmdir -- ");
+            out.extend_from_slice(&quote(&src));
+            out.push(b'\n');
+            continue;
+        }
+        let dest = relative(&item.dest);
+        if let Some(parent) = dest.parent()
+            && !parent.as_os_str().is_empty()
+            && made.insert(parent.to_path_buf())
+        {
+            out.extend_from_slice(b"mkdir -p -- ");
+            out.extend_from_slice(&quote(parent));
+            out.push(b'\n');
+        }
+        out.extend_from_slice(if item.overwrite {
+            b"mv -f -- "
+        } else {
+            b"mv -n -- "
+        });
+        out.extend_from_slice(&quote(&src));
+        out.push(b' ');
+        out.extend_from_slice(&quote(&dest));
+        out.push(b'\n');
+    }
+    fs::write(path, out)?;
+    make_executable(path)
+}
+
+/// `'it'\''s'`: single quotes keep everything as it is but a single quote
+fn quote(path: &Path) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if b == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    // Executable by whoever may read it
+    let mode = permissions.mode();
+    permissions.set_mode(mode | (mode & 0o444) >> 2);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Categories whose numbered files can make a series
+pub const SERIES_CATEGORIES: &[&str] = &["video", "audio"];
+
+/// Names (without extension) of artwork and metadata that belong to the one
+/// series in their folder
+pub const COMPANION_NAMES: &[&str] = &[
+    "cover", "folder", "poster", "fanart", "banner", "thumb", "front", "tvshow", "album",
+];
+
+/// `series_min_files` unless the config sets it: fewer numbered files are
+/// just files
+pub const DEFAULT_MIN_FILES: u64 = 3;
+
+/// Numbered files of one name, in one folder and category
+struct Series {
+    /// The name as the first file writes it
+    title: String,
+    numbers: BTreeSet<u64>,
+    /// Indexes into the files being grouped
+    members: Vec<usize>,
+}
+
+/// The series a file name is part of, lowercased to compare, and as written
+/// to name its folder, with its number in it: `Show.S01E02.mkv` is episode
+/// 1002 of "Show", `episode01.mkv` number 1 of "episode"
+fn numbered(name: &str) -> Option<(String, String, u64)> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let (prefix, number) = season_episode(stem).or_else(|| trailing_number(stem))?;
+    let title = prefix.trim_end_matches([' ', '-', '_', '.', '#', '(', '[']);
+    let usable = !title.is_empty() && title != "." && title != "..";
+    usable.then(|| (title.to_lowercase(), title.to_string(), number))
+}
+
+/// "Show.S01E02.1080p": what comes before the S01E02
+fn season_episode(stem: &str) -> Option<(&str, u64)> {
+    let bytes = stem.as_bytes();
+    for start in 0..bytes.len() {
+        if !bytes[start].eq_ignore_ascii_case(&b's')
+            || (start > 0 && bytes[start - 1].is_ascii_alphanumeric())
+        {
+            continue;
+        }
+        let season_end = digits_end(bytes, start + 1);
+        if season_end == start + 1
+            || season_end >= bytes.len()
+            || !bytes[season_end].eq_ignore_ascii_case(&b'e')
+        {
+            continue;
+        }
+        let episode_end = digits_end(bytes, season_end + 1);
+        if episode_end == season_end + 1 {
+            continue;
+        }
+        let season: u64 = stem[start + 1..season_end].parse().ok()?;
+        let episode: u64 = stem[season_end + 1..episode_end].parse().ok()?;
+        return Some((&stem[..start], season * 1000 + episode));
+    }
+    None
+}
+
+/// "episode01", "Lecture 3": the name without its last number
+fn trailing_number(stem: &str) -> Option<(&str, u64)> {
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let number = stem[prefix.len()..].parse().ok()?;
+    Some((prefix, number))
+}
+
+fn digits_end(bytes: &[u8], from: usize) -> usize {
+    from + bytes[from.min(bytes.len())..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .count()
+}
+
+/// Puts numbered files of one series into a folder of their own under their
+/// category, and the cover art next to them with them.
+///
+/// `episode01.mkv` ... `episode12.mkv` in one folder, all video, go to
+/// `video/episode`; so does `cover.jpg` beside them (when they are the only
+/// series there), or `episode.nfo`. A series needs `min_files` numbered files
+/// or more; 0 turns grouping off. Runs before `sidecars::group`, so subtitles
+/// still follow their episode.
+pub fn group<T>(files: &mut [(PathBuf, (String, T))], min_files: u64) {
+    if min_files == 0 {
+        return;
+    }
+    let names: Vec<String> = files
+        .iter()
+        .map(|(path, _)| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+
+    // Numbered files by folder, category and series
+    let mut series: BTreeMap<(&Path, &str, String), Series> = BTreeMap::new();
+    for (i, (path, (category, _))) in files.iter().enumerate() {
+        let top = category.split('/').next().unwrap_or(category);
+        if !SERIES_CATEGORIES.contains(&top) {
+            continue;
+        }
+        let Some((key, title, number)) = numbered(&names[i]) else {
+            continue;
+        };
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let entry = series
+            .entry((dir, category.as_str(), key))
+            .or_insert_with(|| Series {
+                title,
+                numbers: BTreeSet::new(),
+                members: Vec::new(),
+            });
+        entry.numbers.insert(number);
+        entry.members.push(i);
+    }
+
+    // Series with enough numbers, by folder: their new category
+    let mut by_dir: HashMap<PathBuf, Vec<(String, String)>> = HashMap::new();
+    let mut placed: Vec<(usize, String)> = Vec::new();
+    for ((dir, category, key), found) in series {
+        if (found.numbers.len() as u64) < min_files {
+            continue;
+        }
+        let folder = format!("{}/{}", category, found.title);
+        for &i in &found.members {
+            placed.push((i, folder.clone()));
+        }
+        by_dir
+            .entry(dir.to_path_buf())
+            .or_default()
+            .push((key, folder));
+    }
+    let grouped: BTreeSet<usize> = placed.iter().map(|(i, _)| *i).collect();
+
+    for i in 0..files.len() {
+        if grouped.contains(&i) {
+            continue;
+        }
+        let dir = files[i].0.parent().unwrap_or(Path::new(""));
+        let Some(found) = by_dir.get(dir) else {
+            continue;
+        };
+        let stem = names[i]
+            .rsplit_once('.')
+            .map_or(names[i].as_str(), |(stem, _)| stem)
+            .to_lowercase();
+        let by_name = found.iter().find(|(key, _)| *key == stem);
+        // Cover art goes with a series only when it cannot be another's
+        let by_art =
+            (found.len() == 1 && COMPANION_NAMES.contains(&stem.as_str())).then(|| &found[0]);
+        if let Some((_, folder)) = by_name.or(by_art) {
+            placed.push((i, folder.clone()));
+        }
+    }
+
+    for (i, folder) in placed {
+        files[i].1.0 = folder;
+    }
+}
@@ -0,0 +1,328 @@
+use crate::paths;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// launchd labels are reverse-DNS
+const LAUNCHD_PREFIX: &str = "io.github.gwangwwan.auto-organize";
+
+/// A scheduled run of the tool on one directory
+pub struct Service {
+    /// Directory being organized (absolute)
+    pub path: PathBuf,
+    /// Seconds between runs; with `watch`, between passes of the daemon
+    pub interval: u64,
+    /// Run as a long-lived `--watch` daemon instead of one-shot runs
+    pub watch: bool,
+    /// Extra arguments for every run, e.g. `--config FILE`
+    pub extra_args: Vec<String>,
+}
+
+/// Parses "90", "90s", "15m", "1h" or "1d" into seconds
+pub fn parse_interval(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(format!("'{}': use a number with s, m, h or d", text)),
+    };
+    match number.parse::<u64>() {
+        Ok(n) if n > 0 => n
+            .checked_mul(scale)
+            .ok_or_else(|| format!("'{}' is too long", text)),
+        _ => Err(format!("'{}': use a number with s, m, h or d", text)),
+    }
+}
+
+/// Unit/task name for a directory: one service per organized directory
+pub fn name_for(path: &Path) -> String {
+    format!("auto-organize-{}", paths::key_for(path))
+}
+
+impl Service {
+    /// The command line every run executes
+    fn command(&self) -> io::Result<Vec<String>> {
+        let exe = env::current_exe()?;
+        let mut command = vec![exe.to_string_lossy().into_owned()];
+        command.extend(self.extra_args.iter().cloned());
+        if self.watch {
+            command.push("--watch".to_string());
+            command.push("--interval".to_string());
+            command.push(self.interval.to_string());
+        }
+        command.push(self.path.to_string_lossy().into_owned());
+        Ok(command)
+    }
+
+    /// Files to write for this platform (path and contents); nothing on
+    /// Windows, where the task lives in Task Scheduler itself
+    pub fn files(&self) -> io::Result<Vec<(PathBuf, String)>> {
+        let name = name_for(&self.path);
+        let command = self.command()?;
+        if cfg!(target_os = "macos") {
+            let label = format!("{}.{}", LAUNCHD_PREFIX, paths::key_for(&self.path));
+            Ok(vec![(
+                launch_agents_dir()?.join(format!("{}.plist", label)),
+                launchd_plist(&label, &command, self.interval, self.watch),
+            )])
+        } else if cfg!(windows) {
+            Ok(Vec::new())
+        } else {
+            let dir = systemd_user_dir()?;
+            let mut files = vec![(
+                dir.join(format!("{}.service", name)),
+                systemd_service(&self.path, &command, self.watch),
+            )];
+            if !self.watch {
+                files.push((
+                    dir.join(format!("{}.timer", name)),
+                    systemd_timer(&self.path, self.interval),
+                ));
+            }
+            Ok(files)
+        }
+    }
+
+    /// Writes the unit files and registers them with the platform scheduler
+    pub fn install(&self) -> io::Result<Vec<PathBuf>> {
+        let files = self.files()?;
+        for (path, contents) in &files {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+
+        let name = name_for(&self.path);
+        if cfg!(target_os = "macos") {
+            let plist = files[0].0.to_string_lossy().into_owned();
+            // Replacing an existing agent: unload first, ignoring "not loaded"
+            let _ = Command::new("launchctl").args(["unload", &plist]).output();
+            run("launchctl", &["load", "-w", &plist])?;
+        } else if cfg!(windows) {
+            let command = self.command()?;
+            let mut args = vec![
+                "/Create".to_string(),
+                "/F".to_string(),
+                "/TN".to_string(),
+                name,
+                "/TR".to_string(),
+                windows_command_line(&command),
+            ];
+            args.extend(schtasks_schedule(self.interval, self.watch));
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            run("schtasks", &args)?;
+        } else {
+            let unit = if self.watch {
+                format!("{}.service", name)
+            } else {
+                format!("{}.timer", name)
+            };
+            run("systemctl", &["--user", "daemon-reload"])?;
+            run("systemctl", &["--user", "enable", "--now", &unit])?;
+        }
+        Ok(files.into_iter().map(|(path, _)| path).collect())
+    }
+}
+
+/// Stops and removes the service for `path`. Returns what was removed
+/// (unit files, or the task name on Windows); empty if nothing was installed.
+pub fn uninstall(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let name = name_for(path);
+    let mut removed = Vec::new();
+
+    if cfg!(target_os = "macos") {
+        let label = format!("{}.{}", LAUNCHD_PREFIX, paths::key_for(path));
+        let plist = launch_agents_dir()?.join(format!("{}.plist", label));
+        if plist.exists() {
+            let _ = Command::new("launchctl")
+                .args(["unload", "-w"])
+                .arg(&plist)
+                .output();
+            fs::remove_file(&plist)?;
+            removed.push(plist);
+        }
+    } else if cfg!(windows) {
+        let status = Command::new("schtasks")
+            .args(["/Delete", "/F", "/TN", &name])
+            .output()?;
+        if status.status.success() {
+            removed.push(PathBuf::from(name));
+        }
+    } else {
+        let dir = systemd_user_dir()?;
+        let units = [format!("{}.timer", name), format!("{}.service", name)];
+        for unit in &units {
+            let file = dir.join(unit);
+            if file.exists() {
+                let _ = Command::new("systemctl")
+                    .args(["--user", "disable", "--now", unit])
+                    .output();
+                fs::remove_file(&file)?;
+                removed.push(file);
+            }
+        }
+        if !removed.is_empty() {
+            // The files are gone either way; systemd forgets them at next login
+            let _ = run("systemctl", &["--user", "daemon-reload"]);
+        }
+    }
+    Ok(removed)
+}
+
+/// Runs a registration command, failing with its error output
+pub fn run(program: &str, args: &[&str]) -> io::Result<()> {
+    let output = Command::new(program).args(args).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{} {} failed: {}",
+        program,
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
+
+pub fn home() -> io::Result<PathBuf> {
+    env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::other("HOME is not set"))
+}
+
+fn systemd_user_dir() -> io::Result<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => home()?.join(".config"),
+    };
+    Ok(config.join("systemd/user"))
+}
+
+fn launch_agents_dir() -> io::Result<PathBuf> {
+    Ok(home()?.join("Library/LaunchAgents"))
+}
+
+fn systemd_service(path: &Path, command: &[String], watch: bool) -> String {
+    let exec = command
+        .iter()
+        .map(|arg| systemd_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut unit = format!(
+        "[Unit]\nDescription=auto-organize {}\n\n[Service]\n",
+        path.display().to_string().replace('%', "%%")
+    );
+    if watch {
+        unit.push_str(&format!(
+            "ExecStart={}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+            exec
+        ));
+    } else {
+        unit.push_str(&format!("Type=oneshot\nExecStart={}\n", exec));
+    }
+    unit
+}
+
+fn systemd_timer(path: &Path, interval: u64) -> String {
+    format!(
+        "[Unit]\nDescription=Run auto-organize on {} every {}s\n\n\
+         [Timer]\nOnBootSec=60\nOnUnitActiveSec={}\nPersistent=true\n\n\
+         [Install]\nWantedBy=timers.target\n",
+        path.display().to_string().replace('%', "%%"),
+        interval,
+        interval
+    )
+}
+
+/// Quotes an ExecStart argument; `%` starts a specifier in unit files
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+fn launchd_plist(label: &str, command: &[String], interval: u64, watch: bool) -> String {
+    let arguments: String = command
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    let schedule = if watch {
+        "    <key>KeepAlive</key>\n    <true/>\n".to_string()
+    } else {
+        format!(
+            "    <key>StartInterval</key>\n    <integer>{}</integer>\n",
+            interval
+        )
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n\
+         \x20   <key>Label</key>\n    <string>{}</string>\n\
+         \x20   <key>ProgramArguments</key>\n    <array>\n{}    </array>\n\
+         {}\
+         \x20   <key>RunAtLoad</key>\n    <true/>\n\
+         </dict>\n</plist>\n",
+        xml_escape(label),
+        arguments,
+        schedule
+    )
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Task Scheduler takes the whole command as one string
+fn windows_command_line(command: &[String]) -> String {
+    command
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `schtasks` schedules in minutes (up to a day) or days; a daemon starts at logon
+fn schtasks_schedule(interval: u64, watch: bool) -> Vec<String> {
+    if watch {
+        return vec!["/SC".to_string(), "ONLOGON".to_string()];
+    }
+    let (schedule, modifier) = if interval < 86_400 {
+        ("MINUTE", interval.div_ceil(60).clamp(1, 1439))
+    } else {
+        ("DAILY", (interval / 86_400).min(365))
+    };
+    vec![
+        "/SC".to_string(),
+        schedule.to_string(),
+        "/MO".to_string(),
+        modifier.to_string(),
+    ]
+}
+
+/// For `--print`: what would be registered on Windows, as a command line
+pub fn schtasks_preview(service: &Service) -> io::Result<String> {
+    let mut line = format!(
+        "schtasks /Create /F /TN {} /TR {}",
+        name_for(&service.path),
+        windows_command_line(&service.command()?)
+    );
+    for arg in schtasks_schedule(service.interval, service.watch) {
+        line.push(' ');
+        line.push_str(&arg);
+    }
+    Ok(line)
+}
@@ -0,0 +1,167 @@
+use crate::date::DateTime;
+use crate::paths;
+use crate::plan::{EntryKind, PlannedMove};
+use crate::tsv::{escape, unescape};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER: &str = "# auto-organize shadow log v1";
+
+/// What `--shadow` watched: every move a pass would have made, the first
+/// time it was planned, and when its file went away without the tool
+pub struct Entry {
+    pub kind: EntryKind,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub category: String,
+    /// When a pass first planned the move (seconds since the epoch)
+    pub seen: i64,
+    /// When the file was found moved or deleted by someone else
+    pub gone: Option<i64>,
+}
+
+/// Appends to the shadow log of a directory as passes go by
+pub struct ShadowLog {
+    file: File,
+    /// Logged moves whose file is still where it was, by source
+    pending: HashMap<PathBuf, String>,
+}
+
+/// Where the shadow log of `target_dir` is kept:
+/// `<data dir>/shadow/<key>.tsv`, keyed like journals
+pub fn path_for(target_dir: &Path) -> Option<PathBuf> {
+    Some(
+        paths::data_dir()?
+            .join("shadow")
+            .join(format!("{}.tsv", paths::key_for(target_dir))),
+    )
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or(path.to_path_buf())
+}
+
+impl ShadowLog {
+    /// Opens the log of `target_dir` to add to it, or starts one
+    pub fn open(target_dir: &Path) -> io::Result<ShadowLog> {
+        let path = path_for(target_dir)
+            .ok_or_else(|| io::Error::other("no data directory (HOME is not set)"))?;
+        let pending = match load(&path) {
+            Ok(entries) => entries
+                .into_iter()
+                .filter(|e| e.gone.is_none())
+                .map(|e| (e.src, e.category))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", HEADER)?;
+            writeln!(file, "# target: {}", absolute(target_dir).display())?;
+        }
+        Ok(ShadowLog { file, pending })
+    }
+
+    /// Logs a move a pass would have made; false if it was logged before
+    pub fn record(&mut self, item: &PlannedMove) -> io::Result<bool> {
+        let src = absolute(&item.src);
+        if self.pending.get(&src) == Some(&item.category) {
+            return Ok(false);
+        }
+        let kind = match item.kind {
+            EntryKind::File => "file",
+            EntryKind::Dir => "dir",
+            EntryKind::Merge => "merge",
+        };
+        writeln!(
+            self.file,
+            "seen\t{}\t{}\t{}\t{}\t{}",
+            DateTime::now().to_unix(),
+            kind,
+            escape(&src.to_string_lossy()),
+            escape(&absolute(&item.dest).to_string_lossy()),
+            escape(&item.category)
+        )?;
+        self.pending.insert(src, item.category.clone());
+        Ok(true)
+    }
+
+    /// Notes logged files that are no longer where they were: someone moved
+    /// or deleted them before a real run would have
+    pub fn sweep(&mut self) -> io::Result<usize> {
+        let gone: Vec<PathBuf> = self
+            .pending
+            .keys()
+            .filter(|src| fs::symlink_metadata(src).is_err())
+            .cloned()
+            .collect();
+        let now = DateTime::now().to_unix();
+        for src in &gone {
+            writeln!(
+                self.file,
+                "gone\t{}\t{}",
+                now,
+                escape(&src.to_string_lossy())
+            )?;
+            self.pending.remove(src);
+        }
+        Ok(gone.len())
+    }
+}
+
+/// Reads a shadow log back, oldest entry first
+pub fn load(path: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    // The entry still waiting for each source, by index
+    let mut waiting: HashMap<PathBuf, usize> = HashMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["seen", seen, kind, src, dest, category] => {
+                let Ok(seen) = seen.parse() else {
+                    continue;
+                };
+                let kind = match kind {
+                    "dir" => EntryKind::Dir,
+                    "merge" => EntryKind::Merge,
+                    _ => EntryKind::File,
+                };
+                let src = PathBuf::from(unescape(src));
+                let dest = PathBuf::from(unescape(dest));
+                let category = unescape(category);
+                // Planned again for another category: the rules changed since
+                if let Some(&i) = waiting.get(&src) {
+                    let entry: &mut Entry = &mut entries[i];
+                    (entry.kind, entry.dest, entry.category) = (kind, dest, category);
+                    continue;
+                }
+                waiting.insert(src.clone(), entries.len());
+                entries.push(Entry {
+                    kind,
+                    src,
+                    dest,
+                    category,
+                    seen,
+                    gone: None,
+                });
+            }
+            ["gone", when, src] => {
+                if let (Ok(when), Some(i)) =
+                    (when.parse(), waiting.remove(Path::new(&unescape(src))))
+                {
+                    entries[i].gone = Some(when);
+                }
+            }
+            // Comments, and a line cut short by a crash
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,102 @@
+use crate::config::SharedConfig;
+use crate::owner;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Characters that end the user prefix of a file name (`alice_scan.pdf`, `bob - notes.txt`)
+const PREFIX_SEPARATORS: &[char] = &['_', '-', ' ', '.'];
+
+/// A user files are given to in shared mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    /// The account name, which names their tree (see `user_root`)
+    pub name: String,
+    /// Their user id, which moved files are given to (none on Windows)
+    pub uid: Option<u32>,
+}
+
+/// The user a file belongs to in shared mode: a known user its name starts
+/// with (`match = "prefix"`), else the account that owns it
+pub fn user_for(path: &Path, meta: &fs::Metadata, config: &SharedConfig) -> Option<User> {
+    if config.by_prefix
+        && let Some(name) = prefix_user(path, config)
+    {
+        let uid = owner::account_id(&name);
+        return Some(User { name, uid });
+    }
+    let name = owner::owner_of(path, meta).filter(|name| name.parse::<u32>().is_err())?;
+    Some(User {
+        name,
+        uid: owner::uid_of(meta),
+    })
+}
+
+fn prefix_user(path: &Path, config: &SharedConfig) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let (prefix, _) = name.split_once(PREFIX_SEPARATORS)?;
+    let prefix = prefix.to_lowercase();
+    if prefix.is_empty() {
+        return None;
+    }
+    if config.users.is_empty() {
+        return (prefix.parse::<u32>().is_err() && owner::is_account(&prefix)).then_some(prefix);
+    }
+    config
+        .users
+        .iter()
+        .find(|user| user.to_lowercase() == prefix)
+        .cloned()
+}
+
+/// The root of `user`'s tree
+pub fn user_root(config: &SharedConfig, user: &str) -> PathBuf {
+    // A domain-qualified name must still be one folder level
+    config.root.join(user.replace(['/', '\\'], "_"))
+}
+
+/// After a move into the tree at `root`: gives the account `uid` (the user
+/// the move was planned for) `dest` (everything in it, for a folder) and the
+/// folders above it up to `root`, which is closed to others when `private`
+/// is set
+pub fn hand_over(config: &SharedConfig, root: &Path, uid: u32, dest: &Path) -> io::Result<()> {
+    give_tree(dest, uid)?;
+    let mut dir = dest.parent();
+    while let Some(current) = dir
+        && current.starts_with(root)
+    {
+        owner::set_owner(current, uid)?;
+        dir = current.parent();
+    }
+    if config.private {
+        restrict(root)?;
+    }
+    Ok(())
+}
+
+fn give_tree(path: &Path, uid: u32) -> io::Result<()> {
+    owner::set_owner(path, uid)?;
+    if fs::symlink_metadata(path)?.is_dir() {
+        for entry in fs::read_dir(path)? {
+            give_tree(&entry?.path(), uid)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restrict(root: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(root)?.permissions();
+    if permissions.mode() & 0o077 != 0 {
+        permissions.set_mode(permissions.mode() & 0o7700);
+        fs::set_permissions(root, permissions)?;
+    }
+    Ok(())
+}
+
+/// Windows: new folders inherit the ACLs of the shared root; set those up once
+#[cfg(not(unix))]
+fn restrict(_root: &Path) -> io::Result<()> {
+    Ok(())
+}
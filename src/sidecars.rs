@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Companion files that belong with a primary file of the same name
+pub const BUILTIN_SIDECAR_EXTENSIONS: &[&str] = &[
+    "xmp", "aae", "thm", // photo edits and thumbnails
+    "srt", "vtt", "ass", "ssa", "sub", "idx", // subtitles
+    "sha256", "sha512", "sha1", "md5", "sig", "asc", // checksums and signatures
+];
+
+/// Gives every sidecar the placement (category, and anything else that decides
+/// the destination) of its primary file so the pair moves as a unit.
+///
+/// `photo.jpg.xmp` pairs with `photo.jpg`, `video.srt` and `video.en.srt` pair
+/// with `video.mp4`, as long as both are in the same folder. Sidecars
+/// without a primary keep their own placement.
+pub fn group<T: Clone>(files: &mut [(PathBuf, T)], sidecar_exts: &HashSet<String>) {
+    let dirs: Vec<PathBuf> = files
+        .iter()
+        .map(|(path, _)| path.parent().unwrap_or(Path::new("")).to_path_buf())
+        .collect();
+    let names: Vec<String> = files
+        .iter()
+        .map(|(path, _)| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase()
+        })
+        .collect();
+    let is_sidecar: Vec<bool> = names
+        .iter()
+        .map(|name| {
+            name.rsplit_once('.')
+                .is_some_and(|(_, ext)| sidecar_exts.contains(ext))
+        })
+        .collect();
+
+    // Primary candidates, by full name and by stem
+    let mut by_name: HashMap<(&Path, &str), usize> = HashMap::new();
+    let mut by_stem: HashMap<(&Path, &str), usize> = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        if is_sidecar[i] {
+            continue;
+        }
+        by_name.insert((&dirs[i], name), i);
+        if let Some((stem, _)) = name.rsplit_once('.') {
+            // Several primaries can share a stem (video.mp4 + video.jpg); keep the first by name
+            by_stem
+                .entry((&dirs[i], stem))
+                .and_modify(|j| {
+                    if names[i] < names[*j] {
+                        *j = i;
+                    }
+                })
+                .or_insert(i);
+        }
+    }
+
+    for i in 0..files.len() {
+        if !is_sidecar[i] {
+            continue;
+        }
+        let Some((mut stem, _)) = names[i].rsplit_once('.') else {
+            continue;
+        };
+
+        // Try "video.en" and then "video" so language-tagged subtitles still pair up
+        let mut primary = None;
+        for _ in 0..2 {
+            let key = (dirs[i].as_path(), stem);
+            primary = by_name.get(&key).or_else(|| by_stem.get(&key)).copied();
+            if primary.is_some() {
+                break;
+            }
+            match stem.rsplit_once('.') {
+                Some((shorter, _)) => stem = shorter,
+                None => break,
+            }
+        }
+
+        if let Some(p) = primary {
+            files[i].1 = files[p].1.clone();
+        }
+    }
+}
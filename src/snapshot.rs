@@ -0,0 +1,172 @@
+use crate::date::DateTime;
+use crate::paths;
+use crate::tsv;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// One file of a listing: size and modification time (seconds since the epoch)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileInfo {
+    pub size: u64,
+    pub modified: i64,
+}
+
+/// Every file below a directory, by path relative to it
+pub struct Snapshot {
+    /// When the listing was taken (seconds since the epoch)
+    pub taken: i64,
+    pub files: BTreeMap<PathBuf, FileInfo>,
+}
+
+/// Where the first-run listing of `target_dir` is kept:
+/// `<data dir>/snapshots/<key>.tsv`, keyed like journals
+pub fn path_for(target_dir: &Path) -> Option<PathBuf> {
+    Some(
+        paths::data_dir()?
+            .join("snapshots")
+            .join(format!("{}.tsv", paths::key_for(target_dir))),
+    )
+}
+
+/// Lists every file below `root`, recursively (without following symlinks).
+/// Entries starting with `skip_prefix` are ignored.
+pub fn take(root: &Path, skip_prefix: &str) -> io::Result<Snapshot> {
+    let mut files = BTreeMap::new();
+    walk(root, Path::new(""), skip_prefix, &mut files)?;
+    Ok(Snapshot {
+        taken: DateTime::now().to_unix(),
+        files,
+    })
+}
+
+fn walk(
+    dir: &Path,
+    relative: &Path,
+    skip_prefix: &str,
+    files: &mut BTreeMap<PathBuf, FileInfo>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir.join(relative))?.flatten() {
+        let name = entry.file_name();
+        if name.to_string_lossy().starts_with(skip_prefix) {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let path = relative.join(&name);
+        if meta.is_dir() {
+            // A folder that cannot be read is listed as far as it goes
+            let _ = walk(dir, &path, skip_prefix, files);
+        } else {
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs() as i64);
+            files.insert(
+                path,
+                FileInfo {
+                    size: meta.len(),
+                    modified,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The saved listing; `None` if the directory was never snapshotted
+pub fn load(path: &Path) -> io::Result<Option<Snapshot>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut snapshot = Snapshot {
+        taken: 0,
+        files: BTreeMap::new(),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Some(taken) = line.strip_prefix("# taken\t") {
+            snapshot.taken = taken.parse().unwrap_or(0);
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let [path, size, modified] = fields[..]
+            && let (Ok(size), Ok(modified)) = (size.parse(), modified.parse())
+        {
+            snapshot.files.insert(
+                PathBuf::from(tsv::unescape(path)),
+                FileInfo { size, modified },
+            );
+        }
+    }
+    Ok(Some(snapshot))
+}
+
+pub fn save(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    writeln!(file, "# taken\t{}", snapshot.taken)?;
+    for (path, info) in &snapshot.files {
+        writeln!(
+            file,
+            "{}\t{}\t{}",
+            tsv::escape(&path.to_string_lossy()),
+            info.size,
+            info.modified
+        )?;
+    }
+    file.flush()
+}
+
+/// How a directory changed since its snapshot
+#[derive(Default)]
+pub struct Changes {
+    /// Files now elsewhere below the directory: old path, new path
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    /// Files no longer found anywhere below it
+    pub gone: Vec<PathBuf>,
+    /// Files still in place, with different contents
+    pub changed: Vec<PathBuf>,
+    /// Files that were not there
+    pub added: Vec<PathBuf>,
+}
+
+/// Compares a snapshot with the current listing. A file that left its place
+/// is found again by name, size and modification time (moves keep all three).
+pub fn diff(before: &Snapshot, now: &Snapshot) -> Changes {
+    let mut changes = Changes::default();
+    let mut arrivals: HashMap<(&OsStr, FileInfo), Vec<&PathBuf>> = HashMap::new();
+    for (path, info) in &now.files {
+        match before.files.get(path) {
+            Some(old) if old != info => changes.changed.push(path.clone()),
+            Some(_) => {}
+            None => arrivals
+                .entry((path.file_name().unwrap_or_default(), *info))
+                .or_default()
+                .push(path),
+        }
+    }
+    for (path, info) in &before.files {
+        if now.files.contains_key(path) {
+            continue;
+        }
+        // Names change when a conflict is renamed; only the exact name is followed
+        let key = (path.file_name().unwrap_or_default(), *info);
+        match arrivals.get_mut(&key).and_then(|found| found.pop()) {
+            Some(found) => changes.moved.push((path.clone(), found.clone())),
+            None => changes.gone.push(path.clone()),
+        }
+    }
+    changes.added = arrivals.into_values().flatten().cloned().collect();
+    changes.added.sort();
+    changes
+}
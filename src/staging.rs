@@ -0,0 +1,171 @@
+use crate::history;
+use crate::vfs::FileSystem;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Files replaced by `--on-conflict overwrite` wait here, one folder per run
+pub const STAGING_DIR: &str = ".auto-organize-staging";
+
+/// How long staged files are kept unless `staging_days` says otherwise
+pub const DEFAULT_KEEP_DAYS: u64 = 7;
+
+/// Where one run puts the files it would otherwise destroy
+pub struct Staging {
+    /// The organized directory; staged files keep their path relative to it
+    root: PathBuf,
+    /// `<root>/.auto-organize-staging/<run-id>`; `None` when staging is off
+    dir: Option<PathBuf>,
+    /// Files staged so far
+    pub staged: usize,
+}
+
+impl Staging {
+    /// Staging for run `run` (see `history::new_id`) in `root`; with
+    /// `keep_days` 0 replaced files are deleted right away, as without staging
+    pub fn new(root: &Path, keep_days: u64, run: &str) -> Self {
+        Staging {
+            root: root.to_path_buf(),
+            dir: (keep_days > 0).then(|| root.join(STAGING_DIR).join(run)),
+            staged: 0,
+        }
+    }
+
+    /// The run's staging folder, if staging is on
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Moves `victim` (about to be replaced) out of the way on `fs`. Without
+    /// staging it is deleted: moves never replace what is at their
+    /// destination, so the name must be free first.
+    pub fn stage(&mut self, victim: &Path, fs: &dyn FileSystem) -> io::Result<()> {
+        let Some(dir) = &self.dir else {
+            return fs.remove_file(victim);
+        };
+        let relative = match victim.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => Path::new(victim.file_name().unwrap_or_default()),
+        };
+        let dest = dir.join(relative);
+        if let Some(parent) = dest.parent() {
+            fs.create_dir_all(parent)?;
+        }
+        fs.move_path(victim, &dest, false)?;
+        self.staged += 1;
+        Ok(())
+    }
+}
+
+/// Where `run` put the file that was at `victim` before it was replaced,
+/// if it is still there
+pub fn staged_file(root: &Path, run: &str, victim: &Path) -> Option<PathBuf> {
+    let relative = victim
+        .strip_prefix(root)
+        .ok()
+        .map(Path::to_path_buf)
+        .or_else(|| victim.file_name().map(PathBuf::from))?;
+    let path = root.join(STAGING_DIR).join(run).join(relative);
+    path.is_file().then_some(path)
+}
+
+/// Deletes staged runs of `root` older than `older_than_days` (all of them
+/// with 0). Returns the run folders removed (or that would be, in a dry run).
+pub fn purge(root: &Path, older_than_days: u64, dry_run: bool) -> io::Result<Vec<PathBuf>> {
+    let staging = root.join(STAGING_DIR);
+    let entries = match fs::read_dir(&staging) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(older_than_days.saturating_mul(86_400)))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut removed = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Anything not named like a run is not ours to delete; run IDs carry
+        // the time the run started, so ages are known without trusting mtimes
+        let Some(created) = history::started(&name) else {
+            continue;
+        };
+        if older_than_days > 0 && created > cutoff {
+            continue;
+        }
+        let path = entry.path();
+        if !dry_run {
+            fs::remove_dir_all(&path)?;
+        }
+        removed.push(path);
+    }
+    removed.sort();
+
+    if !dry_run && fs::read_dir(&staging)?.next().is_none() {
+        fs::remove_dir(&staging)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::RealFs;
+
+    #[test]
+    fn staged_files_keep_their_place_under_the_run() {
+        let root = std::env::temp_dir().join("auto-organize-staging-stage");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("images")).unwrap();
+        let victim = root.join("images/a.jpg");
+        fs::write(&victim, "old").unwrap();
+
+        let run = history::new_id();
+        let mut staging = Staging::new(&root, DEFAULT_KEEP_DAYS, &run);
+        staging.stage(&victim, &RealFs::default()).unwrap();
+        assert!(!victim.exists());
+        assert_eq!(staging.staged, 1);
+        let staged = staged_file(&root, &run, &victim).unwrap();
+        assert_eq!(
+            staged,
+            root.join(STAGING_DIR).join(&run).join("images/a.jpg")
+        );
+        assert_eq!(fs::read_to_string(staged).unwrap(), "old");
+
+        // With 0 days there is no staging: the file is just deleted
+        fs::write(&victim, "new").unwrap();
+        let mut off = Staging::new(&root, 0, &run);
+        off.stage(&victim, &RealFs::default()).unwrap();
+        assert!(!victim.exists());
+        assert_eq!(off.staged, 0);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn purge_removes_only_runs_past_the_cutoff() {
+        let root = std::env::temp_dir().join("auto-organize-staging-purge");
+        let _ = fs::remove_dir_all(&root);
+        let staging = root.join(STAGING_DIR);
+        let old = staging.join("2020-01-01T00-00-00");
+        let recent = staging.join(history::new_id());
+        let other = staging.join("notes");
+        for dir in [&old, &recent, &other] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("a.txt"), "x").unwrap();
+        }
+
+        assert_eq!(purge(&root, 7, true).unwrap(), vec![old.clone()]);
+        assert!(old.exists());
+        assert_eq!(purge(&root, 7, false).unwrap(), vec![old.clone()]);
+        assert!(!old.exists() && recent.exists());
+
+        // 0 days is every run, but never a folder not named like one
+        assert_eq!(purge(&root, 0, false).unwrap(), vec![recent.clone()]);
+        assert!(!recent.exists() && other.exists());
+        fs::remove_dir_all(&other).unwrap();
+        assert!(purge(&root, 0, false).unwrap().is_empty());
+        assert!(!staging.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
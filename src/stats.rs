@@ -0,0 +1,117 @@
+use crate::analyze::human_size;
+use crate::paths;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Files and bytes in each category folder, after a run
+pub type Snapshot = BTreeMap<String, (u64, u64)>;
+
+/// Where the last run's snapshot of `target_dir` is kept:
+/// `<data dir>/stats/<key>.tsv`, keyed like journals
+pub fn path_for(target_dir: &Path) -> Option<PathBuf> {
+    Some(
+        paths::data_dir()?
+            .join("stats")
+            .join(format!("{}.tsv", paths::key_for(target_dir))),
+    )
+}
+
+/// Counts what the existing `folders` of `root` hold, recursively (without
+/// following symlinks). Entries starting with `skip_prefix` are ignored.
+pub fn snapshot(root: &Path, folders: &HashSet<String>, skip_prefix: &str) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for name in folders {
+        let dir = root.join(name);
+        if !fs::symlink_metadata(&dir).is_ok_and(|m| m.is_dir()) {
+            continue;
+        }
+        let mut totals = (0, 0);
+        tally(&dir, skip_prefix, &mut totals);
+        snapshot.insert(name.clone(), totals);
+    }
+    snapshot
+}
+
+fn tally(dir: &Path, skip_prefix: &str, totals: &mut (u64, u64)) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(skip_prefix) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            tally(&entry.path(), skip_prefix, totals);
+        } else if file_type.is_file()
+            && let Ok(meta) = entry.metadata()
+        {
+            totals.0 += 1;
+            totals.1 += meta.len();
+        }
+    }
+}
+
+/// The previous snapshot; `None` before the first run
+pub fn load(path: &Path) -> io::Result<Option<Snapshot>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut snapshot = Snapshot::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let [name, files, bytes] = fields[..]
+            && let (Ok(files), Ok(bytes)) = (files.parse(), bytes.parse())
+        {
+            snapshot.insert(name.to_string(), (files, bytes));
+        }
+    }
+    Ok(Some(snapshot))
+}
+
+pub fn save(path: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    for (name, (files, bytes)) in snapshot {
+        writeln!(file, "{}\t{}\t{}", name, files, bytes)?;
+    }
+    file.flush()
+}
+
+/// One line per category that changed: "+240 images (+1.1 GiB)",
+/// "Others shrank by 1.2 GiB (35 file(s) fewer)"
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<String> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    let mut lines = Vec::new();
+    for name in names {
+        let (old_files, old_bytes) = before.get(name).copied().unwrap_or_default();
+        let (new_files, new_bytes) = after.get(name).copied().unwrap_or_default();
+        let files = new_files as i64 - old_files as i64;
+        let size = human_size(new_bytes.abs_diff(old_bytes));
+        let line = if files == 0 && new_bytes == old_bytes {
+            continue;
+        } else if files >= 0 && new_bytes >= old_bytes {
+            format!("+{} {} (+{})", files, name, size)
+        } else if files <= 0 && new_bytes <= old_bytes && new_bytes != old_bytes {
+            format!("{} shrank by {} ({} file(s) fewer)", name, size, -files)
+        } else if files <= 0 && new_bytes <= old_bytes {
+            format!("{} shrank by {} file(s)", name, -files)
+        } else if new_bytes > old_bytes {
+            format!("{}: {} file(s), +{}", name, files, size)
+        } else {
+            format!("{}: +{} file(s), -{}", name, files, size)
+        };
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,151 @@
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// When category tags are shown in color, with their emoji (`--color`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    /// When writing to a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Whether what is printed to stdout gets colors
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                io::stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
+        }
+    }
+}
+
+/// A terminal color, by its ANSI code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color(u8);
+
+impl Color {
+    /// The terminal's own text color
+    pub const PLAIN: Color = Color(0);
+
+    /// Parses "red", "bright-blue"... or "none" (`PLAIN`)
+    pub fn parse(name: &str) -> Result<Color, String> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        if name == "none" {
+            return Ok(Color::PLAIN);
+        }
+        let (base, offset) = match name.strip_prefix("bright-") {
+            Some(base) => (base, 90),
+            None => (name, 30),
+        };
+        NAMES
+            .iter()
+            .position(|known| *known == base)
+            .map(|i| Color(offset + i as u8))
+            .ok_or_else(|| {
+                format!(
+                    "unknown color '{}' (black, red, green, yellow, blue, magenta, cyan, white, bright-<color> or none)",
+                    name
+                )
+            })
+    }
+}
+
+/// How a category is tagged in color output (`[categories.<name>] color`
+/// and `emoji`); what is not set comes from the parent category, then the
+/// built-in labels
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Label {
+    pub color: Option<Color>,
+    /// An empty one shows none
+    pub emoji: Option<String>,
+}
+
+/// Top-level folder -> ANSI color and emoji
+#[rustfmt::skip]
+const BUILTIN: &[(&str, u8, &str)] = &[
+    ("images",        35, "\u{1F5BC}\u{FE0F}"),
+    ("documents",     33, "\u{1F4C4}"),
+    ("spreadsheets",  32, "\u{1F4CA}"),
+    ("presentations", 33, "\u{1F4FD}\u{FE0F}"),
+    ("email",         36, "\u{2709}\u{FE0F}"),
+    ("archives",      31, "\u{1F4E6}"),
+    ("audio",         36, "\u{1F3B5}"),
+    ("video",         34, "\u{1F3AC}"),
+    ("code",          32, "\u{1F4BB}"),
+    ("web",           32, "\u{1F310}"),
+    ("APPS",          95, "\u{1F9E9}"),
+    ("fonts",         37, "\u{1F524}"),
+    ("ebooks",        33, "\u{1F4DA}"),
+    ("databases",     94, "\u{1F5C4}\u{FE0F}"),
+    ("3d",            95, "\u{1F9CA}"),
+    ("torrents",      94, "\u{1F9F2}"),
+    ("Folders",       34, "\u{1F4C1}"),
+    ("Others",        90, "\u{2754}"),
+    ("Trash",         91, "\u{1F5D1}\u{FE0F}"),
+    ("Quarantine",    91, "\u{26A0}\u{FE0F}"),
+    ("Conflicts",     93, "\u{1F500}"),
+];
+
+/// Prints category tags as `[images      ]`, or with colors on as the
+/// category's emoji and the tag in its color
+#[derive(Debug, Clone, Default)]
+pub struct Style {
+    enabled: bool,
+    /// Category -> what the config sets for it
+    labels: BTreeMap<String, Label>,
+}
+
+impl Style {
+    pub fn new(enabled: bool, labels: BTreeMap<String, Label>) -> Self {
+        Style { enabled, labels }
+    }
+
+    /// The color and emoji of `category`, or those of the nearest parent
+    /// category that has them
+    pub fn label(&self, category: &str) -> (Color, &str) {
+        let (mut color, mut emoji) = (None, None);
+        let mut folder = category;
+        loop {
+            if let Some(label) = self.labels.get(folder) {
+                color = color.or(label.color);
+                emoji = emoji.or(label.emoji.as_deref());
+            }
+            match folder.rfind(['/', '\\']) {
+                Some(end) => folder = &folder[..end],
+                None => break,
+            }
+        }
+        let builtin = BUILTIN.iter().find(|(name, _, _)| *name == folder);
+        (
+            color
+                .or(builtin.map(|&(_, code, _)| Color(code)))
+                .unwrap_or(Color::PLAIN),
+            emoji.or(builtin.map(|&(_, _, emoji)| emoji)).unwrap_or(""),
+        )
+    }
+
+    /// `[category    ]`, padded as the plain output always was
+    pub fn tag(&self, category: &str) -> String {
+        let tag = format!("[{:<12}]", category);
+        if !self.enabled {
+            return tag;
+        }
+        let (color, emoji) = self.label(category);
+        // Two columns either way, so the names still line up
+        let emoji = if emoji.is_empty() { "  " } else { emoji };
+        match color {
+            Color::PLAIN => format!("{} {}", emoji, tag),
+            Color(code) => format!("{} \x1b[{}m{}\x1b[0m", emoji, code, tag),
+        }
+    }
+}
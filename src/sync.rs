@@ -0,0 +1,66 @@
+use crate::config::SyncConfig;
+use crate::glob::GlobSet;
+
+/// Files (and folders) that sync tools are still writing or keep for
+/// themselves; moving them breaks the transfer or the sync state
+pub const BUILTIN_IGNORE_PATTERNS: &[&str] = &[
+    ".syncthing.*.tmp", // Syncthing, in-progress download
+    "~syncthing~*.tmp", // Syncthing on Windows
+    ".stfolder",        // Syncthing folder marker
+    ".stversions",
+    ".stignore",
+    "*.~tmp~",
+    "*.rsync-partial",
+    ".rsync-partial", // rsync --partial-dir
+    ".dropbox",
+    ".dropbox.cache",
+];
+
+/// Copies a sync tool made when two devices changed the same file
+pub const BUILTIN_CONFLICT_PATTERNS: &[&str] = &[
+    "* (conflicted copy *)*", // Dropbox, Nextcloud
+    "*'s conflicted copy*",   // Dropbox (older clients)
+    "*.sync-conflict-*",      // Syncthing
+    "* (Conflict *)*",        // Nextcloud desktop
+];
+
+/// Category that conflict copies go into
+pub const CONFLICTS_CATEGORY: &str = "Conflicts";
+
+/// How a file relates to a sync tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncFile {
+    /// Being transferred, or the tool's own bookkeeping: leave it alone
+    Ignored,
+    /// A conflict copy
+    Conflict,
+}
+
+/// Built-in (or configured) sync patterns, ready to match file names
+pub struct SyncRules {
+    ignore: GlobSet,
+    conflict: GlobSet,
+}
+
+impl SyncRules {
+    pub fn new(config: &SyncConfig) -> Self {
+        let or_builtin = |list: &Option<Vec<String>>, builtin: &[&str]| match list {
+            Some(list) => GlobSet::new(list),
+            None => GlobSet::new(builtin),
+        };
+        SyncRules {
+            ignore: or_builtin(&config.ignore_patterns, BUILTIN_IGNORE_PATTERNS),
+            conflict: or_builtin(&config.conflict_patterns, BUILTIN_CONFLICT_PATTERNS),
+        }
+    }
+
+    pub fn check(&self, name: &str) -> Option<SyncFile> {
+        if self.ignore.matches_any(name) {
+            Some(SyncFile::Ignored)
+        } else if self.conflict.matches_any(name) {
+            Some(SyncFile::Conflict)
+        } else {
+            None
+        }
+    }
+}
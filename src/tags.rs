@@ -0,0 +1,173 @@
+use crate::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Tags of files in a folder whose filesystem has no extended attributes
+pub const SIDECAR: &str = ".tags.json";
+
+/// Where a tag was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    /// An extended attribute: `user.xdg.tags` (as KDE and GNOME tools read
+    /// it), a Finder tag on macOS
+    Attribute,
+    /// The folder's `.tags.json`
+    Sidecar,
+}
+
+/// Adds tags to files without moving them (`--tag-only`). Sidecar files
+/// are only written by `finish`, once per folder.
+#[derive(Default)]
+pub struct Tagger {
+    /// Folder -> file name -> tags, for folders that need a `.tags.json`
+    sidecars: BTreeMap<PathBuf, BTreeMap<String, Vec<String>>>,
+}
+
+impl Tagger {
+    /// Adds `tag` to `path`, in an extended attribute where the filesystem
+    /// allows, else in its folder's `.tags.json`. A tag it has is kept once.
+    pub fn tag(&mut self, path: &Path, tag: &str) -> io::Result<Store> {
+        if sys::add_tag(path, tag).is_ok() {
+            return Ok(Store::Attribute);
+        }
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+        if !self.sidecars.contains_key(dir) {
+            let existing = read_sidecar(&dir.join(SIDECAR))?;
+            self.sidecars.insert(dir.to_path_buf(), existing);
+        }
+        let tags = self
+            .sidecars
+            .get_mut(dir)
+            .map(|files| files.entry(name).or_default())
+            .expect("loaded above");
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+        Ok(Store::Sidecar)
+    }
+
+    /// Writes the `.tags.json` files
+    pub fn finish(self) -> io::Result<()> {
+        for (dir, files) in self.sidecars {
+            write_sidecar(&dir.join(SIDECAR), &files)?;
+        }
+        Ok(())
+    }
+}
+
+/// `{"name": ["tag", ...], ...}`; empty if the file does not exist
+fn read_sidecar(path: &Path) -> io::Result<BTreeMap<String, Vec<String>>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    parse_sidecar(&text).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a tags file", path.display()),
+        )
+    })
+}
+
+fn parse_sidecar(text: &str) -> Option<BTreeMap<String, Vec<String>>> {
+    let mut files = BTreeMap::new();
+    let mut rest = text.trim_start().strip_prefix('{')?.trim_start();
+    while !rest.starts_with('}') {
+        let (name, after) = json::parse_string(rest)?;
+        rest = after.trim_start().strip_prefix(':')?.trim_start();
+        rest = rest.strip_prefix('[')?.trim_start();
+        let mut tags = Vec::new();
+        while !rest.starts_with(']') {
+            let (tag, after) = json::parse_string(rest)?;
+            tags.push(tag);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+        }
+        files.insert(name, tags);
+        rest = rest[1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+    Some(files)
+}
+
+fn write_sidecar(path: &Path, files: &BTreeMap<String, Vec<String>>) -> io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let mut out = io::BufWriter::new(fs::File::create(&tmp)?);
+    writeln!(out, "{{")?;
+    for (i, (name, tags)) in files.iter().enumerate() {
+        let tags: Vec<String> = tags.iter().map(|t| json::string(t)).collect();
+        let comma = if i + 1 < files.len() { "," } else { "" };
+        writeln!(
+            out,
+            "  {}: [{}]{}",
+            json::string(name),
+            tags.join(", "),
+            comma
+        )?;
+    }
+    writeln!(out, "}}")?;
+    out.flush()?;
+    drop(out);
+    fs::rename(&tmp, path)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod sys {
+    use crate::xattr;
+    use std::io;
+    use std::path::Path;
+
+    /// Comma-separated, as the freedesktop.org convention has it
+    const ATTRIBUTE: &str = "user.xdg.tags";
+
+    pub fn add_tag(path: &Path, tag: &str) -> io::Result<()> {
+        let existing = xattr::get(path, ATTRIBUTE)?.unwrap_or_default();
+        let existing = String::from_utf8_lossy(&existing);
+        let mut tags: Vec<&str> = existing.split(',').filter(|t| !t.is_empty()).collect();
+        if tags.contains(&tag) {
+            return Ok(());
+        }
+        tags.push(tag);
+        xattr::set(path, ATTRIBUTE, tags.join(",").as_bytes())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod sys {
+    use crate::plist;
+    use crate::xattr;
+    use std::io;
+    use std::path::Path;
+
+    /// Finder tags: a property list of `name` or `name\ncolor` strings
+    const ATTRIBUTE: &str = "com.apple.metadata:_kMDItemUserTags";
+
+    pub fn add_tag(path: &Path, tag: &str) -> io::Result<()> {
+        let mut tags = xattr::get(path, ATTRIBUTE)?
+            .map(|data| plist::strings(&data))
+            .unwrap_or_default();
+        if tags.iter().any(|t| t.split('\n').next() == Some(tag)) {
+            return Ok(());
+        }
+        tags.push(tag.to_string());
+        xattr::set(path, ATTRIBUTE, &plist::string_array(&tags))
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    /// NTFS has no tags Explorer shows for every file type; use the sidecar
+    pub fn add_tag(_path: &Path, _tag: &str) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,150 @@
+use crate::config;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Offered at the prompt when previews are built in
+#[cfg(feature = "preview")]
+const PREVIEW_HINT: &str = ", ? = preview";
+#[cfg(not(feature = "preview"))]
+const PREVIEW_HINT: &str = "";
+
+/// Interactive mode: asks which category an unknown extension belongs to and,
+/// unless learning is off, saves the answer to `[extensions]` in the config so
+/// the question never comes up again.
+pub struct Teacher {
+    answers: HashMap<String, String>,
+    /// Where answers are saved; `None` disables learning
+    config_path: Option<PathBuf>,
+    categories: Vec<String>,
+    /// Stdin was closed; everything unknown goes to Others from here on
+    exhausted: bool,
+}
+
+impl Teacher {
+    pub fn new(config_path: Option<PathBuf>, mut categories: Vec<String>) -> Self {
+        categories.sort();
+        categories.dedup();
+        Teacher {
+            answers: HashMap::new(),
+            config_path,
+            categories,
+            exhausted: false,
+        }
+    }
+
+    /// Returns the category for an unknown extension, asking the user the first
+    /// time about `example`, a file that has it
+    pub fn category_for(&mut self, ext: &str, example: &Path) -> String {
+        if let Some(category) = self.answers.get(ext) {
+            return category.clone();
+        }
+
+        let category = self.ask(ext, example);
+        self.learn(ext, &category);
+        category
+    }
+
+    /// Many files of an unknown extension are going to Others: offers them
+    /// a category named after the extension. Returns it if accepted.
+    pub fn offer_category(&mut self, ext: &str, count: usize) -> Option<String> {
+        if self.exhausted || config::validate_category(ext).is_err() {
+            return None;
+        }
+        print!(
+            "{} files with extension '.{}' are going to Others. Give them a category `{}`? [y/N]: ",
+            count, ext, ext
+        );
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+        match io::stdin().lock().read_line(&mut answer) {
+            Ok(0) | Err(_) => {
+                println!();
+                self.exhausted = true;
+                return None;
+            }
+            Ok(_) => {}
+        }
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return None;
+        }
+        self.learn(ext, ext);
+        Some(ext.to_string())
+    }
+
+    /// Remembers the answer for this run and, with learning on, in the config
+    fn learn(&mut self, ext: &str, category: &str) {
+        self.answers.insert(ext.to_string(), category.to_string());
+
+        if category != "Others"
+            && let Some(path) = &self.config_path
+        {
+            match config::set_string(path, "extensions", ext, category) {
+                Ok(()) => println!(
+                    "    Learned: .{} -> {} (saved to {})",
+                    ext,
+                    category,
+                    path.display()
+                ),
+                Err(e) => eprintln!("Error saving to config '{}': {}", path.display(), e),
+            }
+        }
+    }
+
+    fn ask(&mut self, ext: &str, example: &Path) -> String {
+        if self.exhausted {
+            return "Others".to_string();
+        }
+
+        let name = example.file_name().unwrap_or_default();
+        println!("Unknown extension '.{}' (e.g. {:?})", ext, name);
+        println!("    Categories: {}", self.categories.join(", "));
+
+        let stdin = io::stdin();
+        loop {
+            print!(
+                "Which category should `.{}` go to? [Enter = Others{}]: ",
+                ext, PREVIEW_HINT
+            );
+            let _ = io::stdout().flush();
+
+            let mut answer = String::new();
+            match stdin.lock().read_line(&mut answer) {
+                Ok(0) | Err(_) => {
+                    println!();
+                    self.exhausted = true;
+                    return "Others".to_string();
+                }
+                Ok(_) => {}
+            }
+
+            let answer = answer.trim();
+            if answer.is_empty() {
+                return "Others".to_string();
+            }
+            #[cfg(feature = "preview")]
+            if answer == "?" {
+                show_preview(example);
+                continue;
+            }
+            match config::validate_category(answer) {
+                Ok(()) => return answer.to_string(),
+                Err(e) => println!("    {}; try again", e),
+            }
+        }
+    }
+}
+
+/// Prints what `path` holds, to answer by
+#[cfg(feature = "preview")]
+fn show_preview(path: &Path) {
+    match crate::preview::describe(path) {
+        Ok(lines) => {
+            for line in lines {
+                println!("    {}", line);
+            }
+        }
+        Err(e) => println!("    No preview: {}", e),
+    }
+}
@@ -0,0 +1,95 @@
+use crate::tsv;
+
+/// Placeholders a template may use:
+/// `{category}` where the entry goes (or a container such as Folders),
+/// `{name}`, `{stem}` (name without extension), `{ext}` (without the dot),
+/// `{size}` in bytes (`-` for folders), `{modified}` (UTC, ISO 8601),
+/// `{owner}` (account name), `{origin}` (download site), `{kind}` (file, dir or
+/// merge), `{src}` and `{dest}` paths, and `{run}` (the run's ID, see `history`)
+pub const PLACEHOLDERS: &[&str] = &[
+    "category", "name", "stem", "ext", "size", "modified", "owner", "origin", "kind", "src",
+    "dest", "run",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field(String),
+}
+
+/// A line template such as `{category}\t{name}\t{size}`.
+///
+/// `\t`, `\n` and `\\` are escapes, `{{` and `}}` are literal braces. Values
+/// are escaped the same way (a tab in a file name prints as `\t`), so every
+/// rendered line stays one record for awk and friends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+                    None => return Err("template ends with '\\'".to_string()),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed '{{{}'", name)),
+                        }
+                    }
+                    if !PLACEHOLDERS.contains(&name.as_str()) {
+                        return Err(format!(
+                            "unknown placeholder {{{}}} (available: {})",
+                            name,
+                            PLACEHOLDERS.join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(name));
+                }
+                '}' => return Err("unmatched '}' (write '}}' for a literal brace)".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Fills in the placeholders; `value` is asked for each one in turn
+    pub fn render(&self, value: impl Fn(&str) -> String) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Field(name) => out.push_str(&tsv::escape(&value(name))),
+            }
+        }
+        out
+    }
+}
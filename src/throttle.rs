@@ -0,0 +1,204 @@
+use crate::interrupt;
+use crate::netfs;
+use crate::paths;
+use crate::vfs::{DirEntry, FileSystem, Metadata};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Backend name for network shares without limits of their own type
+pub const NETWORK: &str = "network";
+
+/// Backend name for everything else
+pub const LOCAL: &str = "local";
+
+/// How long a move waits before looking for a free transfer slot again
+const SLOT_POLL: Duration = Duration::from_millis(100);
+
+/// How hard a run may work one destination backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Changes (moves, copies, removals, new folders) per second at most
+    pub ops_per_second: Option<u64>,
+    /// Moves onto it at the same time at most, across all running instances
+    pub transfers: Option<u64>,
+}
+
+impl Limits {
+    pub fn is_empty(&self) -> bool {
+        self.ops_per_second.is_none() && self.transfers.is_none()
+    }
+}
+
+/// Limits by backend: a filesystem type as `netfs` names it (`nfs4`,
+/// `cifs`, `ext4`...), else `network` or `local`. `all` (`--fs-ops-limit`,
+/// `--max-transfers`) overrides them everywhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    pub backends: BTreeMap<String, Limits>,
+    pub all: Limits,
+}
+
+impl Policy {
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty() && self.backends.values().all(Limits::is_empty)
+    }
+
+    /// The limits for a destination on `backend`
+    pub fn limits(&self, backend: &str) -> Limits {
+        let fallback = if netfs::is_network(backend) {
+            NETWORK
+        } else {
+            LOCAL
+        };
+        let own = self
+            .backends
+            .get(backend)
+            .or_else(|| self.backends.get(fallback))
+            .copied()
+            .unwrap_or_default();
+        Limits {
+            ops_per_second: self.all.ops_per_second.or(own.ops_per_second),
+            transfers: self.all.transfers.or(own.transfers),
+        }
+    }
+}
+
+/// A filesystem whose changes are paced to its destination backend's limits.
+/// The pace is shared by everything in the process that goes through it;
+/// transfer slots are lock files in the data directory, so runs organizing
+/// into the same NAS at once share them too. Listing and metadata lookups
+/// are not limited.
+pub struct Throttled<F> {
+    inner: F,
+    policy: Policy,
+    /// Folder -> backend, since looking a mount up reads the mount table
+    backends: Mutex<HashMap<PathBuf, String>>,
+    /// Backend -> when its next change may start
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl<F: FileSystem> Throttled<F> {
+    pub fn new(inner: F, policy: Policy) -> Self {
+        Throttled {
+            inner,
+            policy,
+            backends: Mutex::new(HashMap::new()),
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The backend `path` is changed on: the type of the filesystem its
+    /// nearest existing folder is on
+    fn backend(&self, path: &Path) -> String {
+        let folder = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.is_dir())
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        let mut backends = self.backends.lock().unwrap_or_else(|e| e.into_inner());
+        backends
+            .entry(folder)
+            .or_insert_with_key(|folder| {
+                netfs::filesystem_type(folder).unwrap_or_else(|| LOCAL.to_string())
+            })
+            .clone()
+    }
+
+    /// Waits for the next change allowed on `path`'s backend; `Some` holds a
+    /// transfer slot when `transfer` is set and the backend limits them
+    fn admit(&self, path: &Path, transfer: bool) -> io::Result<Option<File>> {
+        let backend = self.backend(path);
+        let limits = self.policy.limits(&backend);
+        if let Some(rate) = limits.ops_per_second.filter(|&rate| rate > 0) {
+            let wait = {
+                let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                let at = next.get(&backend).copied().unwrap_or(now).max(now);
+                // A rate of billions is fine too: it rounds to no wait at all
+                next.insert(
+                    backend.clone(),
+                    at + Duration::from_secs_f64(1.0 / rate as f64),
+                );
+                at - now
+            };
+            if !interrupt::sleep(wait) {
+                return Err(interrupt::cancelled());
+            }
+        }
+        match limits.transfers.filter(|_| transfer) {
+            Some(slots) => slot(&backend, slots).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Takes one of the `slots` transfer slots of `backend`, waiting until one is
+/// free. The slot is held until the file is dropped.
+fn slot(backend: &str, slots: u64) -> io::Result<File> {
+    let dir = paths::data_dir()
+        .ok_or_else(|| io::Error::other("no data directory to keep transfer slots in"))?
+        .join("transfers");
+    fs::create_dir_all(&dir)?;
+    loop {
+        for i in 0..slots.max(1) {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(dir.join(format!("{}-{}.lock", backend, i)))?;
+            match file.try_lock() {
+                Ok(()) => return Ok(file),
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+        if !interrupt::sleep(SLOT_POLL) {
+            return Err(interrupt::cancelled());
+        }
+    }
+}
+
+impl<F: FileSystem> FileSystem for Throttled<F> {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        self.inner.read_dir(dir)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        self.admit(dir, false)?;
+        self.inner.create_dir_all(dir)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        self.admit(dir, false)?;
+        self.inner.remove_dir(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.admit(path, false)?;
+        self.inner.remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let _slot = self.admit(to, true)?;
+        self.inner.rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let _slot = self.admit(to, true)?;
+        self.inner.copy(from, to)
+    }
+
+    fn move_path(&self, from: &Path, to: &Path, verify: bool) -> io::Result<()> {
+        let _slot = self.admit(to, true)?;
+        self.inner.move_path(from, to, verify)
+    }
+}
@@ -0,0 +1,246 @@
+use crate::date::DateTime;
+use crate::mover::{self, CopyOptions};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Moves a file into the user's trash and returns where it ended up.
+///
+/// On Linux/BSD this follows the freedesktop.org trash spec: the home trash,
+/// or for a file on another volume that volume's `.Trash-$uid`. On macOS it
+/// uses `~/.Trash`. A file that cannot be renamed into a trash (another
+/// volume without one of its own) is copied there, then removed. Other
+/// platforms are not supported.
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let path = path.canonicalize()?;
+
+    if cfg!(target_os = "macos") {
+        let trash = home_dir()?.join(".Trash");
+        fs::create_dir_all(&trash)?;
+        return trash_into(&path, &trash, Layout::Flat);
+    }
+
+    if cfg!(unix) {
+        let home = freedesktop_trash_dir()?;
+        fs::create_dir_all(&home)?;
+        if let Some((trash, topdir)) = volume_trash(&path, &home) {
+            return trash_into(&path, &trash, Layout::Volume(&topdir));
+        }
+        return trash_into(&path, &home, Layout::Home);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "moving to trash is not supported on this platform",
+    ))
+}
+
+/// How a trash folder is laid out
+#[derive(Debug, Clone, Copy)]
+enum Layout<'a> {
+    /// Files right in it (`~/.Trash`)
+    Flat,
+    /// freedesktop.org: `files/` and `info/`, with absolute paths in the info
+    Home,
+    /// Likewise, with paths relative to the top folder of the volume
+    Volume(&'a Path),
+}
+
+/// Moves `path` into the trash at `trash` under a name free there, writing
+/// its `.trashinfo` first where the layout has one. Neither the file nor the
+/// info file replaces anything: a name taken meanwhile moves on to the next.
+fn trash_into(path: &Path, trash: &Path, layout: Layout) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let (files_dir, info_dir) = match layout {
+        Layout::Flat => (trash.to_path_buf(), None),
+        Layout::Home | Layout::Volume(_) => (trash.join("files"), Some(trash.join("info"))),
+    };
+    fs::create_dir_all(&files_dir)?;
+    if let Some(info_dir) = &info_dir {
+        fs::create_dir_all(info_dir)?;
+    }
+
+    for n in 1.. {
+        let name = numbered(file_name, n);
+        let dest = files_dir.join(&name);
+        if fs::symlink_metadata(&dest).is_ok() {
+            continue;
+        }
+        let info_path = match &info_dir {
+            Some(info_dir) => {
+                let mut info_name = name.clone();
+                info_name.push(".trashinfo");
+                let info_path = info_dir.join(info_name);
+                let shown = match layout {
+                    Layout::Volume(topdir) => path.strip_prefix(topdir).unwrap_or(path),
+                    _ => path,
+                };
+                match write_info(&info_path, shown) {
+                    Ok(()) => Some(info_path),
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            None => None,
+        };
+        match mover::move_path(path, &dest, &CopyOptions::default()) {
+            Ok(()) => return Ok(dest),
+            Err(e) => {
+                if let Some(info_path) = &info_path {
+                    let _ = fs::remove_file(info_path);
+                }
+                if e.kind() != io::ErrorKind::AlreadyExists {
+                    return Err(e);
+                }
+            }
+        }
+    }
+    unreachable!("every trash name is taken")
+}
+
+/// `name`, then `name.2`, `name.3`...
+fn numbered(name: &OsStr, n: u32) -> OsString {
+    let mut candidate = name.to_os_string();
+    if n > 1 {
+        candidate.push(format!(".{}", n));
+    }
+    candidate
+}
+
+/// Writes the `.trashinfo` of a file trashed from `shown`, unless one of that
+/// name exists. A volume's trash has paths relative to its top folder, as the
+/// spec recommends, so the drive can be mounted elsewhere; `DeletionDate` is
+/// local time.
+fn write_info(info_path: &Path, shown: &Path) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(info_path)?;
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(shown.as_os_str().as_encoded_bytes()),
+        DateTime::now_local().to_iso()
+    );
+    if let Err(e) = file.write_all(info.as_bytes()) {
+        let _ = fs::remove_file(info_path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn home_dir() -> io::Result<PathBuf> {
+    env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))
+}
+
+fn freedesktop_trash_dir() -> io::Result<PathBuf> {
+    match env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(data) => Ok(PathBuf::from(data).join("Trash")),
+        None => Ok(home_dir()?.join(".local/share/Trash")),
+    }
+}
+
+/// For a file on another volume than the home trash: that volume's
+/// `$topdir/.Trash-$uid` and its top folder, made if missing. `None` when
+/// the file is on the home trash's volume, or the volume's trash cannot be
+/// used (read-only, not ours, a symlink).
+#[cfg(unix)]
+fn volume_trash(path: &Path, home: &Path) -> Option<(PathBuf, PathBuf)> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    unsafe extern "C" {
+        fn getuid() -> u32;
+    }
+
+    let device = fs::symlink_metadata(path).ok()?.dev();
+    if fs::metadata(home).ok()?.dev() == device {
+        return None;
+    }
+    // The top folder of the volume: the last folder up that is still on it
+    let mut topdir = path.parent()?;
+    while let Some(parent) = topdir.parent()
+        && fs::metadata(parent).is_ok_and(|meta| meta.dev() == device)
+    {
+        topdir = parent;
+    }
+    // SAFETY: `getuid` takes nothing and cannot fail
+    let uid = unsafe { getuid() };
+    let trash = topdir.join(format!(".Trash-{}", uid));
+    match fs::DirBuilder::new().mode(0o700).create(&trash) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(_) => return None,
+    }
+    let meta = fs::symlink_metadata(&trash).ok()?;
+    (meta.is_dir() && meta.uid() == uid).then(|| (trash, topdir.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn volume_trash(_path: &Path, _home: &Path) -> Option<(PathBuf, PathBuf)> {
+    None
+}
+
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trashed_names_never_replace_one_another() {
+        let dir = std::env::temp_dir().join("auto-organize-trash-names");
+        let _ = fs::remove_dir_all(&dir);
+        let trash = dir.join("Trash");
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        fs::write(dir.join("a/notes.txt"), "first").unwrap();
+        fs::write(dir.join("b/notes.txt"), "second").unwrap();
+
+        let first = trash_into(&dir.join("a/notes.txt"), &trash, Layout::Home).unwrap();
+        let second = trash_into(&dir.join("b/notes.txt"), &trash, Layout::Home).unwrap();
+        assert_eq!(first, trash.join("files/notes.txt"));
+        assert_eq!(second, trash.join("files/notes.txt.2"));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "second");
+        let info = fs::read_to_string(trash.join("info/notes.txt.2.trashinfo")).unwrap();
+        assert!(info.contains(&format!("Path={}/b/notes.txt\n", dir.display())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_kept() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = std::env::temp_dir().join("auto-organize-trash-bytes");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let name = OsStr::from_bytes(b"caf\xe9.txt");
+        fs::write(dir.join(name), "x").unwrap();
+
+        let trash = dir.join("Trash");
+        let dest = trash_into(&dir.join(name), &trash, Layout::Home).unwrap();
+        assert_eq!(dest.file_name(), Some(name));
+        let mut info_name = name.to_os_string();
+        info_name.push(".trashinfo");
+        let info = fs::read_to_string(trash.join("info").join(info_name)).unwrap();
+        assert!(info.contains("caf%E9.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Escapes a field for the tab-separated state files (index, journal)
+pub fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+pub fn unescape(s: &str) -> String {
+    String::from_utf8_lossy(&unescape_bytes(s)).into_owned()
+}
+
+/// Escapes a path like `escape`, keeping bytes of names that are not valid
+/// UTF-8 as `\xNN`, so the path reads back exactly (see `unescape_path`)
+pub fn escape_path(path: &Path) -> String {
+    let mut out = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        out.push_str(&escape(chunk.valid()));
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    out
+}
+
+pub fn unescape_path(s: &str) -> PathBuf {
+    PathBuf::from(os_string(unescape_bytes(s)))
+}
+
+fn unescape_bytes(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push(b'\t'),
+            Some('n') => out.push(b'\n'),
+            Some('x') => {
+                let hex = chars.as_str().get(..2);
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        chars.nth(1);
+                    }
+                    None => out.push(b'x'),
+                }
+            }
+            Some(other) => out.extend_from_slice(other.encode_utf8(&mut [0; 4]).as_bytes()),
+            None => out.push(b'\\'),
+        }
+    }
+    out
+}
+
+#[cfg(unix)]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+/// Names elsewhere are Unicode, or close to it (unpaired UTF-16 surrogates
+/// are replaced)
+#[cfg(not(unix))]
+fn os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
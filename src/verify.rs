@@ -0,0 +1,265 @@
+use crate::analyze;
+use crate::hash;
+use crate::plan::{EntryKind, PlannedMove};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A file (or directory) some record says should exist
+pub struct Expected {
+    /// How to refer to it in the report
+    pub label: String,
+    pub path: PathBuf,
+    /// Recorded size, if known; a mismatch is reported without hashing
+    pub size: Option<u64>,
+    /// `None` for directories: only existence is checked
+    pub sha256: Option<String>,
+}
+
+/// Counts from a verification run
+#[derive(Debug, Default)]
+pub struct Report {
+    pub ok: usize,
+    pub altered: usize,
+    pub moved: usize,
+    pub missing: usize,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.altered == 0 && self.moved == 0 && self.missing == 0
+    }
+}
+
+/// Checks every expected entry, printing one line per problem. Files that are
+/// gone from their recorded place are looked up by name and hash under
+/// `search_root`, so files shuffled around inside the tree show up as moved.
+pub fn verify(expected: &[Expected], search_root: &Path) -> Report {
+    let mut report = Report::default();
+    let mut by_name: Option<HashMap<String, Vec<PathBuf>>> = None;
+
+    for item in expected {
+        let exists = match item.sha256 {
+            Some(_) => item.path.is_file(),
+            None => item.path.is_dir(),
+        };
+
+        if exists {
+            let Some(expected_hash) = &item.sha256 else {
+                report.ok += 1;
+                continue;
+            };
+            if let Some(size) = item.size
+                && fs::metadata(&item.path).is_ok_and(|m| m.len() != size)
+            {
+                println!("[ALTERED] {} (size changed)", item.label);
+                report.altered += 1;
+                continue;
+            }
+            match hash::hash_file(&item.path) {
+                Ok(actual) if &actual == expected_hash => report.ok += 1,
+                Ok(_) => {
+                    println!("[ALTERED] {} (contents changed)", item.label);
+                    report.altered += 1;
+                }
+                Err(e) => {
+                    println!("[ALTERED] {} (cannot read: {})", item.label, e);
+                    report.altered += 1;
+                }
+            }
+            continue;
+        }
+
+        // Only directories are compared by name alone; files must match by hash too
+        let names = by_name.get_or_insert_with(|| index_names(search_root));
+        let file_name = item
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let found = names.get(&file_name).and_then(|candidates| {
+            candidates.iter().find(|candidate| match &item.sha256 {
+                Some(expected_hash) => {
+                    candidate.is_file()
+                        && hash::hash_file(candidate).is_ok_and(|h| &h == expected_hash)
+                }
+                None => candidate.is_dir(),
+            })
+        });
+
+        match found {
+            Some(new_path) => {
+                println!("[MOVED  ] {} -> {}", item.label, new_path.display());
+                report.moved += 1;
+            }
+            None => {
+                println!("[MISSING] {}", item.label);
+                report.missing += 1;
+            }
+        }
+    }
+
+    report
+}
+
+/// Every file and directory name under `root`, for finding moved entries
+fn index_names(root: &Path) -> HashMap<String, Vec<PathBuf>> {
+    let mut names: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                stack.push(path.clone());
+            }
+            names
+                .entry(entry.file_name().to_string_lossy().into_owned())
+                .or_default()
+                .push(path);
+        }
+    }
+    names
+}
+
+/// What a directory held before a run applied its plan, to check afterwards
+/// that every move landed and nothing else went missing (`--verify-run`)
+pub struct RunCheck {
+    /// Every file under the directory, and its size
+    before: BTreeMap<PathBuf, u64>,
+}
+
+impl RunCheck {
+    /// Lists the files under `root` (paths as `root` joined with theirs),
+    /// skipping entries that start with `skip_prefix`
+    pub fn scan(root: &Path, skip_prefix: &str) -> io::Result<RunCheck> {
+        let before = analyze::files(root, skip_prefix)?
+            .into_iter()
+            .map(|file| (root.join(file.path), file.size))
+            .collect();
+        Ok(RunCheck { before })
+    }
+
+    /// Checks the tree against the plan, given whether each move was made,
+    /// printing one line per problem; returns how many there were.
+    ///
+    /// A moved file is at its destination with the size it had, and no longer
+    /// at its source; a moved folder holds what it held; a file that was not
+    /// moved is still where it was; every other file is still there.
+    pub fn check(&self, plan: &[PlannedMove], moved: &[bool]) -> usize {
+        let mut problems = 0;
+        let mut problem = |tag: &str, message: String| {
+            println!("[{}] {}", tag, message);
+            problems += 1;
+        };
+        let exists = |path: &Path| fs::symlink_metadata(path).is_ok();
+        // Sources the plan accounts for: the rest must be left alone
+        let mut planned: HashSet<&Path> = HashSet::new();
+        let mut landed: HashMap<&Path, &Path> = HashMap::new();
+
+        for (item, &moved) in plan.iter().zip(moved) {
+            planned.insert(&item.src);
+            if !moved {
+                if !exists(&item.src) {
+                    problem(
+                        "MISSING",
+                        format!("{} (not moved, but gone)", item.src.display()),
+                    );
+                }
+                continue;
+            }
+            match item.kind {
+                EntryKind::File => {
+                    if let Some(other) = landed.insert(&item.dest, &item.src) {
+                        problem(
+                            "TWICE  ",
+                            format!(
+                                "{} and {} were both moved to {}",
+                                other.display(),
+                                item.src.display(),
+                                item.dest.display()
+                            ),
+                        );
+                    }
+                    match (fs::symlink_metadata(&item.dest), self.before.get(&item.src)) {
+                        (Err(_), _) => problem(
+                            "MISSING",
+                            format!("{} (not at {})", item.src.display(), item.dest.display()),
+                        ),
+                        (Ok(meta), Some(&size)) if meta.is_file() && meta.len() != size => problem(
+                            "ALTERED",
+                            format!(
+                                "{} ({} bytes at {}, {} before)",
+                                item.src.display(),
+                                meta.len(),
+                                item.dest.display(),
+                                size
+                            ),
+                        ),
+                        _ => {}
+                    }
+                    if exists(&item.src) {
+                        problem(
+                            "TWICE  ",
+                            format!(
+                                "{} is still there as well as at {}",
+                                item.src.display(),
+                                item.dest.display()
+                            ),
+                        );
+                    }
+                }
+                // A merge leaves behind what would have replaced something
+                EntryKind::Dir | EntryKind::Merge => {
+                    for (path, &size) in self.before.range(item.src.clone()..) {
+                        let Ok(inner) = path.strip_prefix(&item.src) else {
+                            break;
+                        };
+                        let dest = item.dest.join(inner);
+                        let mut places = vec![dest.as_path()];
+                        if item.kind == EntryKind::Merge {
+                            places.push(path);
+                        }
+                        let sizes: Vec<u64> = places
+                            .iter()
+                            .filter_map(|place| fs::metadata(place).ok())
+                            .map(|meta| meta.len())
+                            .collect();
+                        if sizes.is_empty() {
+                            problem(
+                                "MISSING",
+                                format!("{} (not at {})", path.display(), dest.display()),
+                            );
+                        } else if !sizes.contains(&size) {
+                            problem(
+                                "ALTERED",
+                                format!(
+                                    "{} ({} bytes at {}, {} before)",
+                                    path.display(),
+                                    sizes[0],
+                                    dest.display(),
+                                    size
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for path in self.before.keys() {
+            if !path.ancestors().any(|dir| planned.contains(dir)) && !exists(path) {
+                problem(
+                    "MISSING",
+                    format!("{} (was never to be moved)", path.display()),
+                );
+            }
+        }
+        problems
+    }
+}
@@ -0,0 +1,607 @@
+use crate::mover;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// What planning and applying need to know about an entry (symlinks are
+/// not followed)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub is_dir: bool,
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+/// One entry of a folder listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub name: OsString,
+    pub is_dir: bool,
+}
+
+/// The filesystem operations the planner and mover go through: the disk
+/// (`RealFs`) or a simulation (`MemoryFs`) for tests and previews
+pub trait FileSystem {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>>;
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()>;
+
+    /// Removes an empty folder
+    fn remove_dir(&self, dir: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Renames within one filesystem, replacing a file at `to`
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies a file; returns the bytes copied
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64>;
+
+    /// Moves a file or folder: a rename, or a copy and remove across
+    /// filesystems (`verify` reads copies back first, where supported).
+    /// Like `mover::move_path`, it fails if something is already at `to`.
+    fn move_path(&self, from: &Path, to: &Path, _verify: bool) -> io::Result<()> {
+        if self.metadata(to).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} was taken since the run was planned", to),
+            ));
+        }
+        match self.rename(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices && !self.metadata(from)?.is_dir => {
+                self.copy(from, to)?;
+                self.remove_file(from)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Shared so a simulation can be inspected after the planner is done with it
+impl<T: FileSystem + ?Sized> FileSystem for Arc<T> {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        (**self).read_dir(dir)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        (**self).metadata(path)
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        (**self).create_dir_all(dir)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        (**self).remove_dir(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        (**self).remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        (**self).rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        (**self).copy(from, to)
+    }
+
+    fn move_path(&self, from: &Path, to: &Path, verify: bool) -> io::Result<()> {
+        (**self).move_path(from, to, verify)
+    }
+}
+
+/// The real filesystem; moves go through `mover` (partial copies, resuming,
+/// verification, bandwidth limit)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs {
+    pub copy: mover::CopyOptions,
+}
+
+impl FileSystem for RealFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        Ok(fs::read_dir(dir)?
+            .flatten()
+            .map(|e| DirEntry {
+                path: e.path(),
+                name: e.file_name(),
+                is_dir: e.file_type().is_ok_and(|t| t.is_dir()),
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let meta = fs::symlink_metadata(path)?;
+        Ok(Metadata {
+            is_dir: meta.is_dir(),
+            len: meta.len(),
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        })
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::remove_dir(dir)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        fs::copy(from, to)
+    }
+
+    fn move_path(&self, from: &Path, to: &Path, verify: bool) -> io::Result<()> {
+        if verify {
+            mover::move_path_verified(from, to, &self.copy)
+        } else {
+            mover::move_path(from, to, &self.copy)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Dir,
+    File(Metadata),
+}
+
+/// An in-memory filesystem: paths are plain keys (no symlinks, `..` or
+/// case folding), and every path is on one device. Fill it with
+/// `add_file`/`add_dir`, plan and apply against it (shared through an `Arc`
+/// given to `DestCache::new`), then inspect `files`.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        MemoryFs::default()
+    }
+
+    /// Adds a file of `len` bytes, with its parent folders
+    pub fn add_file(&self, path: impl AsRef<Path>, len: u64, modified: SystemTime) {
+        let path = normalize(path.as_ref());
+        let mut nodes = self.lock();
+        add_parents(&mut nodes, &path);
+        nodes.insert(
+            path,
+            Node::File(Metadata {
+                is_dir: false,
+                len,
+                modified,
+            }),
+        );
+    }
+
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let path = normalize(path.as_ref());
+        let mut nodes = self.lock();
+        add_parents(&mut nodes, &path);
+        nodes.insert(path, Node::Dir);
+    }
+
+    /// Every file, with its size
+    pub fn files(&self) -> Vec<(PathBuf, u64)> {
+        self.lock()
+            .iter()
+            .filter_map(|(path, node)| match node {
+                Node::File(meta) => Some((path.clone(), meta.len)),
+                Node::Dir => None,
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.nodes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Drops `.` components so equal paths are equal keys
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+fn add_parents(nodes: &mut BTreeMap<PathBuf, Node>, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        if parent.as_os_str().is_empty() {
+            break;
+        }
+        nodes.entry(parent.to_path_buf()).or_insert(Node::Dir);
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found", path))
+}
+
+fn require_parent(nodes: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => match nodes.get(parent) {
+            Some(Node::Dir) => Ok(()),
+            Some(Node::File(_)) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                format!("{:?} is a file", parent),
+            )),
+            None => Err(not_found(parent)),
+        },
+        _ => Ok(()),
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let dir = normalize(dir);
+        let nodes = self.lock();
+        match nodes.get(&dir) {
+            Some(Node::Dir) => {}
+            Some(Node::File(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("{:?} is a file", dir),
+                ));
+            }
+            None => return Err(not_found(&dir)),
+        }
+        Ok(nodes
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(dir.as_path()))
+            .map(|(path, node)| DirEntry {
+                path: path.clone(),
+                name: path.file_name().unwrap_or_default().to_owned(),
+                is_dir: matches!(node, Node::Dir),
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        match self.lock().get(&normalize(path)) {
+            Some(Node::File(meta)) => Ok(*meta),
+            Some(Node::Dir) => Ok(Metadata {
+                is_dir: true,
+                len: 0,
+                modified: SystemTime::UNIX_EPOCH,
+            }),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        let mut nodes = self.lock();
+        for path in dir.ancestors() {
+            if let Some(Node::File(_)) = nodes.get(path) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{:?} is a file", path),
+                ));
+            }
+        }
+        add_parents(&mut nodes, &dir);
+        nodes.insert(dir, Node::Dir);
+        Ok(())
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        let mut nodes = self.lock();
+        if !matches!(nodes.get(&dir), Some(Node::Dir)) {
+            return Err(not_found(&dir));
+        }
+        if nodes
+            .keys()
+            .any(|path| path.parent() == Some(dir.as_path()))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{:?} is not empty", dir),
+            ));
+        }
+        nodes.remove(&dir);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut nodes = self.lock();
+        match nodes.get(&path) {
+            Some(Node::File(_)) => {
+                nodes.remove(&path);
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut nodes = self.lock();
+        if !nodes.contains_key(&from) {
+            return Err(not_found(&from));
+        }
+        require_parent(&nodes, &to)?;
+        if let Some(existing) = nodes.get(&to) {
+            let replaceable = matches!((existing, &nodes[&from]), (Node::File(_), Node::File(_)));
+            if !replaceable {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{:?} already exists", to),
+                ));
+            }
+        }
+        // The entry and everything below it
+        let moving: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        for path in moving {
+            if let Some(node) = nodes.remove(&path) {
+                let dest = match path.strip_prefix(&from) {
+                    Ok(relative) if !relative.as_os_str().is_empty() => to.join(relative),
+                    _ => to.clone(),
+                };
+                nodes.insert(dest, node);
+            }
+        }
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut nodes = self.lock();
+        let Some(Node::File(meta)) = nodes.get(&from).cloned() else {
+            return Err(not_found(&from));
+        };
+        require_parent(&nodes, &to)?;
+        nodes.insert(to, Node::File(meta));
+        Ok(meta.len)
+    }
+}
+
+/// A change an `OverlayFs` made on top of the disk
+#[derive(Debug, Clone)]
+enum Change {
+    Dir,
+    File(Metadata),
+    /// Holds what is on disk at this path (a folder or file moved here)
+    Moved(PathBuf),
+    Removed,
+}
+
+/// Where a path of an `OverlayFs` leads
+enum Resolved {
+    Real(PathBuf),
+    Virtual(Metadata),
+    Missing,
+}
+
+/// The disk as it would be after a run, without touching it: reads go to
+/// the disk, and every change (folders created, entries moved, copied or
+/// removed) is only recorded. Dry runs apply their plan to one of these, so
+/// later moves see the folders and names earlier ones would have created.
+#[derive(Debug, Default)]
+pub struct OverlayFs {
+    changes: Mutex<BTreeMap<PathBuf, Change>>,
+}
+
+impl OverlayFs {
+    pub fn new() -> Self {
+        OverlayFs::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Change>> {
+        self.changes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The most specific change at or above `path` decides where it leads
+fn resolve(changes: &BTreeMap<PathBuf, Change>, path: &Path) -> Resolved {
+    for ancestor in path.ancestors() {
+        let Some(change) = changes.get(ancestor) else {
+            continue;
+        };
+        let exact = ancestor == path;
+        return match change {
+            Change::Removed => Resolved::Missing,
+            Change::Dir if exact => Resolved::Virtual(Metadata {
+                is_dir: true,
+                len: 0,
+                modified: SystemTime::UNIX_EPOCH,
+            }),
+            Change::File(meta) if exact => Resolved::Virtual(*meta),
+            Change::Dir | Change::File(_) => Resolved::Missing,
+            Change::Moved(real) => match path.strip_prefix(ancestor) {
+                Ok(rest) if !rest.as_os_str().is_empty() => Resolved::Real(real.join(rest)),
+                _ => Resolved::Real(real.clone()),
+            },
+        };
+    }
+    Resolved::Real(path.to_path_buf())
+}
+
+fn metadata_at(changes: &BTreeMap<PathBuf, Change>, path: &Path) -> io::Result<Metadata> {
+    match resolve(changes, path) {
+        Resolved::Real(real) => RealFs::default().metadata(&real),
+        Resolved::Virtual(meta) => Ok(meta),
+        Resolved::Missing => Err(not_found(path)),
+    }
+}
+
+/// Records `change` at `path`, replacing whatever was recorded below it
+fn record(changes: &mut BTreeMap<PathBuf, Change>, path: &Path, change: Change) {
+    changes.retain(|p, _| !p.starts_with(path));
+    changes.insert(path.to_path_buf(), change);
+}
+
+impl FileSystem for OverlayFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        let dir = normalize(dir);
+        let changes = self.lock();
+        let mut names: Vec<OsString> = match resolve(&changes, &dir) {
+            Resolved::Real(real) => RealFs::default()
+                .read_dir(&real)?
+                .into_iter()
+                .map(|e| e.name)
+                .collect(),
+            Resolved::Virtual(meta) if meta.is_dir => Vec::new(),
+            Resolved::Virtual(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("{:?} is a file", dir),
+                ));
+            }
+            Resolved::Missing => return Err(not_found(&dir)),
+        };
+        names.extend(
+            changes
+                .keys()
+                .filter(|path| path.parent() == Some(dir.as_path()))
+                .filter_map(|path| path.file_name().map(|n| n.to_owned())),
+        );
+        names.sort();
+        names.dedup();
+        Ok(names
+            .into_iter()
+            .filter_map(|name| {
+                let path = dir.join(&name);
+                let meta = metadata_at(&changes, &path).ok()?;
+                Some(DirEntry {
+                    path,
+                    name,
+                    is_dir: meta.is_dir,
+                })
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        metadata_at(&self.lock(), &normalize(path))
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        let mut changes = self.lock();
+        let missing: Vec<PathBuf> = dir
+            .ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .take_while(|a| metadata_at(&changes, a).is_err())
+            .map(Path::to_path_buf)
+            .collect();
+        for ancestor in dir.ancestors().skip(missing.len()) {
+            if metadata_at(&changes, ancestor).is_ok_and(|m| !m.is_dir) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{:?} is a file", ancestor),
+                ));
+            }
+        }
+        for path in missing.iter().rev() {
+            record(&mut changes, path, Change::Dir);
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, dir: &Path) -> io::Result<()> {
+        let dir = normalize(dir);
+        if !self.read_dir(&dir)?.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{:?} is not empty", dir),
+            ));
+        }
+        record(&mut self.lock(), &dir, Change::Removed);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut changes = self.lock();
+        if metadata_at(&changes, &path)?.is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{:?} is a folder", path),
+            ));
+        }
+        record(&mut changes, &path, Change::Removed);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut changes = self.lock();
+        let source = metadata_at(&changes, &from)?;
+        if let Some(parent) = to.parent().filter(|p| !p.as_os_str().is_empty()) {
+            match metadata_at(&changes, parent) {
+                Ok(meta) if meta.is_dir => {}
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotADirectory,
+                        format!("{:?} is a file", parent),
+                    ));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if let Ok(existing) = metadata_at(&changes, &to)
+            && (existing.is_dir || source.is_dir)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists", to),
+            ));
+        }
+
+        // What is there now, and what was changed below it, moves along
+        let moved = match resolve(&changes, &from) {
+            Resolved::Real(real) => Change::Moved(real),
+            _ if source.is_dir => Change::Dir,
+            _ => Change::File(source),
+        };
+        let below: Vec<(PathBuf, Change)> = changes
+            .iter()
+            .filter(|(path, _)| path.starts_with(&from) && **path != from)
+            .filter_map(|(path, change)| {
+                let rest = path.strip_prefix(&from).ok()?;
+                Some((to.join(rest), change.clone()))
+            })
+            .collect();
+        record(&mut changes, &from, Change::Removed);
+        record(&mut changes, &to, moved);
+        changes.extend(below);
+        Ok(())
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut changes = self.lock();
+        let source = metadata_at(&changes, &from)?;
+        if source.is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{:?} is a folder", from),
+            ));
+        }
+        record(&mut changes, &to, Change::File(source));
+        Ok(source.len)
+    }
+}
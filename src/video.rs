@@ -0,0 +1,259 @@
+use crate::config::VideoConfig;
+use crate::glob;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Default name patterns of screen recorders (macOS, Windows, GNOME, OBS, ...)
+const BUILTIN_RECORDING_PATTERNS: &[&str] = &[
+    "screen recording*",
+    "screenrecording*",
+    "screencast*",
+    "screen capture*",
+    "screen_recording*",
+    "bildschirmaufnahme*",
+    "captura de pantalla*",
+    "enregistrement de l*",
+    "recording *",
+    "kooha-*",
+    "simplescreenrecorder-*",
+    "vokoscreen*",
+    // OBS default: "2024-05-01 12-30-45.mkv"
+    "[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9] [0-9][0-9]-[0-9][0-9]-[0-9][0-9].*",
+];
+
+/// Encoder/handler strings screen recorders leave in MP4/MOV metadata
+const RECORDER_MARKERS: &[&[u8]] = &[
+    b"ReplayKit",
+    b"Screen Recording",
+    b"ScreenCaptureKit",
+    b"OBS Studio",
+    b"obs-output",
+    b"Xbox Game Bar",
+];
+
+/// Upper bound on how much of the `moov` box is read looking for metadata
+const MAX_MOOV_BYTES: u64 = 8 * 1024 * 1024;
+
+/// What lightweight parsing of an MP4/MOV container found
+#[derive(Debug, Default)]
+pub struct VideoInfo {
+    pub duration_secs: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Metadata a screen recorder left
+    pub recorder_marker: bool,
+}
+
+/// Whether `name` looks like a screen recorder's output ("Screen Recording ...")
+pub fn is_recording_name(name: &str, config: &VideoConfig) -> bool {
+    match &config.recording_patterns {
+        Some(patterns) => patterns.iter().any(|p| glob::matches(p, name)),
+        None => BUILTIN_RECORDING_PATTERNS
+            .iter()
+            .any(|p| glob::matches(p, name)),
+    }
+}
+
+/// Picks `recordings` or `movies` for a video, or `None` to leave it in `video`.
+///
+/// Signals, strongest first: a screen-recorder file name, recorder metadata in
+/// the container, then duration (long -> movie) and a 16:10 desktop-shaped frame
+/// (short and 16:10 -> recording). Only MP4/MOV/M4V are parsed; other containers
+/// are judged by name alone.
+pub fn subcategory(path: &Path, config: &VideoConfig) -> Option<&'static str> {
+    if is_recording_name(path.file_name()?.to_str()?, config) {
+        return Some("recordings");
+    }
+
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    if !matches!(ext.as_str(), "mp4" | "mov" | "m4v") {
+        return None;
+    }
+    let info = read_mp4_info(path).ok()?;
+
+    if info.recorder_marker {
+        return Some("recordings");
+    }
+    let min_movie_secs = config.movie_min_minutes * 60;
+    if info.duration_secs.is_some_and(|d| d >= min_movie_secs) {
+        return Some("movies");
+    }
+    if let (Some(w), Some(h)) = (info.width, info.height)
+        && is_desktop_shaped(w, h)
+    {
+        return Some("recordings");
+    }
+    None
+}
+
+/// What the `moov` box of an MP4/MOV/M4V tells: duration, frame size
+pub fn probe(path: &Path) -> io::Result<VideoInfo> {
+    read_mp4_info(path)
+}
+
+/// 16:10 frames are typical of laptop screens and rare for camera or film footage
+fn is_desktop_shaped(width: u32, height: u32) -> bool {
+    let (w, h) = (width.max(height) as u64, width.min(height) as u64);
+    h > 0 && w * 10 == h * 16
+}
+
+fn read_mp4_info(path: &Path) -> io::Result<VideoInfo> {
+    let mut file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut info = VideoInfo::default();
+
+    // Walk top-level boxes; `moov` may come before or after the media data
+    let mut offset = 0u64;
+    while file_len.saturating_sub(offset) >= 8 {
+        file.seek(SeekFrom::Start(offset))?;
+        let (box_type, header_len, box_len) = read_box_header(&mut file, file_len - offset)?;
+        if box_len < header_len {
+            break;
+        }
+
+        if &box_type == b"moov" {
+            let body_len = (box_len - header_len).min(MAX_MOOV_BYTES);
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body)?;
+            parse_moov(&body, &mut info);
+            info.recorder_marker = RECORDER_MARKERS
+                .iter()
+                .any(|marker| body.windows(marker.len()).any(|w| w == *marker));
+            break;
+        }
+        // A crafted length can run past any offset there is
+        let Some(next) = offset.checked_add(box_len) else {
+            break;
+        };
+        offset = next;
+    }
+
+    Ok(info)
+}
+
+/// Returns (type, header length, total box length)
+fn read_box_header(file: &mut File, remaining: u64) -> io::Result<([u8; 4], u64, u64)> {
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header)?;
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let box_type = [header[4], header[5], header[6], header[7]];
+
+    match size {
+        0 => Ok((box_type, 8, remaining)), // Extends to end of file
+        1 => {
+            let mut large = [0u8; 8];
+            file.read_exact(&mut large)?;
+            Ok((box_type, 16, u64::from_be_bytes(large)))
+        }
+        _ => Ok((box_type, 8, size)),
+    }
+}
+
+/// Reads duration from `mvhd` and the frame size from the first video `tkhd`
+fn parse_moov(moov: &[u8], info: &mut VideoInfo) {
+    for (box_type, body) in child_boxes(moov) {
+        match &box_type {
+            b"mvhd" => info.duration_secs = parse_mvhd(body),
+            b"trak" if info.width.is_none() => {
+                for (child_type, child) in child_boxes(body) {
+                    if &child_type == b"tkhd"
+                        && let Some((w, h)) = parse_tkhd(child)
+                        && w > 0
+                        && h > 0
+                    {
+                        info.width = Some(w);
+                        info.height = Some(h);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn child_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut offset = 0usize;
+    while data.len().saturating_sub(offset) >= 8 {
+        let size = be_u32(data, offset) as usize;
+        let box_type = [
+            data[offset + 4],
+            data[offset + 5],
+            data[offset + 6],
+            data[offset + 7],
+        ];
+        let Some(end) = offset.checked_add(size) else {
+            break;
+        };
+        if size < 8 || end > data.len() {
+            break;
+        }
+        boxes.push((box_type, &data[offset + 8..end]));
+        offset = end;
+    }
+    boxes
+}
+
+fn parse_mvhd(body: &[u8]) -> Option<u64> {
+    let version = *body.first()?;
+    let (timescale, duration) = if version == 1 {
+        (be_u32_checked(body, 20)? as u64, be_u64_checked(body, 24)?)
+    } else {
+        (
+            be_u32_checked(body, 12)? as u64,
+            be_u32_checked(body, 16)? as u64,
+        )
+    };
+    (timescale > 0).then(|| duration / timescale)
+}
+
+/// Width and height are 16.16 fixed point at the end of the box
+fn parse_tkhd(body: &[u8]) -> Option<(u32, u32)> {
+    let version = *body.first()?;
+    let offset = if version == 1 { 88 } else { 76 };
+    let width = be_u32_checked(body, offset)? >> 16;
+    let height = be_u32_checked(body, offset + 4)? >> 16;
+    Some((width, height))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn be_u32_checked(data: &[u8], offset: usize) -> Option<u32> {
+    (offset + 4 <= data.len()).then(|| be_u32(data, offset))
+}
+
+fn be_u64_checked(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn huge_box_lengths_end_the_walk() {
+        let path = std::env::temp_dir().join("auto-organize-video-huge-box.mp4");
+        // An empty box, then one with a 64-bit length that runs past the
+        // end of any offset
+        let mut data = vec![0, 0, 0, 8];
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(b"free");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        data.extend_from_slice(&[0; 16]);
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(probe(&path).unwrap().duration_secs, None);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1678 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A WebAssembly page of linear memory
+pub const PAGE_SIZE: usize = 64 * 1024;
+/// Memory a module may have, in pages: 16 MiB
+const MAX_PAGES: usize = 256;
+/// Table entries a module may have
+const MAX_TABLE: usize = 100_000;
+/// Locals one function may declare
+const MAX_LOCALS: usize = 50_000;
+/// Instructions one call may run before it is stopped
+const FUEL: u64 = 100_000_000;
+/// Calls that may be nested
+const MAX_DEPTH: usize = 1000;
+/// What a null reference is held as (function references are their index)
+const NULL_REF: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+    FuncRef,
+    ExternRef,
+}
+
+impl ValType {
+    fn from_byte(b: u8) -> Result<ValType, String> {
+        match b {
+            0x7f => Ok(ValType::I32),
+            0x7e => Ok(ValType::I64),
+            0x7d => Ok(ValType::F32),
+            0x7c => Ok(ValType::F64),
+            0x70 => Ok(ValType::FuncRef),
+            0x6f => Ok(ValType::ExternRef),
+            _ => Err(format!("unsupported value type 0x{:02x}", b)),
+        }
+    }
+
+    /// What a local or table slot of this type starts as
+    fn zero(self) -> u64 {
+        match self {
+            ValType::FuncRef | ValType::ExternRef => NULL_REF,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FuncType {
+    params: Vec<ValType>,
+    results: Vec<ValType>,
+}
+
+/// Where a `block`, `loop` or `if` ends (and where an `if`'s `else` is),
+/// found once when the module is read
+#[derive(Debug, Clone, Copy)]
+struct BlockEnds {
+    else_at: Option<usize>,
+    end_at: usize,
+}
+
+struct Function {
+    ty: usize,
+    /// Declared locals, after the parameters
+    locals: Vec<ValType>,
+    /// The body's instructions, ending with its `end`
+    code: Vec<u8>,
+    blocks: HashMap<usize, BlockEnds>,
+}
+
+struct Global {
+    ty: ValType,
+    /// A constant expression, ending with `end`
+    init: Vec<u8>,
+}
+
+struct Element {
+    /// Table offset of an active segment; `None` for passive and declarative ones
+    offset: Option<Vec<u8>>,
+    /// Function indices, `None` for null references
+    items: Vec<Option<u32>>,
+}
+
+struct Data {
+    /// Memory offset of an active segment; `None` for a passive one
+    offset: Option<Vec<u8>>,
+    bytes: Vec<u8>,
+}
+
+/// A WebAssembly module, read and checked enough to run: the MVP
+/// instruction set with sign extension, saturating conversions, bulk memory
+/// and reference types (one table and one memory), no SIMD or threads.
+///
+/// Modules get nothing from outside: one that imports anything is refused,
+/// so code run from it sees only its own memory and the bytes it is given.
+pub struct Module {
+    types: Vec<FuncType>,
+    functions: Vec<Function>,
+    table: Option<(u32, Option<u32>)>,
+    memory: Option<(u32, Option<u32>)>,
+    globals: Vec<Global>,
+    exports: HashMap<String, (u8, u32)>,
+    start: Option<u32>,
+    elements: Vec<Element>,
+    data: Vec<Data>,
+}
+
+const EXPORT_FUNC: u8 = 0x00;
+
+impl Module {
+    pub fn parse(bytes: &[u8]) -> Result<Module, String> {
+        let mut r = Reader::new(bytes);
+        if r.bytes(4)? != b"\0asm" {
+            return Err("not a WebAssembly module".to_string());
+        }
+        if r.bytes(4)? != [1, 0, 0, 0] {
+            return Err("not a version 1 WebAssembly module".to_string());
+        }
+        let mut module = Module {
+            types: Vec::new(),
+            functions: Vec::new(),
+            table: None,
+            memory: None,
+            globals: Vec::new(),
+            exports: HashMap::new(),
+            start: None,
+            elements: Vec::new(),
+            data: Vec::new(),
+        };
+        let mut function_types = Vec::new();
+        while !r.at_end() {
+            let id = r.byte()?;
+            let size = r.u32()? as usize;
+            let mut s = Reader::new(r.bytes(size)?);
+            match id {
+                0 | 12 => {}
+                1 => {
+                    for _ in 0..s.u32()? {
+                        if s.byte()? != 0x60 {
+                            return Err("malformed function type".to_string());
+                        }
+                        let params = s.val_types()?;
+                        let results = s.val_types()?;
+                        module.types.push(FuncType { params, results });
+                    }
+                }
+                2 => {
+                    if s.u32()? > 0 {
+                        let from = s.name()?;
+                        let name = s.name()?;
+                        return Err(format!(
+                            "it imports {}.{}, and modules get nothing from outside",
+                            from, name
+                        ));
+                    }
+                }
+                3 => {
+                    for _ in 0..s.u32()? {
+                        function_types.push(s.u32()? as usize);
+                    }
+                }
+                4 => {
+                    for _ in 0..s.u32()? {
+                        ValType::from_byte(s.byte()?)?;
+                        if module.table.replace(s.limits()?).is_some() {
+                            return Err("more than one table".to_string());
+                        }
+                    }
+                }
+                5 => {
+                    for _ in 0..s.u32()? {
+                        if module.memory.replace(s.limits()?).is_some() {
+                            return Err("more than one memory".to_string());
+                        }
+                    }
+                }
+                6 => {
+                    for _ in 0..s.u32()? {
+                        let ty = ValType::from_byte(s.byte()?)?;
+                        s.byte()?;
+                        let init = s.const_expr()?;
+                        module.globals.push(Global { ty, init });
+                    }
+                }
+                7 => {
+                    for _ in 0..s.u32()? {
+                        let name = s.name()?;
+                        let kind = s.byte()?;
+                        let index = s.u32()?;
+                        module.exports.insert(name, (kind, index));
+                    }
+                }
+                8 => module.start = Some(s.u32()?),
+                9 => {
+                    for _ in 0..s.u32()? {
+                        module.elements.push(s.element()?);
+                    }
+                }
+                10 => {
+                    let count = s.u32()? as usize;
+                    if count != function_types.len() {
+                        return Err("function and code sections differ".to_string());
+                    }
+                    for &ty in &function_types {
+                        if ty >= module.types.len() {
+                            return Err(format!("unknown type {}", ty));
+                        }
+                        let size = s.u32()? as usize;
+                        module.functions.push(Function::parse(ty, s.bytes(size)?)?);
+                    }
+                }
+                11 => {
+                    for _ in 0..s.u32()? {
+                        let (offset, bytes) = match s.u32()? {
+                            0 => (Some(s.const_expr()?), s.byte_vec()?),
+                            1 => (None, s.byte_vec()?),
+                            2 => {
+                                s.u32()?;
+                                (Some(s.const_expr()?), s.byte_vec()?)
+                            }
+                            flags => return Err(format!("unsupported data segment {}", flags)),
+                        };
+                        module.data.push(Data { offset, bytes });
+                    }
+                }
+                _ => return Err(format!("unknown section {}", id)),
+            }
+        }
+        if module.functions.len() != function_types.len() {
+            return Err("functions without code".to_string());
+        }
+        Ok(module)
+    }
+
+    /// Whether the module exports a function called `name`
+    pub fn exports_function(&self, name: &str) -> bool {
+        self.exported_function(name).is_some()
+    }
+
+    fn exported_function(&self, name: &str) -> Option<u32> {
+        match self.exports.get(name)? {
+            (EXPORT_FUNC, index) if (*index as usize) < self.functions.len() => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+impl Function {
+    fn parse(ty: usize, body: &[u8]) -> Result<Function, String> {
+        let mut r = Reader::new(body);
+        let mut locals = Vec::new();
+        for _ in 0..r.u32()? {
+            let count = r.u32()? as usize;
+            let ty = ValType::from_byte(r.byte()?)?;
+            if locals.len() + count > MAX_LOCALS {
+                return Err(format!("a function with over {} locals", MAX_LOCALS));
+            }
+            locals.extend(std::iter::repeat_n(ty, count));
+        }
+        let code = r.rest().to_vec();
+        let blocks = block_ends(&code)?;
+        Ok(Function {
+            ty,
+            locals,
+            code,
+            blocks,
+        })
+    }
+}
+
+/// Finds where each block of a function body ends, checking on the way that
+/// every instruction is one that can be run
+fn block_ends(code: &[u8]) -> Result<HashMap<usize, BlockEnds>, String> {
+    let mut open: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut ends = HashMap::new();
+    let mut r = Reader::new(code);
+    while !r.at_end() {
+        let at = r.pos;
+        match r.byte()? {
+            0x02..=0x04 => {
+                r.block_type()?;
+                open.push((at, None));
+            }
+            0x05 => match open.last_mut() {
+                Some((_, else_at)) => *else_at = Some(at),
+                None => return Err("else outside of an if".to_string()),
+            },
+            0x0b => match open.pop() {
+                Some((start, else_at)) => {
+                    ends.insert(
+                        start,
+                        BlockEnds {
+                            else_at,
+                            end_at: at,
+                        },
+                    );
+                }
+                None if r.at_end() => return Ok(ends),
+                None => return Err("instructions after a function's end".to_string()),
+            },
+            op => r.immediates(op)?,
+        }
+    }
+    Err("a function without an end".to_string())
+}
+
+/// Reads the parts of a module: LEB128 numbers, names, vectors
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self.data.get(self.pos).ok_or("unexpected end")?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).ok_or("unexpected end")?;
+        let bytes = self.data.get(self.pos..end).ok_or("unexpected end")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        self.pos = self.data.len();
+        rest
+    }
+
+    fn unsigned(&mut self, bits: u32) -> Result<u64, String> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift >= bits {
+                return Err("integer too large".to_string());
+            }
+            value |= ((b & 0x7f) as u64) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn signed(&mut self, bits: u32) -> Result<i64, String> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift >= bits {
+                return Err("integer too large".to_string());
+            }
+            value |= ((b & 0x7f) as i64) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(self.unsigned(32)? as u32)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let bytes = self.byte_vec()?;
+        String::from_utf8(bytes).map_err(|_| "a name that is not UTF-8".to_string())
+    }
+
+    fn byte_vec(&mut self) -> Result<Vec<u8>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.bytes(len)?.to_vec())
+    }
+
+    fn val_types(&mut self) -> Result<Vec<ValType>, String> {
+        (0..self.u32()?)
+            .map(|_| ValType::from_byte(self.byte()?))
+            .collect()
+    }
+
+    fn limits(&mut self) -> Result<(u32, Option<u32>), String> {
+        match self.byte()? {
+            0x00 => Ok((self.u32()?, None)),
+            0x01 => Ok((self.u32()?, Some(self.u32()?))),
+            _ => Err("unsupported limits (shared or 64-bit memory)".to_string()),
+        }
+    }
+
+    /// A block's type: none, one result, or an index into the type section
+    fn block_type(&mut self) -> Result<BlockType, String> {
+        match self.data.get(self.pos) {
+            Some(0x40) => {
+                self.pos += 1;
+                Ok(BlockType::Empty)
+            }
+            Some(&b) if ValType::from_byte(b).is_ok() => {
+                self.pos += 1;
+                Ok(BlockType::Value)
+            }
+            _ => {
+                let index = self.signed(33)?;
+                usize::try_from(index)
+                    .map(BlockType::Type)
+                    .map_err(|_| "malformed block type".to_string())
+            }
+        }
+    }
+
+    /// An initializer (up to and with its `end`)
+    fn const_expr(&mut self) -> Result<Vec<u8>, String> {
+        let start = self.pos;
+        loop {
+            match self.byte()? {
+                0x0b => return Ok(self.data[start..self.pos].to_vec()),
+                op => self.immediates(op)?,
+            }
+        }
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        let flags = self.u32()?;
+        if flags > 7 {
+            return Err(format!("unsupported element segment {}", flags));
+        }
+        let active = flags & 1 == 0;
+        let offset = if active {
+            if flags & 2 != 0 && self.u32()? != 0 {
+                return Err("more than one table".to_string());
+            }
+            Some(self.const_expr()?)
+        } else {
+            None
+        };
+        // Segments other than the MVP's say what they hold
+        if flags & 3 != 0 {
+            self.byte()?;
+        }
+        let mut items = Vec::new();
+        for _ in 0..self.u32()? {
+            if flags & 4 == 0 {
+                items.push(Some(self.u32()?));
+                continue;
+            }
+            let expr = self.const_expr()?;
+            items.push(match expr.first() {
+                Some(0xd2) => Some(Reader::new(&expr[1..]).u32()?),
+                Some(0xd0) => None,
+                _ => return Err("unsupported element expression".to_string()),
+            });
+        }
+        Ok(Element { offset, items })
+    }
+
+    /// Skips the immediates of instruction `op`; fails on one that cannot be run
+    fn immediates(&mut self, op: u8) -> Result<(), String> {
+        match op {
+            0x00 | 0x01 | 0x05 | 0x0b | 0x0f | 0x1a | 0x1b | 0x45..=0xc4 | 0xd1 => {}
+            0x02..=0x04 => {
+                self.block_type()?;
+            }
+            0x0c | 0x0d | 0x10 | 0x20..=0x26 | 0xd2 => {
+                self.u32()?;
+            }
+            0x0e => {
+                for _ in 0..=self.u32()? {
+                    self.u32()?;
+                }
+            }
+            0x11 | 0x28..=0x3e => {
+                self.u32()?;
+                self.u32()?;
+            }
+            0x1c => {
+                self.val_types()?;
+            }
+            0x3f | 0x40 | 0xd0 => {
+                self.byte()?;
+            }
+            0x41 => {
+                self.signed(32)?;
+            }
+            0x42 => {
+                self.signed(64)?;
+            }
+            0x43 => {
+                self.bytes(4)?;
+            }
+            0x44 => {
+                self.bytes(8)?;
+            }
+            0xfc => match self.u32()? {
+                0..=7 => {}
+                8 => {
+                    self.u32()?;
+                    self.byte()?;
+                }
+                9 | 13 | 15..=17 => {
+                    self.u32()?;
+                }
+                10 => {
+                    self.bytes(2)?;
+                }
+                11 => {
+                    self.byte()?;
+                }
+                12 | 14 => {
+                    self.u32()?;
+                    self.u32()?;
+                }
+                sub => return Err(format!("unsupported instruction 0xfc {}", sub)),
+            },
+            _ => return Err(format!("unsupported instruction 0x{:02x}", op)),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BlockType {
+    Empty,
+    Value,
+    Type(usize),
+}
+
+/// A call in progress
+struct Frame {
+    func: usize,
+    pc: usize,
+    locals: Vec<u64>,
+    /// Labels of enclosing calls are below this
+    labels_base: usize,
+    /// Values of enclosing calls are below this
+    stack_base: usize,
+}
+
+/// Where a branch out of a block goes
+struct Label {
+    /// Values below the block's
+    height: usize,
+    /// Values a branch carries: a loop's parameters, else the block's results
+    arity: usize,
+    target: usize,
+}
+
+/// A module ready to run: its memory, globals and table. Cloning gives an
+/// independent copy, to run from the same starting state again.
+#[derive(Clone)]
+pub struct Instance {
+    module: Arc<Module>,
+    memory: Vec<u8>,
+    max_pages: usize,
+    globals: Vec<u64>,
+    table: Vec<u64>,
+    max_table: usize,
+    /// Passive segments, emptied when dropped
+    elements: Vec<Vec<Option<u32>>>,
+    data: Vec<Vec<u8>>,
+    /// Instructions each call may run
+    fuel: u64,
+}
+
+impl Instance {
+    /// Sets up memory, globals and the table, and runs the start function
+    pub fn new(module: Arc<Module>) -> Result<Instance, String> {
+        let (pages, max_pages) = match module.memory {
+            Some((min, max)) => (
+                min as usize,
+                max.map_or(MAX_PAGES, |max| (max as usize).min(MAX_PAGES)),
+            ),
+            None => (0, 0),
+        };
+        if pages > MAX_PAGES {
+            return Err(format!(
+                "it needs {} MiB of memory, more than the {} MiB it may have",
+                (pages * PAGE_SIZE) >> 20,
+                (MAX_PAGES * PAGE_SIZE) >> 20
+            ));
+        }
+        let (slots, max_table) = match module.table {
+            Some((min, max)) => (
+                min as usize,
+                max.map_or(MAX_TABLE, |max| (max as usize).min(MAX_TABLE)),
+            ),
+            None => (0, 0),
+        };
+        if slots > MAX_TABLE {
+            return Err(format!("a table of over {} entries", MAX_TABLE));
+        }
+        let mut instance = Instance {
+            memory: vec![0; pages * PAGE_SIZE],
+            max_pages,
+            globals: Vec::new(),
+            table: vec![NULL_REF; slots],
+            max_table,
+            elements: Vec::new(),
+            data: Vec::new(),
+            fuel: FUEL,
+            module: Arc::clone(&module),
+        };
+        for global in &module.globals {
+            let value = instance.eval(&global.init)?;
+            instance.globals.push(value);
+        }
+        for element in &module.elements {
+            let Some(offset) = &element.offset else {
+                instance.elements.push(element.items.clone());
+                continue;
+            };
+            let at = instance.eval(offset)? as u32 as usize;
+            let slots = instance
+                .table
+                .get_mut(at..at + element.items.len())
+                .ok_or("an element segment past the end of the table")?;
+            for (slot, item) in slots.iter_mut().zip(&element.items) {
+                *slot = item.map_or(NULL_REF, u64::from);
+            }
+            instance.elements.push(Vec::new());
+        }
+        for data in &module.data {
+            let Some(offset) = &data.offset else {
+                instance.data.push(data.bytes.clone());
+                continue;
+            };
+            let at = instance.eval(offset)? as u32 as usize;
+            instance
+                .memory
+                .get_mut(at..at + data.bytes.len())
+                .ok_or("a data segment past the end of memory")?
+                .copy_from_slice(&data.bytes);
+            instance.data.push(Vec::new());
+        }
+        if let Some(start) = module.start {
+            instance.run(start as usize, Vec::new())?;
+        }
+        Ok(instance)
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Calls the exported function `name` with integer arguments (an `i32`
+    /// as its bits, zero-extended), returning its results the same way
+    pub fn call(&mut self, name: &str, args: &[u64]) -> Result<Vec<u64>, String> {
+        let func = self
+            .module
+            .exported_function(name)
+            .ok_or_else(|| format!("no exported function {:?}", name))?;
+        let ty = &self.module.types[self.module.functions[func as usize].ty];
+        if ty.params.len() != args.len() {
+            return Err(format!(
+                "{} takes {} argument(s), not {}",
+                name,
+                ty.params.len(),
+                args.len()
+            ));
+        }
+        let args = args
+            .iter()
+            .zip(&ty.params)
+            .map(|(&arg, ty)| {
+                if *ty == ValType::I32 {
+                    arg as u32 as u64
+                } else {
+                    arg
+                }
+            })
+            .collect();
+        self.run(func as usize, args)
+    }
+
+    /// Evaluates a constant expression
+    fn eval(&self, expr: &[u8]) -> Result<u64, String> {
+        let mut r = Reader::new(expr);
+        let mut stack: Vec<u64> = Vec::new();
+        loop {
+            let value = match r.byte()? {
+                0x0b => {
+                    return stack
+                        .pop()
+                        .ok_or_else(|| "an empty initializer".to_string());
+                }
+                0x41 => r.signed(32)? as i32 as u32 as u64,
+                0x42 => r.signed(64)? as u64,
+                0x43 => u32::from_le_bytes(r.bytes(4)?.try_into().unwrap()) as u64,
+                0x44 => u64::from_le_bytes(r.bytes(8)?.try_into().unwrap()),
+                0x23 => *self
+                    .globals
+                    .get(r.u32()? as usize)
+                    .ok_or("an initializer reading a later global")?,
+                0xd0 => {
+                    r.byte()?;
+                    NULL_REF
+                }
+                0xd2 => r.u32()? as u64,
+                op @ (0x6a..=0x6c | 0x7c..=0x7e) => {
+                    let (b, a) = (stack.pop(), stack.pop());
+                    let (Some(a), Some(b)) = (a, b) else {
+                        return Err("a malformed initializer".to_string());
+                    };
+                    match op {
+                        0x6a => (a as u32).wrapping_add(b as u32) as u64,
+                        0x6b => (a as u32).wrapping_sub(b as u32) as u64,
+                        0x6c => (a as u32).wrapping_mul(b as u32) as u64,
+                        0x7c => a.wrapping_add(b),
+                        0x7d => a.wrapping_sub(b),
+                        _ => a.wrapping_mul(b),
+                    }
+                }
+                op => return Err(format!("unsupported initializer instruction 0x{:02x}", op)),
+            };
+            stack.push(value);
+        }
+    }
+
+    /// Runs function `func` to its end. Calls are frames on a stack of their
+    /// own, not Rust calls, so deep recursion in a module cannot overflow ours.
+    fn run(&mut self, func: usize, args: Vec<u64>) -> Result<Vec<u64>, String> {
+        let module = Arc::clone(&self.module);
+        let module: &Module = &module;
+        let mut stack: Vec<u64> = args;
+        let mut labels: Vec<Label> = Vec::new();
+        let mut calls: Vec<Frame> = Vec::new();
+        let mut frame = enter(module, func, &mut stack, &labels)?;
+        let mut fuel = self.fuel;
+
+        macro_rules! pop {
+            () => {
+                stack.pop().ok_or("value stack underflow")?
+            };
+        }
+        macro_rules! pop_i32 {
+            () => {
+                pop!() as u32 as i32
+            };
+        }
+        macro_rules! pop_f32 {
+            () => {
+                f32::from_bits(pop!() as u32)
+            };
+        }
+        macro_rules! pop_f64 {
+            () => {
+                f64::from_bits(pop!())
+            };
+        }
+        macro_rules! push_i32 {
+            ($v:expr) => {
+                stack.push($v as u32 as u64)
+            };
+        }
+        macro_rules! push_f32 {
+            ($v:expr) => {
+                stack.push(f32::to_bits($v) as u64)
+            };
+        }
+        macro_rules! push_f64 {
+            ($v:expr) => {
+                stack.push(f64::to_bits($v))
+            };
+        }
+        macro_rules! i32_op {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop_i32!();
+                let $a = pop_i32!();
+                push_i32!($e)
+            }};
+        }
+        macro_rules! i64_op {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop!() as i64;
+                let $a = pop!() as i64;
+                stack.push($e as u64)
+            }};
+        }
+        macro_rules! f32_op {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop_f32!();
+                let $a = pop_f32!();
+                push_f32!($e)
+            }};
+        }
+        macro_rules! f64_op {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop_f64!();
+                let $a = pop_f64!();
+                push_f64!($e)
+            }};
+        }
+        macro_rules! f32_cmp {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop_f32!();
+                let $a = pop_f32!();
+                push_i32!($e)
+            }};
+        }
+        macro_rules! f64_cmp {
+            (|$a:ident, $b:ident| $e:expr) => {{
+                let $b = pop_f64!();
+                let $a = pop_f64!();
+                push_i32!($e)
+            }};
+        }
+
+        loop {
+            fuel = fuel
+                .checked_sub(1)
+                .ok_or("it ran for too long (out of fuel)")?;
+            let code = &module.functions[frame.func].code;
+            let mut r = Reader::new(code);
+            r.pos = frame.pc;
+            let at = r.pos;
+            let op = r.byte()?;
+            match op {
+                0x00 => return Err("unreachable executed".to_string()),
+                0x01 => {}
+                0x02..=0x04 => {
+                    let (params, results) = block_arity(module, r.block_type()?)?;
+                    let ends = module.functions[frame.func].blocks[&at];
+                    let taken = op != 0x04 || pop_i32!() != 0;
+                    let height = stack
+                        .len()
+                        .checked_sub(params)
+                        .ok_or("value stack underflow")?;
+                    match op {
+                        0x02 => labels.push(Label {
+                            height,
+                            arity: results,
+                            target: ends.end_at + 1,
+                        }),
+                        // Branching to a loop starts it again
+                        0x03 => labels.push(Label {
+                            height,
+                            arity: params,
+                            target: at,
+                        }),
+                        _ => {
+                            let label = Label {
+                                height,
+                                arity: results,
+                                target: ends.end_at + 1,
+                            };
+                            if taken {
+                                labels.push(label);
+                            } else if let Some(else_at) = ends.else_at {
+                                labels.push(label);
+                                r.pos = else_at + 1;
+                            } else {
+                                r.pos = ends.end_at + 1;
+                            }
+                        }
+                    }
+                }
+                // The end of an `if`'s first branch
+                0x05 => {
+                    let label = labels.pop().ok_or("else outside of an if")?;
+                    r.pos = label.target;
+                }
+                0x0b => {
+                    if labels.len() > frame.labels_base {
+                        labels.pop();
+                    } else {
+                        match leave(module, &mut frame, &mut calls, &mut stack, &mut labels) {
+                            Some(()) => continue,
+                            None => return Ok(stack),
+                        }
+                    }
+                }
+                0x0c..=0x0e => {
+                    let depth = match op {
+                        0x0c => r.u32()?,
+                        0x0d => {
+                            let depth = r.u32()?;
+                            if pop_i32!() == 0 {
+                                frame.pc = r.pos;
+                                continue;
+                            }
+                            depth
+                        }
+                        _ => {
+                            let count = r.u32()?;
+                            let mut targets = Vec::with_capacity(count as usize + 1);
+                            for _ in 0..=count {
+                                targets.push(r.u32()?);
+                            }
+                            let index = pop_i32!() as u32 as usize;
+                            targets[index.min(count as usize)]
+                        }
+                    } as usize;
+                    let inner = labels.len() - frame.labels_base;
+                    if depth >= inner {
+                        match leave(module, &mut frame, &mut calls, &mut stack, &mut labels) {
+                            Some(()) => continue,
+                            None => return Ok(stack),
+                        }
+                    }
+                    let index = labels.len() - 1 - depth;
+                    let label = &labels[index];
+                    let carried = stack
+                        .len()
+                        .checked_sub(label.arity)
+                        .filter(|&carried| carried >= label.height)
+                        .ok_or("value stack underflow")?;
+                    stack.drain(label.height..carried);
+                    r.pos = label.target;
+                    labels.truncate(index);
+                }
+                0x0f => match leave(module, &mut frame, &mut calls, &mut stack, &mut labels) {
+                    Some(()) => continue,
+                    None => return Ok(stack),
+                },
+                0x10 | 0x11 => {
+                    let callee = if op == 0x10 {
+                        r.u32()? as usize
+                    } else {
+                        let ty = module
+                            .types
+                            .get(r.u32()? as usize)
+                            .ok_or("call_indirect with an unknown type")?;
+                        r.u32()?;
+                        let index = pop_i32!() as u32 as usize;
+                        let callee = match self.table.get(index) {
+                            Some(&NULL_REF) => {
+                                return Err("call of an uninitialized table entry".to_string());
+                            }
+                            Some(&callee) => callee as usize,
+                            None => return Err("call past the end of the table".to_string()),
+                        };
+                        let actual = module.functions.get(callee).map(|f| &module.types[f.ty]);
+                        if actual != Some(ty) {
+                            return Err("indirect call of a function of another type".to_string());
+                        }
+                        callee
+                    };
+                    if calls.len() >= MAX_DEPTH {
+                        return Err("calls nested too deeply".to_string());
+                    }
+                    frame.pc = r.pos;
+                    let next = enter(module, callee, &mut stack, &labels)?;
+                    calls.push(std::mem::replace(&mut frame, next));
+                    continue;
+                }
+                0x1a => {
+                    pop!();
+                }
+                0x1b | 0x1c => {
+                    if op == 0x1c {
+                        r.val_types()?;
+                    }
+                    let condition = pop_i32!();
+                    let b = pop!();
+                    let a = pop!();
+                    stack.push(if condition != 0 { a } else { b });
+                }
+                0x20 => {
+                    let value = *frame.locals.get(r.u32()? as usize).ok_or("unknown local")?;
+                    stack.push(value);
+                }
+                0x21 | 0x22 => {
+                    let index = r.u32()? as usize;
+                    let value = if op == 0x21 {
+                        pop!()
+                    } else {
+                        *stack.last().ok_or("value stack underflow")?
+                    };
+                    *frame.locals.get_mut(index).ok_or("unknown local")? = value;
+                }
+                0x23 => {
+                    let value = *self
+                        .globals
+                        .get(r.u32()? as usize)
+                        .ok_or("unknown global")?;
+                    stack.push(value);
+                }
+                0x24 => {
+                    let index = r.u32()? as usize;
+                    let value = pop!();
+                    let ty = module.globals.get(index).ok_or("unknown global")?.ty;
+                    self.globals[index] = if ty == ValType::I32 || ty == ValType::F32 {
+                        value as u32 as u64
+                    } else {
+                        value
+                    };
+                }
+                0x25 => {
+                    r.u32()?;
+                    let index = pop_i32!() as u32 as usize;
+                    let value = *self.table.get(index).ok_or("table access out of bounds")?;
+                    stack.push(value);
+                }
+                0x26 => {
+                    r.u32()?;
+                    let value = pop!();
+                    let index = pop_i32!() as u32 as usize;
+                    *self
+                        .table
+                        .get_mut(index)
+                        .ok_or("table access out of bounds")? = value;
+                }
+                0x28..=0x35 => {
+                    r.u32()?;
+                    let offset = r.u32()? as u64;
+                    let address = pop_i32!() as u32 as u64 + offset;
+                    let value = match op {
+                        0x28 | 0x2a => self.load(address, 4)?,
+                        0x29 | 0x2b => self.load(address, 8)?,
+                        0x2c => self.load(address, 1)? as u8 as i8 as i32 as u32 as u64,
+                        0x2d | 0x31 => self.load(address, 1)?,
+                        0x2e => self.load(address, 2)? as u16 as i16 as i32 as u32 as u64,
+                        0x2f | 0x33 => self.load(address, 2)?,
+                        0x30 => self.load(address, 1)? as u8 as i8 as i64 as u64,
+                        0x32 => self.load(address, 2)? as u16 as i16 as i64 as u64,
+                        0x34 => self.load(address, 4)? as u32 as i32 as i64 as u64,
+                        _ => self.load(address, 4)?,
+                    };
+                    stack.push(value);
+                }
+                0x36..=0x3e => {
+                    r.u32()?;
+                    let offset = r.u32()? as u64;
+                    let value = pop!();
+                    let address = pop_i32!() as u32 as u64 + offset;
+                    let width = match op {
+                        0x36 | 0x38 | 0x3e => 4,
+                        0x37 | 0x39 => 8,
+                        0x3a | 0x3c => 1,
+                        _ => 2,
+                    };
+                    self.store(address, width, value)?;
+                }
+                0x3f => {
+                    r.byte()?;
+                    push_i32!(self.memory.len() / PAGE_SIZE);
+                }
+                0x40 => {
+                    r.byte()?;
+                    let delta = pop_i32!() as u32 as usize;
+                    let pages = self.memory.len() / PAGE_SIZE;
+                    if pages + delta <= self.max_pages {
+                        self.memory.resize((pages + delta) * PAGE_SIZE, 0);
+                        push_i32!(pages);
+                    } else {
+                        push_i32!(-1i32);
+                    }
+                }
+                0x41 => push_i32!(r.signed(32)?),
+                0x42 => stack.push(r.signed(64)? as u64),
+                0x43 => stack.push(u32::from_le_bytes(r.bytes(4)?.try_into().unwrap()) as u64),
+                0x44 => stack.push(u64::from_le_bytes(r.bytes(8)?.try_into().unwrap())),
+
+                0x45 => {
+                    let a = pop_i32!();
+                    push_i32!(a == 0)
+                }
+                0x46 => i32_op!(|a, b| a == b),
+                0x47 => i32_op!(|a, b| a != b),
+                0x48 => i32_op!(|a, b| a < b),
+                0x49 => i32_op!(|a, b| (a as u32) < b as u32),
+                0x4a => i32_op!(|a, b| a > b),
+                0x4b => i32_op!(|a, b| a as u32 > b as u32),
+                0x4c => i32_op!(|a, b| a <= b),
+                0x4d => i32_op!(|a, b| a as u32 <= b as u32),
+                0x4e => i32_op!(|a, b| a >= b),
+                0x4f => i32_op!(|a, b| a as u32 >= b as u32),
+                0x50 => {
+                    let a = pop!();
+                    push_i32!(a == 0)
+                }
+                0x51 => i64_op!(|a, b| a == b),
+                0x52 => i64_op!(|a, b| a != b),
+                0x53 => i64_op!(|a, b| a < b),
+                0x54 => i64_op!(|a, b| (a as u64) < b as u64),
+                0x55 => i64_op!(|a, b| a > b),
+                0x56 => i64_op!(|a, b| a as u64 > b as u64),
+                0x57 => i64_op!(|a, b| a <= b),
+                0x58 => i64_op!(|a, b| a as u64 <= b as u64),
+                0x59 => i64_op!(|a, b| a >= b),
+                0x5a => i64_op!(|a, b| a as u64 >= b as u64),
+                0x5b => f32_cmp!(|a, b| a == b),
+                0x5c => f32_cmp!(|a, b| a != b),
+                0x5d => f32_cmp!(|a, b| a < b),
+                0x5e => f32_cmp!(|a, b| a > b),
+                0x5f => f32_cmp!(|a, b| a <= b),
+                0x60 => f32_cmp!(|a, b| a >= b),
+                0x61 => f64_cmp!(|a, b| a == b),
+                0x62 => f64_cmp!(|a, b| a != b),
+                0x63 => f64_cmp!(|a, b| a < b),
+                0x64 => f64_cmp!(|a, b| a > b),
+                0x65 => f64_cmp!(|a, b| a <= b),
+                0x66 => f64_cmp!(|a, b| a >= b),
+
+                0x67 => {
+                    let a = pop_i32!();
+                    push_i32!(a.leading_zeros())
+                }
+                0x68 => {
+                    let a = pop_i32!();
+                    push_i32!(a.trailing_zeros())
+                }
+                0x69 => {
+                    let a = pop_i32!();
+                    push_i32!(a.count_ones())
+                }
+                0x6a => i32_op!(|a, b| a.wrapping_add(b)),
+                0x6b => i32_op!(|a, b| a.wrapping_sub(b)),
+                0x6c => i32_op!(|a, b| a.wrapping_mul(b)),
+                0x6d => i32_op!(|a, b| {
+                    if b == 0 {
+                        return Err("integer divide by zero".to_string());
+                    }
+                    a.checked_div(b).ok_or("integer overflow")?
+                }),
+                0x6e => i32_op!(|a, b| (a as u32)
+                    .checked_div(b as u32)
+                    .ok_or("integer divide by zero")?),
+                0x6f => i32_op!(|a, b| {
+                    if b == 0 {
+                        return Err("integer divide by zero".to_string());
+                    }
+                    a.wrapping_rem(b)
+                }),
+                0x70 => i32_op!(|a, b| (a as u32)
+                    .checked_rem(b as u32)
+                    .ok_or("integer divide by zero")?),
+                0x71 => i32_op!(|a, b| a & b),
+                0x72 => i32_op!(|a, b| a | b),
+                0x73 => i32_op!(|a, b| a ^ b),
+                0x74 => i32_op!(|a, b| a.wrapping_shl(b as u32)),
+                0x75 => i32_op!(|a, b| a.wrapping_shr(b as u32)),
+                0x76 => i32_op!(|a, b| (a as u32).wrapping_shr(b as u32)),
+                0x77 => i32_op!(|a, b| a.rotate_left(b as u32 % 32)),
+                0x78 => i32_op!(|a, b| a.rotate_right(b as u32 % 32)),
+
+                0x79 => {
+                    let a = pop!();
+                    stack.push(a.leading_zeros() as u64)
+                }
+                0x7a => {
+                    let a = pop!();
+                    stack.push(a.trailing_zeros() as u64)
+                }
+                0x7b => {
+                    let a = pop!();
+                    stack.push(a.count_ones() as u64)
+                }
+                0x7c => i64_op!(|a, b| a.wrapping_add(b)),
+                0x7d => i64_op!(|a, b| a.wrapping_sub(b)),
+                0x7e => i64_op!(|a, b| a.wrapping_mul(b)),
+                0x7f => i64_op!(|a, b| {
+                    if b == 0 {
+                        return Err("integer divide by zero".to_string());
+                    }
+                    a.checked_div(b).ok_or("integer overflow")?
+                }),
+                0x80 => i64_op!(|a, b| (a as u64)
+                    .checked_div(b as u64)
+                    .ok_or("integer divide by zero")?),
+                0x81 => i64_op!(|a, b| {
+                    if b == 0 {
+                        return Err("integer divide by zero".to_string());
+                    }
+                    a.wrapping_rem(b)
+                }),
+                0x82 => i64_op!(|a, b| (a as u64)
+                    .checked_rem(b as u64)
+                    .ok_or("integer divide by zero")?),
+                0x83 => i64_op!(|a, b| a & b),
+                0x84 => i64_op!(|a, b| a | b),
+                0x85 => i64_op!(|a, b| a ^ b),
+                0x86 => i64_op!(|a, b| a.wrapping_shl(b as u32)),
+                0x87 => i64_op!(|a, b| a.wrapping_shr(b as u32)),
+                0x88 => i64_op!(|a, b| (a as u64).wrapping_shr(b as u32)),
+                0x89 => i64_op!(|a, b| a.rotate_left((b % 64) as u32)),
+                0x8a => i64_op!(|a, b| a.rotate_right((b % 64) as u32)),
+
+                0x8b..=0x91 => {
+                    let a = pop_f32!();
+                    push_f32!(match op {
+                        0x8b => a.abs(),
+                        0x8c => -a,
+                        0x8d => a.ceil(),
+                        0x8e => a.floor(),
+                        0x8f => a.trunc(),
+                        0x90 => a.round_ties_even(),
+                        _ => a.sqrt(),
+                    })
+                }
+                0x92 => f32_op!(|a, b| a + b),
+                0x93 => f32_op!(|a, b| a - b),
+                0x94 => f32_op!(|a, b| a * b),
+                0x95 => f32_op!(|a, b| a / b),
+                0x96 => f32_op!(|a, b| min(a as f64, b as f64) as f32),
+                0x97 => f32_op!(|a, b| max(a as f64, b as f64) as f32),
+                0x98 => f32_op!(|a, b| a.copysign(b)),
+                0x99..=0x9f => {
+                    let a = pop_f64!();
+                    push_f64!(match op {
+                        0x99 => a.abs(),
+                        0x9a => -a,
+                        0x9b => a.ceil(),
+                        0x9c => a.floor(),
+                        0x9d => a.trunc(),
+                        0x9e => a.round_ties_even(),
+                        _ => a.sqrt(),
+                    })
+                }
+                0xa0 => f64_op!(|a, b| a + b),
+                0xa1 => f64_op!(|a, b| a - b),
+                0xa2 => f64_op!(|a, b| a * b),
+                0xa3 => f64_op!(|a, b| a / b),
+                0xa4 => f64_op!(|a, b| min(a, b)),
+                0xa5 => f64_op!(|a, b| max(a, b)),
+                0xa6 => f64_op!(|a, b| a.copysign(b)),
+
+                0xa7 => {
+                    let a = pop!();
+                    push_i32!(a)
+                }
+                0xa8 => {
+                    let a = pop_f32!() as f64;
+                    push_i32!(truncate(a, true, 32)? as i32)
+                }
+                0xa9 => {
+                    let a = pop_f32!() as f64;
+                    push_i32!(truncate(a, false, 32)? as u32)
+                }
+                0xaa => {
+                    let a = pop_f64!();
+                    push_i32!(truncate(a, true, 32)? as i32)
+                }
+                0xab => {
+                    let a = pop_f64!();
+                    push_i32!(truncate(a, false, 32)? as u32)
+                }
+                0xac => {
+                    let a = pop_i32!();
+                    stack.push(a as i64 as u64)
+                }
+                0xad => {
+                    let a = pop!();
+                    stack.push(a as u32 as u64)
+                }
+                0xae => {
+                    let a = pop_f32!() as f64;
+                    stack.push(truncate(a, true, 64)? as i64 as u64)
+                }
+                0xaf => {
+                    let a = pop_f32!() as f64;
+                    stack.push(truncate(a, false, 64)? as u64)
+                }
+                0xb0 => {
+                    let a = pop_f64!();
+                    stack.push(truncate(a, true, 64)? as i64 as u64)
+                }
+                0xb1 => {
+                    let a = pop_f64!();
+                    stack.push(truncate(a, false, 64)? as u64)
+                }
+                0xb2 => {
+                    let a = pop_i32!();
+                    push_f32!(a as f32)
+                }
+                0xb3 => {
+                    let a = pop_i32!();
+                    push_f32!(a as u32 as f32)
+                }
+                0xb4 => {
+                    let a = pop!();
+                    push_f32!(a as i64 as f32)
+                }
+                0xb5 => {
+                    let a = pop!();
+                    push_f32!(a as f32)
+                }
+                0xb6 => {
+                    let a = pop_f64!();
+                    push_f32!(a as f32)
+                }
+                0xb7 => {
+                    let a = pop_i32!();
+                    push_f64!(a as f64)
+                }
+                0xb8 => {
+                    let a = pop_i32!();
+                    push_f64!(a as u32 as f64)
+                }
+                0xb9 => {
+                    let a = pop!();
+                    push_f64!(a as i64 as f64)
+                }
+                0xba => {
+                    let a = pop!();
+                    push_f64!(a as f64)
+                }
+                0xbb => {
+                    let a = pop_f32!();
+                    push_f64!(a as f64)
+                }
+                // Values are kept as their bits: reinterpreting changes nothing
+                0xbc..=0xbf => {}
+                0xc0 => {
+                    let a = pop_i32!();
+                    push_i32!(a as i8 as i32)
+                }
+                0xc1 => {
+                    let a = pop_i32!();
+                    push_i32!(a as i16 as i32)
+                }
+                0xc2 => {
+                    let a = pop!();
+                    stack.push(a as i8 as i64 as u64)
+                }
+                0xc3 => {
+                    let a = pop!();
+                    stack.push(a as i16 as i64 as u64)
+                }
+                0xc4 => {
+                    let a = pop!();
+                    stack.push(a as i32 as i64 as u64)
+                }
+
+                0xd0 => {
+                    r.byte()?;
+                    stack.push(NULL_REF);
+                }
+                0xd1 => {
+                    let a = pop!();
+                    push_i32!(a == NULL_REF)
+                }
+                0xd2 => stack.push(r.u32()? as u64),
+
+                0xfc => self.run_prefixed(&mut r, &mut stack)?,
+                _ => return Err(format!("unsupported instruction 0x{:02x}", op)),
+            }
+            frame.pc = r.pos;
+        }
+    }
+
+    /// The `0xfc` instructions: saturating conversions, bulk memory and tables
+    fn run_prefixed(&mut self, r: &mut Reader, stack: &mut Vec<u64>) -> Result<(), String> {
+        let mut pop = || stack.pop().ok_or("value stack underflow");
+        let sub = r.u32()?;
+        let value = match sub {
+            // `as` saturates, and turns NaN into 0, as these do
+            0 => f32::from_bits(pop()? as u32) as i32 as u32 as u64,
+            1 => f32::from_bits(pop()? as u32) as u32 as u64,
+            2 => f64::from_bits(pop()?) as i32 as u32 as u64,
+            3 => f64::from_bits(pop()?) as u32 as u64,
+            4 => f32::from_bits(pop()? as u32) as i64 as u64,
+            5 => f32::from_bits(pop()? as u32) as u64,
+            6 => f64::from_bits(pop()?) as i64 as u64,
+            7 => f64::from_bits(pop()?) as u64,
+            8 | 10 | 11 | 12 | 14 | 17 => {
+                let a = r.u32()? as usize;
+                r.second_immediate(sub)?;
+                let n = pop()? as u32 as usize;
+                let src = pop()?;
+                let dest = pop()? as u32 as usize;
+                match sub {
+                    // memory.init: a data segment into memory
+                    8 => {
+                        let src = src as u32 as usize;
+                        let bytes = self
+                            .data
+                            .get(a)
+                            .and_then(|d| d.get(src..src.checked_add(n)?))
+                            .ok_or("memory.init out of bounds")?;
+                        self.memory
+                            .get_mut(dest..dest.checked_add(n).ok_or("out of bounds")?)
+                            .ok_or("memory.init out of bounds")?
+                            .copy_from_slice(bytes);
+                    }
+                    10 => {
+                        let src = src as u32 as usize;
+                        let len = self.memory.len();
+                        if src.max(dest) + n > len {
+                            return Err("memory.copy out of bounds".to_string());
+                        }
+                        self.memory.copy_within(src..src + n, dest);
+                    }
+                    11 => {
+                        self.memory
+                            .get_mut(dest..dest + n)
+                            .ok_or("memory.fill out of bounds")?
+                            .fill(src as u8);
+                    }
+                    // table.init: an element segment into the table
+                    12 => {
+                        let src = src as u32 as usize;
+                        let items = self
+                            .elements
+                            .get(a)
+                            .and_then(|e| e.get(src..src.checked_add(n)?))
+                            .ok_or("table.init out of bounds")?;
+                        let slots = self
+                            .table
+                            .get_mut(dest..dest + n)
+                            .ok_or("table.init out of bounds")?;
+                        for (slot, item) in slots.iter_mut().zip(items) {
+                            *slot = item.map_or(NULL_REF, u64::from);
+                        }
+                    }
+                    14 => {
+                        let src = src as u32 as usize;
+                        if src.max(dest) + n > self.table.len() {
+                            return Err("table.copy out of bounds".to_string());
+                        }
+                        self.table.copy_within(src..src + n, dest);
+                    }
+                    // table.fill: index, value, count
+                    _ => {
+                        self.table
+                            .get_mut(dest..dest + n)
+                            .ok_or("table.fill out of bounds")?
+                            .fill(src);
+                    }
+                }
+                return Ok(());
+            }
+            9 => {
+                let index = r.u32()? as usize;
+                if let Some(data) = self.data.get_mut(index) {
+                    data.clear();
+                }
+                return Ok(());
+            }
+            13 => {
+                let index = r.u32()? as usize;
+                if let Some(element) = self.elements.get_mut(index) {
+                    element.clear();
+                }
+                return Ok(());
+            }
+            15 => {
+                r.u32()?;
+                let n = pop()? as u32 as usize;
+                let init = pop()?;
+                let size = self.table.len();
+                if size + n <= self.max_table {
+                    self.table.resize(size + n, init);
+                    size as u32 as u64
+                } else {
+                    u32::MAX as u64
+                }
+            }
+            16 => {
+                r.u32()?;
+                self.table.len() as u32 as u64
+            }
+            _ => return Err(format!("unsupported instruction 0xfc {}", sub)),
+        };
+        stack.push(value);
+        Ok(())
+    }
+
+    /// `width` bytes at `address`, little-endian
+    fn load(&self, address: u64, width: usize) -> Result<u64, String> {
+        let start = usize::try_from(address).map_err(|_| "memory access out of bounds")?;
+        let bytes = self
+            .memory
+            .get(start..start.saturating_add(width))
+            .ok_or("memory access out of bounds")?;
+        let mut buf = [0; 8];
+        buf[..width].copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn store(&mut self, address: u64, width: usize, value: u64) -> Result<(), String> {
+        let start = usize::try_from(address).map_err(|_| "memory access out of bounds")?;
+        self.memory
+            .get_mut(start..start.saturating_add(width))
+            .ok_or("memory access out of bounds")?
+            .copy_from_slice(&value.to_le_bytes()[..width]);
+        Ok(())
+    }
+}
+
+impl Reader<'_> {
+    /// Skips the second immediate of a `0xfc` instruction, if it has one (a
+    /// memory's is a byte)
+    fn second_immediate(&mut self, sub: u32) -> Result<(), String> {
+        match sub {
+            12 | 14 => {
+                self.u32()?;
+            }
+            8 | 10 => {
+                self.byte()?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Starts a call of `func`, taking its arguments off the stack
+fn enter(
+    module: &Module,
+    func: usize,
+    stack: &mut Vec<u64>,
+    labels: &[Label],
+) -> Result<Frame, String> {
+    let function = module
+        .functions
+        .get(func)
+        .ok_or("call of an unknown function")?;
+    let params = module.types[function.ty].params.len();
+    let stack_base = stack
+        .len()
+        .checked_sub(params)
+        .ok_or("value stack underflow")?;
+    let mut locals: Vec<u64> = stack.drain(stack_base..).collect();
+    locals.extend(function.locals.iter().map(|ty| ty.zero()));
+    Ok(Frame {
+        func,
+        pc: 0,
+        locals,
+        labels_base: labels.len(),
+        stack_base,
+    })
+}
+
+/// Returns from the current call, keeping its results; `None` when it was
+/// the outermost one
+fn leave(
+    module: &Module,
+    frame: &mut Frame,
+    calls: &mut Vec<Frame>,
+    stack: &mut Vec<u64>,
+    labels: &mut Vec<Label>,
+) -> Option<()> {
+    let results = module.types[module.functions[frame.func].ty].results.len();
+    let carried = stack.len().saturating_sub(results);
+    stack.drain(frame.stack_base..carried.max(frame.stack_base));
+    labels.truncate(frame.labels_base);
+    *frame = calls.pop()?;
+    Some(())
+}
+
+/// How many values a block takes and leaves
+fn block_arity(module: &Module, ty: BlockType) -> Result<(usize, usize), String> {
+    match ty {
+        BlockType::Empty => Ok((0, 0)),
+        BlockType::Value => Ok((0, 1)),
+        BlockType::Type(index) => {
+            let ty = module
+                .types
+                .get(index)
+                .ok_or("a block of an unknown type")?;
+            Ok((ty.params.len(), ty.results.len()))
+        }
+    }
+}
+
+/// Rounds toward zero for a conversion to an integer of `bits`, failing
+/// where WebAssembly traps: NaN, or out of the integer's range
+fn truncate(x: f64, signed: bool, bits: i32) -> Result<f64, String> {
+    if x.is_nan() {
+        return Err("invalid conversion to integer".to_string());
+    }
+    let t = x.trunc();
+    let limit = 2f64.powi(if signed { bits - 1 } else { bits });
+    let fits = if signed {
+        t >= -limit && t < limit
+    } else {
+        t > -1.0 && t < limit
+    };
+    if fits {
+        Ok(t)
+    } else {
+        Err("integer overflow".to_string())
+    }
+}
+
+/// WebAssembly's `min`: NaN if either is, and -0 below +0
+fn min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else if a == b {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with one page of memory and one function `f` of no
+    /// parameters, returning `results`, whose code is `body`
+    fn module(results: &[u8], body: &[u8]) -> Instance {
+        fn section(out: &mut Vec<u8>, id: u8, content: &[u8]) {
+            out.push(id);
+            out.push(content.len() as u8);
+            out.extend_from_slice(content);
+        }
+        let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+        let mut ty = vec![1, 0x60, 0, results.len() as u8];
+        ty.extend_from_slice(results);
+        section(&mut bytes, 1, &ty);
+        section(&mut bytes, 3, &[1, 0]);
+        section(&mut bytes, 5, &[1, 0, 1]);
+        section(&mut bytes, 7, &[1, 1, b'f', EXPORT_FUNC, 0]);
+        let mut code = vec![1, body.len() as u8 + 2, 0];
+        code.extend_from_slice(body);
+        code.push(0x0b);
+        section(&mut bytes, 10, &code);
+        Instance::new(Arc::new(Module::parse(&bytes).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn runs_and_returns_results() {
+        // i32.const 40; i32.const 2; i32.add
+        let mut instance = module(&[0x7f], &[0x41, 40, 0x41, 2, 0x6a]);
+        assert_eq!(instance.call("f", &[]), Ok(vec![42]));
+        assert!(instance.call("g", &[]).is_err());
+    }
+
+    #[test]
+    fn endless_loops_run_out_of_fuel() {
+        // loop; br 0; end
+        let mut instance = module(&[], &[0x03, 0x40, 0x0c, 0, 0x0b]);
+        instance.fuel = 10_000;
+        let err = instance.call("f", &[]).unwrap_err();
+        assert!(err.contains("out of fuel"), "{}", err);
+    }
+
+    #[test]
+    fn memory_outside_the_module_is_out_of_bounds() {
+        // i32.const 65533; i32.load: the last 3 bytes of the page, and one past
+        let mut instance = module(&[0x7f], &[0x41, 0xfd, 0xff, 0x03, 0x28, 2, 0]);
+        let err = instance.call("f", &[]).unwrap_err();
+        assert!(err.contains("out of bounds"), "{}", err);
+
+        // i32.const -1; i32.const 7; i32.store
+        let mut instance = module(&[], &[0x41, 0x7f, 0x41, 7, 0x36, 2, 0]);
+        let err = instance.call("f", &[]).unwrap_err();
+        assert!(err.contains("out of bounds"), "{}", err);
+        assert!(instance.memory().iter().all(|&b| b == 0));
+
+        // i32.const 65532; i32.load: the last 4 bytes, which are the module's
+        let mut instance = module(&[0x7f], &[0x41, 0xfc, 0xff, 0x03, 0x28, 2, 0]);
+        instance.memory_mut()[PAGE_SIZE - 4..].copy_from_slice(&7u32.to_le_bytes());
+        assert_eq!(instance.call("f", &[]), Ok(vec![7]));
+    }
+}
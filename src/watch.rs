@@ -0,0 +1,196 @@
+use crate::config::{self, Config, Document};
+use crate::date::DateTime;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// When watch mode polls for changes instead of running a pass every interval
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PollMode {
+    /// When the target is on a network share (SMB, NFS, sshfs...)
+    Auto,
+    Always,
+    Never,
+}
+
+/// Name -> size and modification time of each entry of a folder
+type Listing = BTreeMap<OsString, (u64, Option<SystemTime>)>;
+
+/// Watch mode for network shares, where other clients' writes raise no
+/// events and may still be in progress: the target's entries (sizes and
+/// modification times) are listed every poll, and a pass runs once they
+/// differ from after the last pass and held still for a whole poll.
+pub struct ChangePoller {
+    dir: PathBuf,
+    skip_prefix: &'static str,
+    seen: Option<Listing>,
+    processed: Option<Listing>,
+}
+
+impl ChangePoller {
+    /// Entries starting with `skip_prefix` (the tool's own files) are ignored
+    pub fn new(dir: PathBuf, skip_prefix: &'static str) -> Self {
+        ChangePoller {
+            dir,
+            skip_prefix,
+            seen: None,
+            processed: None,
+        }
+    }
+
+    /// Lists the folder again; true if a pass should run now (always the first time)
+    pub fn ready(&mut self) -> bool {
+        let now = self.listing();
+        let settled = self.seen.as_ref() == Some(&now);
+        self.seen = Some(now);
+        match &self.processed {
+            None => true,
+            Some(processed) => settled && self.seen.as_ref() != Some(processed),
+        }
+    }
+
+    /// Records the folder as a pass left it
+    pub fn passed(&mut self) {
+        let listing = self.listing();
+        self.seen = Some(listing.clone());
+        self.processed = Some(listing);
+    }
+
+    fn listing(&self) -> Listing {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Listing::new();
+        };
+        entries
+            .flatten()
+            .filter(|e| {
+                !e.file_name()
+                    .to_string_lossy()
+                    .starts_with(self.skip_prefix)
+            })
+            .filter_map(|e| {
+                let meta = e.metadata().ok()?;
+                Some((e.file_name(), (meta.len(), meta.modified().ok())))
+            })
+            .collect()
+    }
+}
+
+/// Polls the config file between watch passes and hands back a new config
+/// when it changed and validates. An invalid edit keeps the previous config.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    document: Document,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        // Startup already loaded (and validated) this file; only the raw form is kept for diffs
+        let document = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| config::parse_document(&text).ok())
+            .unwrap_or_default();
+
+        ConfigWatcher {
+            path,
+            modified,
+            document,
+        }
+    }
+
+    /// Returns the new config if the file changed since the last poll and is valid
+    pub fn poll(&mut self) -> Option<Config> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        if modified.is_none() {
+            log(&format!(
+                "Config '{}' was removed; keeping current config",
+                self.path.display()
+            ));
+            return None;
+        }
+        self.load()
+    }
+
+    /// Reads the config file again even if it looks unchanged (`ctl reload-config`)
+    pub fn reload(&mut self) -> Option<Config> {
+        self.modified = modified_time(&self.path);
+        if self.modified.is_none() {
+            log(&format!(
+                "Config '{}' not found; keeping current config",
+                self.path.display()
+            ));
+            return None;
+        }
+        self.load()
+    }
+
+    fn load(&mut self) -> Option<Config> {
+        let loaded = fs::read_to_string(&self.path)
+            .map_err(|e| config::ConfigError::Io(self.path.clone(), e))
+            .and_then(|text| config::parse_document(&text))
+            .and_then(|doc| Config::from_document(&doc).map(|config| (doc, config)));
+
+        match loaded {
+            Ok((document, config)) => {
+                log(&format!("Config '{}' reloaded", self.path.display()));
+                for line in diff(&self.document, &document) {
+                    println!("    {}", line);
+                }
+                self.document = document;
+                Some(config)
+            }
+            Err(e) => {
+                log(&format!(
+                    "Error in config '{}': {} (keeping previous config)",
+                    self.path.display(),
+                    e
+                ));
+                None
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn log(message: &str) {
+    println!("[{}] {}", DateTime::now().to_iso(), message);
+}
+
+/// Describes what changed between two versions of the config, one line per key
+fn diff(old: &Document, new: &Document) -> Vec<String> {
+    let old = old.flatten();
+    let new = new.flatten();
+    let mut lines = Vec::new();
+
+    for (key, value) in &new {
+        match old.get(key) {
+            None => lines.push(format!("+ {} = {}", key, value)),
+            Some(previous) if previous != value => {
+                lines.push(format!("~ {} = {} (was {})", key, value, previous))
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, value) in &old {
+        if !new.contains_key(key) {
+            lines.push(format!("- {} = {}", key, value));
+        }
+    }
+
+    if lines.is_empty() {
+        lines.push("(no effective changes)".to_string());
+    }
+    lines
+}
@@ -0,0 +1,144 @@
+pub use sys::{get, set};
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{CString, c_char, c_void};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[cfg(target_os = "macos")]
+    unsafe extern "C" {
+        fn getxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+            position: u32,
+            options: i32,
+        ) -> isize;
+        fn setxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            position: u32,
+            options: i32,
+        ) -> i32;
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    unsafe extern "C" {
+        fn getxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+        fn setxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: i32,
+        ) -> i32;
+    }
+
+    /// Attributes read here (tags, download URLs) are short
+    const MAX_ATTRIBUTE: usize = 64 * 1024;
+
+    /// `ENODATA` (Linux) / `ENOATTR` (BSD, macOS): the file has no such attribute
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const NO_ATTRIBUTE: i32 = 61;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const NO_ATTRIBUTE: i32 = 93;
+
+    fn c_strings(path: &Path, name: &str) -> io::Result<(CString, CString)> {
+        let invalid = |_| io::Error::from(io::ErrorKind::InvalidInput);
+        Ok((
+            CString::new(path.as_os_str().as_bytes()).map_err(invalid)?,
+            CString::new(name).map_err(invalid)?,
+        ))
+    }
+
+    /// The attribute's value, `None` if the file has none
+    pub fn get(path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let (path, name) = c_strings(path, name)?;
+        let mut value = vec![0u8; MAX_ATTRIBUTE];
+        // SAFETY: both strings are NUL-terminated and `value` holds `size` bytes
+        #[cfg(target_os = "macos")]
+        let len = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+                0,
+                0,
+            )
+        };
+        #[cfg(not(target_os = "macos"))]
+        let len = unsafe {
+            getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if len < 0 {
+            let error = io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(NO_ATTRIBUTE) => Ok(None),
+                _ => Err(error),
+            };
+        }
+        value.truncate(len as usize);
+        Ok(Some(value))
+    }
+
+    pub fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+        let (path, name) = c_strings(path, name)?;
+        // SAFETY: both strings are NUL-terminated and `value` is `size` bytes
+        #[cfg(target_os = "macos")]
+        let result = unsafe {
+            setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                0,
+            )
+        };
+        #[cfg(not(target_os = "macos"))]
+        let result = unsafe {
+            setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Windows: no extended attributes (alternate data streams are not used here)
+#[cfg(not(unix))]
+mod sys {
+    use std::io;
+    use std::path::Path;
+
+    pub fn get(_path: &Path, _name: &str) -> io::Result<Option<Vec<u8>>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
@@ -0,0 +1,390 @@
+use crate::date::DateTime;
+use crate::deflate;
+use crate::hash::Crc32;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// General purpose flag: names are UTF-8
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// Files bigger than this are stored uncompressed and streamed, not held in memory
+const DEFLATE_LIMIT: u64 = 64 * 1024 * 1024;
+/// Plain zip (no Zip64) cannot describe entries or archives past 4 GiB
+pub const MAX_SIZE: u64 = u32::MAX as u64;
+
+/// What was written for (or read back from) one archive member
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub crc32: u32,
+    pub size: u64,
+}
+
+struct CentralEntry {
+    entry: ZipEntry,
+    method: u16,
+    compressed_size: u32,
+    time: u16,
+    date: u16,
+    mode: u32,
+    offset: u32,
+}
+
+/// Writes a zip archive one file at a time (deflate, or stored when that is smaller)
+pub struct ZipWriter {
+    writer: BufWriter<File>,
+    offset: u64,
+    entries: Vec<CentralEntry>,
+}
+
+impl ZipWriter {
+    pub fn create(path: &Path) -> io::Result<ZipWriter> {
+        Ok(ZipWriter {
+            writer: BufWriter::new(File::create(path)?),
+            offset: 0,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Adds the file at `path` to the archive under `name`
+    pub fn add_file(&mut self, name: &str, path: &Path) -> io::Result<ZipEntry> {
+        let meta = fs::metadata(path)?;
+        let size = meta.len();
+        if size > MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file too large for a zip archive (4 GiB max)",
+            ));
+        }
+
+        let (time, date) = dos_timestamp(meta.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+
+        // Small files are compressed in memory; big ones are streamed as-is
+        let (method, crc32, data) = if size <= DEFLATE_LIMIT {
+            let contents = fs::read(path)?;
+            let mut crc = Crc32::default();
+            crc.update(&contents);
+            let packed = deflate::compress(&contents);
+            if packed.len() < contents.len() {
+                (DEFLATED, crc.finish(), Some(packed))
+            } else {
+                (STORED, crc.finish(), Some(contents))
+            }
+        } else {
+            (STORED, crc_file(path)?, None)
+        };
+        let compressed_size = data.as_ref().map_or(size, |d| d.len() as u64);
+
+        let entry_end = self.offset + 30 + name.len() as u64 + compressed_size;
+        if entry_end > MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "archive would exceed 4 GiB",
+            ));
+        }
+
+        let entry = CentralEntry {
+            entry: ZipEntry {
+                name: name.to_string(),
+                crc32,
+                size,
+            },
+            method,
+            compressed_size: compressed_size as u32,
+            time,
+            date,
+            mode: file_mode(&meta),
+            offset: self.offset as u32,
+        };
+
+        let w = &mut self.writer;
+        w.write_all(&LOCAL_HEADER.to_le_bytes())?;
+        w.write_all(&20u16.to_le_bytes())?; // Version needed: 2.0
+        w.write_all(&FLAG_UTF8.to_le_bytes())?;
+        w.write_all(&method.to_le_bytes())?;
+        w.write_all(&time.to_le_bytes())?;
+        w.write_all(&date.to_le_bytes())?;
+        w.write_all(&crc32.to_le_bytes())?;
+        w.write_all(&entry.compressed_size.to_le_bytes())?;
+        w.write_all(&(size as u32).to_le_bytes())?;
+        w.write_all(&(name.len() as u16).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?; // Extra field length
+        w.write_all(name.as_bytes())?;
+
+        match data {
+            Some(data) => w.write_all(&data)?,
+            None => {
+                let copied = io::copy(&mut File::open(path)?, w)?;
+                if copied != size {
+                    return Err(io::Error::other("file changed while being archived"));
+                }
+            }
+        }
+
+        self.offset = entry_end;
+        let written = entry.entry.clone();
+        self.entries.push(entry);
+        Ok(written)
+    }
+
+    /// Writes the central directory and flushes the archive to disk
+    pub fn finish(mut self) -> io::Result<()> {
+        let start = self.offset;
+        let w = &mut self.writer;
+
+        for e in &self.entries {
+            w.write_all(&CENTRAL_HEADER.to_le_bytes())?;
+            w.write_all(&(3u16 << 8 | 20).to_le_bytes())?; // Made by: Unix, 2.0
+            w.write_all(&20u16.to_le_bytes())?;
+            w.write_all(&FLAG_UTF8.to_le_bytes())?;
+            w.write_all(&e.method.to_le_bytes())?;
+            w.write_all(&e.time.to_le_bytes())?;
+            w.write_all(&e.date.to_le_bytes())?;
+            w.write_all(&e.entry.crc32.to_le_bytes())?;
+            w.write_all(&e.compressed_size.to_le_bytes())?;
+            w.write_all(&(e.entry.size as u32).to_le_bytes())?;
+            w.write_all(&(e.entry.name.len() as u16).to_le_bytes())?;
+            w.write_all(&0u16.to_le_bytes())?; // Extra field length
+            w.write_all(&0u16.to_le_bytes())?; // Comment length
+            w.write_all(&0u16.to_le_bytes())?; // Disk number
+            w.write_all(&0u16.to_le_bytes())?; // Internal attributes
+            w.write_all(&(e.mode << 16).to_le_bytes())?;
+            w.write_all(&e.offset.to_le_bytes())?;
+            w.write_all(e.entry.name.as_bytes())?;
+            self.offset += 46 + e.entry.name.len() as u64;
+        }
+
+        if self.offset > MAX_SIZE || self.entries.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "archive too large for plain zip",
+            ));
+        }
+
+        let count = self.entries.len() as u16;
+        w.write_all(&END_OF_CENTRAL_DIR.to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?; // This disk
+        w.write_all(&0u16.to_le_bytes())?; // Disk with the central directory
+        w.write_all(&count.to_le_bytes())?;
+        w.write_all(&count.to_le_bytes())?;
+        w.write_all(&((self.offset - start) as u32).to_le_bytes())?;
+        w.write_all(&(start as u32).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?; // Comment length
+
+        w.flush()?;
+        w.get_ref().sync_all()
+    }
+}
+
+/// Reads an archive written by `ZipWriter` back, decompressing every member
+/// and checking it against its recorded CRC and size
+pub fn read_back(path: &Path) -> io::Result<Vec<ZipEntry>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < 22 {
+        return Err(corrupt("too short"));
+    }
+
+    // No archive comment is ever written, so the end record is the last 22 bytes
+    file.seek(SeekFrom::Start(len - 22))?;
+    let end = read_bytes(&mut file, 22)?;
+    if u32_at(&end, 0) != END_OF_CENTRAL_DIR {
+        return Err(corrupt("missing end of central directory"));
+    }
+    let count = u16_at(&end, 10) as usize;
+    let central_offset = u32_at(&end, 16) as u64;
+
+    file.seek(SeekFrom::Start(central_offset))?;
+    let mut members = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = read_bytes(&mut file, 46)?;
+        if u32_at(&header, 0) != CENTRAL_HEADER {
+            return Err(corrupt("bad central directory entry"));
+        }
+        let name_len = u16_at(&header, 28) as usize;
+        let skip = u16_at(&header, 30) as i64 + u16_at(&header, 32) as i64;
+        let name = String::from_utf8(read_bytes(&mut file, name_len)?)
+            .map_err(|_| corrupt("member name is not UTF-8"))?;
+        file.seek_relative(skip)?;
+
+        members.push((
+            ZipEntry {
+                name,
+                crc32: u32_at(&header, 16),
+                size: u32_at(&header, 24) as u64,
+            },
+            u16_at(&header, 10),
+            u32_at(&header, 20) as u64,
+            u32_at(&header, 42) as u64,
+        ));
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    for (entry, method, compressed_size, offset) in members {
+        file.seek(SeekFrom::Start(offset))?;
+        let header = read_bytes(&mut file, 30)?;
+        if u32_at(&header, 0) != LOCAL_HEADER {
+            return Err(corrupt("bad local header"));
+        }
+        let skip = u16_at(&header, 26) as i64 + u16_at(&header, 28) as i64;
+        file.seek_relative(skip)?;
+
+        let mut crc = Crc32::default();
+        let mut size = 0u64;
+        match method {
+            STORED => {
+                let mut remaining = compressed_size;
+                let mut buf = vec![0u8; 1024 * 1024];
+                while remaining > 0 {
+                    let n = remaining.min(buf.len() as u64) as usize;
+                    file.read_exact(&mut buf[..n])?;
+                    crc.update(&buf[..n]);
+                    remaining -= n as u64;
+                }
+                size = compressed_size;
+            }
+            DEFLATED => {
+                let packed = read_bytes(&mut file, compressed_size as usize)?;
+                let data = deflate::decompress(&packed)?;
+                crc.update(&data);
+                size += data.len() as u64;
+            }
+            _ => return Err(corrupt("unsupported compression method")),
+        }
+
+        if crc.finish() != entry.crc32 || size != entry.size {
+            return Err(corrupt(&format!(
+                "{} does not match its checksum",
+                entry.name
+            )));
+        }
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// CRC-32 of a file's contents, as recorded in zip headers
+pub fn crc_file(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut crc = Crc32::default();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(crc.finish());
+        }
+        crc.update(&buf[..n]);
+    }
+}
+
+/// MS-DOS time and date fields (2-second resolution, years 1980..=2107)
+fn dos_timestamp(time: SystemTime) -> (u16, u16) {
+    let t = DateTime::from_system_time(time);
+    if t.year < 1980 {
+        return (0, 1 << 5 | 1); // 1980-01-01 00:00
+    }
+    let year = (t.year - 1980).min(127) as u16;
+    let time = (t.hour as u16) << 11 | (t.minute as u16) << 5 | ((t.second as u16) / 2);
+    let date = year << 9 | (t.month as u16) << 5 | t.day as u16;
+    (time, date)
+}
+
+#[cfg(unix)]
+fn file_mode(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn file_mode(meta: &fs::Metadata) -> u32 {
+    if meta.permissions().readonly() {
+        0o100444
+    } else {
+        0o100644
+    }
+}
+
+fn read_bytes(reader: &mut impl Read, n: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; n];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt archive: {}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_read_back_as_written() {
+        let dir = std::env::temp_dir().join("auto-organize-zip-round-trip");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let text = "line of a log file\n".repeat(5000);
+        fs::write(dir.join("log.txt"), &text).unwrap();
+        let noise: Vec<u8> = (0..5000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        fs::write(dir.join("noise.bin"), &noise).unwrap();
+        fs::write(dir.join("empty"), "").unwrap();
+
+        let archive = dir.join("out.zip");
+        let mut writer = ZipWriter::create(&archive).unwrap();
+        let mut written = Vec::new();
+        for name in ["log.txt", "noise.bin", "empty"] {
+            written.push(writer.add_file(name, &dir.join(name)).unwrap());
+        }
+        writer.finish().unwrap();
+
+        assert_eq!(read_back(&archive).unwrap(), written);
+        assert_eq!(written[0].size, text.len() as u64);
+        assert_eq!(written[0].crc32, crc_file(&dir.join("log.txt")).unwrap());
+        assert!(fs::metadata(&archive).unwrap().len() < text.len() as u64);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn damaged_archives_fail_to_read_back() {
+        let dir = std::env::temp_dir().join("auto-organize-zip-damaged");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "a".repeat(1000)).unwrap();
+        let archive = dir.join("out.zip");
+        let mut writer = ZipWriter::create(&archive).unwrap();
+        writer.add_file("a.txt", &dir.join("a.txt")).unwrap();
+        writer.finish().unwrap();
+
+        // Flip a byte of the compressed data, just past the 30-byte header and name
+        let mut bytes = fs::read(&archive).unwrap();
+        bytes[30 + "a.txt".len() + 2] ^= 0xff;
+        fs::write(&archive, &bytes).unwrap();
+        assert!(read_back(&archive).is_err());
+
+        fs::write(&archive, &bytes[..10]).unwrap();
+        assert!(read_back(&archive).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}